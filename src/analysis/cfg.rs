@@ -0,0 +1,461 @@
+use crate::dbg::symbols::SymbolTable;
+use crate::hw::instruction::Instruction;
+use crate::hw::mem::Mem;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+const INSTRUCTION_SIZE: u16 = 2;
+
+/// Why control leaves an edge's source block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumEdgeKind
+{
+    /// Into the next instruction (including the not-taken side of a skip and the return site of a call).
+    Next,
+    Jump,
+    /// The taken side of a skip instruction.
+    Skip,
+}
+
+impl EnumEdgeKind
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Self::Next => "next",
+            Self::Jump => "jump",
+            Self::Skip => "skip",
+        }
+    }
+}
+
+/// How a basic block ends.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumBlockEnd
+{
+    /// The next instruction starts another block.
+    Fallthrough,
+    Jump,
+    Skip,
+    Call { target: u16 },
+    Return,
+    Halt,
+    /// BNNN: the target depends on V0 (or VX), so the traversal can't follow it.
+    IndirectJump { base: u16 },
+    /// An opcode that doesn't decode (or a 0NNN machine-code call), or one running off the end of memory.
+    Invalid,
+}
+
+#[derive(Clone, Debug)]
+pub struct BasicBlock
+{
+    pub start: u16,
+    /// Addresses and opcodes, in order.
+    pub instructions: Vec<(u16, u16)>,
+    pub end: EnumBlockEnd,
+    pub successors: Vec<(u16, EnumEdgeKind)>,
+}
+
+/// A subroutine (or the entry point) and the blocks reachable from it without following calls.
+#[derive(Clone, Debug)]
+pub struct Function
+{
+    pub entry: u16,
+    pub blocks: BTreeSet<u16>,
+    pub callees: BTreeSet<u16>,
+}
+
+/// A static control-flow graph, built by recursive descent from an entry point.
+///
+/// Jumps, skips, calls and returns are followed; BNNN jumps can't be resolved statically, so
+/// the code they reach is missing from the graph and the jumps are listed in 'get_unresolved'.
+pub struct ControlFlowGraph
+{
+    entry: u16,
+    blocks: BTreeMap<u16, BasicBlock>,
+    functions: BTreeMap<u16, Function>,
+    unresolved: Vec<u16>,
+}
+
+impl ControlFlowGraph
+{
+    /// Follows every path from 'entry' through the code in 'mem'.
+    pub fn analyze(mem: &Mem, entry: u16) -> Self
+    {
+        let (leaders, call_targets) = Self::find_leaders(mem, entry);
+        let mut blocks = BTreeMap::new();
+        let mut unresolved = Vec::new();
+
+        for start in leaders.iter()
+        {
+            let block = Self::build_block(mem, *start, &leaders);
+
+            if let EnumBlockEnd::IndirectJump { .. } = block.end
+            {
+                unresolved.push(block.instructions.last().unwrap().0);
+            }
+
+            blocks.insert(*start, block);
+        }
+
+        let mut result = Self { entry, blocks, functions: BTreeMap::new(), unresolved };
+
+        for function_entry in std::iter::once(entry).chain(call_targets)
+        {
+            let function = result.build_function(function_entry);
+            result.functions.insert(function_entry, function);
+        }
+
+        result
+    }
+
+    /// Where control goes after the instruction at 'addr', without following calls (so the return site is next).
+    fn successors(addr: u16, instruction: Instruction) -> Vec<(u16, EnumEdgeKind)>
+    {
+        let next = addr.wrapping_add(INSTRUCTION_SIZE);
+
+        match instruction
+        {
+            Instruction::Halt | Instruction::Return | Instruction::JumpOffset { .. } | Instruction::MachineCall { .. } | Instruction::Unknown => Vec::new(),
+            Instruction::Jump { nnn } => vec![(nnn, EnumEdgeKind::Jump)],
+            Instruction::SkipIfEqual { .. } | Instruction::SkipIfNotEqual { .. } | Instruction::SkipIfRegistersEqual { .. }
+                | Instruction::SkipIfRegistersNotEqual { .. } | Instruction::SkipIfKey { .. } | Instruction::SkipIfNotKey { .. } =>
+            {
+                vec![(next, EnumEdgeKind::Next), (next.wrapping_add(INSTRUCTION_SIZE), EnumEdgeKind::Skip)]
+            },
+            _ => vec![(next, EnumEdgeKind::Next)],
+        }
+    }
+
+    /// True if the instruction is the last one of its block.
+    fn ends_block(instruction: Instruction) -> bool
+    {
+        !matches!(Self::successors(0, instruction).as_slice(), [(_, EnumEdgeKind::Next)]) || matches!(instruction, Instruction::Call { .. })
+    }
+
+    /// Visits every reachable instruction. Returns the block leaders and the call targets.
+    fn find_leaders(mem: &Mem, entry: u16) -> (BTreeSet<u16>, BTreeSet<u16>)
+    {
+        let mut visited = BTreeSet::new();
+        let mut leaders = BTreeSet::from([entry]);
+        let mut call_targets = BTreeSet::new();
+        let mut work_list = vec![entry];
+
+        while let Some(addr) = work_list.pop()
+        {
+            if !visited.insert(addr)
+            {
+                continue;
+            }
+
+            let instruction = match mem.read_u16(addr as usize)
+            {
+                Some(raw_opcode) => Instruction::decode(raw_opcode),
+                None => { continue; },
+            };
+
+            let successors = Self::successors(addr, instruction);
+
+            if let Instruction::Call { nnn } = instruction
+            {
+                call_targets.insert(nnn);
+                leaders.insert(nnn);
+                work_list.push(nnn);
+            }
+
+            if Self::ends_block(instruction)
+            {
+                leaders.extend(successors.iter().map(|(target, _)| *target));
+            }
+
+            work_list.extend(successors.iter().map(|(target, _)| *target));
+        }
+
+        // Don't start blocks at addresses outside of memory.
+        leaders.retain(|addr| visited.contains(addr));
+        call_targets.retain(|addr| visited.contains(addr));
+
+        (leaders, call_targets)
+    }
+
+    fn build_block(mem: &Mem, start: u16, leaders: &BTreeSet<u16>) -> BasicBlock
+    {
+        let mut block = BasicBlock { start, instructions: Vec::new(), end: EnumBlockEnd::Invalid, successors: Vec::new() };
+        let mut addr = start;
+
+        loop
+        {
+            let raw_opcode = match mem.read_u16(addr as usize)
+            {
+                Some(raw_opcode) => raw_opcode,
+                None => { return block; },
+            };
+
+            let instruction = Instruction::decode(raw_opcode);
+            block.instructions.push((addr, raw_opcode));
+            block.successors = Self::successors(addr, instruction);
+
+            block.end = match instruction
+            {
+                Instruction::Halt => EnumBlockEnd::Halt,
+                Instruction::Return => EnumBlockEnd::Return,
+                Instruction::Jump { .. } => EnumBlockEnd::Jump,
+                Instruction::JumpOffset { nnn, .. } => EnumBlockEnd::IndirectJump { base: nnn },
+                Instruction::Call { nnn } => EnumBlockEnd::Call { target: nnn },
+                Instruction::MachineCall { .. } | Instruction::Unknown => EnumBlockEnd::Invalid,
+                _ if Self::ends_block(instruction) => EnumBlockEnd::Skip,
+                _ => EnumBlockEnd::Fallthrough,
+            };
+
+            let next = addr.wrapping_add(INSTRUCTION_SIZE);
+
+            if block.end != EnumBlockEnd::Fallthrough || leaders.contains(&next)
+            {
+                // A successor that isn't a block (e.g. past the end of memory) isn't an edge.
+                block.successors.retain(|(target, _)| leaders.contains(target));
+                return block;
+            }
+
+            addr = next;
+        }
+    }
+
+    fn build_function(&self, entry: u16) -> Function
+    {
+        let mut function = Function { entry, blocks: BTreeSet::new(), callees: BTreeSet::new() };
+        let mut work_list = vec![entry];
+
+        while let Some(start) = work_list.pop()
+        {
+            if !function.blocks.insert(start)
+            {
+                continue;
+            }
+
+            if let Some(block) = self.blocks.get(&start)
+            {
+                if let EnumBlockEnd::Call { target } = block.end
+                {
+                    function.callees.insert(target);
+                }
+
+                work_list.extend(block.successors.iter().map(|(target, _)| *target));
+            }
+        }
+
+        function
+    }
+
+    pub fn get_entry(&self) -> u16
+    {
+        self.entry
+    }
+
+    pub fn get_blocks(&self) -> &BTreeMap<u16, BasicBlock>
+    {
+        &self.blocks
+    }
+
+    pub fn get_functions(&self) -> &BTreeMap<u16, Function>
+    {
+        &self.functions
+    }
+
+    /// Addresses of the BNNN jumps whose targets weren't followed.
+    pub fn get_unresolved(&self) -> &[u16]
+    {
+        &self.unresolved
+    }
+
+    /// The function's label, or 'main' / 'sub_XXX' without one.
+    fn function_name(&self, entry: u16, symbols: &SymbolTable) -> String
+    {
+        if let Some(symbol) = symbols.at(entry)
+        {
+            return symbol.name.clone();
+        }
+
+        else if entry == self.entry
+        {
+            return String::from("main");
+        }
+
+        format!("sub_{:03X}", entry)
+    }
+
+    /// The graph in Graphviz DOT: one cluster per function, solid edges for control flow
+    /// and dashed ones for calls. Blocks shared between functions are drawn in the first one.
+    pub fn format_dot(&self, symbols: &SymbolTable, stream: &mut String)
+    {
+        stream.push_str("digraph cfg {\n");
+        stream.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+        let mut drawn = BTreeSet::new();
+
+        for function in self.functions.values()
+        {
+            stream.push_str(&format!("    subgraph cluster_{0:03X} {{\n        label=\"{1} (0x{0:03X})\";\n", function.entry, self.function_name(function.entry, symbols)));
+
+            for start in function.blocks.iter().filter(|start| self.blocks.contains_key(start))
+            {
+                if !drawn.insert(*start)
+                {
+                    continue;
+                }
+
+                let block = &self.blocks[start];
+                let label: String = block.instructions.iter()
+                    .map(|(addr, raw_opcode)| format!("0x{0:03X}: {1}\\l", addr, symbols.disassemble(*raw_opcode)))
+                    .collect();
+                let color = if let EnumBlockEnd::IndirectJump { .. } = block.end { ", color=red" } else { "" };
+
+                stream.push_str(&format!("        b_{0:03X} [label=\"{1}\"{2}];\n", start, label, color));
+            }
+
+            stream.push_str("    }\n");
+        }
+
+        for block in self.blocks.values()
+        {
+            for (target, kind) in block.successors.iter()
+            {
+                stream.push_str(&format!("    b_{0:03X} -> b_{1:03X} [label=\"{2}\"];\n", block.start, target, kind.name()));
+            }
+
+            match block.end
+            {
+                EnumBlockEnd::Call { target } if self.blocks.contains_key(&target) =>
+                {
+                    stream.push_str(&format!("    b_{0:03X} -> b_{1:03X} [label=\"call\", style=dashed];\n", block.start, target));
+                },
+                EnumBlockEnd::IndirectJump { base } =>
+                {
+                    stream.push_str(&format!("    unresolved_{0:03X} [label=\"0x{1:03X} + V0?\", shape=diamond, color=red];\n", block.start, base));
+                    stream.push_str(&format!("    b_{0:03X} -> unresolved_{0:03X} [style=dotted];\n", block.start));
+                },
+                _ => {},
+            }
+        }
+
+        stream.push_str("}\n");
+    }
+
+    /// The call graph in Graphviz DOT: one node per function.
+    pub fn format_call_graph_dot(&self, symbols: &SymbolTable, stream: &mut String)
+    {
+        stream.push_str("digraph calls {\n");
+        stream.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+        for function in self.functions.values()
+        {
+            stream.push_str(&format!("    f_{0:03X} [label=\"{1}\"];\n", function.entry, self.function_name(function.entry, symbols)));
+
+            for callee in function.callees.iter()
+            {
+                stream.push_str(&format!("    f_{0:03X} -> f_{1:03X};\n", function.entry, callee));
+            }
+        }
+
+        stream.push_str("}\n");
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::analysis::cfg::{ControlFlowGraph, EnumBlockEnd, EnumEdgeKind};
+    use crate::dbg::symbols::SymbolTable;
+    use crate::hw::mem::Mem;
+
+    fn load(program: &[u16]) -> Mem
+    {
+        let mut mem = Mem::new(4096);
+
+        for (i, instruction) in program.iter().enumerate()
+        {
+            mem.write_u16(0x200 + i * 2, *instruction);
+        }
+
+        mem
+    }
+
+    #[test]
+    fn splits_blocks_at_skips_jumps_and_calls()
+    {
+        // 0x200: V0 = 0, 0x202: call 0x20C, 0x204: skip if V0 == 5, 0x206: jump 0x202, 0x208: halt
+        // 0x20A: (never reached) / 0x20C: V0 += 1, return
+        let mem = load(&[0x6000, 0x220C, 0x3005, 0x1202, 0x0000, 0xFFFF, 0x7001, 0x00EE]);
+        let cfg = ControlFlowGraph::analyze(&mem, 0x200);
+
+        let starts: Vec<u16> = cfg.get_blocks().keys().copied().collect();
+        assert_eq!(starts, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20C]);
+
+        let blocks = cfg.get_blocks();
+        assert_eq!(blocks[&0x200].end, EnumBlockEnd::Fallthrough);
+        assert_eq!(blocks[&0x200].successors, [(0x202, EnumEdgeKind::Next)]);
+        assert_eq!(blocks[&0x202].end, EnumBlockEnd::Call { target: 0x20C });
+        assert_eq!(blocks[&0x204].successors, [(0x206, EnumEdgeKind::Next), (0x208, EnumEdgeKind::Skip)]);
+        assert_eq!(blocks[&0x206].successors, [(0x202, EnumEdgeKind::Jump)]);
+        assert_eq!(blocks[&0x208].end, EnumBlockEnd::Halt);
+        assert_eq!(blocks[&0x20C].instructions.len(), 2);
+        assert_eq!(blocks[&0x20C].end, EnumBlockEnd::Return);
+
+        let functions = cfg.get_functions();
+        assert_eq!(functions.len(), 2);
+        assert!(functions[&0x200].callees.contains(&0x20C));
+        assert!(!functions[&0x200].blocks.contains(&0x20C));
+        assert_eq!(functions[&0x20C].blocks.len(), 1);
+
+        // 0NNN calls machine code, not a CHIP-8 subroutine.
+        let cfg = ControlFlowGraph::analyze(&load(&[0x6000, 0x020C]), 0x200);
+        assert_eq!(cfg.get_blocks()[&0x200].end, EnumBlockEnd::Invalid);
+        assert_eq!(cfg.get_functions().len(), 1);
+    }
+
+    #[test]
+    fn indirect_jumps_are_unresolved()
+    {
+        // 0x200: V0 = 2, 0x202: jump 0x300 + V0
+        let mem = load(&[0x6002, 0xB300]);
+        let cfg = ControlFlowGraph::analyze(&mem, 0x200);
+
+        assert_eq!(cfg.get_unresolved(), [0x202]);
+        assert_eq!(cfg.get_blocks()[&0x200].end, EnumBlockEnd::IndirectJump { base: 0x300 });
+
+        let mut stream = String::new();
+        cfg.format_dot(&SymbolTable::new(), &mut stream);
+        assert!(stream.contains("b_200 [label=\"0x200: LD V0, 0x02\\l0x202: JP V0, 0x300\\l\", color=red];"));
+        assert!(stream.contains("b_200 -> unresolved_200 [style=dotted];"));
+    }
+
+    #[test]
+    fn dot_output_has_clusters_and_call_edges()
+    {
+        // 0x200: call 0x206, 0x202: jump 0x200, 0x204: (data) / 0x206: return
+        let mem = load(&[0x2206, 0x1200, 0x1234, 0x00EE]);
+        let cfg = ControlFlowGraph::analyze(&mem, 0x200);
+
+        let mut stream = String::new();
+        cfg.format_dot(&SymbolTable::new(), &mut stream);
+
+        assert!(stream.starts_with("digraph cfg {\n"));
+        assert!(stream.contains("subgraph cluster_200 {\n        label=\"main (0x200)\";"));
+        assert!(stream.contains("subgraph cluster_206 {\n        label=\"sub_206 (0x206)\";"));
+        assert!(stream.contains("b_200 -> b_202 [label=\"next\"];"));
+        assert!(stream.contains("b_202 -> b_200 [label=\"jump\"];"));
+        assert!(stream.contains("b_200 -> b_206 [label=\"call\", style=dashed];"));
+        assert!(!stream.contains("0x204"));
+
+        let mut stream = String::new();
+        cfg.format_call_graph_dot(&SymbolTable::new(), &mut stream);
+        assert_eq!(stream, "digraph calls {\n    node [shape=box, fontname=\"monospace\"];\n    f_200 [label=\"main\"];\n    f_200 -> f_206;\n    f_206 [label=\"sub_206\"];\n}\n");
+
+        // Labels name the functions and call targets.
+        let symbols = SymbolTable::parse("start 0x200\nreset 0x206\n").unwrap();
+        let mut stream = String::new();
+        cfg.format_dot(&symbols, &mut stream);
+        assert!(stream.contains("label=\"reset (0x206)\";"));
+        assert!(stream.contains("b_200 [label=\"0x200: CALL 0x206  ; reset\\l\"];"));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::env::config_data::VERSION;
+use crate::hw::fault::EnumFault;
+use crate::hw::frame::FrameConfig;
+use crate::machine::Machine;
+
+use std::time::Duration;
+
+const STARTING_PC: u16 = 0x200;
+
+/// A synthetic program that loops forever and stresses one part of the interpreter.
+pub struct Workload
+{
+    pub name: &'static str,
+    pub description: &'static str,
+    pub program: &'static [u16],
+}
+
+pub const WORKLOADS: [Workload; 4] = [
+    Workload
+    {
+        name: "alu",
+        description: "register arithmetic, shifts and skips",
+        // V0 = 0, V1 = 1, then loop: V0 += V1, V1 += V0, V2 ^= V1, V3 >>= 1, skip if VF == 0, VF = 0
+        program: &[0x6000, 0x6101, 0x8014, 0x8104, 0x8213, 0x8336, 0x3F00, 0x6F00, 0x1204],
+    },
+    Workload
+    {
+        name: "call",
+        description: "nested subroutine calls and returns",
+        // 0x200: call 0x206, V0 += 1, loop / 0x206: call 0x20C, V1 += 1, return / 0x20C: V2 += 1, return
+        program: &[0x2206, 0x7001, 0x1200, 0x220C, 0x7101, 0x00EE, 0x7201, 0x00EE],
+    },
+    Workload
+    {
+        name: "draw",
+        description: "font sprites across the screen, clearing every 256 draws",
+        // 0x200: clear, V0 = 0, V1 = 0
+        // 0x206: I = font(V2), draw at (V0, V1), V0 += 5, V2 += 3, V1 += 1, loop to 0x206 until V1 wraps, restart
+        program: &[0x00E0, 0x6000, 0x6100, 0xF229, 0xD015, 0x7005, 0x7203, 0x7101, 0x3100, 0x1206, 0x1200],
+    },
+    Workload
+    {
+        name: "memcopy",
+        description: "16-byte block copies through FX65/FX55",
+        // I = 0x300, load V0-VF, I = 0x400, store V0-VF, loop
+        program: &[0xA300, 0xFF65, 0xA400, 0xFF55, 0x1200],
+    },
+];
+
+pub fn find_workload(name: &str) -> Option<&'static Workload>
+{
+    WORKLOADS.iter().find(|workload| workload.name == name)
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchResult
+{
+    pub workload: &'static str,
+    pub instructions: u64,
+    pub frames: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchResult
+{
+    pub fn instructions_per_sec(&self) -> f64
+    {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn ns_per_instruction(&self) -> f64
+    {
+        self.elapsed.as_nanos() as f64 / self.instructions.max(1) as f64
+    }
+
+    pub fn frames_per_sec(&self) -> f64
+    {
+        self.frames as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Runs a workload unthrottled (frame by frame, so timers tick too) until at least 'cycles' instructions ran.
+pub fn run_workload(workload: &'static Workload, cycles: u64, frame_config: &FrameConfig, decode_cache: bool) -> Result<BenchResult, EnumFault>
+{
+    let rom: Vec<u8> = workload.program.iter().flat_map(|word| word.to_be_bytes()).collect();
+
+    let mut machine = Machine::with_frame_config(4096, STARTING_PC, frame_config.clone());
+    machine.set_decode_cache(decode_cache);
+    machine.get_cpu_mut().set_rng_seed(1);
+    machine.load_rom(&rom).expect("Workloads fit in memory");
+
+    let start = std::time::Instant::now();
+
+    // NOTE: The workloads never halt, but don't spin forever if one is broken.
+    while machine.get_cycles() < cycles && machine.run_frame()? {}
+
+    Ok(BenchResult { workload: workload.name, instructions: machine.get_cycles(), frames: machine.get_frames(), elapsed: start.elapsed() })
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumBenchFormat
+{
+    Text,
+    /// A single JSON object, for comparing results between commits.
+    Json,
+}
+
+impl std::fmt::Display for EnumBenchFormat
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumBenchFormat
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        match text
+        {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown format '{}' (expected text or json)", text)),
+        }
+    }
+}
+
+/// Formats results as a table (text) or as one JSON object (json).
+pub fn format_results(results: &[BenchResult], format: EnumBenchFormat, decode_cache: bool, stream: &mut String)
+{
+    match format
+    {
+        EnumBenchFormat::Text =>
+        {
+            stream.push_str(&format!("hchip8 {0} bench (decode cache {1})\n", VERSION, if decode_cache { "on" } else { "off" }));
+            stream.push_str(&format!("{0:<10}{1:>14}{2:>10}{3:>16}{4:>10}{5:>14}\n", "workload", "instructions", "seconds", "instructions/s", "ns/instr", "frames/s"));
+
+            for result in results
+            {
+                stream.push_str(&format!("{0:<10}{1:>14}{2:>10.3}{3:>16.0}{4:>10.2}{5:>14.0}\n", result.workload, result.instructions,
+                                         result.elapsed.as_secs_f64(), result.instructions_per_sec(), result.ns_per_instruction(), result.frames_per_sec()));
+            }
+        },
+        EnumBenchFormat::Json =>
+        {
+            let entries: Vec<String> = results.iter().map(|result| format!(
+                "{{\"workload\":\"{0}\",\"instructions\":{1},\"frames\":{2},\"seconds\":{3:.6},\"instructions_per_sec\":{4:.1},\"ns_per_instruction\":{5:.3},\"frames_per_sec\":{6:.1}}}",
+                result.workload, result.instructions, result.frames, result.elapsed.as_secs_f64(),
+                result.instructions_per_sec(), result.ns_per_instruction(), result.frames_per_sec())).collect();
+
+            stream.push_str(&format!("{{\"version\":\"{0}\",\"decode_cache\":{1},\"results\":[{2}]}}\n", VERSION, decode_cache, entries.join(",")));
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::bench::{find_workload, format_results, run_workload, BenchResult, EnumBenchFormat, WORKLOADS};
+    use crate::hw::frame::FrameConfig;
+
+    use std::time::Duration;
+
+    #[test]
+    fn workloads_run_without_faults()
+    {
+        for workload in WORKLOADS.iter()
+        {
+            for decode_cache in [false, true]
+            {
+                let result = run_workload(workload, 20_000, &FrameConfig::default(), decode_cache).unwrap();
+
+                assert!(result.instructions >= 20_000, "{} stopped early", workload.name);
+                assert_eq!(result.frames, result.instructions / 10);
+            }
+        }
+    }
+
+    #[test]
+    fn find_workload_by_name()
+    {
+        assert_eq!(find_workload("draw").unwrap().name, "draw");
+        assert!(find_workload("sleep").is_none());
+    }
+
+    #[test]
+    fn results_derive_rates()
+    {
+        let result = BenchResult { workload: "alu", instructions: 2_000, frames: 200, elapsed: Duration::from_micros(1) };
+
+        assert_eq!(result.instructions_per_sec(), 2e9);
+        assert_eq!(result.ns_per_instruction(), 0.5);
+        assert_eq!(result.frames_per_sec(), 2e8);
+    }
+
+    #[test]
+    fn json_output_is_one_object()
+    {
+        let result = BenchResult { workload: "alu", instructions: 1_000, frames: 100, elapsed: Duration::from_millis(1) };
+        let mut stream = String::new();
+        format_results(&[result.clone(), result], EnumBenchFormat::Json, true, &mut stream);
+
+        assert!(stream.starts_with("{\"version\":"));
+        assert!(stream.contains("\"decode_cache\":true"));
+        assert!(stream.contains("{\"workload\":\"alu\",\"instructions\":1000,\"frames\":100,\"seconds\":0.001000,\"instructions_per_sec\":1000000.0,"));
+        assert_eq!(stream.lines().count(), 1);
+    }
+}
@@ -0,0 +1,69 @@
+//! Interpreter micro-benchmark: runs a register-heavy loop and reports instructions per second.
+//!
+//! Usage: interp_bench [--instructions <count>] [--baseline <instructions/s>] [--decode-cache]
+//!
+//! Build with '--release' for meaningful numbers. Pass the result of an earlier run as the baseline to see the change.
+
+use hchip8::hw::cpu::CPU;
+use hchip8::hw::fault::EnumTickOutcome;
+
+const STARTING_PC: u16 = 0x200;
+const DEFAULT_INSTRUCTIONS: u64 = 20_000_000;
+
+// V0 = 0, V1 = 1, then loop: V0 += V1, V1 += V0, V2 ^= V1, V3 >>= 1, skip if VF == 0, VF = 0, jump to loop
+const PROGRAM: [u16; 9] = [0x6000, 0x6101, 0x8014, 0x8104, 0x8213, 0x8336, 0x3F00, 0x6F00, 0x1204];
+
+fn parse_arg<T: std::str::FromStr>(args: &[String], name: &str) -> Option<T>
+{
+    let index = args.iter().position(|arg| arg == name)?;
+
+    match args.get(index + 1).and_then(|value| value.parse::<T>().ok())
+    {
+        Some(value) => Some(value),
+        None =>
+        {
+            println!("[ERROR]: Expected a number after '{}'", name);
+            std::process::exit(-1);
+        },
+    }
+}
+
+fn main()
+{
+    let args: Vec<String> = std::env::args().collect();
+    let instructions = parse_arg::<u64>(&args, "--instructions").unwrap_or(DEFAULT_INSTRUCTIONS);
+    let opt_baseline = parse_arg::<f64>(&args, "--baseline");
+
+    let mut cpu = CPU::new(4096, STARTING_PC);
+    cpu.set_decode_cache(args.iter().any(|arg| arg == "--decode-cache"));
+
+    for (i, instruction) in PROGRAM.iter().enumerate()
+    {
+        cpu.get_mem_mut().write_u16(STARTING_PC as usize + i * 2, *instruction);
+    }
+
+    let start = std::time::Instant::now();
+
+    for _ in 0..instructions
+    {
+        match cpu.tick()
+        {
+            Ok(EnumTickOutcome::Executed) => {},
+            outcome =>
+            {
+                println!("[ERROR]: Benchmark program stopped unexpectedly: {:?}", outcome);
+                std::process::exit(-1);
+            },
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let per_second = instructions as f64 / elapsed;
+
+    println!("{0} instructions in {1:.3}s = {2:.0} instructions/s", instructions, elapsed, per_second);
+
+    if let Some(baseline) = opt_baseline
+    {
+        println!("{0:+.1}% vs baseline of {1:.0} instructions/s", (per_second / baseline - 1.0) * 100.0, baseline);
+    }
+}
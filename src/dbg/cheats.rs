@@ -0,0 +1,356 @@
+use crate::hw::cpu::CPU;
+use crate::hw::mem::{parse_byte_pattern, Mem, MemSnapshot};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumCheatKind
+{
+    /// Forces the bytes back at every frame (infinite lives and the like).
+    Freeze,
+    /// Writes the bytes once.
+    Patch,
+}
+
+impl std::fmt::Display for EnumCheatKind
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Freeze => write!(f, "freeze"),
+            Self::Patch => write!(f, "patch"),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumCheatKind
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        match text
+        {
+            "freeze" => Ok(Self::Freeze),
+            "patch" => Ok(Self::Patch),
+            _ => Err(format!("Unknown cheat '{}' (expected freeze or patch)", text)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cheat
+{
+    pub kind: EnumCheatKind,
+    pub addr: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl Cheat
+{
+    /// Parses '<freeze|patch> <addr> <bytes>', e.g. 'freeze 0x3F0 09' or 'patch 0x2A4 12 A0'.
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut tokens = text.split_whitespace();
+
+        let kind = tokens.next().ok_or(String::from("Expected 'freeze' or 'patch'"))?.parse::<EnumCheatKind>()?;
+        let addr = match tokens.next()
+        {
+            Some(token) => parse_address(token).ok_or(format!("Invalid address '{}'", token))?,
+            None => { return Err(String::from("Expected an address")); },
+        };
+
+        let rest: Vec<&str> = tokens.collect();
+        let bytes = parse_byte_pattern(&rest.join(" "))?.into_iter()
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(String::from("Cheats can't write '??'"))?;
+
+        Ok(Self { kind, addr, bytes })
+    }
+
+    /// Writes the bytes, skipping memory that already holds them (host writes flush the decode cache).
+    /// Returns false if they don't fit in memory.
+    pub fn apply(&self, cpu: &mut CPU) -> bool
+    {
+        let mem = cpu.get_mem();
+
+        if self.addr.checked_add(self.bytes.len()).is_none_or(|end| end > mem.size())
+        {
+            return false;
+        }
+
+        let differs = self.bytes.iter().enumerate().any(|(offset, value)| mem.read_u8(self.addr + offset) != Some(*value));
+
+        if differs
+        {
+            let mem = cpu.get_mem_mut();
+
+            for (offset, value) in self.bytes.iter().enumerate()
+            {
+                mem.write_u8(self.addr + offset, *value);
+            }
+        }
+
+        true
+    }
+}
+
+impl std::fmt::Display for Cheat
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{0} 0x{1:03X}", self.kind, self.addr)?;
+
+        for value in &self.bytes
+        {
+            write!(f, " {:02X}", value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The active cheats. Patches are applied as they're added, freezes again at every frame (see 'Machine::add_cheat').
+#[derive(Clone, Debug, Default)]
+pub struct CheatList
+{
+    cheats: Vec<Cheat>,
+}
+
+impl CheatList
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Parses a cheat file: one cheat per line (see 'Cheat::parse'), '#' starts a comment.
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut cheats = Vec::new();
+
+        for (index, line) in text.lines().enumerate()
+        {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty()
+            {
+                continue;
+            }
+
+            cheats.push(Cheat::parse(line).map_err(|e| format!("line {0}: {1}", index + 1, e))?);
+        }
+
+        Ok(Self { cheats })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String>
+    {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read cheat file '{0}': {1}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("'{0}' {1}", path, e))
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool
+    {
+        self.cheats.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cheat>
+    {
+        self.cheats.iter()
+    }
+
+    pub fn add(&mut self, cheat: Cheat)
+    {
+        self.cheats.push(cheat);
+    }
+
+    /// Removes the cheats starting at 'addr'. Returns false if there were none.
+    pub fn remove(&mut self, addr: usize) -> bool
+    {
+        let count = self.cheats.len();
+        self.cheats.retain(|cheat| cheat.addr != addr);
+
+        self.cheats.len() != count
+    }
+
+    #[inline]
+    pub fn apply_freezes(&self, cpu: &mut CPU)
+    {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.kind == EnumCheatKind::Freeze)
+        {
+            cheat.apply(cpu);
+        }
+    }
+}
+
+/// How a value must have changed since the last search step to stay a candidate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumSearchFilter
+{
+    Same,
+    Changed,
+    Increased,
+    Decreased,
+    Equals(u8),
+}
+
+impl std::fmt::Display for EnumSearchFilter
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Same => write!(f, "same"),
+            Self::Changed => write!(f, "changed"),
+            Self::Increased => write!(f, "increased"),
+            Self::Decreased => write!(f, "decreased"),
+            Self::Equals(value) => write!(f, "0x{:02X}", value),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumSearchFilter
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        match text
+        {
+            "same" => Ok(Self::Same),
+            "changed" => Ok(Self::Changed),
+            "increased" | "inc" => Ok(Self::Increased),
+            "decreased" | "dec" => Ok(Self::Decreased),
+            _ => parse_address(text).and_then(|value| u8::try_from(value).ok()).map(Self::Equals)
+                .ok_or(format!("Invalid search filter '{}' (expected same, changed, increased, decreased or a byte value)", text)),
+        }
+    }
+}
+
+/// Narrows down where a game keeps a value (lives, score...) by comparing memory between search steps:
+/// start, lose a life, keep the 'decreased' bytes, and so on.
+pub struct CheatSearch
+{
+    snapshot: MemSnapshot,
+    candidates: Vec<usize>,
+}
+
+impl CheatSearch
+{
+    /// Starts with every address in 'range' as a candidate.
+    pub fn new(mem: &Mem, range: std::ops::Range<usize>) -> Self
+    {
+        Self { snapshot: mem.snapshot(), candidates: range.filter(|addr| *addr < mem.size()).collect() }
+    }
+
+    /// Keeps the candidates that match 'filter' against their value at the last step. Returns how many are left.
+    pub fn filter(&mut self, mem: &Mem, filter: EnumSearchFilter) -> usize
+    {
+        let snapshot = &self.snapshot;
+
+        self.candidates.retain(|addr|
+        {
+            let old = snapshot.read_u8(*addr).unwrap_or_default();
+            let new = mem.read_u8(*addr).unwrap_or_default();
+
+            match filter
+            {
+                EnumSearchFilter::Same => new == old,
+                EnumSearchFilter::Changed => new != old,
+                EnumSearchFilter::Increased => new > old,
+                EnumSearchFilter::Decreased => new < old,
+                EnumSearchFilter::Equals(value) => new == value,
+            }
+        });
+
+        self.snapshot = mem.snapshot();
+
+        self.candidates.len()
+    }
+
+    pub fn get_candidates(&self) -> &[usize]
+    {
+        &self.candidates
+    }
+}
+
+/// Parses a decimal or '0x'-prefixed hexadecimal number.
+fn parse_address(text: &str) -> Option<usize>
+{
+    match text.strip_prefix("0x").or(text.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse::<usize>().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::cheats::{Cheat, CheatList, CheatSearch, EnumCheatKind, EnumSearchFilter};
+    use crate::hw::cpu::CPU;
+    use crate::hw::mem::Mem;
+
+    #[test]
+    fn parse_cheat_file()
+    {
+        let cheats = CheatList::parse("# Infinite lives\nfreeze 0x3F0 09\n\npatch 0x2A4 12 A0  # skip the intro\n").unwrap();
+        let cheats: Vec<&Cheat> = cheats.iter().collect();
+
+        assert_eq!(cheats, [&Cheat { kind: EnumCheatKind::Freeze, addr: 0x3F0, bytes: vec![0x09] },
+                            &Cheat { kind: EnumCheatKind::Patch, addr: 0x2A4, bytes: vec![0x12, 0xA0] }]);
+        assert_eq!(cheats[1].to_string(), "patch 0x2A4 12 A0");
+
+        assert_eq!(CheatList::parse("freeze 0x3F0 09\nfreeze 0x3F1 ??\n").unwrap_err(), "line 2: Cheats can't write '??'");
+        assert!(CheatList::parse("poke 0x3F0 09").unwrap_err().starts_with("line 1: Unknown cheat 'poke'"));
+        assert!(CheatList::parse("freeze lives 09").unwrap_err().starts_with("line 1: Invalid address 'lives'"));
+        assert!(CheatList::parse("patch 0x3F0").is_err());
+    }
+
+    #[test]
+    fn freezes_and_patches()
+    {
+        let mut cpu = CPU::new(4096, 0x200);
+        let mut cheats = CheatList::new();
+        cheats.add(Cheat::parse("freeze 0x3F0 09").unwrap());
+        cheats.add(Cheat::parse("patch 0x300 AA").unwrap());
+
+        cpu.get_mem_mut().write_u8(0x3F0, 0x02);
+        cheats.apply_freezes(&mut cpu);
+
+        assert_eq!(cpu.get_mem().read_u8(0x3F0), Some(0x09));
+        assert_eq!(cpu.get_mem().read_u8(0x300), Some(0x00));
+
+        assert!(!Cheat::parse("patch 0xFFF 01 02").unwrap().apply(&mut cpu));
+        assert!(!Cheat::parse("freeze 0xFFFFFFFFFFFFFFFF 01").unwrap().apply(&mut cpu));
+        assert!(cheats.remove(0x3F0));
+        assert!(!cheats.remove(0x3F0));
+    }
+
+    #[test]
+    fn search_narrows_candidates()
+    {
+        let mut mem = Mem::new(16);
+        mem.write_u8(3, 5);
+        mem.write_u8(7, 5);
+        mem.write_u8(9, 1);
+
+        let mut search = CheatSearch::new(&mem, 0..32);
+        assert_eq!(search.get_candidates().len(), 16);
+        assert_eq!(search.filter(&mem, EnumSearchFilter::Equals(5)), 2);
+
+        // Lose a life: only address 3 counts down.
+        mem.write_u8(3, 4);
+        mem.write_u8(9, 0);
+        assert_eq!(search.filter(&mem, EnumSearchFilter::Decreased), 1);
+        assert_eq!(search.get_candidates(), [3]);
+
+        assert_eq!(search.filter(&mem, EnumSearchFilter::Same), 1);
+        assert_eq!(search.filter(&mem, EnumSearchFilter::Changed), 0);
+
+        assert_eq!("inc".parse::<EnumSearchFilter>(), Ok(EnumSearchFilter::Increased));
+        assert_eq!("0x2A".parse::<EnumSearchFilter>(), Ok(EnumSearchFilter::Equals(0x2A)));
+        assert!("256".parse::<EnumSearchFilter>().is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::dbg::source_map::SourceMap;
+use crate::hw::instruction::disassemble;
+use crate::hw::mem::Mem;
+use crate::hw::observer::Observer;
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+const EXECUTED: u8 = 0x01;
+const READ: u8 = 0x02;
+const WRITTEN: u8 = 0x04;
+
+/// Bytes per row of the coverage map.
+const MAP_ROW_SIZE: usize = 64;
+
+/// How a byte of memory was used, by precedence (code that was also read or written still counts as code).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumByteUse
+{
+    Executed,
+    Written,
+    Read,
+    Untouched,
+}
+
+impl EnumByteUse
+{
+    /// The character used in the coverage map.
+    pub fn symbol(&self) -> char
+    {
+        match self
+        {
+            Self::Executed => 'x',
+            Self::Written => 'w',
+            Self::Read => 'r',
+            Self::Untouched => '.',
+        }
+    }
+}
+
+/// Records which bytes of memory were executed, read as data or written during a run.
+pub struct Coverage
+{
+    flags: Vec<u8>,
+    /// Executions per instruction address.
+    hits: Vec<u64>,
+}
+
+impl Coverage
+{
+    pub fn new(mem_size: usize) -> Self
+    {
+        Self { flags: vec![0; mem_size], hits: vec![0; mem_size] }
+    }
+
+    fn mark(&mut self, addr: usize, flag: u8)
+    {
+        if let Some(flags) = self.flags.get_mut(addr)
+        {
+            *flags |= flag;
+        }
+    }
+
+    #[inline]
+    pub fn on_read(&mut self, addr: usize)
+    {
+        self.mark(addr, READ);
+    }
+
+    #[inline]
+    pub fn on_write(&mut self, addr: usize)
+    {
+        self.mark(addr, WRITTEN);
+    }
+
+    pub fn get_hits(&self, addr: u16) -> u64
+    {
+        self.hits.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    pub fn is_executed(&self, addr: usize) -> bool
+    {
+        self.flags.get(addr).is_some_and(|flags| flags & EXECUTED != 0)
+    }
+
+    pub fn is_read(&self, addr: usize) -> bool
+    {
+        self.flags.get(addr).is_some_and(|flags| flags & READ != 0)
+    }
+
+    pub fn is_written(&self, addr: usize) -> bool
+    {
+        self.flags.get(addr).is_some_and(|flags| flags & WRITTEN != 0)
+    }
+
+    pub fn get_use(&self, addr: usize) -> EnumByteUse
+    {
+        if self.is_executed(addr)
+        {
+            return EnumByteUse::Executed;
+        }
+
+        else if self.is_written(addr)
+        {
+            return EnumByteUse::Written;
+        }
+
+        else if self.is_read(addr)
+        {
+            return EnumByteUse::Read;
+        }
+
+        EnumByteUse::Untouched
+    }
+
+    /// 'xrw' style flags for one byte (a '-' for each kind of use that didn't happen).
+    fn flag_text(&self, addr: usize) -> String
+    {
+        let mut text = String::with_capacity(3);
+        text.push(if self.is_executed(addr) { 'x' } else { '-' });
+        text.push(if self.is_read(addr) { 'r' } else { '-' });
+        text.push(if self.is_written(addr) { 'w' } else { '-' });
+        text
+    }
+
+    /// A summary, a map of every byte in 'range' ('x' executed, 'w' written, 'r' read, '.' untouched)
+    /// and an annotated disassembly with execution counts.
+    pub fn format_report(&self, mem: &Mem, range: Range<usize>, stream: &mut String)
+    {
+        let size = range.len();
+        let count = |test: &dyn Fn(usize) -> bool| range.clone().filter(|addr| test(*addr)).count();
+        let percent = |count: usize| 100.0 * count as f64 / size.max(1) as f64;
+
+        let executed = count(&|addr| self.is_executed(addr));
+        let read = count(&|addr| self.is_read(addr));
+        let written = count(&|addr| self.is_written(addr));
+        let untouched = count(&|addr| self.get_use(addr) == EnumByteUse::Untouched);
+
+        stream.push_str(&format!("Coverage of 0x{0:03X}-0x{1:03X} ({2} bytes):\n", range.start, range.end.saturating_sub(1), size));
+        stream.push_str(&format!("  executed:  {0:>6} ({1:.1}%)\n", executed, percent(executed)));
+        stream.push_str(&format!("  read:      {0:>6} ({1:.1}%)\n", read, percent(read)));
+        stream.push_str(&format!("  written:   {0:>6} ({1:.1}%)\n", written, percent(written)));
+        stream.push_str(&format!("  untouched: {0:>6} ({1:.1}%)\n", untouched, percent(untouched)));
+
+        stream.push_str("\nMap (x executed, w written, r read, . untouched):\n");
+
+        for row_start in range.clone().step_by(MAP_ROW_SIZE)
+        {
+            let row: String = (row_start..(row_start + MAP_ROW_SIZE).min(range.end)).map(|addr| self.get_use(addr).symbol()).collect();
+            stream.push_str(&format!("  0x{0:03X}  {1}\n", row_start, row));
+        }
+
+        stream.push_str("\nDisassembly:\n");
+
+        let mut addr = range.start;
+
+        while addr < range.end
+        {
+            // Code can start at odd addresses; show a lone byte before it as data so the instruction lines up.
+            if (!self.is_executed(addr) && self.get_hits((addr + 1) as u16) > 0) || addr + 1 == range.end
+            {
+                let value = mem.read_u8(addr).unwrap_or_default();
+                stream.push_str(&format!("  0x{0:03X}  {1:>10}  {2:02X}    {3}  DB 0x{2:02X}\n", addr, "-", value, self.flag_text(addr)));
+                addr += 1;
+                continue;
+            }
+
+            let raw_opcode = mem.read_u16(addr).unwrap_or_default();
+            let hits = self.get_hits(addr as u16);
+            let hits_text = if hits > 0 { hits.to_string() } else { String::from("-") };
+            let mut flags = self.flag_text(addr);
+
+            // Show the second byte's flags too if it was used differently (e.g. written over).
+            if self.flag_text(addr + 1) != flags
+            {
+                flags = format!("{0}/{1}", flags, self.flag_text(addr + 1));
+            }
+
+            stream.push_str(&format!("  0x{0:03X}  {1:>10}  {2:04X}  {3}  {4}\n", addr, hits_text, raw_opcode, flags, disassemble(raw_opcode)));
+            addr += 2;
+        }
+    }
+
+    /// lcov tracefile records ('SF', 'DA', 'LF', 'LH') for the lines in a source map.
+    /// A line's count is the number of times its instructions ran.
+    pub fn format_lcov(&self, source_map: &SourceMap, stream: &mut String)
+    {
+        let mut lines: Vec<BTreeMap<u32, u64>> = vec![BTreeMap::new(); source_map.get_files().len()];
+
+        for (addr, location) in source_map.iter()
+        {
+            *lines[location.file].entry(location.line).or_insert(0) += self.get_hits(addr);
+        }
+
+        stream.push_str("TN:\n");
+
+        for (file, file_lines) in source_map.get_files().iter().zip(lines.iter())
+        {
+            stream.push_str(&format!("SF:{}\n", file));
+
+            for (line, hits) in file_lines.iter()
+            {
+                stream.push_str(&format!("DA:{0},{1}\n", line, hits));
+            }
+
+            stream.push_str(&format!("LF:{}\n", file_lines.len()));
+            stream.push_str(&format!("LH:{}\n", file_lines.values().filter(|hits| **hits > 0).count()));
+            stream.push_str("end_of_record\n");
+        }
+    }
+}
+
+impl Observer for Coverage
+{
+    /// Both bytes of a fetched instruction count as code.
+    fn on_fetch(&mut self, pc: u16, _raw_opcode: u16)
+    {
+        let addr = pc as usize;
+
+        if let Some(hits) = self.hits.get_mut(addr)
+        {
+            *hits += 1;
+        }
+
+        self.mark(addr, EXECUTED);
+        self.mark(addr + 1, EXECUTED);
+    }
+
+    fn on_mem_read(&mut self, _pc: u16, addr: usize, _value: u8)
+    {
+        self.on_read(addr);
+    }
+
+    fn on_mem_write(&mut self, _pc: u16, addr: usize, _old_value: u8, _value: u8)
+    {
+        self.on_write(addr);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::coverage::{Coverage, EnumByteUse};
+    use crate::dbg::source_map::SourceMap;
+    use crate::hw::mem::Mem;
+    use crate::hw::observer::Observer;
+
+    #[test]
+    fn classifies_bytes()
+    {
+        let mut coverage = Coverage::new(4096);
+        coverage.on_fetch(0x200, 0);
+        coverage.on_fetch(0x200, 0);
+        coverage.on_read(0x300);
+        coverage.on_write(0x301);
+        coverage.on_read(0x301);
+        coverage.on_write(0x201);
+
+        assert_eq!(coverage.get_hits(0x200), 2);
+        assert_eq!(coverage.get_use(0x200), EnumByteUse::Executed);
+        assert_eq!(coverage.get_use(0x201), EnumByteUse::Executed);
+        assert!(coverage.is_written(0x201));
+        assert_eq!(coverage.get_use(0x300), EnumByteUse::Read);
+        assert_eq!(coverage.get_use(0x301), EnumByteUse::Written);
+        assert_eq!(coverage.get_use(0x302), EnumByteUse::Untouched);
+
+        // Out of range accesses are ignored.
+        coverage.on_fetch(0xFFFF, 0);
+    }
+
+    #[test]
+    fn report_maps_and_annotates_the_rom()
+    {
+        let mut mem = Mem::new(4096);
+        // 0x200: I = 0x206, 0x202: V0 += 1 (never executed), 0x204: halt, 0x206: data
+        mem.write_u16(0x200, 0xA206);
+        mem.write_u16(0x202, 0x7001);
+        mem.write_u16(0x206, 0xF0F0);
+
+        let mut coverage = Coverage::new(4096);
+        coverage.on_fetch(0x200, 0);
+        coverage.on_fetch(0x204, 0);
+        coverage.on_read(0x206);
+
+        let mut stream = String::new();
+        coverage.format_report(&mem, 0x200..0x208, &mut stream);
+
+        assert!(stream.contains("  executed:       4 (50.0%)\n"));
+        assert!(stream.contains("  0x200  xx..xxr.\n"));
+        assert!(stream.contains("  0x200           1  A206  x--  LD I, 0x206\n"));
+        assert!(stream.contains("  0x202           -  7001  ---  ADD V0, 0x01\n"));
+        assert!(stream.contains("  0x206           -  F0F0  -r-/---  DW 0xF0F0\n"));
+    }
+
+    #[test]
+    fn lcov_counts_per_source_line()
+    {
+        let source_map = SourceMap::parse("0x200 main.8o:1\n0x202 main.8o:2\n0x204 main.8o:2\n0x206 main.8o:5\n").unwrap();
+
+        let mut coverage = Coverage::new(4096);
+        coverage.on_fetch(0x200, 0);
+        coverage.on_fetch(0x204, 0);
+        coverage.on_fetch(0x204, 0);
+
+        let mut stream = String::new();
+        coverage.format_lcov(&source_map, &mut stream);
+
+        assert_eq!(stream, "TN:\nSF:main.8o\nDA:1,1\nDA:2,2\nDA:5,0\nLF:3\nLH:2\nend_of_record\n");
+    }
+}
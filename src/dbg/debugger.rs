@@ -0,0 +1,1242 @@
+use crate::dbg::cheats::{Cheat, CheatSearch, EnumCheatKind, EnumSearchFilter};
+use crate::dbg::smc::SmcDetector;
+use crate::dbg::symbols::SymbolTable;
+use crate::dbg::watchpoint::{EnumWatchKind, WatchHit};
+use crate::hw::cpu::EnumDumpFormat;
+use crate::hw::fault::EnumFault;
+use crate::hw::frame::EnumSpeed;
+use crate::hw::mem::{format_changes, parse_byte_pattern, MemSnapshot};
+use crate::machine::Machine;
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+
+const HELP_TEXT: &str = "Commands:
+    help | h                          Show this message
+    step | s [count]                  Execute 'count' instructions (default 1)
+    continue | c                      Resume execution until a watchpoint triggers
+    watch | w <addr>[-<end>] [== v]   Break when the address (range) is written (optionally with value v)
+    rwatch | rw <addr>[-<end>] [== v] Break when the address (range) is read
+    awatch | aw <addr>[-<end>] [== v] Break when the address (range) is read or written
+    delete | d <id>                   Delete a watchpoint
+    break | b <addr>                  Break before the instruction at the address executes
+    clear | cl <addr>                 Delete the breakpoint at the address
+    watches | i                       List watchpoints and breakpoints
+    backtrace | bt                    Show the call stack
+    disasm | x [addr] [count]         Disassemble 'count' instructions (default 8) from the address (default pc)
+    regs | r [text|json]              Print the CPU state ('--dump-format' by default)
+    mem | m <addr>[-<end>]            Hexdump the range (64 bytes from a single address)
+    find | f <bytes>                  Find a byte pattern in memory, e.g. 'find A2 F0 ?? 12'
+    snap                              Remember the memory contents for 'diff'
+    diff                              Show the bytes changed since 'snap'
+    smc                               List the writes into code so far (needs '--smc')
+    freeze <addr> <bytes>             Force the bytes back at every frame, e.g. 'freeze lives 09'
+    patch <addr> <bytes>              Write the bytes once
+    unfreeze <addr>                   Delete the cheats at the address
+    cheats                            List the cheats
+    search [new | same | changed | increased | decreased | <value>]
+                                      Start a value search, or keep the candidates that changed that way
+    speed [multiplier | turbo]        Show or change the emulation speed
+    echo <text>                       Print the text
+    if <a> <op> <b> <command>         Run the command only if the condition holds, e.g. 'if V0 == 3 regs'
+    assert <a> <op> <b> [message]     Report a failure (and exit with an error code) unless the condition holds
+    quit | q                          Exit the emulator
+
+Addresses can be numbers, labels from '--symbols' or 'label+offset'.
+Conditions compare V0-VF, I, PC, DT, ST, memory bytes '[addr]' and numbers with ==, !=, <, <=, > or >=.
+Any command can end with '> file' (or '>> file' to append) to write its output to a file.";
+
+const DEFAULT_DISASM_COUNT: usize = 8;
+const DEFAULT_HEXDUMP_SIZE: usize = 64;
+/// Search candidates are only listed once there are this few.
+const MAX_LISTED_CANDIDATES: usize = 16;
+
+/// Commands from a '--debug-script' file, run in place of the prompt.
+struct Script
+{
+    name: String,
+    /// (line number, command), without blank lines and '#' comments.
+    lines: VecDeque<(usize, String)>,
+    /// The line of the command being run, for error messages.
+    current_line: usize,
+}
+
+pub struct Debugger
+{
+    paused: bool,
+    quit: bool,
+    last_command: String,
+    symbols: SymbolTable,
+    snapshot: Option<MemSnapshot>,
+    search: Option<CheatSearch>,
+    dump_format: EnumDumpFormat,
+    script: Option<Script>,
+    /// Failed asserts and errors in script commands.
+    failures: usize,
+}
+
+impl Debugger
+{
+    pub fn new() -> Self
+    {
+        Self::with_symbols(SymbolTable::new())
+    }
+
+    /// A debugger that shows and accepts the labels in 'symbols'.
+    pub fn with_symbols(symbols: SymbolTable) -> Self
+    {
+        Self { paused: true, quit: false, last_command: String::new(), symbols, snapshot: None, search: None, dump_format: EnumDumpFormat::Text,
+               script: None, failures: 0 }
+    }
+
+    /// Takes the commands from the file at 'path' instead of stdin. The emulator quits when the script ends.
+    pub fn load_script(&mut self, path: &str) -> Result<(), String>
+    {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read debug script '{0}': {1}", path, e))?;
+        self.set_script(path, &text);
+
+        Ok(())
+    }
+
+    /// Like 'load_script', with the script's text. 'name' is only used in messages.
+    pub fn set_script(&mut self, name: &str, text: &str)
+    {
+        let lines = text.lines().enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| (number, String::from(line)))
+            .collect();
+
+        self.script = Some(Script { name: String::from(name), lines, current_line: 0 });
+    }
+
+    pub fn has_script(&self) -> bool
+    {
+        self.script.is_some()
+    }
+
+    /// The number of failed asserts, plus errors in script commands (a script with a typo shouldn't pass).
+    pub fn get_failures(&self) -> usize
+    {
+        self.failures
+    }
+
+    /// The default format for 'regs'.
+    pub fn set_dump_format(&mut self, format: EnumDumpFormat)
+    {
+        self.dump_format = format;
+    }
+
+    pub fn is_paused(&self) -> bool
+    {
+        self.paused
+    }
+
+    pub fn pause(&mut self)
+    {
+        self.paused = true;
+    }
+
+    pub fn should_quit(&self) -> bool
+    {
+        self.quit
+    }
+
+    /// Reports a fault the host chose to break on and drops back into the REPL.
+    pub fn on_fault(&mut self, fault: &EnumFault, out: &mut dyn Write)
+    {
+        let _ = writeln!(out, "[FAULT]: {}", fault);
+        self.pause();
+    }
+
+    /// Reports a watchpoint hit and drops back into the REPL.
+    pub fn on_watch_hit(&mut self, hit: &WatchHit, out: &mut dyn Write)
+    {
+        let _ = writeln!(out, "{}", hit);
+        self.pause();
+    }
+
+    /// Reports reaching a breakpoint and drops back into the REPL.
+    pub fn on_breakpoint(&mut self, addr: u16, out: &mut dyn Write)
+    {
+        let _ = writeln!(out, "Breakpoint hit at {}", self.symbols.format_addr(addr));
+        self.pause();
+    }
+
+    /// Prints the writes into code since the last call, as they happen (execution carries on).
+    pub fn report_smc(&self, machine: &mut Machine, out: &mut dyn Write)
+    {
+        if let Some(smc) = machine.find_observer_mut::<SmcDetector>()
+        {
+            for event in smc.take_new_events()
+            {
+                let _ = writeln!(out, "Self-modifying code: {}", event.describe(&self.symbols));
+            }
+        }
+    }
+
+    /// Reads commands from stdin (or the script) until execution is resumed or the user quits.
+    pub fn repl(&mut self, machine: &mut Machine)
+    {
+        let stdin = std::io::stdin();
+        let mut stdout = std::io::stdout();
+
+        while self.paused && !self.quit
+        {
+            if self.script.is_some()
+            {
+                self.run_script_line(machine, &mut stdout);
+                continue;
+            }
+
+            print!("(hchip8 {}) ", self.symbols.format_addr(machine.get_cpu().get_pc()));
+            let _ = stdout.flush();
+
+            let mut line = String::new();
+
+            match stdin.lock().read_line(&mut line)
+            {
+                // EOF
+                Ok(0) => { self.quit = true; },
+                Ok(_) => { self.execute(machine, &line, &mut stdout); },
+                Err(e) =>
+                {
+                    println!("[ERROR]: Failed to read debugger command: {}", e);
+                    self.quit = true;
+                },
+            }
+        }
+    }
+
+    /// Runs the rest of the script once the CPU has halted, so checks on the final state still happen.
+    pub fn finish_script(&mut self, machine: &mut Machine, out: &mut dyn Write)
+    {
+        while self.script.is_some() && !self.quit
+        {
+            self.run_script_line(machine, out);
+        }
+    }
+
+    /// Runs the next script command, echoed after a prompt like typed ones, or quits at the end of the script.
+    fn run_script_line(&mut self, machine: &mut Machine, out: &mut dyn Write)
+    {
+        let next = self.script.as_mut().and_then(|script| script.lines.pop_front());
+
+        match next
+        {
+            Some((number, command)) =>
+            {
+                if let Some(script) = self.script.as_mut()
+                {
+                    script.current_line = number;
+                }
+
+                let _ = writeln!(out, "(hchip8 {0}) {1}", self.symbols.format_addr(machine.get_cpu().get_pc()), command);
+                self.execute(machine, &command, out);
+            },
+            None => { self.quit = true; },
+        }
+    }
+
+    /// 'script:line: ' while running a script, for messages.
+    fn location(&self) -> String
+    {
+        match self.script.as_ref()
+        {
+            Some(script) => format!("{0}:{1}: ", script.name, script.current_line),
+            None => String::new(),
+        }
+    }
+
+    /// Executes a single debugger command. An empty line repeats the last command.
+    pub fn execute(&mut self, machine: &mut Machine, line: &str, out: &mut dyn Write)
+    {
+        let mut line = line.trim().to_string();
+
+        if line.is_empty()
+        {
+            line = self.last_command.clone();
+        }
+
+        else
+        {
+            self.last_command = line.clone();
+        }
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if tokens.is_empty()
+        {
+            return;
+        }
+
+        let result = match split_redirect(&tokens)
+        {
+            Ok((command, Some((path, append)))) => self.run_redirected(machine, command, path, append),
+            Ok((command, None)) => self.dispatch(machine, command, out),
+            Err(msg) => Err(msg),
+        };
+
+        if let Err(msg) = result
+        {
+            let _ = writeln!(out, "[ERROR]: {0}{1}", self.location(), msg);
+
+            if self.script.is_some()
+            {
+                self.failures += 1;
+            }
+        }
+    }
+
+    /// Runs the command with its output going to the file at 'path' instead.
+    fn run_redirected(&mut self, machine: &mut Machine, tokens: &[&str], path: &str, append: bool) -> Result<(), String>
+    {
+        let mut buffer = Vec::<u8>::new();
+        let result = self.dispatch(machine, tokens, &mut buffer);
+
+        let mut file = std::fs::OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(path)
+            .map_err(|e| format!("Failed to open '{0}': {1}", path, e))?;
+        file.write_all(&buffer).map_err(|e| format!("Failed to write '{0}': {1}", path, e))?;
+
+        result
+    }
+
+    fn dispatch(&mut self, machine: &mut Machine, tokens: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        match tokens[0]
+        {
+            "help" | "h" => writeln!(out, "{}", HELP_TEXT).map_err(|e| e.to_string()),
+            "step" | "s" => self.step(machine, &tokens[1..], out),
+            "continue" | "c" =>
+            {
+                self.paused = false;
+                Ok(())
+            },
+            "watch" | "w" => self.add_watch(machine, EnumWatchKind::Write, &tokens[1..], out),
+            "rwatch" | "rw" => self.add_watch(machine, EnumWatchKind::Read, &tokens[1..], out),
+            "awatch" | "aw" => self.add_watch(machine, EnumWatchKind::Access, &tokens[1..], out),
+            "delete" | "d" => Self::delete_watch(machine, &tokens[1..], out),
+            "break" | "b" => self.add_breakpoint(machine, &tokens[1..], out),
+            "clear" | "cl" => self.remove_breakpoint(machine, &tokens[1..], out),
+            "watches" | "i" => self.list_watches(machine, out),
+            "backtrace" | "bt" => self.backtrace(machine, out),
+            "disasm" | "x" => self.disasm(machine, &tokens[1..], out),
+            "regs" | "r" => self.regs(machine, &tokens[1..], out),
+            "mem" | "m" => self.hexdump(machine, &tokens[1..], out),
+            "find" | "f" => self.find(machine, &tokens[1..], out),
+            "snap" =>
+            {
+                self.snapshot = Some(machine.get_cpu().get_mem().snapshot());
+                writeln!(out, "Snapshot taken").map_err(|e| e.to_string())
+            },
+            "diff" => self.diff(machine, out),
+            "smc" => self.list_smc(machine, out),
+            "freeze" => self.add_cheat(machine, EnumCheatKind::Freeze, &tokens[1..], out),
+            "patch" => self.add_cheat(machine, EnumCheatKind::Patch, &tokens[1..], out),
+            "unfreeze" => self.remove_cheat(machine, &tokens[1..], out),
+            "cheats" => self.list_cheats(machine, out),
+            "search" => self.search(machine, &tokens[1..], out),
+            "speed" => Self::speed(machine, &tokens[1..], out),
+            "echo" => writeln!(out, "{}", tokens[1..].join(" ")).map_err(|e| e.to_string()),
+            "if" =>
+            {
+                let (holds, _) = self.evaluate(machine, &tokens[1..])?;
+
+                match tokens.get(4..)
+                {
+                    Some([]) | None => Err(String::from("Expected a command after the condition")),
+                    Some(command) if holds => self.dispatch(machine, command, out),
+                    Some(_) => Ok(()),
+                }
+            },
+            "assert" => self.assert(machine, &tokens[1..], out),
+            "quit" | "q" =>
+            {
+                self.quit = true;
+                Ok(())
+            },
+            other => Err(format!("Unknown command '{}' (try 'help')", other)),
+        }
+    }
+
+    /// The value of a condition operand: V0-VF, I, PC, DT, ST, a memory byte '[addr]', or a number or label.
+    fn operand(&self, machine: &Machine, text: &str) -> Result<usize, String>
+    {
+        let cpu = machine.get_cpu();
+
+        if let Some(addr) = text.strip_prefix('[').and_then(|text| text.strip_suffix(']'))
+        {
+            let addr = parse_address(addr, &self.symbols).ok_or(format!("Invalid address '{}'", addr))?;
+            return cpu.get_mem().read_u8(addr).map(usize::from).ok_or(format!("Address 0x{:03X} is out of memory", addr));
+        }
+
+        let value = match text.to_ascii_uppercase().as_str()
+        {
+            "I" => cpu.get_reg_i() as usize,
+            "PC" => cpu.get_pc() as usize,
+            "DT" => cpu.get_delay_timer() as usize,
+            "ST" => cpu.get_sound_timer() as usize,
+            reg if reg.len() == 2 && reg.starts_with('V') && reg.as_bytes()[1].is_ascii_hexdigit() =>
+            {
+                let index = usize::from_str_radix(&reg[1..], 16).unwrap_or_default();
+                cpu.get_registers()[index] as usize
+            },
+            _ => parse_address(text, &self.symbols).ok_or(format!("Invalid value '{}' (expected a register, [addr], number or label)", text))?,
+        };
+
+        Ok(value)
+    }
+
+    /// Evaluates the condition '<a> <op> <b>' at the start of 'args'. Also returns the values that were compared,
+    /// e.g. 'V0 = 0x03', for messages (numbers are left out, they speak for themselves).
+    fn evaluate(&self, machine: &Machine, args: &[&str]) -> Result<(bool, String), String>
+    {
+        let (lhs, op, rhs) = match args
+        {
+            [lhs, op, rhs, ..] => (*lhs, *op, *rhs),
+            _ => { return Err(String::from("Expected a condition like 'V0 == 3'")); },
+        };
+
+        let left = self.operand(machine, lhs)?;
+        let right = self.operand(machine, rhs)?;
+
+        let holds = match op
+        {
+            "==" => left == right,
+            "!=" => left != right,
+            "<" => left < right,
+            "<=" => left <= right,
+            ">" => left > right,
+            ">=" => left >= right,
+            other => { return Err(format!("Unknown comparison '{}' (expected ==, !=, <, <=, > or >=)", other)); },
+        };
+
+        let values: Vec<String> = [(lhs, left), (rhs, right)].iter()
+            .filter(|(text, _)| parse_number(text).is_none())
+            .map(|(text, value)| format!("{0} = 0x{1:02X}", text, value))
+            .collect();
+
+        Ok((holds, values.join(", ")))
+    }
+
+    fn assert(&mut self, machine: &Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let (holds, values) = self.evaluate(machine, args)?;
+
+        if holds
+        {
+            return Ok(());
+        }
+
+        self.failures += 1;
+
+        let condition = args[..3].join(" ");
+        let message = match args.get(3..)
+        {
+            Some([]) | None => format!("{0} failed ({1})", condition, values),
+            Some(message) => format!("{0} ({1} failed: {2})", message.join(" "), condition, values),
+        };
+
+        writeln!(out, "[ASSERT]: {0}{1}", self.location(), message).map_err(|e| e.to_string())
+    }
+
+    fn step(&mut self, machine: &mut Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let count = match args.first()
+        {
+            Some(arg) => parse_number(arg).ok_or(format!("Invalid step count '{}'", arg))?,
+            None => 1,
+        };
+
+        for _ in 0..count
+        {
+            if machine.is_halted()
+            {
+                break;
+            }
+
+            let result = machine.step();
+            self.report_smc(machine, out);
+
+            if let Err(fault) = result
+            {
+                writeln!(out, "[FAULT]: {}", fault).map_err(|e| e.to_string())?;
+                break;
+            }
+
+            if let Some(hit) = machine.take_watch_hit()
+            {
+                self.on_watch_hit(&hit, out);
+                break;
+            }
+        }
+
+        if machine.is_halted()
+        {
+            writeln!(out, "CPU is halted").map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn add_watch(&self, machine: &mut Machine, kind: EnumWatchKind, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let (start, end) = match args.first()
+        {
+            Some(arg) => parse_range(arg, &self.symbols).ok_or(format!("Invalid address or range '{}'", arg))?,
+            None => { return Err(String::from("Expected an address or range")); },
+        };
+
+        let value = match args.get(1..)
+        {
+            Some([]) | None => None,
+            Some(["==", value]) =>
+            {
+                let value = parse_number(value).filter(|value| *value <= 0xFF).ok_or(format!("Invalid byte value '{}'", value))?;
+                Some(value as u8)
+            },
+            Some(rest) => { return Err(format!("Unexpected arguments '{}' (expected '== <value>')", rest.join(" "))); },
+        };
+
+        let id = machine.get_cpu_mut().get_watch_list_mut().add(kind, start, end, value);
+        writeln!(out, "Watchpoint {0}: {1} 0x{2:03X}-0x{3:03X}", id, kind.name(), start.min(end), start.max(end)).map_err(|e| e.to_string())
+    }
+
+    fn delete_watch(machine: &mut Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let id = match args.first()
+        {
+            Some(arg) => parse_number(arg).ok_or(format!("Invalid watchpoint id '{}'", arg))?,
+            None => { return Err(String::from("Expected a watchpoint id")); },
+        };
+
+        if !machine.get_cpu_mut().get_watch_list_mut().remove(id as u32)
+        {
+            return Err(format!("No watchpoint with id {}", id));
+        }
+
+        writeln!(out, "Deleted watchpoint {}", id).map_err(|e| e.to_string())
+    }
+
+    fn parse_breakpoint(&self, args: &[&str]) -> Result<u16, String>
+    {
+        match args.first()
+        {
+            Some(arg) => parse_address(arg, &self.symbols).and_then(|addr| u16::try_from(addr).ok()).ok_or(format!("Invalid address or label '{}'", arg)),
+            None => Err(String::from("Expected an address or label")),
+        }
+    }
+
+    /// The address with its label and, when the label is defined right there, its source location.
+    fn describe(&self, addr: u16) -> String
+    {
+        match self.symbols.at(addr).and_then(|symbol| symbol.location.as_ref())
+        {
+            Some((file, line)) => format!("{0} ({1}:{2})", self.symbols.format_addr(addr), file, line),
+            None => self.symbols.format_addr(addr),
+        }
+    }
+
+    fn add_breakpoint(&self, machine: &mut Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let addr = self.parse_breakpoint(args)?;
+
+        if !machine.add_breakpoint(addr)
+        {
+            return Err(format!("There already is a breakpoint at {}", self.symbols.format_addr(addr)));
+        }
+
+        writeln!(out, "Breakpoint at {}", self.describe(addr)).map_err(|e| e.to_string())
+    }
+
+    fn remove_breakpoint(&self, machine: &mut Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let addr = self.parse_breakpoint(args)?;
+
+        if !machine.remove_breakpoint(addr)
+        {
+            return Err(format!("No breakpoint at {}", self.symbols.format_addr(addr)));
+        }
+
+        writeln!(out, "Deleted breakpoint at {}", self.symbols.format_addr(addr)).map_err(|e| e.to_string())
+    }
+
+    /// The current pc, then the call site of each return address on the stack (innermost first).
+    fn backtrace(&self, machine: &Machine, out: &mut dyn Write) -> Result<(), String>
+    {
+        let cpu = machine.get_cpu();
+        writeln!(out, "#0 {}", self.describe(cpu.get_pc())).map_err(|e| e.to_string())?;
+
+        for (depth, ret_address) in cpu.get_call_stack().iter().rev().enumerate()
+        {
+            let call_site = ret_address.wrapping_sub(2);
+            writeln!(out, "#{0} {1}", depth + 1, self.symbols.format_addr(call_site)).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn disasm(&self, machine: &Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let pc = machine.get_cpu().get_pc();
+
+        let start = match args.first()
+        {
+            Some(arg) => parse_address(arg, &self.symbols).ok_or(format!("Invalid address or label '{}'", arg))?,
+            None => pc as usize,
+        };
+
+        let count = match args.get(1)
+        {
+            Some(arg) => parse_number(arg).ok_or(format!("Invalid instruction count '{}'", arg))?,
+            None => DEFAULT_DISASM_COUNT,
+        };
+
+        let mem = machine.get_cpu().get_mem();
+        let breakpoints: Vec<u16> = machine.get_breakpoints().collect();
+
+        for addr in (start..).step_by(2).take(count)
+        {
+            let raw_opcode = match mem.read_u16(addr)
+            {
+                Some(raw_opcode) => raw_opcode,
+                None => { break; },
+            };
+
+            let addr = addr as u16;
+            let marker = if addr == pc { "=>" } else if breakpoints.contains(&addr) { " *" } else { "  " };
+
+            // Label the start of each symbol on its own line, like an assembler listing.
+            if let Some(symbol) = self.symbols.at(addr)
+            {
+                writeln!(out, "{}:", symbol.name).map_err(|e| e.to_string())?;
+            }
+
+            writeln!(out, "{0} 0x{1:03X}  {2:04X}  {3}", marker, addr, raw_opcode, self.symbols.disassemble(raw_opcode)).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn regs(&self, machine: &Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let format = match args.first()
+        {
+            Some(arg) => arg.parse::<EnumDumpFormat>()?,
+            None => self.dump_format,
+        };
+
+        let mut stream = String::new();
+        machine.get_cpu().format_state(format, &mut stream);
+        write!(out, "{}", stream).map_err(|e| e.to_string())
+    }
+
+    fn hexdump(&self, machine: &Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let (start, end) = match args.first()
+        {
+            Some(arg) if arg.contains('-') => parse_range(arg, &self.symbols).ok_or(format!("Invalid address or range '{}'", arg))?,
+            Some(arg) =>
+            {
+                let start = parse_address(arg, &self.symbols).ok_or(format!("Invalid address '{}'", arg))?;
+                (start, start.saturating_add(DEFAULT_HEXDUMP_SIZE - 1))
+            },
+            None => { return Err(String::from("Expected an address or range")); },
+        };
+
+        let mut stream = String::new();
+        machine.get_cpu().get_mem().hexdump(start.min(end)..start.max(end).saturating_add(1), &mut stream);
+        write!(out, "{}", stream).map_err(|e| e.to_string())
+    }
+
+    fn find(&self, machine: &Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let pattern = parse_byte_pattern(&args.join(" "))?;
+        let mem = machine.get_cpu().get_mem();
+        let matches = mem.find(&pattern, 0..mem.size());
+
+        if matches.is_empty()
+        {
+            return writeln!(out, "Not found").map_err(|e| e.to_string());
+        }
+
+        for addr in matches
+        {
+            writeln!(out, "{}", self.symbols.format_addr(addr as u16)).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn diff(&self, machine: &Machine, out: &mut dyn Write) -> Result<(), String>
+    {
+        let snapshot = self.snapshot.as_ref().ok_or(String::from("No snapshot yet (use 'snap')"))?;
+        let changes = snapshot.diff(machine.get_cpu().get_mem());
+
+        if changes.is_empty()
+        {
+            return writeln!(out, "No changes").map_err(|e| e.to_string());
+        }
+
+        let mut stream = String::new();
+        format_changes(&changes, &mut stream);
+        write!(out, "{}", stream).map_err(|e| e.to_string())
+    }
+
+    fn list_smc(&self, machine: &Machine, out: &mut dyn Write) -> Result<(), String>
+    {
+        let smc = machine.find_observer::<SmcDetector>().ok_or(String::from("Self-modifying code detection is off (start with '--smc')"))?;
+
+        let mut stream = String::new();
+        smc.format_report(&self.symbols, &mut stream);
+        write!(out, "{}", stream).map_err(|e| e.to_string())
+    }
+
+    fn add_cheat(&self, machine: &mut Machine, kind: EnumCheatKind, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let addr = match args.first()
+        {
+            Some(arg) => parse_address(arg, &self.symbols).ok_or(format!("Invalid address or label '{}'", arg))?,
+            None => { return Err(String::from("Expected an address and bytes")); },
+        };
+
+        let bytes = parse_byte_pattern(&args[1..].join(" "))?.into_iter()
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(String::from("Cheats can't write '??'"))?;
+
+        let cheat = Cheat { kind, addr, bytes };
+
+        if !machine.add_cheat(cheat.clone())
+        {
+            return Err(format!("'{}' doesn't fit in memory", cheat));
+        }
+
+        writeln!(out, "Cheat: {}", cheat).map_err(|e| e.to_string())
+    }
+
+    fn remove_cheat(&self, machine: &mut Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let addr = match args.first()
+        {
+            Some(arg) => parse_address(arg, &self.symbols).ok_or(format!("Invalid address or label '{}'", arg))?,
+            None => { return Err(String::from("Expected an address")); },
+        };
+
+        if !machine.remove_cheat(addr)
+        {
+            return Err(format!("No cheat at 0x{:03X}", addr));
+        }
+
+        writeln!(out, "Deleted the cheats at 0x{:03X}", addr).map_err(|e| e.to_string())
+    }
+
+    fn list_cheats(&self, machine: &Machine, out: &mut dyn Write) -> Result<(), String>
+    {
+        if machine.get_cheats().is_empty()
+        {
+            return writeln!(out, "No cheats").map_err(|e| e.to_string());
+        }
+
+        for cheat in machine.get_cheats().iter()
+        {
+            writeln!(out, "{}", cheat).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// 'search new' starts over with every address, 'search <filter>' narrows the candidates down and 'search' lists them.
+    fn search(&mut self, machine: &Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let mem = machine.get_cpu().get_mem();
+
+        match args.first()
+        {
+            Some(&"new") =>
+            {
+                self.search = Some(CheatSearch::new(mem, 0..mem.size()));
+            },
+            Some(arg) =>
+            {
+                let filter = arg.parse::<EnumSearchFilter>()?;
+                self.search.as_mut().ok_or(String::from("No search yet (use 'search new')"))?.filter(mem, filter);
+            },
+            None => {},
+        }
+
+        let candidates = self.search.as_ref().ok_or(String::from("No search yet (use 'search new')"))?.get_candidates();
+        writeln!(out, "{} candidate(s)", candidates.len()).map_err(|e| e.to_string())?;
+
+        if candidates.len() <= MAX_LISTED_CANDIDATES
+        {
+            for addr in candidates
+            {
+                let value = mem.read_u8(*addr).unwrap_or_default();
+                writeln!(out, "{0} = 0x{1:02X}", self.symbols.format_addr(*addr as u16), value).map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn speed(machine: &mut Machine, args: &[&str], out: &mut dyn Write) -> Result<(), String>
+    {
+        let frame_config = machine.get_frame_config_mut();
+
+        if let Some(arg) = args.first()
+        {
+            frame_config.set_speed(arg.parse::<EnumSpeed>()?);
+        }
+
+        writeln!(out, "Speed: {0} ({1} instructions per frame)", frame_config.get_speed(), frame_config.get_instructions_per_frame()).map_err(|e| e.to_string())
+    }
+
+    fn list_watches(&self, machine: &Machine, out: &mut dyn Write) -> Result<(), String>
+    {
+        for addr in machine.get_breakpoints()
+        {
+            writeln!(out, "break {}", self.describe(addr)).map_err(|e| e.to_string())?;
+        }
+
+        let watch_list = machine.get_cpu().get_watch_list();
+
+        if watch_list.is_empty()
+        {
+            return writeln!(out, "No watchpoints").map_err(|e| e.to_string());
+        }
+
+        for watchpoint in watch_list.iter()
+        {
+            write!(out, "{0}: {1} 0x{2:03X}-0x{3:03X}", watchpoint.id, watchpoint.kind.name(), watchpoint.start, watchpoint.end).map_err(|e| e.to_string())?;
+
+            if let Some(value) = watchpoint.value
+            {
+                write!(out, " == 0x{:02X}", value).map_err(|e| e.to_string())?;
+            }
+
+            writeln!(out).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Debugger
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Parses a decimal or '0x'-prefixed hexadecimal number.
+fn parse_number(text: &str) -> Option<usize>
+{
+    match text.strip_prefix("0x").or(text.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse::<usize>().ok(),
+    }
+}
+
+/// Parses a number or a label (optionally 'label+offset').
+fn parse_address(text: &str, symbols: &SymbolTable) -> Option<usize>
+{
+    parse_number(text).or(symbols.resolve(text).map(|addr| addr as usize))
+}
+
+/// Where a command's output goes instead of the screen: (path, append).
+type Redirect<'a> = Option<(&'a str, bool)>;
+
+/// Splits a trailing '> file' or '>> file' off a command, returning (command, Some((path, append))). The comparisons
+/// in 'if' and 'assert' conditions aren't redirections.
+fn split_redirect<'a>(tokens: &'a [&'a str]) -> Result<(&'a [&'a str], Redirect<'a>), String>
+{
+    let mut start = 0;
+
+    while matches!(tokens.get(start), Some(&"if") | Some(&"assert"))
+    {
+        start += 4;
+    }
+
+    let index = match tokens.iter().skip(start).position(|token| *token == ">" || *token == ">>")
+    {
+        Some(offset) => start + offset,
+        None => { return Ok((tokens, None)); },
+    };
+
+    match &tokens[index + 1..]
+    {
+        [path] if index > 0 => Ok((&tokens[..index], Some((path, tokens[index] == ">>")))),
+        _ => Err(String::from("Expected a command, then a single file name after '>' or '>>'")),
+    }
+}
+
+/// Parses either a single address or an inclusive 'start-end' range.
+fn parse_range(text: &str, symbols: &SymbolTable) -> Option<(usize, usize)>
+{
+    match text.split_once('-')
+    {
+        Some((start, end)) => Some((parse_address(start, symbols)?, parse_address(end, symbols)?)),
+        None =>
+        {
+            let addr = parse_address(text, symbols)?;
+            Some((addr, addr))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::debugger::{parse_number, parse_range, split_redirect, Debugger};
+    use crate::dbg::smc::SmcDetector;
+    use crate::dbg::symbols::SymbolTable;
+    use crate::hw::cpu::EnumDumpFormat;
+    use crate::hw::frame::EnumSpeed;
+    use crate::machine::Machine;
+
+    const STARTING_PC: u16 = 0x200;
+
+    fn run(debugger: &mut Debugger, machine: &mut Machine, line: &str) -> String
+    {
+        let mut out = Vec::<u8>::new();
+        debugger.execute(machine, line, &mut out);
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse_numbers_and_ranges()
+    {
+        assert_eq!(parse_number("512"), Some(512));
+        assert_eq!(parse_number("0x200"), Some(0x200));
+        assert_eq!(parse_number("0xZZ"), None);
+        let symbols = SymbolTable::parse("sprite 0x300\n").unwrap();
+        assert_eq!(parse_range("0x300", &symbols), Some((0x300, 0x300)));
+        assert_eq!(parse_range("0x300-0x30F", &symbols), Some((0x300, 0x30F)));
+        assert_eq!(parse_range("0x300-", &symbols), None);
+        assert_eq!(parse_range("sprite-sprite+15", &symbols), Some((0x300, 0x30F)));
+        assert_eq!(parse_range("ball", &symbols), None);
+    }
+
+    #[test]
+    fn add_list_and_delete_watchpoints()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        let output = run(&mut debugger, &mut machine, "watch 0x300-0x30F == 0x42");
+        assert!(output.contains("Watchpoint 1"));
+
+        let output = run(&mut debugger, &mut machine, "watches");
+        assert!(output.contains("1: write 0x300-0x30F == 0x42"));
+
+        let output = run(&mut debugger, &mut machine, "delete 1");
+        assert!(output.contains("Deleted watchpoint 1"));
+        assert!(machine.get_cpu().get_watch_list().is_empty());
+
+        let output = run(&mut debugger, &mut machine, "delete 1");
+        assert!(output.contains("[ERROR]"));
+    }
+
+    #[test]
+    fn bad_watch_value_is_rejected()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        let output = run(&mut debugger, &mut machine, "rwatch 0x300 == 0x100");
+        assert!(output.contains("[ERROR]"));
+        assert!(machine.get_cpu().get_watch_list().is_empty());
+    }
+
+    #[test]
+    fn continue_and_quit_update_state()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+        assert!(debugger.is_paused());
+
+        run(&mut debugger, &mut machine, "continue");
+        assert!(!debugger.is_paused());
+
+        run(&mut debugger, &mut machine, "quit");
+        assert!(debugger.should_quit());
+    }
+
+    #[test]
+    fn step_stops_at_watchpoint()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        // V1 = 0x10, I = 0x300, dump V0..V1, V2 = 0x01, halt
+        let program: [u16; 5] = [0x6110, 0xA300, 0xF155, 0x6201, 0x0000];
+
+        for (i, instruction) in program.iter().enumerate()
+        {
+            machine.get_cpu_mut().get_mem_mut().write_u16(STARTING_PC as usize + 2 * i, *instruction);
+        }
+
+        run(&mut debugger, &mut machine, "watch 0x301");
+        let output = run(&mut debugger, &mut machine, "step 10");
+
+        assert!(output.contains("Watchpoint 1 hit: write of 0x301 (0x00 -> 0x10) by instruction 0xF155 at pc 0x204"));
+        assert_eq!(machine.get_cpu().get_pc(), 0x206);
+        assert!(!machine.is_halted());
+
+        let output = run(&mut debugger, &mut machine, "step 10");
+        assert!(output.contains("CPU is halted"));
+    }
+
+    #[test]
+    fn step_stops_at_fault()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        // Return with an empty stack.
+        machine.get_cpu_mut().get_mem_mut().write_u16(STARTING_PC as usize, 0x00EE);
+
+        let output = run(&mut debugger, &mut machine, "step 5");
+        assert!(output.contains("[FAULT]: Stack underflow"));
+        assert!(machine.get_cpu().get_last_fault().is_some());
+        assert!(!machine.is_halted());
+    }
+
+    #[test]
+    fn breakpoints_by_label()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let symbols = SymbolTable::parse("main 0x200\nadd_one 0x206 test.8o:7\n").unwrap();
+        let mut debugger = Debugger::with_symbols(symbols);
+
+        // 0x200: call 0x206, 0x202: jump 0x200, 0x204: (unused) / 0x206: V0 += 1, return
+        machine.load_rom(&[0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE]).unwrap();
+
+        let output = run(&mut debugger, &mut machine, "break add_one+2");
+        assert_eq!(output, "Breakpoint at 0x208 <add_one+0x2>\n");
+
+        let output = run(&mut debugger, &mut machine, "break add_one");
+        assert_eq!(output, "Breakpoint at 0x206 <add_one> (test.8o:7)\n");
+        assert!(run(&mut debugger, &mut machine, "break add_one").contains("[ERROR]"));
+
+        assert_eq!(machine.run_frame(), Ok(false));
+        assert_eq!(machine.take_break_hit(), Some(0x206));
+
+        let output = run(&mut debugger, &mut machine, "backtrace");
+        assert_eq!(output, "#0 0x206 <add_one> (test.8o:7)\n#1 0x200 <main>\n");
+
+        let output = run(&mut debugger, &mut machine, "disasm main 5");
+        assert_eq!(output, "main:\n   0x200  2206  CALL 0x206  ; add_one\n   0x202  1200  JP 0x200  ; main\n   0x204  0000  HALT\n\
+                            add_one:\n=> 0x206  7001  ADD V0, 0x01\n * 0x208  00EE  RET\n");
+
+        let output = run(&mut debugger, &mut machine, "clear 0x208");
+        assert!(output.contains("Deleted breakpoint at 0x208 <add_one+0x2>"));
+
+        let output = run(&mut debugger, &mut machine, "watches");
+        assert!(output.starts_with("break 0x206 <add_one> (test.8o:7)\n"));
+
+        assert!(run(&mut debugger, &mut machine, "break nowhere").contains("Invalid address or label 'nowhere'"));
+    }
+
+    #[test]
+    fn regs_uses_the_dump_format()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+        machine.load_rom(&[0x60, 0x2A, 0x00, 0x00]).unwrap();
+        run(&mut debugger, &mut machine, "step");
+
+        assert!(run(&mut debugger, &mut machine, "regs").contains("\tV0=2A V1=00"));
+        assert!(run(&mut debugger, &mut machine, "r json").starts_with("{\"pc\":514,"));
+        assert!(run(&mut debugger, &mut machine, "r xml").contains("[ERROR]: Unknown format 'xml'"));
+
+        debugger.set_dump_format(EnumDumpFormat::Json);
+        assert!(run(&mut debugger, &mut machine, "regs").contains("\"registers\":[42,0,"));
+    }
+
+    #[test]
+    fn smc_is_reported_while_stepping()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::with_symbols(SymbolTable::parse("start 0x200\n").unwrap());
+
+        assert!(run(&mut debugger, &mut machine, "smc").contains("[ERROR]: Self-modifying code detection is off"));
+
+        // V0 = 0x61, I = 0x200, store V0 over the first instruction, halt
+        machine.add_observer(Box::new(SmcDetector::new(4096)));
+        machine.load_rom(&[0x60, 0x61, 0xA2, 0x00, 0xF0, 0x55, 0x00, 0x00]).unwrap();
+
+        assert_eq!(run(&mut debugger, &mut machine, "step 2"), "");
+        assert_eq!(run(&mut debugger, &mut machine, "step"),
+                   "Self-modifying code: 0x204 <start+0x4> wrote 0x200 <start>: 6061 LD V0, 0x61 -> 6161 LD V1, 0x61 (overwrote executed code)\n");
+        assert!(run(&mut debugger, &mut machine, "smc").starts_with("Self-modifying code: 1 write(s) into code\n"));
+    }
+
+    #[test]
+    fn memory_dump_find_and_diff()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::with_symbols(SymbolTable::parse("sprite 0x300\n").unwrap());
+
+        // I = 0x300, V0 = 0x42, store V0, halt
+        machine.load_rom(&[0xA3, 0x00, 0x60, 0x42, 0xF0, 0x55, 0x00, 0x00]).unwrap();
+
+        let output = run(&mut debugger, &mut machine, "mem 0x200");
+        assert_eq!(output.lines().count(), 4);
+        assert!(output.starts_with("0x0200  A3 00 60 42 F0 55 00 00  00 00 00 00 00 00 00 00 |..`B.U..........|\n"));
+
+        let output = run(&mut debugger, &mut machine, "m 0x202-0x203");
+        assert_eq!(output, "0x0200        60 42                                      |  `B            |\n");
+
+        // Past the end of memory there's nothing to show.
+        assert_eq!(run(&mut debugger, &mut machine, "mem 0xFFFFFFFFFFFFFFFF"), "");
+        assert_eq!(run(&mut debugger, &mut machine, "mem 0x0-0xFFFFFFFFFFFFFFFF").lines().count(), 256);
+
+        assert!(run(&mut debugger, &mut machine, "diff").contains("[ERROR]: No snapshot yet"));
+        run(&mut debugger, &mut machine, "snap");
+        assert_eq!(run(&mut debugger, &mut machine, "diff"), "No changes\n");
+
+        run(&mut debugger, &mut machine, "step 3");
+        assert_eq!(run(&mut debugger, &mut machine, "diff"), "0x0300         00 -> 42\n");
+
+        assert_eq!(run(&mut debugger, &mut machine, "find 60 ?? F0"), "0x202\n");
+        assert_eq!(run(&mut debugger, &mut machine, "find 42 00 00 00 00"), "0x300 <sprite>\n");
+        assert_eq!(run(&mut debugger, &mut machine, "find 12 34 56"), "Not found\n");
+        assert!(run(&mut debugger, &mut machine, "find 1").contains("[ERROR]"));
+    }
+
+    #[test]
+    fn conditions_and_asserts()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::with_symbols(SymbolTable::parse("score 0x300\n").unwrap());
+
+        // I = 0x300, V0 = 0x03, store V0, halt
+        machine.load_rom(&[0xA3, 0x00, 0x60, 0x03, 0xF0, 0x55, 0x00, 0x00]).unwrap();
+        run(&mut debugger, &mut machine, "step 3");
+
+        assert_eq!(run(&mut debugger, &mut machine, "if v0 == 3 echo three"), "three\n");
+        assert_eq!(run(&mut debugger, &mut machine, "if V0 != 3 echo not three"), "");
+        assert_eq!(run(&mut debugger, &mut machine, "if [score] >= V0 if I == score echo both"), "both\n");
+        assert_eq!(run(&mut debugger, &mut machine, "if PC > 0x205 echo past"), "past\n");
+        assert!(run(&mut debugger, &mut machine, "if V0 == 3").contains("[ERROR]: Expected a command"));
+        assert!(run(&mut debugger, &mut machine, "if V0 ~ 3 echo").contains("[ERROR]: Unknown comparison '~'"));
+        assert!(run(&mut debugger, &mut machine, "if VG == 3 echo").contains("[ERROR]: Invalid value 'VG'"));
+
+        assert_eq!(run(&mut debugger, &mut machine, "assert V0 < 4"), "");
+        assert_eq!(run(&mut debugger, &mut machine, "assert V0 == 5"), "[ASSERT]: V0 == 5 failed (V0 = 0x03)\n");
+        assert_eq!(run(&mut debugger, &mut machine, "assert [score] == DT score was not stored"),
+                   "[ASSERT]: score was not stored ([score] == DT failed: [score] = 0x03, DT = 0x00)\n");
+        assert_eq!(debugger.get_failures(), 2);
+    }
+
+    #[test]
+    fn output_redirection()
+    {
+        assert_eq!(split_redirect(&["regs", ">", "out.txt"]), Ok((&["regs"][..], Some(("out.txt", false)))));
+        assert_eq!(split_redirect(&["if", "V0", ">", "1", "regs", ">>", "out.txt"]), Ok((&["if", "V0", ">", "1", "regs"][..], Some(("out.txt", true)))));
+        assert_eq!(split_redirect(&["assert", "V0", ">", "1"]), Ok((&["assert", "V0", ">", "1"][..], None)));
+        assert!(split_redirect(&["regs", ">"]).is_err());
+        assert!(split_redirect(&[">", "out.txt"]).is_err());
+
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        let path = std::env::temp_dir().join(format!("hchip8-{}-redirect.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        assert_eq!(run(&mut debugger, &mut machine, &format!("echo first > {}", path)), "");
+        run(&mut debugger, &mut machine, &format!("echo second >> {}", path));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "first\nsecond\n");
+
+        run(&mut debugger, &mut machine, &format!("echo third > {}", path));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "third\n");
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn scripts_run_to_the_end()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        // V0 = 0x07, halt
+        machine.load_rom(&[0x60, 0x07, 0x00, 0x00]).unwrap();
+        debugger.set_script("test.dbg", "# Check V0\n\nstep\nassert V0 == 7\nassert V0 == 8\nbogus\n");
+
+        let mut out = Vec::<u8>::new();
+        debugger.finish_script(&mut machine, &mut out);
+
+        assert!(debugger.should_quit());
+        assert_eq!(debugger.get_failures(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "(hchip8 0x200) step\n\
+                                                     (hchip8 0x202) assert V0 == 7\n\
+                                                     (hchip8 0x202) assert V0 == 8\n\
+                                                     [ASSERT]: test.dbg:5: V0 == 8 failed (V0 = 0x07)\n\
+                                                     (hchip8 0x202) bogus\n\
+                                                     [ERROR]: test.dbg:6: Unknown command 'bogus' (try 'help')\n");
+    }
+
+    #[test]
+    fn cheats_and_value_search()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::with_symbols(SymbolTable::parse("lives 0x300\n").unwrap());
+
+        // I = lives, V0 = 3, store V0, V0 = 2, store V0, halt
+        machine.load_rom(&[0xA3, 0x00, 0x60, 0x03, 0xF0, 0x55, 0x60, 0x02, 0xF0, 0x55, 0x00, 0x00]).unwrap();
+
+        assert!(run(&mut debugger, &mut machine, "search same").contains("[ERROR]: No search yet"));
+        run(&mut debugger, &mut machine, "step 3");
+        assert_eq!(run(&mut debugger, &mut machine, "search new"), "4096 candidate(s)\n");
+        assert_eq!(run(&mut debugger, &mut machine, "search 3"), "2 candidate(s)\n0x203 = 0x03\n0x300 <lives> = 0x03\n");
+
+        run(&mut debugger, &mut machine, "step 2");
+        assert_eq!(run(&mut debugger, &mut machine, "search decreased"), "1 candidate(s)\n0x300 <lives> = 0x02\n");
+        assert!(run(&mut debugger, &mut machine, "search up").contains("[ERROR]: Invalid search filter 'up'"));
+
+        assert_eq!(run(&mut debugger, &mut machine, "freeze lives 09"), "Cheat: freeze 0x300 09\n");
+        assert_eq!(run(&mut debugger, &mut machine, "patch 0x400 12 34"), "Cheat: patch 0x400 12 34\n");
+        assert_eq!(machine.get_cpu().get_mem().read_u8(0x300), Some(0x09));
+        assert_eq!(run(&mut debugger, &mut machine, "cheats"), "freeze 0x300 09\npatch 0x400 12 34\n");
+        assert!(run(&mut debugger, &mut machine, "freeze 0xFFF 01 02").contains("[ERROR]: 'freeze 0xFFF 01 02' doesn't fit"));
+
+        assert_eq!(run(&mut debugger, &mut machine, "unfreeze lives"), "Deleted the cheats at 0x300\n");
+        assert!(run(&mut debugger, &mut machine, "unfreeze lives").contains("[ERROR]: No cheat at 0x300"));
+    }
+
+    #[test]
+    fn speed_changes_frame_config()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        run(&mut debugger, &mut machine, "speed turbo");
+        assert_eq!(machine.get_frame_config().get_speed(), EnumSpeed::Turbo);
+
+        run(&mut debugger, &mut machine, "speed 0.5");
+        assert_eq!(machine.get_frame_config().get_speed(), EnumSpeed::Multiplier(0.5));
+
+        let output = run(&mut debugger, &mut machine, "speed 0");
+        assert_eq!(machine.get_frame_config().get_speed(), EnumSpeed::Multiplier(0.5));
+        assert!(output.contains("[ERROR]"));
+
+        let output = run(&mut debugger, &mut machine, "speed 1e-300");
+        assert_eq!(machine.get_frame_config().get_speed(), EnumSpeed::Multiplier(0.5));
+        assert!(output.contains("must be between"));
+    }
+
+    #[test]
+    fn unknown_command_reports_error()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let mut debugger = Debugger::new();
+
+        let output = run(&mut debugger, &mut machine, "frobnicate");
+        assert!(output.contains("Unknown command 'frobnicate'"));
+    }
+}
@@ -0,0 +1,9 @@
+pub mod cheats;
+pub mod coverage;
+pub mod debugger;
+pub mod profiler;
+pub mod smc;
+pub mod source_map;
+pub mod symbols;
+pub mod trace;
+pub mod watchpoint;
@@ -0,0 +1,391 @@
+use crate::hw::cpu::CPU;
+use crate::hw::fault::{EnumFault, EnumTickOutcome};
+use crate::hw::instruction::{disassemble, Instruction};
+use crate::hw::mem::Mem;
+use crate::hw::observer::Observer;
+
+use std::collections::HashMap;
+
+/// The root of the call tree (code that isn't inside any subroutine call).
+const ROOT_NODE: usize = 0;
+const ROOT_NAME: &str = "main";
+
+/// One distinct call stack: the subroutine entered and the stack it was called from.
+struct StackNode
+{
+    parent: usize,
+    /// None for the root.
+    entry: Option<u16>,
+    /// Instructions executed with exactly this call stack.
+    cycles: u64,
+}
+
+/// Per-subroutine totals. Inclusive cycles include the subroutines it called, exclusive ones don't.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SubroutineStats
+{
+    /// None for the root ("main").
+    pub entry: Option<u16>,
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+/// A backward jump and the instructions it repeats.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LoopStats
+{
+    pub start: u16,
+    /// The address of the jump that closes the loop.
+    pub end: u16,
+    /// How many times the closing jump ran.
+    pub iterations: u64,
+    /// Instructions executed between 'start' and 'end' (inclusive).
+    pub cycles: u64,
+}
+
+/// Counts executions per address and per call stack while the guest runs.
+///
+/// Each instruction costs one cycle. Calls and returns are followed with a shadow call stack, so the
+/// counts can be reported per subroutine or exported as collapsed stacks for flamegraph tools.
+pub struct Profiler
+{
+    hits: Vec<u64>,
+    nodes: Vec<StackNode>,
+    children: HashMap<(usize, u16), usize>,
+    current: usize,
+    calls: HashMap<u16, u64>,
+    total: u64,
+    /// The instruction running pushed a return address, so it's a call to wherever the pc goes.
+    calling: bool,
+}
+
+impl Profiler
+{
+    pub fn new(mem_size: usize) -> Self
+    {
+        Self {
+            hits: vec![0; mem_size],
+            nodes: vec![StackNode { parent: ROOT_NODE, entry: None, cycles: 0 }],
+            children: HashMap::new(),
+            current: ROOT_NODE,
+            calls: HashMap::new(),
+            total: 0,
+            calling: false,
+        }
+    }
+
+    pub fn on_call(&mut self, target: u16)
+    {
+        let parent = self.current;
+        let next_index = self.nodes.len();
+        let child = *self.children.entry((parent, target)).or_insert(next_index);
+
+        if child == next_index
+        {
+            self.nodes.push(StackNode { parent, entry: Some(target), cycles: 0 });
+        }
+
+        self.current = child;
+        *self.calls.entry(target).or_insert(0) += 1;
+    }
+
+    pub fn on_return(&mut self)
+    {
+        // NOTE: A return at the root means the ROM manipulated the stack in a way we couldn't follow; stay at the root.
+        self.current = self.nodes[self.current].parent;
+    }
+
+    pub fn get_total(&self) -> u64
+    {
+        self.total
+    }
+
+    pub fn get_hits(&self, addr: u16) -> u64
+    {
+        self.hits.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    /// Executed addresses by descending hit count (ties by address).
+    pub fn hot_addresses(&self) -> Vec<(u16, u64)>
+    {
+        let mut result: Vec<(u16, u64)> = self.hits.iter().enumerate()
+            .filter(|(_, hits)| **hits > 0)
+            .map(|(addr, hits)| (addr as u16, *hits))
+            .collect();
+
+        result.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        result
+    }
+
+    /// The entries on a node's stack, outermost first (the root is not included).
+    fn stack_of(&self, node: usize) -> Vec<u16>
+    {
+        let mut stack = Vec::new();
+        let mut index = node;
+
+        while index != ROOT_NODE
+        {
+            stack.push(self.nodes[index].entry.unwrap());
+            index = self.nodes[index].parent;
+        }
+
+        stack.reverse();
+
+        stack
+    }
+
+    /// Totals per subroutine by descending inclusive cycles. The root always comes first.
+    pub fn subroutines(&self) -> Vec<SubroutineStats>
+    {
+        let mut stats: HashMap<u16, SubroutineStats> = HashMap::new();
+
+        for (index, node) in self.nodes.iter().enumerate().skip(1)
+        {
+            let mut stack = self.stack_of(index);
+            let entry = node.entry.unwrap();
+
+            stats.entry(entry).or_insert(SubroutineStats { entry: Some(entry), calls: 0, inclusive: 0, exclusive: 0 }).exclusive += node.cycles;
+
+            // Recursive subroutines appear more than once on a stack, but only count once.
+            stack.sort_unstable();
+            stack.dedup();
+
+            for addr in stack
+            {
+                stats.entry(addr).or_insert(SubroutineStats { entry: Some(addr), calls: 0, inclusive: 0, exclusive: 0 }).inclusive += node.cycles;
+            }
+        }
+
+        for (entry, calls) in self.calls.iter()
+        {
+            if let Some(stat) = stats.get_mut(entry)
+            {
+                stat.calls = *calls;
+            }
+        }
+
+        let mut result: Vec<SubroutineStats> = stats.into_values().collect();
+        result.sort_by(|a, b| b.inclusive.cmp(&a.inclusive).then(a.entry.cmp(&b.entry)));
+        result.insert(0, SubroutineStats { entry: None, calls: 0, inclusive: self.total, exclusive: self.nodes[ROOT_NODE].cycles });
+
+        result
+    }
+
+    /// Loops closed by backward jumps, by descending cycles. Uses the current memory contents to find the jumps.
+    pub fn hot_loops(&self, mem: &Mem) -> Vec<LoopStats>
+    {
+        let mut result = Vec::new();
+
+        for (addr, hits) in self.hits.iter().enumerate()
+        {
+            if *hits == 0
+            {
+                continue;
+            }
+
+            if let Some(Instruction::Jump { nnn }) = mem.read_u16(addr).map(Instruction::decode)
+            {
+                if (nnn as usize) <= addr
+                {
+                    let cycles = self.hits[nnn as usize..=addr].iter().sum();
+                    result.push(LoopStats { start: nnn, end: addr as u16, iterations: *hits, cycles });
+                }
+            }
+        }
+
+        result.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.start.cmp(&b.start)));
+
+        result
+    }
+
+    fn name_of(entry: Option<u16>) -> String
+    {
+        match entry
+        {
+            Some(addr) => format!("sub_{:03X}", addr),
+            None => ROOT_NAME.to_string(),
+        }
+    }
+
+    fn percent(&self, cycles: u64) -> f64
+    {
+        100.0 * cycles as f64 / self.total.max(1) as f64
+    }
+
+    /// A human readable report: the hottest addresses with their disassembly, subroutines and hot loops.
+    pub fn format_report(&self, mem: &Mem, max_rows: usize, stream: &mut String)
+    {
+        stream.push_str(&format!("Profile: {} instructions\n", self.total));
+
+        stream.push_str("\nHot addresses:\n");
+        stream.push_str(&format!("  {0:<7}{1:>12}{2:>8}  {3}\n", "addr", "hits", "%", "instruction"));
+
+        for (addr, hits) in self.hot_addresses().into_iter().take(max_rows)
+        {
+            let text = mem.read_u16(addr as usize).map(disassemble).unwrap_or_default();
+            stream.push_str(&format!("  0x{0:03X}  {1:>12}{2:>7.2}%  {3}\n", addr, hits, self.percent(hits), text));
+        }
+
+        stream.push_str("\nSubroutines:\n");
+        stream.push_str(&format!("  {0:<10}{1:>10}{2:>14}{3:>8}{4:>14}{5:>8}\n", "name", "calls", "inclusive", "%", "exclusive", "%"));
+
+        for stat in self.subroutines().into_iter().take(max_rows)
+        {
+            stream.push_str(&format!("  {0:<10}{1:>10}{2:>14}{3:>7.2}%{4:>14}{5:>7.2}%\n", Self::name_of(stat.entry), stat.calls,
+                                     stat.inclusive, self.percent(stat.inclusive), stat.exclusive, self.percent(stat.exclusive)));
+        }
+
+        let loops = self.hot_loops(mem);
+
+        if !loops.is_empty()
+        {
+            stream.push_str("\nHot loops:\n");
+            stream.push_str(&format!("  {0:<15}{1:>12}{2:>14}{3:>8}\n", "range", "iterations", "cycles", "%"));
+
+            for stat in loops.into_iter().take(max_rows)
+            {
+                stream.push_str(&format!("  0x{0:03X}-0x{1:03X}  {2:>12}{3:>14}{4:>7.2}%\n", stat.start, stat.end, stat.iterations,
+                                         stat.cycles, self.percent(stat.cycles)));
+            }
+        }
+    }
+
+    /// One 'main;sub_206;sub_20C <cycles>' line per call stack, as read by flamegraph.pl and compatible tools.
+    pub fn format_collapsed(&self, stream: &mut String)
+    {
+        for (index, node) in self.nodes.iter().enumerate()
+        {
+            if node.cycles == 0
+            {
+                continue;
+            }
+
+            let mut names = vec![ROOT_NAME.to_string()];
+            names.extend(self.stack_of(index).into_iter().map(|addr| Self::name_of(Some(addr))));
+
+            stream.push_str(&format!("{0} {1}\n", names.join(";"), node.cycles));
+        }
+    }
+}
+
+impl Observer for Profiler
+{
+    /// Counts the instruction before it executes, so a call is counted in its caller.
+    fn on_fetch(&mut self, pc: u16, _raw_opcode: u16)
+    {
+        if let Some(hits) = self.hits.get_mut(pc as usize)
+        {
+            *hits += 1;
+        }
+
+        self.nodes[self.current].cycles += 1;
+        self.total += 1;
+    }
+
+    fn on_execute(&mut self, cpu: &CPU, _pc: u16, _raw_opcode: u16, _result: &Result<EnumTickOutcome, EnumFault>)
+    {
+        if self.calling
+        {
+            self.calling = false;
+            self.on_call(cpu.get_pc());
+        }
+    }
+
+    fn on_stack_push(&mut self, _pc: u16, _ret_address: u16, _depth: usize)
+    {
+        self.calling = true;
+    }
+
+    fn on_stack_pop(&mut self, _pc: u16, _ret_address: u16, _depth: usize)
+    {
+        self.on_return();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::profiler::{LoopStats, Profiler, SubroutineStats};
+    use crate::hw::mem::Mem;
+    use crate::hw::observer::Observer;
+
+    #[test]
+    fn counts_hits_and_subroutines()
+    {
+        let mut profiler = Profiler::new(4096);
+
+        // main: 0x200 (call 0x300), 0x300 (call 0x400), 0x400 x3, return, 0x302 return, 0x202
+        profiler.on_fetch(0x200, 0);
+        profiler.on_call(0x300);
+        profiler.on_fetch(0x300, 0);
+        profiler.on_call(0x400);
+        profiler.on_fetch(0x400, 0);
+        profiler.on_fetch(0x400, 0);
+        profiler.on_fetch(0x402, 0);
+        profiler.on_return();
+        profiler.on_fetch(0x302, 0);
+        profiler.on_return();
+        profiler.on_fetch(0x202, 0);
+
+        assert_eq!(profiler.get_total(), 7);
+        assert_eq!(profiler.get_hits(0x400), 2);
+        assert_eq!(profiler.hot_addresses()[0], (0x400, 2));
+
+        let subroutines = profiler.subroutines();
+        assert_eq!(subroutines[0], SubroutineStats { entry: None, calls: 0, inclusive: 7, exclusive: 2 });
+        assert_eq!(subroutines[1], SubroutineStats { entry: Some(0x300), calls: 1, inclusive: 5, exclusive: 2 });
+        assert_eq!(subroutines[2], SubroutineStats { entry: Some(0x400), calls: 1, inclusive: 3, exclusive: 3 });
+
+        let mut stream = String::new();
+        profiler.format_collapsed(&mut stream);
+        assert_eq!(stream, "main 2\nmain;sub_300 2\nmain;sub_300;sub_400 3\n");
+    }
+
+    #[test]
+    fn recursion_counts_once_inclusive()
+    {
+        let mut profiler = Profiler::new(4096);
+
+        profiler.on_call(0x300);
+        profiler.on_fetch(0x300, 0);
+        profiler.on_call(0x300);
+        profiler.on_fetch(0x300, 0);
+        profiler.on_return();
+        profiler.on_return();
+        // An unmatched return stays at the root.
+        profiler.on_return();
+        profiler.on_fetch(0x200, 0);
+
+        let subroutines = profiler.subroutines();
+        assert_eq!(subroutines[1], SubroutineStats { entry: Some(0x300), calls: 2, inclusive: 2, exclusive: 2 });
+        assert_eq!(subroutines[0].exclusive, 1);
+    }
+
+    #[test]
+    fn backward_jumps_are_loops()
+    {
+        let mut mem = Mem::new(4096);
+        // 0x200: V0 += 1, 0x202: skip if V0 == 0, 0x204: jump 0x200
+        mem.write_u16(0x200, 0x7001);
+        mem.write_u16(0x202, 0x3000);
+        mem.write_u16(0x204, 0x1200);
+
+        let mut profiler = Profiler::new(4096);
+
+        for _ in 0..3
+        {
+            profiler.on_fetch(0x200, 0);
+            profiler.on_fetch(0x202, 0);
+            profiler.on_fetch(0x204, 0);
+        }
+
+        assert_eq!(profiler.hot_loops(&mem), [LoopStats { start: 0x200, end: 0x204, iterations: 3, cycles: 9 }]);
+
+        let mut stream = String::new();
+        profiler.format_report(&mem, 10, &mut stream);
+        assert!(stream.contains("0x200             3  33.33%  ADD V0, 0x01"));
+        assert!(stream.contains("0x200-0x204"));
+    }
+}
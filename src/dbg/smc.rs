@@ -0,0 +1,328 @@
+use crate::dbg::symbols::SymbolTable;
+use crate::hw::cpu::CPU;
+use crate::hw::fault::{EnumFault, EnumTickOutcome};
+use crate::hw::observer::Observer;
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Which side of the patch was seen first.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EnumSmcKind
+{
+    /// An instruction that had already run was written over.
+    CodeOverwritten,
+    /// Bytes written earlier were later run as an instruction.
+    PatchExecuted,
+}
+
+impl std::fmt::Display for EnumSmcKind
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::CodeOverwritten => write!(f, "overwrote executed code"),
+            Self::PatchExecuted => write!(f, "patched code executed"),
+        }
+    }
+}
+
+/// One write into code.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SmcEvent
+{
+    pub kind: EnumSmcKind,
+    /// The instruction that did the write.
+    pub writer_pc: u16,
+    /// The start of the instruction that was patched.
+    pub addr: u16,
+    pub old_opcode: u16,
+    pub new_opcode: u16,
+}
+
+impl SmcEvent
+{
+    /// '0x20A <patch> wrote 0x212 <loop+0x2>: 6001 LD V0, 0x01 -> 6005 LD V0, 0x05 (overwrote executed code)'
+    pub fn describe(&self, symbols: &SymbolTable) -> String
+    {
+        format!("{0} wrote {1}: {2:04X} {3} -> {4:04X} {5} ({6})", symbols.format_addr(self.writer_pc), symbols.format_addr(self.addr),
+                self.old_opcode, symbols.disassemble(self.old_opcode), self.new_opcode, symbols.disassemble(self.new_opcode), self.kind)
+    }
+}
+
+/// A write that hasn't been executed (yet).
+#[derive(Clone, Copy, Debug)]
+struct PendingWrite
+{
+    writer_pc: u16,
+    /// The byte before the first write.
+    old_value: u8,
+}
+
+/// A write by the running instruction, checked once the instruction has finished.
+#[derive(Clone, Copy, Debug)]
+struct Write
+{
+    writer_pc: u16,
+    addr: usize,
+    old_value: u8,
+    value: u8,
+}
+
+/// Spots self-modifying code: writes into bytes that have been executed, and bytes that are executed after being written.
+pub struct SmcDetector
+{
+    /// Bytes that have been fetched as part of an instruction.
+    executed: Vec<bool>,
+    /// Addresses that instructions have started at.
+    starts: Vec<bool>,
+    /// Written bytes that haven't been executed yet.
+    pending: HashMap<usize, PendingWrite>,
+    /// The running instruction's writes. Telling what they patched takes memory, which only 'on_execute' is handed.
+    writes: Vec<Write>,
+    events: Vec<SmcEvent>,
+    /// Events before this index were already handed out by 'take_new_events'.
+    reported: usize,
+}
+
+impl SmcDetector
+{
+    pub fn new(mem_size: usize) -> Self
+    {
+        Self { executed: vec![false; mem_size], starts: vec![false; mem_size], pending: HashMap::new(), writes: Vec::new(), events: Vec::new(),
+               reported: 0 }
+    }
+
+    fn is_executed(&self, addr: usize) -> bool
+    {
+        self.executed.get(addr).copied().unwrap_or(false)
+    }
+
+    fn is_start(&self, addr: usize) -> bool
+    {
+        self.starts.get(addr).copied().unwrap_or(false)
+    }
+
+    /// Checks a write into the byte at 'addr'; 'read' gives memory as it was right after the write.
+    fn check_write(&mut self, write: Write, read: impl Fn(usize) -> u8)
+    {
+        let Write { writer_pc, addr, old_value, value } = write;
+
+        if old_value == value
+        {
+            return;
+        }
+
+        if !self.is_executed(addr)
+        {
+            self.pending.entry(addr).or_insert(PendingWrite { writer_pc, old_value });
+            return;
+        }
+
+        // The byte is the first or second half of an instruction that already ran.
+        let start = if self.is_start(addr) || addr == 0 || !self.is_start(addr - 1) { addr } else { addr - 1 };
+        let new_opcode = u16::from_be_bytes([read(start), read(start + 1)]);
+        let old_opcode = if start == addr { (new_opcode & 0x00FF) | ((old_value as u16) << 8) } else { (new_opcode & 0xFF00) | old_value as u16 };
+
+        self.events.push(SmcEvent { kind: EnumSmcKind::CodeOverwritten, writer_pc, addr: start as u16, old_opcode, new_opcode });
+    }
+
+    pub fn get_events(&self) -> &[SmcEvent]
+    {
+        &self.events
+    }
+
+    /// The events since the last call (for reporting them as they happen).
+    pub fn take_new_events(&mut self) -> &[SmcEvent]
+    {
+        let start = self.reported;
+        self.reported = self.events.len();
+
+        &self.events[start..]
+    }
+
+    /// Every distinct patch with how often it happened, in the order they were first seen.
+    pub fn format_report(&self, symbols: &SymbolTable, stream: &mut String)
+    {
+        stream.push_str(&format!("Self-modifying code: {} write(s) into code\n", self.events.len()));
+
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut first_seen: HashMap<SmcEvent, usize> = HashMap::new();
+
+        for (index, event) in self.events.iter().enumerate()
+        {
+            let first = *first_seen.entry(*event).or_insert(index);
+            *counts.entry(first).or_insert(0) += 1;
+        }
+
+        for (index, count) in counts
+        {
+            let suffix = if count > 1 { format!(" x{}", count) } else { String::new() };
+            stream.push_str(&format!("  {0}{1}\n", self.events[index].describe(symbols), suffix));
+        }
+    }
+}
+
+impl Observer for SmcDetector
+{
+    fn on_fetch(&mut self, pc: u16, raw_opcode: u16)
+    {
+        let addr = pc as usize;
+
+        if !self.pending.is_empty()
+        {
+            let first = self.pending.remove(&addr);
+            let second = self.pending.remove(&(addr + 1));
+
+            if let Some(write) = first.or(second)
+            {
+                let high = first.map_or((raw_opcode >> 8) as u8, |write| write.old_value);
+                let low = second.map_or(raw_opcode as u8, |write| write.old_value);
+
+                self.events.push(SmcEvent { kind: EnumSmcKind::PatchExecuted, writer_pc: write.writer_pc, addr: pc,
+                                            old_opcode: u16::from_be_bytes([high, low]), new_opcode: raw_opcode });
+            }
+        }
+
+        for byte_addr in [addr, addr + 1]
+        {
+            if let Some(flag) = self.executed.get_mut(byte_addr)
+            {
+                *flag = true;
+            }
+        }
+
+        if let Some(start) = self.starts.get_mut(addr)
+        {
+            *start = true;
+        }
+    }
+
+    fn on_mem_write(&mut self, pc: u16, addr: usize, old_value: u8, value: u8)
+    {
+        self.writes.push(Write { writer_pc: pc, addr, old_value, value });
+    }
+
+    /// Checks the instruction's writes in order. Memory holds all of them by now, so for each one the writes after
+    /// it are undone.
+    fn on_execute(&mut self, cpu: &CPU, _pc: u16, _raw_opcode: u16, _result: &Result<EnumTickOutcome, EnumFault>)
+    {
+        if self.writes.is_empty()
+        {
+            return;
+        }
+
+        let writes = std::mem::take(&mut self.writes);
+        let mem = cpu.get_mem();
+
+        for (index, write) in writes.iter().enumerate()
+        {
+            let later = &writes[index + 1..];
+            let read = |addr: usize| match later.iter().find(|later_write| later_write.addr == addr)
+            {
+                Some(later_write) => later_write.old_value,
+                None => mem.read_u8(addr).unwrap_or_default(),
+            };
+
+            self.check_write(*write, read);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::smc::{EnumSmcKind, SmcDetector, SmcEvent};
+    use crate::dbg::symbols::SymbolTable;
+    use crate::hw::cpu::CPU;
+    use crate::hw::fault::EnumTickOutcome;
+    use crate::hw::observer::Observer;
+
+    /// Fetches the instruction at 'pc' as the CPU would, then has it write 'writes' (address and value pairs).
+    fn run(smc: &mut SmcDetector, cpu: &mut CPU, pc: u16, writes: &[(usize, u8)])
+    {
+        let raw_opcode = cpu.get_mem().read_u16(pc as usize).unwrap();
+        smc.on_fetch(pc, raw_opcode);
+
+        for (addr, value) in writes
+        {
+            let old_value = cpu.get_mem().read_u8(*addr).unwrap();
+            cpu.get_mem_mut().write_u8(*addr, *value);
+            smc.on_mem_write(pc, *addr, old_value, *value);
+        }
+
+        smc.on_execute(cpu, pc, raw_opcode, &Ok(EnumTickOutcome::Executed));
+    }
+
+    #[test]
+    fn writes_into_executed_code()
+    {
+        let mut cpu = CPU::new(4096, 0x200);
+        cpu.get_mem_mut().write_u16(0x210, 0x6001);
+
+        let mut smc = SmcDetector::new(4096);
+        run(&mut smc, &mut cpu, 0x210, &[]);
+
+        // Patch the immediate byte, then write the same value again (not a change).
+        run(&mut smc, &mut cpu, 0x204, &[(0x211, 0x05), (0x211, 0x05)]);
+
+        assert_eq!(smc.get_events(), [SmcEvent { kind: EnumSmcKind::CodeOverwritten, writer_pc: 0x204, addr: 0x210, old_opcode: 0x6001, new_opcode: 0x6005 }]);
+        assert_eq!(smc.take_new_events().len(), 1);
+        assert!(smc.take_new_events().is_empty());
+    }
+
+    #[test]
+    fn one_instruction_patching_both_bytes()
+    {
+        let mut cpu = CPU::new(4096, 0x200);
+        cpu.get_mem_mut().write_u16(0x210, 0x6001);
+
+        let mut smc = SmcDetector::new(4096);
+        run(&mut smc, &mut cpu, 0x210, &[]);
+
+        // Like FX55 storing V0-V1 over it: each write is reported with the memory it left behind.
+        run(&mut smc, &mut cpu, 0x204, &[(0x210, 0x61), (0x211, 0x02)]);
+
+        assert_eq!(smc.get_events(), [SmcEvent { kind: EnumSmcKind::CodeOverwritten, writer_pc: 0x204, addr: 0x210, old_opcode: 0x6001, new_opcode: 0x6101 },
+                                      SmcEvent { kind: EnumSmcKind::CodeOverwritten, writer_pc: 0x204, addr: 0x210, old_opcode: 0x6101, new_opcode: 0x6102 }]);
+    }
+
+    #[test]
+    fn executing_written_bytes()
+    {
+        let mut cpu = CPU::new(4096, 0x200);
+        let mut smc = SmcDetector::new(4096);
+
+        // Data writes that are never executed aren't reported.
+        run(&mut smc, &mut cpu, 0x202, &[(0x300, 0x12)]);
+
+        run(&mut smc, &mut cpu, 0x206, &[(0x220, 0x70), (0x221, 0x03)]);
+        run(&mut smc, &mut cpu, 0x220, &[]);
+        run(&mut smc, &mut cpu, 0x220, &[]);
+
+        assert_eq!(smc.get_events(), [SmcEvent { kind: EnumSmcKind::PatchExecuted, writer_pc: 0x206, addr: 0x220, old_opcode: 0x0000, new_opcode: 0x7003 }]);
+    }
+
+    #[test]
+    fn report_groups_repeats()
+    {
+        let mut cpu = CPU::new(4096, 0x200);
+        cpu.get_mem_mut().write_u16(0x210, 0x6001);
+
+        let mut smc = SmcDetector::new(4096);
+        run(&mut smc, &mut cpu, 0x210, &[]);
+
+        for value in [0x05, 0x01, 0x05]
+        {
+            run(&mut smc, &mut cpu, 0x204, &[(0x211, value)]);
+        }
+
+        let symbols = SymbolTable::parse("counter 0x210\n").unwrap();
+        let mut stream = String::new();
+        smc.format_report(&symbols, &mut stream);
+
+        assert_eq!(stream, "Self-modifying code: 3 write(s) into code\n\
+                            \x20 0x204 wrote 0x210 <counter>: 6001 LD V0, 0x01 -> 6005 LD V0, 0x05 (overwrote executed code) x2\n\
+                            \x20 0x204 wrote 0x210 <counter>: 6005 LD V0, 0x05 -> 6001 LD V0, 0x01 (overwrote executed code)\n");
+    }
+}
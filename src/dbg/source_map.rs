@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+/// Where an address came from in the assembler's source.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SourceLocation
+{
+    /// Index into 'SourceMap::get_files'.
+    pub file: usize,
+    pub line: u32,
+}
+
+/// Maps ROM addresses to source lines, one '<addr> <file>:<line>' per line:
+///
+/// ```text
+/// # pong.8o
+/// 0x200 pong.8o:12
+/// 0x202 pong.8o:13
+/// ```
+///
+/// Addresses are hex (the '0x' is optional). Lines starting with '#' are comments.
+#[derive(Debug, Default)]
+pub struct SourceMap
+{
+    files: Vec<String>,
+    locations: BTreeMap<u16, SourceLocation>,
+}
+
+impl SourceMap
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut result = Self::new();
+
+        for (index, raw_line) in text.lines().enumerate()
+        {
+            let line_number = index + 1;
+            let line = raw_line.trim();
+
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+
+            let (addr, location) = line.split_once(char::is_whitespace)
+                .ok_or(format!("line {0}: Expected '<addr> <file>:<line>' but got '{1}'", line_number, line))?;
+
+            let addr = addr.strip_prefix("0x").unwrap_or(addr);
+            let addr = u16::from_str_radix(addr, 16).map_err(|e| format!("line {0}: Invalid address '{1}': {2}", line_number, addr, e))?;
+
+            let (file, source_line) = location.trim().rsplit_once(':')
+                .ok_or(format!("line {0}: Expected '<file>:<line>' but got '{1}'", line_number, location.trim()))?;
+            let source_line = source_line.parse::<u32>().map_err(|e| format!("line {0}: Invalid line number '{1}': {2}", line_number, source_line, e))?;
+
+            result.insert(addr, file, source_line);
+        }
+
+        Ok(result)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String>
+    {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read source map '{0}': {1}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{0}: {1}", path, e))
+    }
+
+    pub fn insert(&mut self, addr: u16, file: &str, line: u32)
+    {
+        let file = match self.files.iter().position(|name| name == file)
+        {
+            Some(index) => index,
+            None =>
+            {
+                self.files.push(String::from(file));
+                self.files.len() - 1
+            },
+        };
+
+        self.locations.insert(addr, SourceLocation { file, line });
+    }
+
+    pub fn get_files(&self) -> &[String]
+    {
+        &self.files
+    }
+
+    pub fn get_location(&self, addr: u16) -> Option<SourceLocation>
+    {
+        self.locations.get(&addr).copied()
+    }
+
+    /// All mapped addresses in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, SourceLocation)> + '_
+    {
+        self.locations.iter().map(|(addr, location)| (*addr, *location))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::source_map::{SourceLocation, SourceMap};
+
+    #[test]
+    fn parse_locations()
+    {
+        let source_map = SourceMap::parse("# test\n0x200 main.8o:3\n202 lib/draw.8o:10\n0x204 main.8o:4\n").unwrap();
+
+        assert_eq!(source_map.get_files(), ["main.8o", "lib/draw.8o"]);
+        assert_eq!(source_map.get_location(0x202), Some(SourceLocation { file: 1, line: 10 }));
+        assert_eq!(source_map.get_location(0x204), Some(SourceLocation { file: 0, line: 4 }));
+        assert_eq!(source_map.get_location(0x206), None);
+        assert_eq!(source_map.iter().count(), 3);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line()
+    {
+        assert!(SourceMap::parse("0x200 main.8o:1\n0x202\n").unwrap_err().starts_with("line 2:"));
+        assert!(SourceMap::parse("0xZZ main.8o:1\n").unwrap_err().contains("Invalid address"));
+        assert!(SourceMap::parse("0x200 main.8o\n").unwrap_err().contains("<file>:<line>"));
+        assert!(SourceMap::parse("0x200 main.8o:x\n").unwrap_err().contains("Invalid line number"));
+    }
+}
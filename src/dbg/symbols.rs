@@ -0,0 +1,263 @@
+use crate::hw::instruction::{disassemble, Instruction};
+
+use std::collections::BTreeMap;
+
+/// A named address, optionally with where the assembler defined it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol
+{
+    pub name: String,
+    pub addr: u16,
+    /// Source file and line.
+    pub location: Option<(String, u32)>,
+}
+
+/// Labels loaded from a symbol file, one '<name> <addr> [<file>:<line>]' per line:
+///
+/// ```text
+/// # name  address  source
+/// main    0x200    pong.8o:1
+/// draw    0x2A4    pong.8o:40
+/// ball    0x3F0
+/// ```
+///
+/// Addresses are decimal or 0x prefixed hex. Lines starting with '#' are comments.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable
+{
+    symbols: Vec<Symbol>,
+    /// Index of the first symbol defined at each address.
+    by_addr: BTreeMap<u16, usize>,
+}
+
+impl SymbolTable
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut result = Self::new();
+
+        for (index, raw_line) in text.lines().enumerate()
+        {
+            let line_number = index + 1;
+            let line = raw_line.trim();
+
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            let (name, addr, opt_location) = match fields.as_slice()
+            {
+                [name, addr] => (*name, *addr, None),
+                [name, addr, location] => (*name, *addr, Some(*location)),
+                _ => { return Err(format!("line {0}: Expected '<name> <addr> [<file>:<line>]' but got '{1}'", line_number, line)); },
+            };
+
+            let addr = parse_addr(addr).ok_or(format!("line {0}: Invalid address '{1}'", line_number, addr))?;
+
+            let location = match opt_location
+            {
+                Some(location) =>
+                {
+                    let (file, source_line) = location.rsplit_once(':')
+                        .ok_or(format!("line {0}: Expected '<file>:<line>' but got '{1}'", line_number, location))?;
+                    let source_line = source_line.parse::<u32>().map_err(|e| format!("line {0}: Invalid line number '{1}': {2}", line_number, source_line, e))?;
+                    Some((String::from(file), source_line))
+                },
+                None => None,
+            };
+
+            result.insert(Symbol { name: String::from(name), addr, location }).map_err(|e| format!("line {0}: {1}", line_number, e))?;
+        }
+
+        Ok(result)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String>
+    {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read symbol file '{0}': {1}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{0}: {1}", path, e))
+    }
+
+    /// Adds a symbol. Names must be unique and must not look like an address or contain a '+'.
+    pub fn insert(&mut self, symbol: Symbol) -> Result<(), String>
+    {
+        if symbol.name.contains('+') || parse_addr(&symbol.name).is_some()
+        {
+            return Err(format!("Invalid symbol name '{}'", symbol.name));
+        }
+
+        if self.get(&symbol.name).is_some()
+        {
+            return Err(format!("Duplicate symbol '{}'", symbol.name));
+        }
+
+        self.by_addr.entry(symbol.addr).or_insert(self.symbols.len());
+        self.symbols.push(symbol);
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.symbols.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol>
+    {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// The symbol defined exactly at 'addr' (the first one, if there are several).
+    pub fn at(&self, addr: u16) -> Option<&Symbol>
+    {
+        self.by_addr.get(&addr).map(|index| &self.symbols[*index])
+    }
+
+    /// The closest symbol at or before 'addr'.
+    pub fn containing(&self, addr: u16) -> Option<&Symbol>
+    {
+        self.by_addr.range(..=addr).next_back().map(|(_, index)| &self.symbols[*index])
+    }
+
+    /// 'label' or 'label+0x4' for the closest symbol at or before 'addr', or None without one.
+    pub fn label(&self, addr: u16) -> Option<String>
+    {
+        let symbol = self.containing(addr)?;
+
+        if symbol.addr == addr
+        {
+            return Some(symbol.name.clone());
+        }
+
+        Some(format!("{0}+0x{1:X}", symbol.name, addr - symbol.addr))
+    }
+
+    /// '0x2A4 <draw+0x4>', or just the address without a symbol.
+    pub fn format_addr(&self, addr: u16) -> String
+    {
+        match self.label(addr)
+        {
+            Some(label) => format!("0x{0:03X} <{1}>", addr, label),
+            None => format!("0x{:03X}", addr),
+        }
+    }
+
+    /// Resolves 'label', 'label+offset' or a plain address.
+    pub fn resolve(&self, text: &str) -> Option<u16>
+    {
+        if let Some(addr) = parse_addr(text)
+        {
+            return Some(addr);
+        }
+
+        let (name, offset) = match text.split_once('+')
+        {
+            Some((name, offset)) => (name, parse_addr(offset)?),
+            None => (text, 0),
+        };
+
+        self.get(name)?.addr.checked_add(offset)
+    }
+
+    /// The disassembly of an opcode, with the label of its target address if it names one exactly.
+    pub fn disassemble(&self, raw_opcode: u16) -> String
+    {
+        let text = disassemble(raw_opcode);
+
+        let target = match Instruction::decode(raw_opcode)
+        {
+            Instruction::Call { nnn } | Instruction::Jump { nnn } | Instruction::SetI { nnn } | Instruction::JumpOffset { nnn, .. } => nnn,
+            _ => { return text; },
+        };
+
+        match self.at(target)
+        {
+            Some(symbol) => format!("{0}  ; {1}", text, symbol.name),
+            None => text,
+        }
+    }
+}
+
+/// Parses a decimal or '0x'-prefixed hexadecimal address.
+fn parse_addr(text: &str) -> Option<u16>
+{
+    match text.strip_prefix("0x").or(text.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse::<u16>().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::symbols::SymbolTable;
+
+    const TEXT: &str = "
+# name address source
+main  0x200  pong.8o:1
+draw  0x2A4  pong.8o:40
+ball  1008
+";
+
+    #[test]
+    fn parse_and_look_up_symbols()
+    {
+        let symbols = SymbolTable::parse(TEXT).unwrap();
+
+        assert_eq!(symbols.get("draw").unwrap().addr, 0x2A4);
+        assert_eq!(symbols.get("draw").unwrap().location, Some((String::from("pong.8o"), 40)));
+        assert_eq!(symbols.get("ball").unwrap().addr, 0x3F0);
+        assert_eq!(symbols.get("ball").unwrap().location, None);
+        assert_eq!(symbols.at(0x200).unwrap().name, "main");
+        assert!(symbols.at(0x202).is_none());
+        assert_eq!(symbols.containing(0x2A8).unwrap().name, "draw");
+        assert!(symbols.containing(0x100).is_none());
+    }
+
+    #[test]
+    fn labels_and_resolving()
+    {
+        let symbols = SymbolTable::parse(TEXT).unwrap();
+
+        assert_eq!(symbols.label(0x2A4).unwrap(), "draw");
+        assert_eq!(symbols.label(0x2A8).unwrap(), "draw+0x4");
+        assert_eq!(symbols.format_addr(0x20C), "0x20C <main+0xC>");
+        assert_eq!(symbols.format_addr(0x1FE), "0x1FE");
+
+        assert_eq!(symbols.resolve("draw"), Some(0x2A4));
+        assert_eq!(symbols.resolve("draw+4"), Some(0x2A8));
+        assert_eq!(symbols.resolve("draw+0x10"), Some(0x2B4));
+        assert_eq!(symbols.resolve("0x300"), Some(0x300));
+        assert_eq!(symbols.resolve("missing"), None);
+    }
+
+    #[test]
+    fn disassembly_names_targets()
+    {
+        let symbols = SymbolTable::parse(TEXT).unwrap();
+
+        assert_eq!(symbols.disassemble(0x22A4), "CALL 0x2A4  ; draw");
+        assert_eq!(symbols.disassemble(0xA3F0), "LD I, 0x3F0  ; ball");
+        assert_eq!(symbols.disassemble(0x1206), "JP 0x206");
+        assert_eq!(symbols.disassemble(0x6001), "LD V0, 0x01");
+    }
+
+    #[test]
+    fn parse_errors_name_the_line()
+    {
+        assert!(SymbolTable::parse("main 0x200\nmain 0x202\n").unwrap_err().contains("line 2: Duplicate symbol 'main'"));
+        assert!(SymbolTable::parse("main\n").unwrap_err().starts_with("line 1:"));
+        assert!(SymbolTable::parse("main 0xZZ\n").unwrap_err().contains("Invalid address"));
+        assert!(SymbolTable::parse("0x10 0x200\n").unwrap_err().contains("Invalid symbol name"));
+        assert!(SymbolTable::parse("main 0x200 pong.8o\n").unwrap_err().contains("<file>:<line>"));
+    }
+}
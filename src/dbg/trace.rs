@@ -0,0 +1,114 @@
+use crate::dbg::symbols::SymbolTable;
+use crate::hw::observer::Observer;
+
+use std::io::Write;
+
+/// Writes one line per executed instruction: its address (with a label if there are symbols), opcode and disassembly.
+pub struct TraceLogger
+{
+    out: Box<dyn Write>,
+    symbols: SymbolTable,
+}
+
+impl TraceLogger
+{
+    pub fn new(out: Box<dyn Write>, symbols: SymbolTable) -> Self
+    {
+        Self { out, symbols }
+    }
+
+    /// Opens (and truncates) a trace file.
+    pub fn create(path: &str, symbols: SymbolTable) -> Result<Self, String>
+    {
+        let file = std::fs::File::create(path).map_err(|e| format!("Failed to create trace file '{0}': {1}", path, e))?;
+        Ok(Self::new(Box::new(std::io::BufWriter::new(file)), symbols))
+    }
+
+    pub fn trace(&mut self, pc: u16, raw_opcode: u16)
+    {
+        let label = self.symbols.label(pc).unwrap_or_default();
+
+        // NOTE: A failing trace file shouldn't stop the emulator.
+        let _ = writeln!(self.out, "0x{0:03X}  {1:<20}  {2:04X}  {3}", pc, label, raw_opcode, self.symbols.disassemble(raw_opcode));
+    }
+}
+
+impl Observer for TraceLogger
+{
+    fn on_fetch(&mut self, pc: u16, raw_opcode: u16)
+    {
+        self.trace(pc, raw_opcode);
+    }
+}
+
+impl Drop for TraceLogger
+{
+    fn drop(&mut self)
+    {
+        let _ = self.out.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::symbols::SymbolTable;
+    use crate::dbg::trace::TraceLogger;
+    use crate::hw::cpu::CPU;
+
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects what the tracer writes so the test can read it back.
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer
+    {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+        {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()>
+        {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_lines_use_labels()
+    {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let symbols = SymbolTable::parse("main 0x200\ndraw 0x2A4\n").unwrap();
+
+        let mut logger = TraceLogger::new(Box::new(SharedBuffer(buffer.clone())), symbols);
+        logger.trace(0x200, 0x22A4);
+        logger.trace(0x2A6, 0x7001);
+        drop(logger);
+
+        let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        assert_eq!(text, "0x200  main                  22A4  CALL 0x2A4  ; draw\n0x2A6  draw+0x2              7001  ADD V0, 0x01\n");
+    }
+
+    #[test]
+    fn logs_fetched_instructions_as_an_observer()
+    {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = CPU::new(4096, 0x200);
+        let id = cpu.add_observer(Box::new(TraceLogger::new(Box::new(SharedBuffer(buffer.clone())), SymbolTable::new())));
+
+        // V0 = 1, halt
+        cpu.get_mem_mut().write_u16(0x200, 0x6001);
+
+        while !cpu.is_halted()
+        {
+            assert!(cpu.tick().is_ok());
+        }
+
+        drop(cpu.remove_observer(id));
+
+        let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        assert_eq!(text, "0x200                        6001  LD V0, 0x01\n0x202                        0000  HALT\n");
+    }
+}
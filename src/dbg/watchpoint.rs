@@ -0,0 +1,229 @@
+/// The kind of memory access performed by an instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumAccess
+{
+    Read,
+    Write,
+}
+
+impl EnumAccess
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Self::Read => "read",
+            Self::Write => "write",
+        }
+    }
+}
+
+/// The kind of memory access a watchpoint triggers on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumWatchKind
+{
+    Read,
+    Write,
+    Access,
+}
+
+impl EnumWatchKind
+{
+    pub fn matches(&self, access: EnumAccess) -> bool
+    {
+        match self
+        {
+            Self::Read => access == EnumAccess::Read,
+            Self::Write => access == EnumAccess::Write,
+            Self::Access => true,
+        }
+    }
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Access => "access",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint
+{
+    pub id: u32,
+    pub kind: EnumWatchKind,
+    pub start: usize,
+    pub end: usize, // NOTE: inclusive.
+    pub value: Option<u8>,
+}
+
+impl Watchpoint
+{
+    pub fn matches(&self, access: EnumAccess, addr: usize, value: u8) -> bool
+    {
+        if !self.kind.matches(access) || addr < self.start || addr > self.end
+        {
+            return false;
+        }
+
+        match self.value
+        {
+            Some(expected) => expected == value,
+            None => true,
+        }
+    }
+}
+
+/// Details about the memory access that triggered a watchpoint.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WatchHit
+{
+    pub id: u32,
+    pub access: EnumAccess,
+    pub addr: usize,
+    pub old_value: u8,
+    pub value: u8,
+    pub pc: u16,
+    pub opcode: u16,
+}
+
+impl std::fmt::Display for WatchHit
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "Watchpoint {0} hit: {1} of 0x{2:03X} ", self.id, self.access.name(), self.addr)?;
+
+        match self.access
+        {
+            EnumAccess::Read => write!(f, "(value 0x{0:02X})", self.value)?,
+            EnumAccess::Write => write!(f, "(0x{0:02X} -> 0x{1:02X})", self.old_value, self.value)?,
+        }
+
+        write!(f, " by instruction 0x{0:04X} at pc 0x{1:03X}", self.opcode, self.pc)
+    }
+}
+
+pub struct WatchList
+{
+    watchpoints: Vec<Watchpoint>,
+    next_id: u32,
+}
+
+impl WatchList
+{
+    pub fn new() -> Self
+    {
+        Self { watchpoints: Vec::new(), next_id: 1 }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.watchpoints.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Watchpoint>
+    {
+        self.watchpoints.iter()
+    }
+
+    /// Adds a watchpoint over the inclusive range [start, end] and returns its id.
+    pub fn add(&mut self, kind: EnumWatchKind, start: usize, end: usize, value: Option<u8>) -> u32
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.push(Watchpoint { id, kind, start: start.min(end), end: start.max(end), value });
+
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool
+    {
+        let len_before = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+
+        self.watchpoints.len() != len_before
+    }
+
+    /// Returns the id of the first watchpoint matching this access, if any.
+    pub fn check(&self, access: EnumAccess, addr: usize, value: u8) -> Option<u32>
+    {
+        self.watchpoints.iter()
+            .find(|watchpoint| watchpoint.matches(access, addr, value))
+            .map(|watchpoint| watchpoint.id)
+    }
+}
+
+impl Default for WatchList
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::watchpoint::{EnumAccess, EnumWatchKind, WatchList};
+
+    #[test]
+    fn empty_list_never_matches()
+    {
+        let watch_list = WatchList::new();
+        assert!(watch_list.is_empty());
+        assert!(watch_list.check(EnumAccess::Read, 0x200, 0).is_none());
+        assert!(watch_list.check(EnumAccess::Write, 0x200, 0).is_none());
+    }
+
+    #[test]
+    fn kind_filters_access()
+    {
+        let mut watch_list = WatchList::new();
+        let read_id = watch_list.add(EnumWatchKind::Read, 0x300, 0x300, None);
+        let write_id = watch_list.add(EnumWatchKind::Write, 0x301, 0x301, None);
+        let access_id = watch_list.add(EnumWatchKind::Access, 0x302, 0x302, None);
+
+        assert_eq!(watch_list.check(EnumAccess::Read, 0x300, 0), Some(read_id));
+        assert!(watch_list.check(EnumAccess::Write, 0x300, 0).is_none());
+        assert_eq!(watch_list.check(EnumAccess::Write, 0x301, 0), Some(write_id));
+        assert!(watch_list.check(EnumAccess::Read, 0x301, 0).is_none());
+        assert_eq!(watch_list.check(EnumAccess::Read, 0x302, 0), Some(access_id));
+        assert_eq!(watch_list.check(EnumAccess::Write, 0x302, 0), Some(access_id));
+    }
+
+    #[test]
+    fn range_is_inclusive()
+    {
+        let mut watch_list = WatchList::new();
+        let id = watch_list.add(EnumWatchKind::Access, 0x310, 0x300, None);
+
+        assert!(watch_list.check(EnumAccess::Read, 0x2FF, 0).is_none());
+        assert_eq!(watch_list.check(EnumAccess::Read, 0x300, 0), Some(id));
+        assert_eq!(watch_list.check(EnumAccess::Read, 0x310, 0), Some(id));
+        assert!(watch_list.check(EnumAccess::Read, 0x311, 0).is_none());
+    }
+
+    #[test]
+    fn value_condition_must_match()
+    {
+        let mut watch_list = WatchList::new();
+        let id = watch_list.add(EnumWatchKind::Write, 0x300, 0x300, Some(0x42));
+
+        assert!(watch_list.check(EnumAccess::Write, 0x300, 0x41).is_none());
+        assert_eq!(watch_list.check(EnumAccess::Write, 0x300, 0x42), Some(id));
+    }
+
+    #[test]
+    fn remove_watchpoint()
+    {
+        let mut watch_list = WatchList::new();
+        let id = watch_list.add(EnumWatchKind::Access, 0x300, 0x300, None);
+
+        assert!(watch_list.remove(id));
+        assert!(!watch_list.remove(id));
+        assert!(watch_list.is_empty());
+    }
+}
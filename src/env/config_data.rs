@@ -1,211 +1,1960 @@
+use crate::bench::{find_workload, EnumBenchFormat};
+use crate::env::config_file::{ConfigEntry, ConfigFile, EnumSection};
+use crate::hw::cpu::EnumDumpFormat;
+use crate::hw::display::Palette;
+use crate::hw::fault::{EnumFaultPolicy, EnumInvalidPolicy};
+use crate::hw::frame::{EnumSpeed, FrameConfig};
+use crate::hw::keypad::KeyMap;
+use crate::hw::mem::parse_byte_pattern;
+use crate::hw::quirks::Quirks;
+use crate::rom::container::{decode_rom, flatten, read_rom_file};
+use crate::rom::crc32::crc32;
+use crate::rom::database::{EnumPlatform, RomDatabase, RomEntry};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const DEFAULT_MEM_SIZE: usize = 4096;
+pub const DEFAULT_STARTING_PC: u16 = 0x200;
+pub const DEFAULT_BENCH_CYCLES: u64 = 10_000_000;
+
+/// The most memory a 16-bit program counter can address.
+const MAX_MEM_SIZE: usize = 0x10000;
+
+/// A command-line option. Config files use the same 'name' (without the leading "--").
+struct OptionSpec
+{
+    name: &'static str,
+    short: Option<char>,
+    /// Placeholder for the value in the help text, or None for flags (which are 'true'/'false' in a config file).
+    value: Option<&'static str>,
+    help: &'static str,
+    /// Only makes sense on the command line.
+    cli_only: bool,
+    /// Only accepted by this subcommand (None for options every mode accepts).
+    command: Option<EnumCommand>,
+}
+
+/// What the binary was asked to do, selected by the first arg.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumCommand
+{
+    /// Run a ROM (the default).
+    Run,
+    /// 'hchip8 bench': run the built-in workloads and report throughput.
+    Bench,
+    /// 'hchip8 cfg': print a ROM's static control-flow graph as Graphviz DOT.
+    Cfg,
+    /// 'hchip8 info': print a ROM's hashes and what the ROM database knows about it.
+    Info,
+    /// 'hchip8 octo': compile Octo source into a ROM.
+    Octo,
+}
+
+impl EnumCommand
+{
+    fn from_arg(arg: &str) -> Option<Self>
+    {
+        match arg
+        {
+            "run" => Some(Self::Run),
+            "bench" => Some(Self::Bench),
+            "cfg" => Some(Self::Cfg),
+            "info" => Some(Self::Info),
+            "octo" => Some(Self::Octo),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Self::Run => "run",
+            Self::Bench => "bench",
+            Self::Cfg => "cfg",
+            Self::Info => "info",
+            Self::Octo => "octo",
+        }
+    }
+}
+
+const OPTIONS: [OptionSpec; 45] = [
+    OptionSpec { name: "help", short: Some('h'), value: None, help: "Show this help and exit", cli_only: true, command: None },
+    OptionSpec { name: "version", short: Some('V'), value: None, help: "Show the version and exit", cli_only: true, command: None },
+    OptionSpec { name: "rom", short: Some('r'), value: Some("<path>"), help: "ROM to load (can also be given as the only positional arg)", cli_only: true, command: None },
+    OptionSpec { name: "config", short: Some('c'), value: Some("<path>"), help: "Read options and per-ROM profiles from a config file", cli_only: true, command: None },
+    OptionSpec { name: "print-config", short: None, value: None, help: "Show the effective configuration and exit", cli_only: true, command: None },
+    OptionSpec { name: "mem-size", short: Some('m'), value: Some("<bytes>"), help: "Size of main memory (default 4096, max 65536)", cli_only: false, command: None },
+    OptionSpec { name: "pc", short: Some('p'), value: Some("<addr>"), help: "Starting pc, where the ROM is loaded (default 0x200)", cli_only: false, command: None },
+    OptionSpec { name: "cpu-hz", short: None, value: Some("<hz>"), help: "Instructions per second at 1x speed (default 600)", cli_only: false, command: None },
+    OptionSpec { name: "ipf", short: None, value: Some("<count>"), help: "Instructions per 60 Hz frame (default 10)", cli_only: false, command: None },
+    OptionSpec { name: "speed", short: Some('s'), value: Some("<mult|turbo>"), help: "Speed multiplier from 0.01 to 1000, e.g. 2 or 0.5x, or turbo", cli_only: false, command: None },
+    OptionSpec { name: "turbo", short: Some('t'), value: None, help: "Run as fast as possible (same as --speed turbo)", cli_only: false, command: None },
+    OptionSpec { name: "quirks", short: Some('q'), value: Some("<list>"), help: "chip8, schip, none or e.g. \"shift-vy, wrap\"", cli_only: false, command: None },
+    OptionSpec { name: "palette", short: None, value: Some("<fg bg>"), help: "Render in color, e.g. \"#FFB000 #1A1A1A\"", cli_only: false, command: None },
+    OptionSpec { name: "keys", short: None, value: Some("<keys>"), help: "Host keys for keypad keys 0-F (default x123qweasdzc4rfv)", cli_only: false, command: None },
+    OptionSpec { name: "on-fault", short: None, value: Some("<policy>"), help: "halt, ignore or break (default halt)", cli_only: false, command: None },
+    OptionSpec { name: "on-invalid", short: None, value: Some("<policy>"), help: "Unknown opcodes: halt, ignore, break or nop (default halt)", cli_only: false, command: None },
+    OptionSpec { name: "zero-halts", short: None, value: None, help: "0000 halts the CPU (default true; otherwise it's an unknown opcode)", cli_only: false, command: None },
+    OptionSpec { name: "decode-cache", short: None, value: None, help: "Cache decoded instructions (default false; same results, only faster)", cli_only: false, command: None },
+    OptionSpec { name: "headless", short: None, value: None, help: "Don't draw the display", cli_only: false, command: None },
+    OptionSpec { name: "debug", short: Some('d'), value: None, help: "Start paused in the debugger", cli_only: false, command: None },
+    OptionSpec { name: "debug-script", short: None, value: Some("<path>"), help: "Run debugger commands from a file (asserts set the exit code)", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "cheats", short: None, value: Some("<path>"), help: "Apply the freeze and patch codes in a cheat file", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "rom-db", short: None, value: Some("<path>"), help: "Extend the built-in ROM database with a local one", cli_only: true, command: None },
+    OptionSpec { name: "identify", short: None, value: None, help: "Apply the ROM database's quirks and speed for the ROM (default true)", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "patch", short: None, value: Some("<path>"), help: "Apply an IPS or BPS patch to the ROM (repeatable, applied in order)", cli_only: true, command: None },
+    OptionSpec { name: "symbols", short: None, value: Some("<path>"), help: "Label addresses in the debugger, traces and disassembly", cli_only: true, command: None },
+    OptionSpec { name: "trace", short: None, value: Some("<path>"), help: "Write every executed instruction to a file", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "profile", short: None, value: None, help: "Count instructions per address and subroutine, report at exit", cli_only: false, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "profile-out", short: None, value: Some("<path>"), help: "Profile and write collapsed stacks for flamegraph tools", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "coverage", short: None, value: None, help: "Report which ROM bytes were executed, read or written at exit", cli_only: false, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "smc", short: None, value: None, help: "Report writes into code (self-modifying code) live in the debugger and at exit", cli_only: false, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "source-map", short: None, value: Some("<path>"), help: "Address to source line map from the assembler", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "lcov", short: None, value: Some("<path>"), help: "Write line coverage in lcov format (needs --source-map)", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "dump-format", short: None, value: Some("<format>"), help: "CPU state dump at halt and in the debugger: text or json (default text)", cli_only: false, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "hexdump", short: None, value: Some("<start-end>"), help: "Hexdump a memory range at exit, e.g. 0x200-0x2FF", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "find", short: None, value: Some("<bytes>"), help: "Find a byte pattern in memory at exit, e.g. 'A2 ?? 60'", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "mem-diff", short: None, value: None, help: "Show the bytes that changed since the ROM was loaded at exit", cli_only: true, command: Some(EnumCommand::Run) },
+    OptionSpec { name: "call-graph", short: None, value: None, help: "Print the call graph instead of the basic blocks", cli_only: true, command: Some(EnumCommand::Cfg) },
+    OptionSpec { name: "cycles", short: None, value: Some("<count>"), help: "Instructions to run per workload (default 10000000)", cli_only: true, command: Some(EnumCommand::Bench) },
+    OptionSpec { name: "workload", short: Some('w'), value: Some("<name>"), help: "Only run this workload (alu, call, draw or memcopy)", cli_only: true, command: Some(EnumCommand::Bench) },
+    OptionSpec { name: "format", short: Some('f'), value: Some("<format>"), help: "text or json (default text)", cli_only: true, command: Some(EnumCommand::Bench) },
+    OptionSpec { name: "out", short: Some('o'), value: Some("<path>"), help: "Where to write the ROM (default: the source with a .ch8 extension)", cli_only: true, command: Some(EnumCommand::Octo) },
+    OptionSpec { name: "target", short: None, value: Some("<platform>"), help: "chip8, schip or xochip (default chip8)", cli_only: true, command: Some(EnumCommand::Octo) },
+    OptionSpec { name: "symbols-out", short: None, value: Some("<path>"), help: "Write the labels as a symbol file for --symbols", cli_only: true, command: Some(EnumCommand::Octo) },
+    OptionSpec { name: "source-map-out", short: None, value: Some("<path>"), help: "Write a source map for --source-map", cli_only: true, command: Some(EnumCommand::Octo) },
+];
+
+fn find_option(name: &str) -> Option<&'static OptionSpec>
+{
+    OPTIONS.iter().find(|option| option.name == name)
+}
+
+fn find_short_option(short: char) -> Option<&'static OptionSpec>
+{
+    OPTIONS.iter().find(|option| option.short == Some(short))
+}
+
+/// Parses a decimal or '0x' prefixed hex number in 'min..=max'.
+fn parse_number(text: &str, min: u64, max: u64) -> Result<u64, String>
+{
+    let value = match text.strip_prefix("0x").or(text.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse::<u64>(),
+    };
+
+    match value
+    {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!("Expected a decimal or 0x hex number from {0} to {1}", min, max)),
+    }
+}
+
+/// One command-line arg, with the value split off an inline '--opt=value'.
+enum EnumArg<'a>
+{
+    Option(&'static OptionSpec, Option<&'a str>),
+    Positional,
+}
+
 pub struct ConfigData
 {
     args: Vec<String>,
-    mem_size: u16,
+    mem_size: usize,
     starting_pc: u16,
+    debug: bool,
+    frame_config: FrameConfig,
+    headless: bool,
+    decode_cache: bool,
+    fault_policy: EnumFaultPolicy,
+    invalid_policy: EnumInvalidPolicy,
+    zero_halts: bool,
+    rom_path: Option<String>,
+    config_path: Option<String>,
+    print_config: bool,
+    quirks: Quirks,
+    palette: Option<Palette>,
+    key_map: KeyMap,
+    /// The config file sections that were applied, in order.
+    profiles: Vec<String>,
+    show_help: bool,
+    show_version: bool,
+    command: EnumCommand,
+    symbols_path: Option<String>,
+    trace_path: Option<String>,
+    debug_script_path: Option<String>,
+    cheats_path: Option<String>,
+    patch_paths: Vec<String>,
+    rom_db_path: Option<String>,
+    identify: bool,
+    /// The ROM database entry for the ROM, if it was identified.
+    rom_entry: Option<RomEntry>,
+    /// Options set by '[rom:..]' and '[crc32:..]' config sections, which the ROM database doesn't override.
+    rom_profile_options: Vec<String>,
+    profile: bool,
+    profile_out: Option<String>,
+    coverage: bool,
+    source_map_path: Option<String>,
+    lcov_path: Option<String>,
+    call_graph: bool,
+    /// Inclusive range.
+    hexdump_range: Option<(usize, usize)>,
+    find_pattern: Option<Vec<Option<u8>>>,
+    mem_diff: bool,
+    dump_format: EnumDumpFormat,
+    smc: bool,
+    bench_cycles: u64,
+    bench_workload: Option<String>,
+    bench_format: EnumBenchFormat,
+    octo_out: Option<String>,
+    octo_target: EnumPlatform,
+    symbols_out: Option<String>,
+    source_map_out: Option<String>,
 }
 
-impl ConfigData
-{
-    pub fn new(args: Vec<String>) -> Self
-    {
-        Self { args, starting_pc: 0, mem_size: 0 }
+impl ConfigData
+{
+    pub fn new(args: Vec<String>) -> Self
+    {
+        Self {
+            args,
+            starting_pc: DEFAULT_STARTING_PC,
+            mem_size: DEFAULT_MEM_SIZE,
+            debug: false,
+            frame_config: FrameConfig::default(),
+            headless: false,
+            decode_cache: false,
+            fault_policy: EnumFaultPolicy::Halt,
+            invalid_policy: EnumInvalidPolicy::Halt,
+            zero_halts: true,
+            rom_path: None,
+            config_path: None,
+            print_config: false,
+            quirks: Quirks::default(),
+            palette: None,
+            key_map: KeyMap::new(),
+            profiles: Vec::new(),
+            show_help: false,
+            show_version: false,
+            command: EnumCommand::Run,
+            symbols_path: None,
+            trace_path: None,
+            debug_script_path: None,
+            cheats_path: None,
+            patch_paths: Vec::new(),
+            rom_db_path: None,
+            identify: true,
+            rom_entry: None,
+            rom_profile_options: Vec::new(),
+            profile: false,
+            profile_out: None,
+            coverage: false,
+            source_map_path: None,
+            lcov_path: None,
+            call_graph: false,
+            hexdump_range: None,
+            find_pattern: None,
+            mem_diff: false,
+            dump_format: EnumDumpFormat::Text,
+            smc: false,
+            bench_cycles: DEFAULT_BENCH_CYCLES,
+            bench_workload: None,
+            bench_format: EnumBenchFormat::Text,
+            octo_out: None,
+            octo_target: EnumPlatform::Chip8,
+            symbols_out: None,
+            source_map_out: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_mem_size(&self) -> usize
+    {
+        self.mem_size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_starting_pc(&self) -> u16
+    {
+        self.starting_pc
+    }
+
+    #[allow(dead_code)]
+    pub fn is_debug(&self) -> bool
+    {
+        self.debug
+    }
+
+    #[allow(dead_code)]
+    pub fn get_frame_config(&self) -> &FrameConfig
+    {
+        &self.frame_config
+    }
+
+    #[allow(dead_code)]
+    pub fn is_headless(&self) -> bool
+    {
+        self.headless
+    }
+
+    #[allow(dead_code)]
+    pub fn is_decode_cache(&self) -> bool
+    {
+        self.decode_cache
+    }
+
+    #[allow(dead_code)]
+    pub fn get_symbols_path(&self) -> Option<&str>
+    {
+        self.symbols_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_trace_path(&self) -> Option<&str>
+    {
+        self.trace_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_debug_script_path(&self) -> Option<&str>
+    {
+        self.debug_script_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_cheats_path(&self) -> Option<&str>
+    {
+        self.cheats_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_rom_db_path(&self) -> Option<&str>
+    {
+        self.rom_db_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_identify(&self) -> bool
+    {
+        self.identify
+    }
+
+    /// What the ROM database knows about the ROM (only looked up for 'run' with '--identify').
+    #[allow(dead_code)]
+    pub fn get_rom_entry(&self) -> Option<&RomEntry>
+    {
+        self.rom_entry.as_ref()
+    }
+
+    /// The built-in ROM database, extended with '--rom-db'.
+    pub fn load_rom_database(&self) -> Result<RomDatabase, String>
+    {
+        let mut database = RomDatabase::builtin();
+
+        if let Some(rom_db_path) = &self.rom_db_path
+        {
+            database.extend(RomDatabase::load(rom_db_path)?);
+        }
+
+        Ok(database)
+    }
+
+    /// The ROM as it ends up in memory: patched, decoded and laid out from its lowest address. This is what ROMs
+    /// are identified by, so a ROM is known however it's stored and a patched one isn't mistaken for the original.
+    pub fn read_rom_image(&self) -> Result<Vec<u8>, String>
+    {
+        let rom_path = self.rom_path.as_deref().ok_or(String::from("No ROM given"))?;
+        let rom = read_rom_file(rom_path, &self.patch_paths)?;
+        let (_, segments) = decode_rom(&rom, self.starting_pc as usize).map_err(|e| format!("Invalid ROM file: {}", e))?;
+
+        flatten(&segments, self.mem_size).ok_or(format!("'{0}' doesn't fit in main memory ({1} bytes)", rom_path, self.mem_size))
+    }
+
+    /// The '--patch' files, in the order they are applied.
+    #[allow(dead_code)]
+    pub fn get_patch_paths(&self) -> &[String]
+    {
+        &self.patch_paths
+    }
+
+    /// True if '--profile' or '--profile-out' was given.
+    #[allow(dead_code)]
+    pub fn is_profile(&self) -> bool
+    {
+        self.profile || self.profile_out.is_some()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_profile_out(&self) -> Option<&str>
+    {
+        self.profile_out.as_deref()
+    }
+
+    /// True if '--coverage' or '--lcov' was given.
+    #[allow(dead_code)]
+    pub fn is_coverage(&self) -> bool
+    {
+        self.coverage || self.lcov_path.is_some()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_source_map_path(&self) -> Option<&str>
+    {
+        self.source_map_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_lcov_path(&self) -> Option<&str>
+    {
+        self.lcov_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_call_graph(&self) -> bool
+    {
+        self.call_graph
+    }
+
+    /// The '--hexdump' range as a half-open range.
+    #[allow(dead_code)]
+    pub fn get_hexdump_range(&self) -> Option<std::ops::Range<usize>>
+    {
+        self.hexdump_range.map(|(start, end)| start..end + 1)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_find_pattern(&self) -> Option<&[Option<u8>]>
+    {
+        self.find_pattern.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_mem_diff(&self) -> bool
+    {
+        self.mem_diff
+    }
+
+    #[allow(dead_code)]
+    pub fn is_smc(&self) -> bool
+    {
+        self.smc
+    }
+
+    #[allow(dead_code)]
+    pub fn get_dump_format(&self) -> EnumDumpFormat
+    {
+        self.dump_format
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fault_policy(&self) -> EnumFaultPolicy
+    {
+        self.fault_policy
+    }
+
+    #[allow(dead_code)]
+    pub fn get_invalid_policy(&self) -> EnumInvalidPolicy
+    {
+        self.invalid_policy
+    }
+
+    #[allow(dead_code)]
+    pub fn is_zero_halts(&self) -> bool
+    {
+        self.zero_halts
+    }
+
+    #[allow(dead_code)]
+    pub fn get_rom_path(&self) -> Option<&str>
+    {
+        self.rom_path.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_print_config(&self) -> bool
+    {
+        self.print_config
+    }
+
+    #[allow(dead_code)]
+    pub fn get_quirks(&self) -> Quirks
+    {
+        self.quirks
+    }
+
+    /// The colors to render with, if any were configured (otherwise the display is rendered as plain text).
+    #[allow(dead_code)]
+    pub fn get_palette(&self) -> Option<&Palette>
+    {
+        self.palette.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_key_map(&self) -> &KeyMap
+    {
+        &self.key_map
+    }
+
+    pub fn get_command(&self) -> EnumCommand
+    {
+        self.command
+    }
+
+    /// Instructions to run per workload in bench mode.
+    #[allow(dead_code)]
+    pub fn get_bench_cycles(&self) -> u64
+    {
+        self.bench_cycles
+    }
+
+    /// The one workload to run in bench mode, or None for all of them.
+    #[allow(dead_code)]
+    pub fn get_bench_workload(&self) -> Option<&str>
+    {
+        self.bench_workload.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bench_format(&self) -> EnumBenchFormat
+    {
+        self.bench_format
+    }
+
+    /// Where 'hchip8 octo' writes the ROM: '--out', or the source path with a '.ch8' extension.
+    #[allow(dead_code)]
+    pub fn get_octo_out(&self) -> String
+    {
+        match &self.octo_out
+        {
+            Some(out) => out.clone(),
+            None => std::path::Path::new(self.rom_path.as_deref().unwrap_or_default()).with_extension("ch8").to_string_lossy().into_owned(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_octo_target(&self) -> EnumPlatform
+    {
+        self.octo_target
+    }
+
+    #[allow(dead_code)]
+    pub fn get_symbols_out(&self) -> Option<&str>
+    {
+        self.symbols_out.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_source_map_out(&self) -> Option<&str>
+    {
+        self.source_map_out.as_deref()
+    }
+
+    pub fn is_help(&self) -> bool
+    {
+        self.show_help
+    }
+
+    #[allow(dead_code)]
+    pub fn is_version(&self) -> bool
+    {
+        self.show_version
+    }
+
+    /// The usage text for '--help', generated from the option table.
+    pub fn help_text() -> String
+    {
+        let mut text = format!("hchip8 {}: a CHIP-8 interpreter\n\nUsage: hchip8 [run] [options] [rom]\n       hchip8 bench [options]\n       hchip8 cfg [options] <rom>\n       hchip8 info [options] <rom>\n       hchip8 octo [options] <source>\n\nOptions:\n", VERSION);
+
+        for option in OPTIONS.iter()
+        {
+            if let Some(command) = option.command
+            {
+                text.push_str(&format!("  {0:<30}{1} ({2} only)\n", format!("    --{0} {1}", option.name, option.value.unwrap_or_default()), option.help, command.name()));
+                continue;
+            }
+
+            let short = option.short.map(|short| format!("-{},", short)).unwrap_or_default();
+            let long = format!("--{0} {1}", option.name, option.value.unwrap_or_default());
+            text.push_str(&format!("  {0:<4}{1:<26}{2}\n", short, long, option.help));
+        }
+
+        text.push_str("\nNumbers can be decimal or 0x prefixed hex. Values can also be given as --opt=value.\n");
+
+        text
+    }
+
+    /// Options that set the same thing share a name, so e.g. '--cpu-hz' on the command line overrides 'ipf' in a file.
+    fn canonical_name(name: &str) -> &str
+    {
+        match name
+        {
+            "cpu-hz" => "ipf",
+            "turbo" => "speed",
+            _ => name,
+        }
+    }
+
+    /// Applies one option from either the command line or a config file. Flags get a value of "true" or "false".
+    /// On failure, returns why the value was rejected.
+    fn apply_option(&mut self, name: &str, value: &str) -> Result<(), String>
+    {
+        fn parse<T>(value: &str) -> Result<T, String>
+            where T: std::str::FromStr, T::Err: std::fmt::Display
+        {
+            value.parse::<T>().map_err(|e| e.to_string())
+        }
+
+        match name
+        {
+            "help" => { self.show_help = parse(value)?; },
+            "version" => { self.show_version = parse(value)?; },
+            "mem-size" => { self.mem_size = parse_number(value, 1, MAX_MEM_SIZE as u64)? as usize; },
+            "pc" => { self.starting_pc = parse_number(value, 0, u16::MAX as u64)? as u16; },
+            "debug" => { self.debug = parse(value)?; },
+            "cpu-hz" | "ipf" =>
+            {
+                let speed = self.frame_config.get_speed();
+
+                let val = parse_number(value, 1, u32::MAX as u64)? as u32;
+
+                if name == "cpu-hz"
+                {
+                    self.frame_config = FrameConfig::from_cpu_hz(val);
+                }
+
+                else
+                {
+                    self.frame_config = FrameConfig::new(val);
+                }
+
+                self.frame_config.set_speed(speed);
+            },
+            "speed" => { self.frame_config.set_speed(parse(value)?); },
+            "turbo" =>
+            {
+                if parse(value)?
+                {
+                    self.frame_config.set_speed(EnumSpeed::Turbo);
+                }
+            },
+            "headless" => { self.headless = parse(value)?; },
+            "decode-cache" => { self.decode_cache = parse(value)?; },
+            "rom" => { self.rom_path = Some(String::from(value)); },
+            "on-fault" => { self.fault_policy = parse(value)?; },
+            "on-invalid" => { self.invalid_policy = parse(value)?; },
+            "zero-halts" => { self.zero_halts = parse(value)?; },
+            "config" => { self.config_path = Some(String::from(value)); },
+            "print-config" => { self.print_config = parse(value)?; },
+            "quirks" => { self.quirks = parse(value)?; },
+            "palette" => { self.palette = if value == "none" { None } else { Some(parse(value)?) }; },
+            "keys" => { self.key_map = parse(value)?; },
+            "symbols" => { self.symbols_path = Some(String::from(value)); },
+            "trace" => { self.trace_path = Some(String::from(value)); },
+            "debug-script" => { self.debug_script_path = Some(String::from(value)); },
+            "cheats" => { self.cheats_path = Some(String::from(value)); },
+            "patch" => { self.patch_paths.push(String::from(value)); },
+            "rom-db" => { self.rom_db_path = Some(String::from(value)); },
+            "identify" => { self.identify = parse(value)?; },
+            "profile" => { self.profile = parse(value)?; },
+            "profile-out" => { self.profile_out = Some(String::from(value)); },
+            "coverage" => { self.coverage = parse(value)?; },
+            "source-map" => { self.source_map_path = Some(String::from(value)); },
+            "lcov" => { self.lcov_path = Some(String::from(value)); },
+            "call-graph" => { self.call_graph = parse(value)?; },
+            "hexdump" =>
+            {
+                let (start, end) = value.split_once('-').ok_or(String::from("Expected '<start>-<end>'"))?;
+                let start = parse_number(start, 0, MAX_MEM_SIZE as u64)? as usize;
+                let end = parse_number(end, start as u64, MAX_MEM_SIZE as u64)? as usize;
+                self.hexdump_range = Some((start, end));
+            },
+            "find" => { self.find_pattern = Some(parse_byte_pattern(value)?); },
+            "mem-diff" => { self.mem_diff = parse(value)?; },
+            "dump-format" => { self.dump_format = parse(value)?; },
+            "smc" => { self.smc = parse(value)?; },
+            "cycles" => { self.bench_cycles = parse_number(value, 1, u64::MAX)?; },
+            "workload" =>
+            {
+                find_workload(value).ok_or(String::from("Unknown workload (expected alu, call, draw or memcopy)"))?;
+                self.bench_workload = Some(String::from(value));
+            },
+            "format" => { self.bench_format = parse(value)?; },
+            "out" => { self.octo_out = Some(String::from(value)); },
+            "target" => { self.octo_target = parse(value)?; },
+            "symbols-out" => { self.symbols_out = Some(String::from(value)); },
+            "source-map-out" => { self.source_map_out = Some(String::from(value)); },
+            _ => { return Err(format!("Unknown option '{}'", name)); },
+        }
+
+        Ok(())
+    }
+
+    /// Applies the ROM database's settings for the ROM, skipping anything already set on the command line or by a
+    /// ROM specific config section. Runs after the config file, so the ROM is laid out with the final memory size and pc.
+    fn apply_rom_database(&mut self, cli_options: &[String]) -> Result<(), (i32, String)>
+    {
+        let database = self.load_rom_database().map_err(|e| (-4, e))?;
+
+        // NOTE: As with '[crc32:..]' sections, a ROM that can't be loaded simply isn't identified; loading it reports the error later.
+        let entry = match self.read_rom_image()
+        {
+            Ok(rom) => database.identify(&rom).cloned(),
+            Err(_) => None,
+        };
+
+        let entry = match entry
+        {
+            Some(entry) => entry,
+            None => { return Ok(()); },
+        };
+
+        for option_entry in &entry.options
+        {
+            let name = option_entry.key.as_str();
+
+            match find_option(name)
+            {
+                Some(option) if !option.cli_only => {},
+                _ => { return Err((-4, format!("{0}:{1}: '{2}' can't be set from the ROM database", entry.source, option_entry.line, name))); },
+            }
+
+            let canonical_name = Self::canonical_name(name);
+
+            if cli_options.iter().chain(&self.rom_profile_options).any(|set_name| set_name == canonical_name)
+            {
+                continue;
+            }
+
+            self.apply_option(name, &option_entry.value)
+                .map_err(|e| (-4, format!("{0}:{1}: Invalid value '{2}' for '{3}': {4}", entry.source, option_entry.line, option_entry.value, name, e)))?;
+        }
+
+        self.rom_entry = Some(entry);
+
+        Ok(())
+    }
+
+    /// Applies the config file sections that match the ROM, skipping anything already set on the command line.
+    fn apply_config_file(&mut self, config_path: &str, cli_options: &[String]) -> Result<(), (i32, String)>
+    {
+        let config_file = ConfigFile::load(config_path).map_err(|e| (-4, e))?;
+
+        let rom_name = self.rom_path.as_deref()
+            .and_then(|rom_path| std::path::Path::new(rom_path).file_name())
+            .map(|file_name| file_name.to_string_lossy().into_owned());
+
+        self.apply_config_sections(config_path, config_file.matching_sections(rom_name.as_deref(), None), cli_options)?;
+
+        // '[crc32:..]' sections come last anyway, so the ROM is hashed as laid out by '[global]' and '[rom:..]'.
+        // NOTE: A ROM that can't be loaded simply matches no hash; loading it reports the error later.
+        let rom_crc32 = match &self.rom_path
+        {
+            Some(_) if config_file.has_crc32_sections() => self.read_rom_image().ok().map(|rom| crc32(&rom)),
+            _ => None,
+        };
+
+        let crc32_sections = config_file.matching_sections(None, rom_crc32).into_iter()
+            .filter(|(section, _)| matches!(section, EnumSection::RomCrc32(_)))
+            .collect();
+
+        self.apply_config_sections(config_path, crc32_sections, cli_options)
+    }
+
+    fn apply_config_sections(&mut self, config_path: &str, sections: Vec<(&EnumSection, &[ConfigEntry])>, cli_options: &[String]) -> Result<(), (i32, String)>
+    {
+        for (section, entries) in sections
+        {
+            for entry in entries
+            {
+                let name = entry.key.as_str();
+
+                let option = match find_option(name)
+                {
+                    Some(option) => option,
+                    None => { return Err((-4, format!("{0}:{1}: Unknown option '{2}'", config_path, entry.line, name))); },
+                };
+
+                if option.cli_only
+                {
+                    return Err((-4, format!("{0}:{1}: '{2}' can only be set on the command line", config_path, entry.line, name)));
+                }
+
+                if cli_options.iter().any(|cli_name| cli_name == Self::canonical_name(name))
+                {
+                    continue;
+                }
+
+                self.apply_option(name, &entry.value)
+                    .map_err(|e| (-4, format!("{0}:{1}: Invalid value '{2}' for '{3}': {4}", config_path, entry.line, entry.value, name, e)))?;
+
+                if *section != EnumSection::Global
+                {
+                    self.rom_profile_options.push(String::from(Self::canonical_name(name)));
+                }
+            }
+
+            let profile = section.to_string();
+
+            if !self.profiles.contains(&profile)
+            {
+                self.profiles.push(profile);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Splits one command-line arg into its option and inline value ('--opt=value').
+    fn split_option(arg: &str) -> Result<EnumArg<'_>, (i32, String)>
+    {
+        let unknown = || Err((-3, format!("Unknown option '{}' (see --help)", arg)));
+
+        if let Some(long) = arg.strip_prefix("--")
+        {
+            let (name, inline_value) = match long.split_once('=')
+            {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+
+            return find_option(name).map(|option| EnumArg::Option(option, inline_value)).map_or_else(unknown, Ok);
+        }
+
+        else if let Some(short) = arg.strip_prefix('-').filter(|short| !short.is_empty())
+        {
+            let mut chars = short.chars();
+            let option = chars.next().and_then(find_short_option);
+
+            // Only a single short option per arg; its value follows as the next arg.
+            return match (option, chars.next())
+            {
+                (Some(option), None) => Ok(EnumArg::Option(option, None)),
+                _ => unknown(),
+            };
+        }
+
+        Ok(EnumArg::Positional)
+    }
+
+    pub fn parse(&mut self) -> Option<(i32, String)>
+    {
+        let arg_count = self.args.len();
+
+        if arg_count == 0
+        {
+            return Some((-1, String::from("No input args to parse")));
+        }
+
+        let mut cli_options = Vec::<String>::new();
+        let mut skip_next = false;
+        let mut options_ended = false;
+        let mut first_arg = 1;
+
+        if let Some(command) = self.args.get(1).and_then(|arg| EnumCommand::from_arg(arg))
+        {
+            self.command = command;
+            first_arg = 2;
+        }
+
+        for i in first_arg..arg_count
+        {
+            if skip_next
+            {
+                skip_next = false;
+                continue;
+            }
+
+            let arg = self.args[i].clone();
+
+            if arg == "--" && !options_ended
+            {
+                options_ended = true;
+                continue;
+            }
+
+            let split = if options_ended { Ok(EnumArg::Positional) } else { Self::split_option(&arg) };
+
+            let (option, value) = match split
+            {
+                Ok(EnumArg::Option(option, Some(inline_value))) => (option, String::from(inline_value)),
+                Ok(EnumArg::Option(option, None)) if option.value.is_none() => (option, String::from("true")),
+                Ok(EnumArg::Option(option, None)) =>
+                {
+                    skip_next = true;
+
+                    match self.args.get(i + 1)
+                    {
+                        Some(value) => (option, value.clone()),
+                        None => { return Some((-2, format!("Expected a value {0} after '{1}'", option.value.unwrap(), arg))); },
+                    }
+                },
+                Err(e) => { return Some(e); },
+                // The ROM path is the only positional arg.
+                Ok(EnumArg::Positional) if self.rom_path.is_none() && self.command != EnumCommand::Bench => (find_option("rom").unwrap(), arg.clone()),
+                Ok(EnumArg::Positional) if self.command != EnumCommand::Bench =>
+                {
+                    return Some((-3, format!("Unexpected arg '{0}' (the ROM is already '{1}')", arg, self.rom_path.as_deref().unwrap())));
+                },
+                Ok(EnumArg::Positional) => { return Some((-3, format!("Unexpected arg '{0}' for '{1}'", arg, self.command.name()))); },
+            };
+
+            if option.command.is_some_and(|command| command != self.command)
+            {
+                return Some((-3, format!("'{0}' only applies to 'hchip8 {1}'", arg, option.command.unwrap().name())));
+            }
+
+            if let Err(e) = self.apply_option(option.name, &value)
+            {
+                let label = arg.split_once('=').map_or(arg.as_str(), |(label, _)| label);
+                return Some((-2, format!("Invalid value '{0}' for '{1}': {2}", value, label, e)));
+            }
+
+            // Nothing else matters when only the help or version was asked for.
+            if self.show_help || self.show_version
+            {
+                return None;
+            }
+
+            cli_options.push(String::from(Self::canonical_name(option.name)));
+        }
+
+        if let Some(config_path) = self.config_path.clone()
+        {
+            if let Err(e) = self.apply_config_file(&config_path, &cli_options)
+            {
+                return Some(e);
+            }
+        }
+
+        if self.identify && self.command == EnumCommand::Run
+        {
+            if let Err(e) = self.apply_rom_database(&cli_options)
+            {
+                return Some(e);
+            }
+        }
+
+        // The whole first instruction has to be in main memory.
+        if self.starting_pc as usize + 2 > self.mem_size
+        {
+            return Some((-2, format!("'--pc' 0x{0:X} is outside of main memory ('--mem-size' is {1} bytes)", self.starting_pc, self.mem_size)));
+        }
+
+        if self.command == EnumCommand::Cfg && self.rom_path.is_none()
+        {
+            return Some((-1, String::from("'hchip8 cfg' needs a ROM to analyze")));
+        }
+
+        if self.command == EnumCommand::Info && self.rom_path.is_none()
+        {
+            return Some((-1, String::from("'hchip8 info' needs a ROM to identify")));
+        }
+
+        if self.command == EnumCommand::Octo && self.rom_path.is_none()
+        {
+            return Some((-1, String::from("'hchip8 octo' needs a source file to compile")));
+        }
+
+        if self.lcov_path.is_some() && self.source_map_path.is_none()
+        {
+            return Some((-2, String::from("'--lcov' needs a '--source-map' to map addresses to lines")));
+        }
+
+        if let Some((start, end)) = self.hexdump_range.filter(|(_, end)| *end >= self.mem_size)
+        {
+            return Some((-2, format!("'--hexdump' 0x{0:X}-0x{1:X} is outside of main memory ('--mem-size' is {2} bytes)", start, end, self.mem_size)));
+        }
+
+        return None;
+    }
+
+    /// The effective configuration in config file syntax (so it can be copied into one).
+    pub fn format_config(&self) -> String
+    {
+        let mut text = String::from("# Effective configuration (command line > [crc32:..] > [rom:..] > rom database > [global])\n");
+
+        if let Some(config_path) = &self.config_path
+        {
+            text.push_str(&format!("# config: {0} (applied: {1})\n", config_path, self.profiles.join(", ")));
+        }
+
+        if let Some(rom_path) = &self.rom_path
+        {
+            text.push_str(&format!("# rom: {}\n", rom_path));
+        }
+
+        if let Some(rom_entry) = &self.rom_entry
+        {
+            text.push_str(&format!("# rom database: {0} ({1})\n", rom_entry.title, rom_entry.source));
+        }
+
+        let palette = self.palette.map(|palette| palette.to_string()).unwrap_or(String::from("none"));
+
+        text.push_str(&format!("mem-size = {}\n", self.mem_size));
+        text.push_str(&format!("pc = 0x{:X}\n", self.starting_pc));
+        text.push_str(&format!("cpu-hz = {}\n", self.frame_config.get_cpu_hz()));
+        text.push_str(&format!("speed = {}\n", self.frame_config.get_speed()));
+        text.push_str(&format!("quirks = {}\n", self.quirks));
+        text.push_str(&format!("palette = {}\n", palette));
+        text.push_str(&format!("keys = {}\n", self.key_map));
+        text.push_str(&format!("on-fault = {}\n", self.fault_policy));
+        text.push_str(&format!("on-invalid = {}\n", self.invalid_policy));
+        text.push_str(&format!("zero-halts = {}\n", self.zero_halts));
+        text.push_str(&format!("debug = {}\n", self.debug));
+        text.push_str(&format!("headless = {}\n", self.headless));
+        text.push_str(&format!("decode-cache = {}\n", self.decode_cache));
+        text.push_str(&format!("dump-format = {}\n", self.dump_format));
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::bench::EnumBenchFormat;
+    use crate::env::config_data::{ConfigData, EnumCommand, DEFAULT_BENCH_CYCLES, DEFAULT_MEM_SIZE, DEFAULT_STARTING_PC};
+    use crate::hw::cpu::EnumDumpFormat;
+    use crate::hw::fault::{EnumFaultPolicy, EnumInvalidPolicy};
+    use crate::hw::frame::EnumSpeed;
+    use crate::hw::quirks::Quirks;
+    use crate::rom::crc32::crc32;
+    use crate::rom::database::EnumPlatform;
+    use crate::rom::sha1::{format_sha1, sha1};
+
+    /// Writes 'contents' to a file in the temp directory that is unique to the calling test.
+    fn write_temp_file(name: &str, contents: &[u8]) -> String
+    {
+        let path = std::env::temp_dir().join(format!("hchip8-{0}-{1}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn parse_no_args_fails()
+    {
+        let args = Vec::<String>::new();
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
+        assert_ne!(opt_error.unwrap().0, 0);
+    }
+
+    #[test]
+    fn parse_starting_pc_valid()
+    {
+        let args = vec![String::from("exe"), String::from("--pc"), String::from("512")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_starting_pc(), 512);
+    }
+
+    #[test]
+    fn parse_starting_pc_missing_value()
+    {
+        let args = vec![String::from("exe"), String::from("--pc")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
+        assert_ne!(opt_error.unwrap().0, 0);
+    }
+
+    #[test]
+    fn parse_starting_pc_bad_value()
+    {
+        let args = vec![String::from("exe"), String::from("--pc"), String::from("-512")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
+    }
+
+    #[test]
+    fn parse_mem_size_valid()
+    {
+        let args = vec![String::from("exe"), String::from("--mem-size"), String::from("4096")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_mem_size(), 4096);
+    }
+
+    #[test]
+    fn parse_pc_and_mem_size_valid()
+    {
+        let args = vec![
+            String::from("exe"),
+            String::from("--pc"),
+            String::from("512"),
+            String::from("--mem-size"),
+            String::from("4096"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_starting_pc(), 512);
+        assert_eq!(config_data.get_mem_size(), 4096);
+    }
+
+    #[test]
+    fn parse_pc_and_mem_size_order_flipped_valid()
+    {
+        let args = vec![
+            String::from("exe"),
+            String::from("--mem-size"),
+            String::from("4096"),
+            String::from("--pc"),
+            String::from("512"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_starting_pc(), 512);
+        assert_eq!(config_data.get_mem_size(), 4096);
+    }
+
+    #[test]
+    fn parse_debug_flag_valid()
+    {
+        let args = vec![String::from("exe"), String::from("--debug")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(!config_data.is_debug());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert!(config_data.is_debug());
+    }
+
+    #[test]
+    fn parse_cpu_hz_valid()
+    {
+        let args = vec![String::from("exe"), String::from("--cpu-hz"), String::from("720")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_frame_config().get_instructions_per_frame(), 12);
+    }
+
+    #[test]
+    fn parse_cpu_hz_limit()
+    {
+        let args = vec![String::from("exe"), String::from("--cpu-hz"), String::from("4294967295")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_frame_config().get_instructions_per_frame(), 71_582_788);
+    }
+
+    #[test]
+    fn parse_ipf_zero_fails()
+    {
+        let args = vec![String::from("exe"), String::from("--ipf"), String::from("0")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
+    }
+
+    #[test]
+    fn parse_speed_and_ipf_valid()
+    {
+        let args = vec![
+            String::from("exe"),
+            String::from("--speed"),
+            String::from("2"),
+            String::from("--ipf"),
+            String::from("15"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_frame_config().get_instructions_per_frame(), 15);
+        assert_eq!(config_data.get_frame_config().get_speed(), EnumSpeed::Multiplier(2.0));
+    }
+
+    #[test]
+    fn parse_turbo_valid()
+    {
+        let args = vec![String::from("exe"), String::from("--turbo")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_frame_config().get_speed(), EnumSpeed::Turbo);
+    }
+
+    #[test]
+    fn parse_speed_bad_value()
+    {
+        let args = vec![String::from("exe"), String::from("--speed"), String::from("-1")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
+        assert!(opt_error.unwrap().1.contains("--speed"));
+    }
+
+    #[test]
+    fn parse_on_fault_valid()
+    {
+        let args = vec![String::from("exe"), String::from("--on-fault"), String::from("break")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert_eq!(config_data.get_fault_policy(), EnumFaultPolicy::Halt);
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_fault_policy(), EnumFaultPolicy::Break);
+    }
+
+    #[test]
+    fn parse_on_fault_bad_value()
+    {
+        let args = vec![String::from("exe"), String::from("--on-fault"), String::from("explode")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
+    }
+
+    #[test]
+    fn parse_on_invalid_and_zero_halts()
+    {
+        let mut args = vec![String::from("exe")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_invalid_policy(), EnumInvalidPolicy::Halt);
+        assert!(config_data.is_zero_halts());
+
+        args.push(String::from("--on-invalid=nop"));
+        args.push(String::from("--zero-halts=false"));
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_invalid_policy(), EnumInvalidPolicy::Nop);
+        assert!(!config_data.is_zero_halts());
+        assert!(config_data.format_config().contains("on-invalid = nop\nzero-halts = false\n"));
+
+        args.push(String::from("--on-invalid=explode"));
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert_eq!(config_data.parse().unwrap().0, -2);
+    }
+
+    #[test]
+    fn parse_rom_path_valid()
+    {
+        let args = vec![String::from("exe"), String::from("--rom"), String::from("games/pong.ch8")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.get_rom_path().is_none());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_rom_path(), Some("games/pong.ch8"));
+    }
+
+    #[test]
+    fn parse_config_file_applies_global_and_rom_sections()
+    {
+        let rom_path = write_temp_file("profile.ch8", &[0x00, 0xE0, 0x12, 0x00]);
+        let config_text = format!("cpu-hz = 900\nquirks = schip\n\n[rom:tetris.ch8]\nspeed = 3\n\n[crc32:{:08x}]\nquirks = chip8\npalette = #FFB000 #000000\n",
+                                  crc32(&[0x00, 0xE0, 0x12, 0x00]));
+        let config_path = write_temp_file("profile.ini", config_text.as_bytes());
+
+        let args = vec![
+            String::from("exe"),
+            String::from("--config"),
+            config_path.clone(),
+            String::from("--rom"),
+            rom_path.clone(),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_frame_config().get_instructions_per_frame(), 15);
+        assert_eq!(config_data.get_frame_config().get_speed(), EnumSpeed::Multiplier(1.0));
+        assert_eq!(config_data.get_quirks(), Quirks::CHIP8);
+        assert_eq!(config_data.get_palette().unwrap().foreground, [0xFF, 0xB0, 0x00]);
+
+        std::fs::remove_file(rom_path).unwrap();
+        std::fs::remove_file(config_path).unwrap();
+    }
+
+    #[test]
+    fn crc32_sections_match_the_loaded_image()
+    {
+        // The same program as hex text matches the binary's CRC-32.
+        let rom_path = write_temp_file("profile.txt", b"0x0200: 00E0 1200\n");
+        let config_path = write_temp_file("crc32.ini", format!("[crc32:{:08x}]\nquirks = chip8\n", crc32(&[0x00, 0xE0, 0x12, 0x00])).as_bytes());
+
+        let args = vec![String::from("exe"), String::from("--config"), config_path.clone(), rom_path.clone()];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_quirks(), Quirks::CHIP8);
+
+        std::fs::remove_file(rom_path).unwrap();
+        std::fs::remove_file(config_path).unwrap();
+    }
+
+    #[test]
+    fn parse_command_line_overrides_config_file()
+    {
+        let config_path = write_temp_file("override.ini", b"ipf = 20\nspeed = 2\nheadless = true\nkeys = 0123456789abcdef\n");
+
+        let args = vec![
+            String::from("exe"),
+            String::from("--cpu-hz"),
+            String::from("600"),
+            String::from("--turbo"),
+            String::from("--config"),
+            config_path.clone(),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_frame_config().get_instructions_per_frame(), 10);
+        assert_eq!(config_data.get_frame_config().get_speed(), EnumSpeed::Turbo);
+        assert!(config_data.is_headless());
+        assert_eq!(config_data.get_key_map().get_key('a'), Some(0xA));
+
+        std::fs::remove_file(config_path).unwrap();
     }
 
-    #[allow(dead_code)]
-    pub fn get_mem_size(&self) -> u16
+    #[test]
+    fn parse_config_file_bad_value_names_the_line()
     {
-        self.mem_size
+        let config_path = write_temp_file("bad.ini", b"# comment\non-fault = explode\n");
+
+        let args = vec![String::from("exe"), String::from("--config"), config_path.clone()];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
+        assert!(opt_error.unwrap().1.contains("bad.ini:2: Invalid value 'explode' for 'on-fault'"));
+
+        std::fs::remove_file(config_path).unwrap();
     }
 
-    #[allow(dead_code)]
-    pub fn get_starting_pc(&self) -> u16
+    #[test]
+    fn parse_missing_config_file_fails()
     {
-        self.starting_pc
+        let args = vec![String::from("exe"), String::from("--config"), String::from("/does/not/exist.ini")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_some());
     }
 
-    pub fn parse(&mut self) -> Option<(i32, String)>
+    #[test]
+    fn print_config_shows_effective_values()
     {
-        let arg_count = self.args.len();
-        println!("[TEST]: arg count is {0}", arg_count);
-
-        if arg_count == 0
-        {
-            return Some((-1, String::from("No input args to parse")));
-        }
+        let args = vec![
+            String::from("exe"),
+            String::from("--quirks"),
+            String::from("shift-vy,wrap"),
+            String::from("--print-config"),
+        ];
 
-        let mut skip_next = false;
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
 
-        for i in 1..arg_count
-        {
-            if skip_next
-            {
-                skip_next = false;
-                continue;
-            }
+        assert!(opt_error.is_none());
+        assert!(config_data.is_print_config());
 
-            let arg = self.args.get(i).unwrap();
+        let text = config_data.format_config();
+        assert!(text.contains("quirks = shift-vy, wrap\n"));
+        assert!(text.contains("cpu-hz = 600\n"));
+        assert!(text.contains("palette = none\n"));
+        assert!(text.contains("dump-format = text\n"));
+    }
 
-            if arg == "--mem-size"
-            {
-                let opt_next_arg = self.args.get(i + 1);
+    #[test]
+    fn parse_defaults()
+    {
+        let args = vec![String::from("exe")];
 
-                if opt_next_arg.is_none()
-                {
-                    return Some((-2, String::from("Expected another arg after '--pc'")));
-                }
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
 
-                let val_result = opt_next_arg.unwrap().parse::<u16>();
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_mem_size(), DEFAULT_MEM_SIZE);
+        assert_eq!(config_data.get_starting_pc(), DEFAULT_STARTING_PC);
+        assert!(config_data.get_rom_path().is_none());
+    }
 
-                match val_result
-                {
-                    Ok(val) => { self.mem_size = val; },
-                    Err(e) => { return Some((-2, e.to_string())); }
-                }
+    #[test]
+    fn parse_hex_short_and_inline_forms()
+    {
+        let args = vec![
+            String::from("exe"),
+            String::from("-p"),
+            String::from("0x600"),
+            String::from("--mem-size=0x2000"),
+            String::from("-d"),
+            String::from("--on-fault=ignore"),
+        ];
 
-                skip_next = true;
-            }
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
 
-            else if arg == "--pc"
-            {
-                let opt_next_arg = self.args.get(i + 1);
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_starting_pc(), 0x600);
+        assert_eq!(config_data.get_mem_size(), 0x2000);
+        assert!(config_data.is_debug());
+        assert_eq!(config_data.get_fault_policy(), EnumFaultPolicy::Ignore);
+    }
 
-                if opt_next_arg.is_none()
-                {
-                    return Some((-2, String::from("Expected another arg after '--pc'")));
-                }
+    #[test]
+    fn parse_positional_rom_path()
+    {
+        let mut args = vec![String::from("exe"), String::from("--headless"), String::from("pong.ch8")];
 
-                let val_result = opt_next_arg.unwrap().parse::<u16>();
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
 
-                match val_result
-                {
-                    Ok(val) => { self.starting_pc = val; },
-                    Err(e) => { return Some((-2, e.to_string())); }
-                }
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_rom_path(), Some("pong.ch8"));
 
-                skip_next = true;
-            }
+        // A second ROM is rejected by name.
+        args.push(String::from("tetris.ch8"));
 
-            else
-            {
-                // TODO: Include the arg in the error message??
-                return Some((-3, String::from("Unexpected arg")));
-            }
-        }
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
 
-        // Some((-3, String::from("Failed to parse input args")))
-        return None;
+        assert!(opt_error.unwrap().1.contains("'tetris.ch8'"));
     }
-}
 
-#[cfg(test)]
-mod tests
-{
-    use crate::env::config_data::ConfigData;
+    #[test]
+    fn parse_rom_path_after_double_dash()
+    {
+        let args = vec![String::from("exe"), String::from("--"), String::from("--weird-name.ch8")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_rom_path(), Some("--weird-name.ch8"));
+    }
 
     #[test]
-    fn parse_no_args_fails()
+    fn parse_unknown_option_names_it()
     {
-        let args = Vec::<String>::new();
+        let args = vec![String::from("exe"), String::from("--mem-sise"), String::from("4096")];
+
         let mut config_data = ConfigData::new(args.clone());
         let opt_error = config_data.parse();
 
         assert!(opt_error.is_some());
-        assert_ne!(opt_error.unwrap().0, 0);
+        assert!(opt_error.unwrap().1.contains("'--mem-sise'"));
     }
 
     #[test]
-    fn parse_starting_pc_valid()
+    fn parse_out_of_range_values_fail()
     {
-        let mut args = Vec::<String>::new();
-        args.push(String::from("exe"));
-        args.push(String::from("--pc"));
-        args.push(String::from("512"));
+        let args = vec![String::from("exe"), String::from("--mem-size=0x10001")];
 
         let mut config_data = ConfigData::new(args.clone());
         let opt_error = config_data.parse();
 
-        assert!(opt_error.is_none());
-        assert_eq!(config_data.get_starting_pc(), 512);
+        assert!(opt_error.unwrap().1.contains("'--mem-size'"));
+
+        // The pc has to leave room for an instruction.
+        let args = vec![
+            String::from("exe"),
+            String::from("--mem-size"),
+            String::from("0x300"),
+            String::from("--pc"),
+            String::from("0x2FF"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.unwrap().1.contains("'--pc'"));
     }
 
     #[test]
-    fn parse_starting_pc_missing_value()
+    fn parse_help_and_version()
     {
-        let mut args = Vec::<String>::new();
-        args.push(String::from("exe"));
-        args.push(String::from("--pc"));
+        let args = vec![String::from("exe"), String::from("-h"), String::from("--bogus")];
 
         let mut config_data = ConfigData::new(args.clone());
         let opt_error = config_data.parse();
 
-        assert!(opt_error.is_some());
-        assert_ne!(opt_error.unwrap().0, 0);
+        assert!(opt_error.is_none());
+        assert!(config_data.is_help());
+        assert!(ConfigData::help_text().contains("--mem-size <bytes>"));
+
+        let args = vec![String::from("exe"), String::from("--version")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert!(config_data.is_version());
     }
 
     #[test]
-    fn parse_starting_pc_bad_value()
+    fn parse_decode_cache_is_opt_in()
     {
-        let mut args = Vec::<String>::new();
-        args.push(String::from("exe"));
-        args.push(String::from("--pc"));
-        args.push(String::from("-512"));
+        let mut args = vec![String::from("exe")];
 
         let mut config_data = ConfigData::new(args.clone());
-        let opt_error = config_data.parse();
+        assert!(config_data.parse().is_none());
+        assert!(!config_data.is_decode_cache());
 
-        assert!(opt_error.is_some());
+        args.push(String::from("--decode-cache"));
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert!(config_data.is_decode_cache());
     }
 
     #[test]
-    fn parse_mem_size_valid()
+    fn parse_profile_options()
+    {
+        let args = vec![String::from("exe"), String::from("--profile")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert!(config_data.is_profile());
+        assert!(config_data.get_profile_out().is_none());
+
+        let args = vec![String::from("exe"), String::from("--profile-out=stacks.txt")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert!(config_data.is_profile());
+        assert_eq!(config_data.get_profile_out(), Some("stacks.txt"));
+
+        let args = vec![String::from("exe"), String::from("bench"), String::from("--profile")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().unwrap().1.contains("only applies to 'hchip8 run'"));
+    }
+
+    #[test]
+    fn parse_coverage_options()
     {
-        let mut args = Vec::<String>::new();
-        args.push(String::from("exe"));
-        args.push(String::from("--mem-size"));
-        args.push(String::from("4096"));
+        let mut args = vec![String::from("exe"), String::from("--lcov"), String::from("test.info")];
 
         let mut config_data = ConfigData::new(args.clone());
         let opt_error = config_data.parse();
 
-        assert!(opt_error.is_none());
-        assert_eq!(config_data.get_mem_size(), 4096);
+        assert_eq!(opt_error.unwrap().0, -2);
+
+        args.push(String::from("--source-map"));
+        args.push(String::from("test.map"));
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert!(config_data.is_coverage());
+        assert_eq!(config_data.get_lcov_path(), Some("test.info"));
+        assert_eq!(config_data.get_source_map_path(), Some("test.map"));
     }
 
     #[test]
-    fn parse_pc_and_mem_size_valid()
+    fn parse_debug_script_and_cheats()
+    {
+        let mut args = vec![
+            String::from("exe"),
+            String::from("pong.ch8"),
+            String::from("--debug-script"),
+            String::from("pong.dbg"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_debug_script_path(), Some("pong.dbg"));
+        assert_eq!(config_data.get_cheats_path(), None);
+
+        args.push(String::from("--cheats=pong.cht"));
+        let mut config_data = ConfigData::new(args);
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_cheats_path(), Some("pong.cht"));
+
+        // Scripts drive a running ROM.
+        let args = vec![
+            String::from("exe"),
+            String::from("cfg"),
+            String::from("pong.ch8"),
+            String::from("--debug-script=pong.dbg"),
+        ];
+
+        let mut config_data = ConfigData::new(args);
+        assert_eq!(config_data.parse().map(|(code, _)| code), Some(-3));
+    }
+
+    #[test]
+    fn parse_octo_options()
+    {
+        let mut args = vec![String::from("exe"), String::from("octo"), String::from("games/pong.8o")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_command(), EnumCommand::Octo);
+        assert_eq!(config_data.get_rom_path(), Some("games/pong.8o"));
+        assert_eq!(config_data.get_octo_out(), "games/pong.ch8");
+        assert_eq!(config_data.get_octo_target(), EnumPlatform::Chip8);
+        assert_eq!(config_data.get_symbols_out(), None);
+
+        args.push(String::from("-o"));
+        args.push(String::from("pong.rom"));
+        args.push(String::from("--target=xochip"));
+        args.push(String::from("--symbols-out=pong.sym"));
+        args.push(String::from("--source-map-out"));
+        args.push(String::from("pong.map"));
+
+        let mut config_data = ConfigData::new(args);
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_octo_out(), "pong.rom");
+        assert_eq!(config_data.get_octo_target(), EnumPlatform::XoChip);
+        assert_eq!(config_data.get_symbols_out(), Some("pong.sym"));
+        assert_eq!(config_data.get_source_map_out(), Some("pong.map"));
+
+        let mut args = vec![String::from("exe"), String::from("octo")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert_eq!(config_data.parse().map(|(code, _)| code), Some(-1));
+
+        args.push(String::from("pong.8o"));
+        args.push(String::from("--target=c64"));
+
+        let mut config_data = ConfigData::new(args);
+        assert_eq!(config_data.parse().map(|(code, _)| code), Some(-2));
+
+        // Only the compiler has a target.
+        let args = vec![String::from("exe"), String::from("pong.ch8"), String::from("--target=schip")];
+
+        let mut config_data = ConfigData::new(args);
+        assert_eq!(config_data.parse().map(|(code, _)| code), Some(-3));
+    }
+
+    #[test]
+    fn parse_repeated_patches()
+    {
+        let args = vec![
+            String::from("exe"),
+            String::from("pong.ch8"),
+            String::from("--patch"),
+            String::from("fix.ips"),
+            String::from("--patch=translation.bps"),
+        ];
+
+        let mut config_data = ConfigData::new(args);
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_patch_paths(), ["fix.ips", "translation.bps"]);
+
+        // The control-flow graph is of the patched ROM.
+        let args = vec![
+            String::from("exe"),
+            String::from("cfg"),
+            String::from("pong.ch8"),
+            String::from("--patch=fix.ips"),
+        ];
+
+        let mut config_data = ConfigData::new(args);
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_patch_paths(), ["fix.ips"]);
+    }
+
+    #[test]
+    fn rom_database_configures_known_roms()
+    {
+        // The 'alu' benchmark, which is in the built-in database with 'quirks = chip8'.
+        let rom_path = write_temp_file("alu.ch8", &[0x60, 0x00, 0x61, 0x01, 0x80, 0x14, 0x81, 0x04, 0x82, 0x13, 0x83, 0x36, 0x3F, 0x00, 0x6F, 0x00, 0x12, 0x04]);
+
+        let args = vec![String::from("exe"), rom_path.clone()];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_quirks(), Quirks::CHIP8);
+        assert_eq!(config_data.get_rom_entry().unwrap().title, "Benchmark: alu");
+        assert!(config_data.format_config().contains("# rom database: Benchmark: alu (<built-in>)\n"));
+
+        // A local database overrides the built-in entry; '[global]' doesn't override the database but '[rom:..]' does.
+        let rom_db_path = write_temp_file("local.db", b"[1bfabd70888aef30b2eb1050310c114a3e5d2eff]\ntitle = Local alu\nquirks = schip\nspeed = 3\n");
+        let config_path = write_temp_file("db.ini", format!("quirks = none\nspeed = 2\nheadless = true\n\n[rom:{}]\nspeed = 4\n",
+                                                             std::path::Path::new(&rom_path).file_name().unwrap().to_string_lossy()).as_bytes());
+
+        let mut args = args.clone();
+        args.push(String::from("--rom-db"));
+        args.push(rom_db_path);
+        args.push(String::from("--config"));
+        args.push(config_path);
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_rom_entry().unwrap().title, "Local alu");
+        assert_eq!(config_data.get_quirks(), Quirks::SCHIP);
+        assert_eq!(config_data.get_frame_config().get_speed(), EnumSpeed::Multiplier(4.0));
+        assert!(config_data.is_headless());
+
+        // The command line wins, and '--identify=false' skips the database.
+        args.push(String::from("--quirks=none"));
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_quirks(), Quirks::default());
+
+        args.push(String::from("--identify=false"));
+        let mut config_data = ConfigData::new(args);
+        assert!(config_data.parse().is_none());
+        assert!(config_data.get_rom_entry().is_none());
+        assert_eq!(config_data.get_frame_config().get_speed(), EnumSpeed::Multiplier(4.0));
+    }
+
+    #[test]
+    fn rom_database_identifies_the_loaded_image()
+    {
+        let alu = [0x60, 0x00, 0x61, 0x01, 0x80, 0x14, 0x81, 0x04, 0x82, 0x13, 0x83, 0x36, 0x3F, 0x00, 0x6F, 0x00, 0x12, 0x04];
+
+        // The 'alu' benchmark as hex text, and as a blank ROM patched into it.
+        let hex_path = write_temp_file("alu.txt", b"# alu\n6000 6101 8014 8104 8213 8336\n3F00 6F00 1204\n");
+        let blank_path = write_temp_file("blank.ch8", &[0xFF; 18]);
+        let mut ips = b"PATCH\x00\x00\x00\x00\x12".to_vec();
+        ips.extend_from_slice(&alu);
+        ips.extend_from_slice(b"EOF");
+        let patch_path = write_temp_file("alu.ips", &ips);
+
+        for args in [vec![hex_path.clone()], vec![blank_path.clone(), format!("--patch={}", patch_path)]]
+        {
+            let mut config_data = ConfigData::new([vec![String::from("exe")], args].concat());
+            assert!(config_data.parse().is_none());
+            assert_eq!(config_data.read_rom_image(), Ok(alu.to_vec()));
+            assert_eq!(config_data.get_rom_entry().unwrap().title, "Benchmark: alu");
+        }
+
+        let mut config_data = ConfigData::new(vec![String::from("exe"), blank_path.clone()]);
+        assert!(config_data.parse().is_none());
+        assert!(config_data.get_rom_entry().is_none());
+
+        std::fs::remove_file(hex_path).unwrap();
+        std::fs::remove_file(blank_path).unwrap();
+        std::fs::remove_file(patch_path).unwrap();
+    }
+
+    #[test]
+    fn rom_database_uses_the_config_file_memory_layout()
+    {
+        // Too big for the default 4 KiB, so it's only identified with the config file's memory size.
+        let rom = vec![0x12; 5000];
+        let rom_path = write_temp_file("big.ch8", &rom);
+        let rom_db_path = write_temp_file("big.db", format!("[{}]\ntitle = Big\nspeed = 3\n", format_sha1(&sha1(&rom))).as_bytes());
+        let config_path = write_temp_file("big.ini", b"[global]\nmem-size = 8192\nspeed = 2\n");
+
+        let args = vec![
+            String::from("exe"),
+            rom_path.clone(),
+            format!("--rom-db={}", rom_db_path),
+            format!("--config={}", config_path),
+        ];
+
+        let mut config_data = ConfigData::new(args);
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_mem_size(), 8192);
+        assert_eq!(config_data.get_rom_entry().unwrap().title, "Big");
+        assert_eq!(config_data.get_frame_config().get_speed(), EnumSpeed::Multiplier(3.0));
+
+        std::fs::remove_file(rom_path).unwrap();
+        std::fs::remove_file(rom_db_path).unwrap();
+        std::fs::remove_file(config_path).unwrap();
+    }
+
+    #[test]
+    fn rom_database_errors()
+    {
+        let rom_path = write_temp_file("db-errors.ch8", &[0x00, 0xE0]);
+        let sha1 = "159ba69f4c40be3042fc54c7fbb2025f7e49f8e0";
+
+        let args = vec![
+            String::from("exe"),
+            rom_path.clone(),
+            String::from("--rom-db"),
+            write_temp_file("bad-option.db", format!("[{}]\ntitle = Clear\ntrace = clear.trace\n", sha1).as_bytes()),
+        ];
+
+        let mut config_data = ConfigData::new(args);
+        let (code, msg) = config_data.parse().unwrap();
+        assert_eq!(code, -4);
+        assert!(msg.ends_with("bad-option.db:3: 'trace' can't be set from the ROM database"), "{}", msg);
+
+        let mut args = vec![String::from("exe"), String::from("info"), String::from("--rom-db=/does/not/exist.db")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert_eq!(config_data.parse().map(|(code, _)| code), Some(-1));
+
+        args.push(rom_path);
+        let mut config_data = ConfigData::new(args);
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_command(), EnumCommand::Info);
+        assert!(config_data.load_rom_database().unwrap_err().contains("/does/not/exist.db"));
+    }
+
+    #[test]
+    fn parse_memory_options()
+    {
+        let args = vec![
+            String::from("exe"),
+            String::from("--hexdump=0x200-0x2FF"),
+            String::from("--find"),
+            String::from("A2 ?? 60"),
+            String::from("--mem-diff"),
+            String::from("--dump-format=json"),
+            String::from("--smc"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_hexdump_range(), Some(0x200..0x300));
+        assert_eq!(config_data.get_find_pattern(), Some([Some(0xA2), None, Some(0x60)].as_slice()));
+        assert!(config_data.is_mem_diff());
+        assert_eq!(config_data.get_dump_format(), EnumDumpFormat::Json);
+        assert!(config_data.is_smc());
+
+        for bad in ["--hexdump=0x300-0x200", "--hexdump=0x200", "--hexdump=0x200-0x1000", "--find=A2 6", "--dump-format=xml"]
+        {
+            let args = vec![String::from("exe"), String::from(bad)];
+
+            let mut config_data = ConfigData::new(args);
+            assert_eq!(config_data.parse().unwrap().0, -2, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_symbols_and_trace()
+    {
+        let args = vec![
+            String::from("exe"),
+            String::from("pong.ch8"),
+            String::from("--symbols"),
+            String::from("pong.sym"),
+            String::from("--trace=pong.trace"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_symbols_path(), Some("pong.sym"));
+        assert_eq!(config_data.get_trace_path(), Some("pong.trace"));
+
+        // Symbols also label the control-flow graph, but there is nothing to trace there.
+        let mut args = vec![
+            String::from("exe"),
+            String::from("cfg"),
+            String::from("pong.ch8"),
+            String::from("--symbols=pong.sym"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+
+        args.push(String::from("--trace=pong.trace"));
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert_eq!(config_data.parse().unwrap().0, -3);
+    }
+
+    #[test]
+    fn parse_cfg_command()
+    {
+        let mut args = vec![String::from("exe"), String::from("cfg")];
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert_eq!(config_data.parse().unwrap().0, -1);
+
+        args.push(String::from("pong.ch8"));
+        args.push(String::from("--call-graph"));
+
+        let mut config_data = ConfigData::new(args.clone());
+        assert!(config_data.parse().is_none());
+        assert_eq!(config_data.get_command(), EnumCommand::Cfg);
+        assert_eq!(config_data.get_rom_path(), Some("pong.ch8"));
+        assert!(config_data.is_call_graph());
+    }
+
+    #[test]
+    fn parse_bench_command()
     {
-        let mut args = Vec::<String>::new();
-        args.push(String::from("exe"));
-        args.push(String::from("--pc"));
-        args.push(String::from("512"));
-        args.push(String::from("--mem-size"));
-        args.push(String::from("4096"));
+        let mut args = vec![String::from("exe"), String::from("bench")];
 
         let mut config_data = ConfigData::new(args.clone());
         let opt_error = config_data.parse();
 
         assert!(opt_error.is_none());
-        assert_eq!(config_data.get_starting_pc(), 512);
-        assert_eq!(config_data.get_mem_size(), 4096);
+        assert_eq!(config_data.get_command(), EnumCommand::Bench);
+        assert_eq!(config_data.get_bench_cycles(), DEFAULT_BENCH_CYCLES);
+        assert!(config_data.get_bench_workload().is_none());
+
+        args.push(String::from("--cycles=0x1000"));
+        args.push(String::from("-w"));
+        args.push(String::from("draw"));
+        args.push(String::from("--format"));
+        args.push(String::from("json"));
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.is_none());
+        assert_eq!(config_data.get_bench_cycles(), 0x1000);
+        assert_eq!(config_data.get_bench_workload(), Some("draw"));
+        assert_eq!(config_data.get_bench_format(), EnumBenchFormat::Json);
     }
 
     #[test]
-    fn parse_pc_and_mem_size_order_flipped_valid()
+    fn parse_bench_options_need_bench_command()
     {
-        let mut args = Vec::<String>::new();
-        args.push(String::from("exe"));
-        args.push(String::from("--mem-size"));
-        args.push(String::from("4096"));
-        args.push(String::from("--pc"));
-        args.push(String::from("512"));
+        let args = vec![String::from("exe"), String::from("--cycles"), String::from("100")];
 
         let mut config_data = ConfigData::new(args.clone());
         let opt_error = config_data.parse();
 
-        assert!(opt_error.is_none());
-        assert_eq!(config_data.get_starting_pc(), 512);
-        assert_eq!(config_data.get_mem_size(), 4096);
+        assert!(opt_error.unwrap().1.contains("'--cycles' only applies to 'hchip8 bench'"));
+
+        let args = vec![
+            String::from("exe"),
+            String::from("bench"),
+            String::from("--workload"),
+            String::from("sleep"),
+        ];
+
+        let mut config_data = ConfigData::new(args.clone());
+        let opt_error = config_data.parse();
+
+        assert!(opt_error.unwrap().1.contains("'sleep'"));
     }
 }
-
@@ -0,0 +1,222 @@
+/// Which ROMs a config file section applies to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EnumSection
+{
+    /// Keys before the first section header, or under '[global]'.
+    Global,
+    /// '[rom:<file name>]', matched against the file name of the ROM path (not the directories).
+    RomName(String),
+    /// '[crc32:<hex>]', matched against the CRC-32 of the ROM image.
+    RomCrc32(u32),
+}
+
+impl EnumSection
+{
+    /// Later sections override earlier ones: global, then file name, then content hash.
+    fn precedence(&self) -> u8
+    {
+        match self
+        {
+            Self::Global => 0,
+            Self::RomName(_) => 1,
+            Self::RomCrc32(_) => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for EnumSection
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Global => write!(f, "global"),
+            Self::RomName(name) => write!(f, "rom:{}", name),
+            Self::RomCrc32(crc) => write!(f, "crc32:{:08x}", crc),
+        }
+    }
+}
+
+/// A 'key = value' line and where it came from (for error messages).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigEntry
+{
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+/// An INI-like configuration file:
+///
+/// ```text
+/// # Global defaults
+/// cpu-hz = 700
+///
+/// [rom:pong.ch8]
+/// quirks = chip8
+/// palette = #FFB000 #1A1A1A
+///
+/// [crc32:1a2b3c4d]
+/// keys = x123qweasdzc4rfv
+/// ```
+///
+/// Lines starting with '#' or ';' are comments. Values may be wrapped in double quotes.
+#[derive(Debug)]
+pub struct ConfigFile
+{
+    sections: Vec<(EnumSection, Vec<ConfigEntry>)>,
+}
+
+impl ConfigFile
+{
+    pub fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut sections = vec![(EnumSection::Global, Vec::new())];
+
+        for (index, raw_line) in text.lines().enumerate()
+        {
+            let line_number = index + 1;
+            let line = raw_line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';')
+            {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[')
+            {
+                let header = header.strip_suffix(']').ok_or(format!("line {}: Expected ']' to close the section header", line_number))?;
+                let section = Self::parse_section(header.trim()).map_err(|e| format!("line {0}: {1}", line_number, e))?;
+                sections.push((section, Vec::new()));
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or(format!("line {0}: Expected 'key = value' but got '{1}'", line_number, line))?;
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+
+            sections.last_mut().unwrap().1.push(ConfigEntry { key: key.trim().to_string(), value: value.to_string(), line: line_number });
+        }
+
+        Ok(Self { sections })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String>
+    {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read config file '{0}': {1}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("{0}: {1}", path, e))
+    }
+
+    fn parse_section(header: &str) -> Result<EnumSection, String>
+    {
+        if header == "global"
+        {
+            return Ok(EnumSection::Global);
+        }
+
+        else if let Some(name) = header.strip_prefix("rom:")
+        {
+            return Ok(EnumSection::RomName(name.trim().to_string()));
+        }
+
+        else if let Some(hex) = header.strip_prefix("crc32:")
+        {
+            let hex = hex.trim();
+            let hex = hex.strip_prefix("0x").unwrap_or(hex);
+            let crc = u32::from_str_radix(hex, 16).map_err(|e| format!("Invalid CRC-32 '{0}': {1}", hex, e))?;
+            return Ok(EnumSection::RomCrc32(crc));
+        }
+
+        Err(format!("Unknown section '[{}]' (expected [global], [rom:<file name>] or [crc32:<hex>])", header))
+    }
+
+    /// True if any section needs the ROM's CRC-32 to be matched.
+    pub fn has_crc32_sections(&self) -> bool
+    {
+        self.sections.iter().any(|(section, _)| matches!(section, EnumSection::RomCrc32(_)))
+    }
+
+    /// The sections that apply to a ROM, from lowest to highest precedence (in file order within a precedence).
+    pub fn matching_sections(&self, rom_name: Option<&str>, rom_crc32: Option<u32>) -> Vec<(&EnumSection, &[ConfigEntry])>
+    {
+        let mut matching: Vec<(&EnumSection, &[ConfigEntry])> = self.sections.iter()
+            .filter(|(section, _)| match section
+            {
+                EnumSection::Global => true,
+                EnumSection::RomName(name) => rom_name == Some(name.as_str()),
+                EnumSection::RomCrc32(crc) => rom_crc32 == Some(*crc),
+            })
+            .map(|(section, entries)| (section, entries.as_slice()))
+            .collect();
+
+        matching.sort_by_key(|(section, _)| section.precedence());
+
+        matching
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::env::config_file::{ConfigFile, EnumSection};
+
+    const TEXT: &str = "
+# defaults
+cpu-hz = 700
+speed = 2
+
+[rom:pong.ch8]
+quirks = chip8
+palette = \"#FFFFFF #000000\"
+
+[crc32:0xCBF43926]
+speed = turbo
+
+[global]
+headless = true
+";
+
+    #[test]
+    fn parse_sections_and_entries()
+    {
+        let config_file = ConfigFile::parse(TEXT).unwrap();
+        let sections = config_file.matching_sections(None, None);
+
+        // Both global blocks apply, in file order.
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].1[0].key, "cpu-hz");
+        assert_eq!(sections[0].1[0].value, "700");
+        assert_eq!(sections[0].1[0].line, 3);
+        assert_eq!(sections[1].1[0].key, "headless");
+        assert!(config_file.has_crc32_sections());
+    }
+
+    #[test]
+    fn rom_sections_match_by_name_and_hash()
+    {
+        let config_file = ConfigFile::parse(TEXT).unwrap();
+        let sections = config_file.matching_sections(Some("pong.ch8"), Some(0xCBF4_3926));
+
+        let names: Vec<String> = sections.iter().map(|(section, _)| section.to_string()).collect();
+        assert_eq!(names, ["global", "global", "rom:pong.ch8", "crc32:cbf43926"]);
+
+        // Quotes are stripped from values.
+        assert_eq!(sections[2].1[1].value, "#FFFFFF #000000");
+        assert_eq!(*sections[3].0, EnumSection::RomCrc32(0xCBF4_3926));
+
+        assert_eq!(config_file.matching_sections(Some("tetris.ch8"), Some(0)).len(), 2);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line()
+    {
+        let result = ConfigFile::parse("cpu-hz = 700\n[rom:pong.ch8\n");
+        assert!(result.unwrap_err().starts_with("line 2:"));
+
+        let result = ConfigFile::parse("[cheats]\n");
+        assert!(result.unwrap_err().contains("[cheats]"));
+
+        let result = ConfigFile::parse("\n\nturbo\n");
+        assert!(result.unwrap_err().starts_with("line 3:"));
+    }
+}
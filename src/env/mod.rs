@@ -1,2 +1,3 @@
 pub mod config_data;
+pub mod config_file;
 
@@ -1,12 +1,97 @@
-use crate::Mem;
-use crate::hw::opcode::Opcode;
-
-use std::collections::HashMap;
+use crate::dbg::watchpoint::{EnumAccess, WatchHit, WatchList};
+use crate::hw::display::Display;
+use crate::hw::fault::{EnumFault, EnumInvalidPolicy, EnumTickOutcome};
+use crate::hw::keypad::{Keypad, KEY_COUNT};
+use crate::hw::mem::Mem;
+use crate::hw::observer::{EnumTimer, Observer, ObserverId, ObserverList};
+use crate::hw::instruction::{DecodeCache, Instruction};
+use crate::hw::quirks::Quirks;
+use crate::hw::rng::Rng;
 
 const INSTRUCTION_SIZE: u16 = 2;
 const STACK_BLOCK_SIZE: u16 = 64;
+const FONT_ADDRESS: u16 = 0x050;
+const FONT_GLYPH_SIZE: u16 = 5;
+pub const REGISTER_COUNT: usize = 16;
+
+// Hex digits 0-F, each 4 pixels wide and 5 rows tall.
+const FONT: [u8; 16 * FONT_GLYPH_SIZE as usize] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70,
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0,
+    0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0,
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40,
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0,
+    0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0,
+    0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0,
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+/// Why the CPU stopped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EnumHaltReason
+{
+    /// The program ran a 0000 halt instruction.
+    Instruction { pc: u16 },
+    /// The host stopped it because of a fault.
+    Fault(EnumFault),
+    /// The host stopped it for any other reason.
+    Host,
+}
+
+impl std::fmt::Display for EnumHaltReason
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Instruction { pc } => write!(f, "halt instruction at 0x{:03X}", pc),
+            Self::Fault(fault) => write!(f, "fault: {}", fault),
+            Self::Host => write!(f, "stopped by the host"),
+        }
+    }
+}
+
+/// How 'CPU::format_state' lays out the state.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EnumDumpFormat
+{
+    /// Hex values, one group per line.
+    #[default]
+    Text,
+    /// A single JSON object with numbers in decimal, for scripts.
+    Json,
+}
+
+impl std::fmt::Display for EnumDumpFormat
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumDumpFormat
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        match text
+        {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown format '{}' (expected text or json)", text)),
+        }
+    }
+}
 
-#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+/// A general purpose register. The discriminant is the register's index in the register file.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[repr(u8)]
 pub enum EnumRegister
 {
     V0, V1, V2, V3, V4, V5, V6, V7,
@@ -15,21 +100,54 @@ pub enum EnumRegister
 
 impl EnumRegister
 {
-    const VALUES: [Self; 16] = [
+    pub const VALUES: [Self; REGISTER_COUNT] = [
         Self::V0, Self::V1, Self::V2, Self::V3, Self::V4, Self::V5, Self::V6, Self::V7,
         Self::V8, Self::V9, Self::VA, Self::VB, Self::VC, Self::VD, Self::VE, Self::VF,
     ];
+
+    /// The register numbered by the low nibble of 'index' (like an opcode's register fields), so it can't fail.
+    #[inline]
+    pub fn from_index(index: usize) -> Self
+    {
+        Self::VALUES[index & 0x0F]
+    }
+
+    #[inline]
+    pub fn index(self) -> usize
+    {
+        self as usize
+    }
 }
 
 pub struct CPU
 {
     mem: Mem,
-    registers: HashMap<EnumRegister, u8>,
+    registers: [u8; REGISTER_COUNT],
     pc: u16,
     sp: u16,
     stack_block: Mem,
     reg_i: u16, // NOTE: 12-bits only.
     halted: bool,
+    halt_reason: Option<EnumHaltReason>,
+    display: Display,
+    keypad: Keypad,
+    delay_timer: u8,
+    sound_timer: u8,
+    watch_list: WatchList,
+    watch_hit: Option<WatchHit>,
+    cur_pc: u16,
+    cur_opcode: u16,
+    rng: Rng,
+    last_fault: Option<EnumFault>,
+    quirks: Quirks,
+    invalid_policy: EnumInvalidPolicy,
+    /// 0000 halts the CPU instead of being an invalid opcode.
+    zero_halts: bool,
+    /// Invalid opcodes met so far, whatever the policy did with them.
+    invalid_count: u64,
+    /// Only present while the decode cache is enabled.
+    decode_cache: Option<DecodeCache>,
+    observers: ObserverList,
 }
 
 impl CPU
@@ -39,11 +157,26 @@ impl CPU
         // According to wikipedia, chip8 reserves the first 512 bytes of main memory.
         // For now, we are assuming the stack pointer will be the first address after that (512).
         let mut result = Self {
-            mem: Mem::new(capacity), registers: HashMap::new(),
+            mem: Mem::new(capacity), registers: [0; REGISTER_COUNT],
             pc : starting_pc, sp: 0,
             stack_block: Mem::new(STACK_BLOCK_SIZE as usize),
             reg_i: 0,
             halted: false,
+            halt_reason: None,
+            display: Display::new(),
+            keypad: Keypad::new(),
+            delay_timer: 0, sound_timer: 0,
+            watch_list: WatchList::new(),
+            watch_hit: None,
+            cur_pc: starting_pc, cur_opcode: 0,
+            rng: Rng::from_time(),
+            last_fault: None,
+            quirks: Quirks::default(),
+            invalid_policy: EnumInvalidPolicy::Halt,
+            zero_halts: true,
+            invalid_count: 0,
+            decode_cache: None,
+            observers: ObserverList::new(),
         };
 
         result.init();
@@ -53,22 +186,11 @@ impl CPU
 
     fn init(&mut self)
     {
-        self.registers.insert(EnumRegister::V0, 0);
-        self.registers.insert(EnumRegister::V1, 0);
-        self.registers.insert(EnumRegister::V2, 0);
-        self.registers.insert(EnumRegister::V3, 0);
-        self.registers.insert(EnumRegister::V4, 0);
-        self.registers.insert(EnumRegister::V5, 0);
-        self.registers.insert(EnumRegister::V6, 0);
-        self.registers.insert(EnumRegister::V7, 0);
-        self.registers.insert(EnumRegister::V8, 0);
-        self.registers.insert(EnumRegister::V9, 0);
-        self.registers.insert(EnumRegister::VA, 0);
-        self.registers.insert(EnumRegister::VB, 0);
-        self.registers.insert(EnumRegister::VC, 0);
-        self.registers.insert(EnumRegister::VD, 0);
-        self.registers.insert(EnumRegister::VE, 0);
-        self.registers.insert(EnumRegister::VF, 0);
+        // The font lives in the interpreter's reserved area (if this machine has one).
+        for (offset, value) in FONT.iter().enumerate()
+        {
+            self.mem.write_u8(FONT_ADDRESS as usize + offset, *value);
+        }
     }
 
     pub fn is_halted(&self) -> bool
@@ -76,441 +198,1516 @@ impl CPU
         self.halted
     }
 
-    fn set_halted(&mut self)
+    fn set_halted(&mut self, reason: EnumHaltReason)
     {
         self.halted = true;
+        self.halt_reason = Some(reason);
     }
 
-    fn has_stack_space(&self) -> bool
+    /// Lets the host stop the CPU.
+    pub fn halt(&mut self)
     {
-        (self.sp as usize) < self.stack_block.size()
+        self.set_halted(EnumHaltReason::Host);
+    }
+
+    /// Lets the host stop the CPU in response to a fault 'tick' reported.
+    pub fn halt_on_fault(&mut self, fault: &EnumFault)
+    {
+        self.set_halted(EnumHaltReason::Fault(fault.clone()));
     }
 
     #[allow(dead_code)]
-    fn pop_stack(&mut self) -> Option<u16>
+    pub fn get_halt_reason(&self) -> Option<&EnumHaltReason>
     {
-        if self.sp < 2
-        {
-            return None;
-        }
+        self.halt_reason.as_ref()
+    }
 
-        self.sp -= INSTRUCTION_SIZE;
-        let result = self.stack_block.read_u16(self.sp as usize);
+    /// The most recent fault reported by 'tick', if any.
+    #[allow(dead_code)]
+    pub fn get_last_fault(&self) -> Option<&EnumFault>
+    {
+        self.last_fault.as_ref()
+    }
 
-        return result;
+    /// Reseeds the generator used by the CXNN instruction (for reproducible runs).
+    #[allow(dead_code)]
+    pub fn set_rng_seed(&mut self, seed: u32)
+    {
+        self.rng = Rng::new(seed);
     }
 
     #[allow(dead_code)]
-    fn push_stack(&mut self, ret_address: u16) -> bool
+    pub fn get_quirks(&self) -> Quirks
     {
-        if self.sp < STACK_BLOCK_SIZE - 1
-        {
-            self.stack_block.write_u16(self.sp as usize, ret_address);
-            self.sp += INSTRUCTION_SIZE;
+        self.quirks
+    }
 
-            return true;
-        }
+    pub fn set_quirks(&mut self, quirks: Quirks)
+    {
+        self.quirks = quirks;
+    }
 
-        return false;
+    #[allow(dead_code)]
+    pub fn get_invalid_policy(&self) -> EnumInvalidPolicy
+    {
+        self.invalid_policy
     }
 
-    pub fn print_state(&self, verbose: bool)
+    pub fn set_invalid_policy(&mut self, policy: EnumInvalidPolicy)
     {
-        let mut stream = String::with_capacity(0x100);
-        stream += "\tMem block size: ";
-        stream += &self.mem.get_capacity().to_string();
+        self.invalid_policy = policy;
+    }
 
-        if verbose
-        {
-            stream += "\n\tStack block: {\n";
-            self.mem.print_state(&mut stream);
-            stream += "\n\t}";
-        }
+    #[allow(dead_code)]
+    pub fn is_zero_halts(&self) -> bool
+    {
+        self.zero_halts
+    }
 
-        stream += "\n\tpc: ";
-        stream += &self.pc.to_string();
-        stream += "\n\tsp: ";
-        stream += &self.sp.to_string();
-        stream += "\n\tStack block: {\n";
-        self.stack_block.print_state(&mut stream);
-        stream += "\n\t}";
-        stream += "\n\thalted: ";
-        stream += &self.halted.to_string();
+    /// Whether 0000 halts (the default) or is handled as an invalid opcode.
+    pub fn set_zero_halts(&mut self, zero_halts: bool)
+    {
+        self.zero_halts = zero_halts;
+    }
 
-        println!("{}", stream);
+    #[allow(dead_code)]
+    pub fn get_invalid_count(&self) -> u64
+    {
+        self.invalid_count
     }
 
-    pub fn tick(&mut self)
+    pub fn get_pc(&self) -> u16
     {
-        if self.is_halted()
+        self.pc
+    }
+
+    #[allow(dead_code)]
+    pub fn get_register(&self, reg: EnumRegister) -> u8
+    {
+        self.read_register(reg)
+    }
+
+    /// V0-VF, indexed by 'EnumRegister::index'.
+    #[allow(dead_code)]
+    pub fn get_registers(&self) -> &[u8; REGISTER_COUNT]
+    {
+        &self.registers
+    }
+
+    /// Lets the host poke a register (e.g. from the debugger).
+    #[allow(dead_code)]
+    pub fn set_register(&mut self, reg: EnumRegister, value: u8)
+    {
+        self.write_register(reg, value);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_reg_i(&self) -> u16
+    {
+        self.reg_i
+    }
+
+    #[allow(dead_code)]
+    pub fn get_mem(&self) -> &Mem
+    {
+        &self.mem
+    }
+
+    /// Direct (host) access to main memory. NOTE: Accesses made through here do not trigger watchpoints,
+    /// and they flush the whole decode cache since any of the memory may be rewritten.
+    #[allow(dead_code)]
+    pub fn get_mem_mut(&mut self) -> &mut Mem
+    {
+        if let Some(decode_cache) = &mut self.decode_cache
         {
-            return;
+            decode_cache.clear();
         }
 
-        // Fetch:
-        let pc_ext = self.pc as usize;
+        &mut self.mem
+    }
 
-        if pc_ext >= self.mem.size()
+    /// Enables or disables caching decoded instructions by address (see 'DecodeCache').
+    /// Execution is identical either way; the cache only skips re-decoding.
+    pub fn set_decode_cache(&mut self, enabled: bool)
+    {
+        if enabled != self.decode_cache.is_some()
         {
-            self.set_halted();
-            return;
+            self.decode_cache = if enabled { Some(DecodeCache::new(self.mem.size())) } else { None };
         }
+    }
 
-        let raw_opcode: u16 = self.mem.read_u16(pc_ext).expect("Ran out of memory (logic error with pc register and main memory capacity).");
-        self.pc += INSTRUCTION_SIZE;
+    #[allow(dead_code)]
+    pub fn is_decode_cache_enabled(&self) -> bool
+    {
+        self.decode_cache.is_some()
+    }
 
-        // Decode:
-        let opcode = Opcode::new(raw_opcode);
+    pub fn get_display(&self) -> &Display
+    {
+        &self.display
+    }
 
-        // Execute:
-        // TODO: Consider moving this to a HashMap??
-        match opcode.a
-        {
-            // Call instruction
-            0 =>
-            {
-                if opcode.b == 0
-                {
-                    if opcode.c == 0 && opcode.d == 0
-                    {
-                        // NOTE: if all 0s (NULL) we will use this as a pseudo halt instruction.
-                        self.set_halted();
-                    }
+    pub fn get_display_mut(&mut self) -> &mut Display
+    {
+        &mut self.display
+    }
 
-                    // TODO: 'disp_clear()'
-                    else if opcode.c == 0x0E && opcode.d == 0x00
-                    {
-                        println!("disp_clear() is not yet supported... this instruction will be no-op'd");
-                        return;
-                    }
+    pub fn get_keypad(&self) -> &Keypad
+    {
+        &self.keypad
+    }
 
-                    else if opcode.c == 0x0E && opcode.d == 0x0E
-                    {
-                        if self.sp < 2
-                        {
-                            println!("[ERROR]: Return instruction was made but we do not have an address to return to... The system will be halted.");
-                            self.set_halted();
-                            return;
-                        }
-
-                        self.sp -= 2;
-                        self.pc = self.stack_block.read_u16(self.sp as usize).expect("Failed to unwrap Option during return instruction.");
-                    }
-
-                    else
-                    {
-                        println!("[1] Could not find instruction for opcode {:?}", opcode);
-                    }
-                }
+    pub fn get_keypad_mut(&mut self) -> &mut Keypad
+    {
+        &mut self.keypad
+    }
 
-                // Call instruction
-                else
-                {
-                    if !self.has_stack_space()
-                    {
-                        println!("[ERROR]: Call instruction was made but we are out of stack space... The system will be halted.");
-                        self.set_halted();
-                        return;
-                    }
-
-                    self.stack_block.write_u16(self.sp as usize, self.pc);
-                    self.sp += INSTRUCTION_SIZE;
-                    self.pc = opcode.raw & 0x0FFF;
-                }
-            },
-            // Jump to 12-bit address.
-            1 =>
-            {
-                let to_addr: u16 = opcode.raw & 0x0FFF;
-                self.pc = to_addr;
-            },
-            // Call to 12-bit address.
-            2 =>
-            {
-                if !self.has_stack_space()
-                {
-                    println!("[ERROR]: Call instruction was made but we are out of stack space... The system will be halted.");
-                    self.set_halted();
-                    return;
-                }
+    #[allow(dead_code)]
+    pub fn get_delay_timer(&self) -> u8
+    {
+        self.delay_timer
+    }
 
-                self.stack_block.write_u16(self.sp as usize, self.pc);
-                self.sp += INSTRUCTION_SIZE;
-                let to_addr: u16 = opcode.raw & 0x0FFF;
-                self.pc = to_addr;
-            },
-            // Set register 'b' to value 'c|d'
-            6 =>
-            {
-                let reg = EnumRegister::VALUES[opcode.b as usize];
-                let value: u8 = (opcode.raw & 0x00FF) as u8;
-                self.write_register(reg, value);
-            },
-            8 =>
-            {
-                let regb = EnumRegister::VALUES[opcode.b as usize];
-                let regc = EnumRegister::VALUES[opcode.c as usize];
-                let value: u8 = self.read_register(regc);
+    #[allow(dead_code)]
+    pub fn get_sound_timer(&self) -> u8
+    {
+        self.sound_timer
+    }
 
-                match opcode.d
-                {
-                    0 =>{
-                        self.write_register(regb, value);
-                    },
-                    1 =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(regb, cur_value | value)
-                    },
-                    2 =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(regb, cur_value & value)
-                    },
-                    3 =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(regb, cur_value ^ value)
-                    },
-                    4 =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(regb, cur_value + value)
-                    },
-                    5 =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(regb, cur_value - value)
-                    },
-                    6 =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(EnumRegister::VF, cur_value & 0x01);
-                        self.write_register(regb, cur_value >> 1)
-                    },
-                    7 =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(regb, value - cur_value)
-                    },
-                    0x0E =>
-                    {
-                        let cur_value = self.read_register(regb);
-                        self.write_register(EnumRegister::VF, (cur_value & 0x80) >> 7);
-                        self.write_register(regb, cur_value << 1);
-                    },
-                    _ => { println!("[2] Could not find instruction for opcode {:?}", opcode); }
-                }
-            },
-            // NOTE: Per optable the 'd' position should be '0'. Our implementation will ignore
-            // this completely.
-            9 =>
-            {
-                let regb = EnumRegister::VALUES[opcode.b as usize];
-                let regc = EnumRegister::VALUES[opcode.c as usize];
-                let left_value = self.read_register(regb);
-                let right_value = self.read_register(regc);
+    /// The buzzer sounds for as long as the sound timer is non-zero.
+    #[allow(dead_code)]
+    pub fn is_sound_active(&self) -> bool
+    {
+        self.sound_timer > 0
+    }
 
-                if left_value != right_value
-                {
-                    self.pc += INSTRUCTION_SIZE;
-                }
-            },
-            0x0A =>
-            {
-                self.reg_i = opcode.b << 8;
-                self.reg_i |= opcode.c << 4;
-                self.reg_i |= opcode.d;
-            },
-            0x0F =>
-            {
-                let last_reg = opcode.b as usize;
+    /// Counts the delay and sound timers down by one. Must be called once per 60 Hz frame.
+    pub fn tick_timers(&mut self)
+    {
+        self.set_timer(EnumTimer::Delay, self.delay_timer.saturating_sub(1));
+        self.set_timer(EnumTimer::Sound, self.sound_timer.saturating_sub(1));
+    }
 
-                match opcode.raw & 0x00FF
-                {
-                    0x0055 =>
-                    {
-                        for offset in 0..last_reg + 1
-                        {
-                            let effective_address: usize = self.reg_i as usize + offset;
-                            let value = self.read_register(EnumRegister::VALUES[offset]);
-                            self.mem.write_u8(effective_address, value);
-                        }
-                    },
-                    0x0065 =>
-                    {
-                        for offset in 0..last_reg + 1
-                        {
-                            let effective_address: usize = self.reg_i as usize + offset;
-                            let value = self.mem.read_u8(effective_address).expect("Address out of space");
-                            self.write_register(EnumRegister::VALUES[offset], value);
-                        }
-                    },
-                    _ => { println!("[3] Could not find instruction for opcode {:?}", opcode);},
-                }
-            },
-            _ => { println!("[4] Could not find instruction for opcode {:?}", opcode); }
+    #[inline]
+    fn set_timer(&mut self, timer: EnumTimer, value: u8)
+    {
+        let slot = match timer
+        {
+            EnumTimer::Delay => &mut self.delay_timer,
+            EnumTimer::Sound => &mut self.sound_timer,
+        };
+
+        let old_value = std::mem::replace(slot, value);
+
+        if old_value != value && !self.observers.is_empty()
+        {
+            self.observers.for_each(|observer| observer.on_timer(timer, old_value, value));
         }
     }
 
-    fn read_register(&self, reg: EnumRegister) -> u8
+    /// Registers an observer that is called as the CPU runs (see 'Observer').
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) -> ObserverId
     {
-        let value = self.registers.get(&reg).expect("Failed to unwrap during read_register routine");
-        *value
+        self.observers.add(observer)
     }
 
-    fn write_register(&mut self, reg: EnumRegister, value: u8)
+    /// Unregisters an observer and hands it back.
+    pub fn remove_observer(&mut self, id: ObserverId) -> Option<Box<dyn Observer>>
     {
-        let value_at = self.registers.get_mut(&reg).expect("Failed to unwrap during write_register routine");
-        *value_at = value;
+        self.observers.remove(id)
     }
-}
 
-#[cfg(test)]
-mod tests
-{
-    #[allow(unused_imports)]
-    use crate::hw::cpu::CPU;
-    use crate::hw::cpu::INSTRUCTION_SIZE;
-    use crate::hw::cpu::STACK_BLOCK_SIZE;
+    /// A registered observer, if it is a 'T'.
+    pub fn get_observer<T: Observer>(&self, id: ObserverId) -> Option<&T>
+    {
+        self.observers.get(id)
+    }
 
-    use super::EnumRegister;
+    pub fn get_observer_mut<T: Observer>(&mut self, id: ObserverId) -> Option<&mut T>
+    {
+        self.observers.get_mut(id)
+    }
 
-    const STARTING_PC: u16 = 0x200;
+    /// The first registered observer that is a 'T' (see 'ObserverList::find').
+    pub fn find_observer<T: Observer>(&self) -> Option<&T>
+    {
+        self.observers.find()
+    }
 
-    #[test]
-    fn cpu_and_registers_are_init_correctly()
+    pub fn find_observer_mut<T: Observer>(&mut self) -> Option<&mut T>
     {
-        let capacity: usize = 4096;
-        let cpu = CPU::new(capacity, STARTING_PC);
-        assert_eq!(cpu.mem.size(), capacity);
+        self.observers.find_mut()
+    }
 
-        let value: u8 = 0;
+    pub fn get_watch_list(&self) -> &WatchList
+    {
+        &self.watch_list
+    }
 
-        for reg in EnumRegister::VALUES
+    pub fn get_watch_list_mut(&mut self) -> &mut WatchList
+    {
+        &mut self.watch_list
+    }
+
+    pub fn has_watch_hit(&self) -> bool
+    {
+        self.watch_hit.is_some()
+    }
+
+    /// Returns (and clears) the watchpoint hit recorded by the last instruction(s), if any.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit>
+    {
+        self.watch_hit.take()
+    }
+
+    fn check_watch(&mut self, access: EnumAccess, addr: usize, old_value: u8, value: u8)
+    {
+        // Only the first hit is kept until the debugger consumes it.
+        if self.watch_hit.is_some()
         {
-            assert_eq!(cpu.read_register(reg), value);
+            return;
         }
 
-        assert_eq!(cpu.pc, STARTING_PC);
-        assert_eq!(cpu.sp, 0);
-        assert_eq!(cpu.stack_block.size(), STACK_BLOCK_SIZE as usize);
-        assert!(!cpu.is_halted());
+        if let Some(id) = self.watch_list.check(access, addr, value)
+        {
+            self.watch_hit = Some(WatchHit { id, access, addr, old_value, value, pc: self.cur_pc, opcode: self.cur_opcode });
+        }
     }
 
-    #[test]
-    fn fill_stack_then_empty()
+    /// Reads a byte of main memory on behalf of the executing instruction.
+    /// NOTE: All instruction data accesses must go through here (or 'write_mem_u8') so watchpoints see them.
+    fn read_mem_u8(&mut self, addr: usize) -> Result<u8, EnumFault>
     {
-        let capacity: usize = 4096;
-        let mut cpu = CPU::new(capacity, STARTING_PC);
-        assert_eq!(cpu.stack_block.size(), STACK_BLOCK_SIZE as usize);
+        let value = match self.mem.read_u8(addr)
+        {
+            Some(value) => value,
+            None => { return Err(EnumFault::MemOutOfBounds { pc: self.cur_pc, addr }); },
+        };
 
-        let half_size = (cpu.stack_block.size() / 2) as u16;
+        if !self.observers.is_empty()
+        {
+            let pc = self.cur_pc;
+            self.observers.for_each(|observer| observer.on_mem_read(pc, addr, value));
+        }
 
-        for i in 0..half_size
+        if !self.watch_list.is_empty()
         {
-            let ret_address = i + 2;
-            assert!(cpu.has_stack_space());
-            assert!(cpu.push_stack(ret_address));
+            self.check_watch(EnumAccess::Read, addr, value, value);
         }
 
-        // Try to push one more
-        assert!(!cpu.has_stack_space());
-        assert!(!cpu.push_stack(0xFFFF));
+        Ok(value)
+    }
 
-        // Pop until empty
-        for i in (0..half_size).rev()
+    /// Writes a byte of main memory on behalf of the executing instruction.
+    fn write_mem_u8(&mut self, addr: usize, value: u8) -> Result<(), EnumFault>
+    {
+        let old_value = match self.mem.read_u8(addr)
         {
-            let expected_ret_address = i + 2;
-            let opt_ret_address = cpu.pop_stack();
-            assert!(opt_ret_address.is_some());
-            assert_eq!(opt_ret_address.unwrap(), expected_ret_address);
+            Some(old_value) => old_value,
+            None => { return Err(EnumFault::MemOutOfBounds { pc: self.cur_pc, addr }); }
+        };
+
+        self.mem.write_u8(addr, value);
+
+        // Self-modifying code: drop any decoded instruction this byte belongs to.
+        if let Some(decode_cache) = &mut self.decode_cache
+        {
+            decode_cache.invalidate(addr);
+        }
+
+        if !self.observers.is_empty()
+        {
+            let pc = self.cur_pc;
+            self.observers.for_each(|observer| observer.on_mem_write(pc, addr, old_value, value));
+        }
+
+        if !self.watch_list.is_empty()
+        {
+            self.check_watch(EnumAccess::Write, addr, old_value, value);
+        }
+
+        return Ok(());
+    }
+
+    #[allow(dead_code)]
+    fn has_stack_space(&self) -> bool
+    {
+        (self.sp as usize) < self.stack_block.size()
+    }
+
+    fn pop_stack(&mut self) -> Option<u16>
+    {
+        if self.sp < 2
+        {
+            return None;
+        }
+
+        self.sp -= INSTRUCTION_SIZE;
+        let result = self.stack_block.read_u16(self.sp as usize);
+
+        if let Some(ret_address) = result.filter(|_| !self.observers.is_empty())
+        {
+            let (pc, depth) = (self.cur_pc, (self.sp / INSTRUCTION_SIZE) as usize);
+            self.observers.for_each(|observer| observer.on_stack_pop(pc, ret_address, depth));
+        }
+
+        return result;
+    }
+
+    fn push_stack(&mut self, ret_address: u16) -> bool
+    {
+        if self.sp < STACK_BLOCK_SIZE - 1
+        {
+            self.stack_block.write_u16(self.sp as usize, ret_address);
+            self.sp += INSTRUCTION_SIZE;
+
+            if !self.observers.is_empty()
+            {
+                let (pc, depth) = (self.cur_pc, (self.sp / INSTRUCTION_SIZE) as usize);
+                self.observers.for_each(|observer| observer.on_stack_push(pc, ret_address, depth));
+            }
+
+            return true;
+        }
+
+        return false;
+    }
+
+    /// The return addresses on the stack, outermost call first.
+    pub fn get_call_stack(&self) -> Vec<u16>
+    {
+        (0..self.sp).step_by(INSTRUCTION_SIZE as usize).filter_map(|addr| self.stack_block.read_u16(addr as usize)).collect()
+    }
+
+    pub fn print_state(&self, format: EnumDumpFormat)
+    {
+        let mut stream = String::with_capacity(0x200);
+        self.format_state(format, &mut stream);
+        print!("{}", stream);
+    }
+
+    /// Registers, I, pc, sp, call stack, timers, keypad, halt/fault state and quirks (memory is left to 'Mem::hexdump').
+    pub fn format_state(&self, format: EnumDumpFormat, stream: &mut String)
+    {
+        let call_stack = self.get_call_stack();
+        let keys: Vec<u8> = (0..KEY_COUNT as u8).filter(|key| self.keypad.is_pressed(*key)).collect();
+
+        match format
+        {
+            EnumDumpFormat::Text =>
+            {
+                stream.push_str(&format!("\tpc: 0x{0:03X}  i: 0x{1:03X}  sp: 0x{2:02X}\n", self.pc, self.reg_i, self.sp));
+
+                for (row_index, values) in self.registers.chunks(8).enumerate()
+                {
+                    let text: Vec<String> = values.iter().enumerate().map(|(index, value)| format!("V{0:X}={1:02X}", row_index * 8 + index, value)).collect();
+                    stream.push_str(&format!("\t{}\n", text.join(" ")));
+                }
+
+                stream.push_str("\tcall stack:");
+
+                if call_stack.is_empty()
+                {
+                    stream.push_str(" empty");
+                }
+
+                // Innermost frame first, like a backtrace.
+                for (depth, ret_address) in call_stack.iter().rev().enumerate()
+                {
+                    stream.push_str(&format!(" #{0} ret 0x{1:03X}", depth, ret_address));
+                }
+
+                stream.push_str(&format!("\n\ttimers: delay 0x{0:02X}  sound 0x{1:02X}\n", self.delay_timer, self.sound_timer));
+
+                let key_text: Vec<String> = keys.iter().map(|key| format!("{:X}", key)).collect();
+                stream.push_str(&format!("\tkeys down: {}\n", if key_text.is_empty() { String::from("none") } else { key_text.join(" ") }));
+
+                match self.halt_reason.as_ref().filter(|_| self.halted)
+                {
+                    Some(reason) => stream.push_str(&format!("\thalted: yes ({})\n", reason)),
+                    None => stream.push_str("\thalted: no\n"),
+                }
+
+                if let Some(fault) = &self.last_fault
+                {
+                    stream.push_str(&format!("\tlast fault: {}\n", fault));
+                }
+
+                stream.push_str(&format!("\tinvalid opcodes: {0} (on-invalid {1}, 0000 {2})\n", self.invalid_count, self.invalid_policy,
+                                         if self.zero_halts { "halts" } else { "is invalid" }));
+                stream.push_str(&format!("\tquirks: {}\n", self.quirks));
+            },
+            EnumDumpFormat::Json =>
+            {
+                let join = |values: &mut dyn Iterator<Item = String>| values.collect::<Vec<String>>().join(",");
+
+                let fault_fields = |fault: &EnumFault| format!("\"pc\":{0},\"message\":\"{1}\"", fault.get_pc(), escape_json(&fault.to_string()));
+
+                let halt_reason = match self.halt_reason.as_ref().filter(|_| self.halted)
+                {
+                    Some(EnumHaltReason::Instruction { pc }) => format!("{{\"kind\":\"instruction\",\"pc\":{}}}", pc),
+                    Some(EnumHaltReason::Fault(fault)) => format!("{{\"kind\":\"fault\",{}}}", fault_fields(fault)),
+                    Some(EnumHaltReason::Host) => String::from("{\"kind\":\"host\"}"),
+                    None => String::from("null"),
+                };
+
+                let last_fault = match &self.last_fault
+                {
+                    Some(fault) => format!("{{{}}}", fault_fields(fault)),
+                    None => String::from("null"),
+                };
+
+                // Values are already JSON, in the order they're printed.
+                let fields = [
+                    ("pc", self.pc.to_string()),
+                    ("i", self.reg_i.to_string()),
+                    ("sp", self.sp.to_string()),
+                    ("registers", format!("[{}]", join(&mut self.registers.iter().map(|value| value.to_string())))),
+                    ("call_stack", format!("[{}]", join(&mut call_stack.iter().map(|ret_address| ret_address.to_string())))),
+                    ("delay_timer", self.delay_timer.to_string()),
+                    ("sound_timer", self.sound_timer.to_string()),
+                    ("keys_down", format!("[{}]", join(&mut keys.iter().map(|key| key.to_string())))),
+                    ("halted", self.halted.to_string()),
+                    ("halt_reason", halt_reason),
+                    ("last_fault", last_fault),
+                    ("invalid_opcodes", self.invalid_count.to_string()),
+                    ("on_invalid", format!("\"{}\"", self.invalid_policy)),
+                    ("zero_halts", self.zero_halts.to_string()),
+                    ("quirks", format!("[{}]", join(&mut self.quirks.enabled().iter().map(|name| format!("\"{}\"", name))))),
+                ];
+
+                stream.push_str(&format!("{{{}}}\n", join(&mut fields.into_iter().map(|(name, value)| format!("\"{0}\":{1}", name, value)))));
+            },
+        }
+    }
+
+    /// Executes the next instruction. On a fault the instruction is abandoned, the fault is recorded as the
+    /// last fault and it is up to the host to decide whether to halt, skip it or break into the debugger.
+    pub fn tick(&mut self) -> Result<EnumTickOutcome, EnumFault>
+    {
+        if self.is_halted()
+        {
+            return Ok(EnumTickOutcome::Halted);
+        }
+
+        let result = self.execute_next();
+
+        if let Err(fault) = &result
+        {
+            self.last_fault = Some(fault.clone());
+        }
+
+        // Hand the observers the whole CPU (they're moved out meanwhile so it can be borrowed).
+        if !self.observers.is_empty()
+        {
+            let mut observers = std::mem::take(&mut self.observers);
+            let (pc, raw_opcode) = (self.cur_pc, self.cur_opcode);
+            observers.for_each(|observer| observer.on_execute(self, pc, raw_opcode, &result));
+            self.observers = observers;
+        }
+
+        return result;
+    }
+
+    fn execute_next(&mut self) -> Result<EnumTickOutcome, EnumFault>
+    {
+        // Fetch and decode (or look up both in the cache):
+        let pc_ext = self.pc as usize;
+        self.cur_pc = self.pc;
+
+        let cached = match &self.decode_cache
+        {
+            Some(decode_cache) => decode_cache.get(pc_ext),
+            None => None,
+        };
+
+        let (raw_opcode, instruction) = match cached
+        {
+            Some(entry) => entry,
+            None =>
+            {
+                let raw_opcode: u16 = match self.mem.read_u16(pc_ext)
+                {
+                    Some(raw_opcode) => raw_opcode,
+                    None =>
+                    {
+                        self.cur_opcode = 0;
+                        return Err(EnumFault::PcOutOfBounds { pc: self.pc });
+                    },
+                };
+
+                let instruction = Instruction::decode(raw_opcode);
+
+                if let Some(decode_cache) = &mut self.decode_cache
+                {
+                    decode_cache.insert(pc_ext, raw_opcode, instruction);
+                }
+
+                (raw_opcode, instruction)
+            },
+        };
+
+        self.cur_opcode = raw_opcode;
+        self.skip_instruction()?;
+
+        if !self.observers.is_empty()
+        {
+            self.observers.for_each(|observer| observer.on_fetch(self.cur_pc, raw_opcode));
         }
 
-        // Try to pop one more
-        assert!(cpu.has_stack_space());
-        assert!(cpu.pop_stack().is_none());
+        self.execute(instruction)
+    }
+
+    /// Moves the pc past one instruction. At the top of a 64K memory there's nothing to move to.
+    fn skip_instruction(&mut self) -> Result<(), EnumFault>
+    {
+        self.pc = self.pc.checked_add(INSTRUCTION_SIZE).ok_or(EnumFault::PcOutOfBounds { pc: self.cur_pc })?;
+        Ok(())
+    }
+
+    /// Executes an instruction fetched from 'cur_pc' (the pc already points at the next one).
+    fn execute(&mut self, instruction: Instruction) -> Result<EnumTickOutcome, EnumFault>
+    {
+        match instruction
+        {
+            Instruction::Halt if self.zero_halts =>
+            {
+                self.set_halted(EnumHaltReason::Instruction { pc: self.cur_pc });
+                return Ok(EnumTickOutcome::Halted);
+            },
+            Instruction::ClearScreen =>
+            {
+                self.display.clear();
+            },
+            Instruction::Return =>
+            {
+                match self.pop_stack()
+                {
+                    Some(ret_address) =>
+                    {
+                        self.pc = ret_address;
+                    },
+                    None => { return Err(EnumFault::StackUnderflow { pc: self.cur_pc }); },
+                }
+            },
+            Instruction::Call { nnn } =>
+            {
+                if !self.push_stack(self.pc)
+                {
+                    return Err(EnumFault::StackOverflow { pc: self.cur_pc });
+                }
+
+                self.pc = nnn;
+            },
+            Instruction::Jump { nnn } =>
+            {
+                self.pc = nnn;
+            },
+            Instruction::SkipIfEqual { x, nn } =>
+            {
+                if self.read_register(x) == nn
+                {
+                    self.skip_instruction()?;
+                }
+            },
+            Instruction::SkipIfNotEqual { x, nn } =>
+            {
+                if self.read_register(x) != nn
+                {
+                    self.skip_instruction()?;
+                }
+            },
+            Instruction::SkipIfRegistersEqual { x, y } =>
+            {
+                if self.read_register(x) == self.read_register(y)
+                {
+                    self.skip_instruction()?;
+                }
+            },
+            Instruction::SkipIfRegistersNotEqual { x, y } =>
+            {
+                if self.read_register(x) != self.read_register(y)
+                {
+                    self.skip_instruction()?;
+                }
+            },
+            Instruction::Set { x, nn } =>
+            {
+                self.write_register(x, nn);
+            },
+            Instruction::AddImmediate { x, nn } =>
+            {
+                let value = self.read_register(x).wrapping_add(nn);
+                self.write_register(x, value);
+            },
+            // NOTE: For the flag setting instructions VF is written last, so it wins if it is also the destination.
+            Instruction::Move { x, y } =>
+            {
+                self.write_register(x, self.read_register(y));
+            },
+            Instruction::Or { x, y } | Instruction::And { x, y } | Instruction::Xor { x, y } =>
+            {
+                let cur_value = self.read_register(x);
+                let value = self.read_register(y);
+
+                let result = match instruction
+                {
+                    Instruction::Or { .. } => cur_value | value,
+                    Instruction::And { .. } => cur_value & value,
+                    _ => cur_value ^ value,
+                };
+
+                self.write_register(x, result);
+
+                if self.quirks.logic_resets_vf
+                {
+                    self.write_register(EnumRegister::VF, 0);
+                }
+            },
+            Instruction::Add { x, y } =>
+            {
+                let (result, carry) = self.read_register(x).overflowing_add(self.read_register(y));
+                self.write_register(x, result);
+                self.write_register(EnumRegister::VF, carry as u8);
+            },
+            Instruction::Sub { x, y } =>
+            {
+                let (result, borrow) = self.read_register(x).overflowing_sub(self.read_register(y));
+                self.write_register(x, result);
+                self.write_register(EnumRegister::VF, !borrow as u8);
+            },
+            Instruction::SubReversed { x, y } =>
+            {
+                let (result, borrow) = self.read_register(y).overflowing_sub(self.read_register(x));
+                self.write_register(x, result);
+                self.write_register(EnumRegister::VF, !borrow as u8);
+            },
+            Instruction::ShiftRight { x, y } =>
+            {
+                let cur_value = self.read_register(if self.quirks.shift_uses_vy { y } else { x });
+                self.write_register(x, cur_value >> 1);
+                self.write_register(EnumRegister::VF, cur_value & 0x01);
+            },
+            Instruction::ShiftLeft { x, y } =>
+            {
+                let cur_value = self.read_register(if self.quirks.shift_uses_vy { y } else { x });
+                self.write_register(x, cur_value << 1);
+                self.write_register(EnumRegister::VF, (cur_value & 0x80) >> 7);
+            },
+            Instruction::SetI { nnn } =>
+            {
+                self.reg_i = nnn;
+            },
+            // Jump to NNN plus V0 (or plus VX with the jump quirk).
+            Instruction::JumpOffset { x, nnn } =>
+            {
+                let offset = self.read_register(if self.quirks.jump_uses_vx { x } else { EnumRegister::V0 }) as u16;
+                self.pc = (nnn + offset) & 0x0FFF;
+            },
+            Instruction::Random { x, nn } =>
+            {
+                let value = self.rng.next_u8() & nn;
+                self.write_register(x, value);
+            },
+            // Draw the 'n' rows tall sprite at address i to position (VX, VY) and set VF on collision.
+            Instruction::Draw { x, y, n } =>
+            {
+                let pos_x = self.read_register(x) as usize;
+                let pos_y = self.read_register(y) as usize;
+                let mut rows = [0u8; 15];
+                let row_count = n as usize;
+
+                for (offset, row) in rows.iter_mut().enumerate().take(row_count)
+                {
+                    *row = self.read_mem_u8(self.reg_i as usize + offset)?;
+                }
+
+                let collision = self.display.draw_sprite(pos_x, pos_y, &rows[..row_count], self.quirks.wrap_sprites);
+                self.write_register(EnumRegister::VF, collision as u8);
+
+                if !self.observers.is_empty()
+                {
+                    let pc = self.cur_pc;
+                    self.observers.for_each(|observer| observer.on_draw(pc, pos_x, pos_y, &rows[..row_count], collision));
+                }
+            },
+            Instruction::SkipIfKey { x } =>
+            {
+                if self.keypad.is_pressed(self.read_register(x))
+                {
+                    self.skip_instruction()?;
+                }
+            },
+            Instruction::SkipIfNotKey { x } =>
+            {
+                if !self.keypad.is_pressed(self.read_register(x))
+                {
+                    self.skip_instruction()?;
+                }
+            },
+            Instruction::GetDelay { x } =>
+            {
+                self.write_register(x, self.delay_timer);
+            },
+            // Block until a key is pressed and store it in VX.
+            Instruction::WaitForKey { x } =>
+            {
+                match self.keypad.first_pressed()
+                {
+                    Some(key) => { self.write_register(x, key); },
+                    None =>
+                    {
+                        // Re-execute this instruction until a key shows up (timers keep running meanwhile).
+                        self.pc = self.cur_pc;
+                        return Ok(EnumTickOutcome::WaitingForKey);
+                    },
+                }
+            },
+            Instruction::SetDelay { x } =>
+            {
+                self.set_timer(EnumTimer::Delay, self.read_register(x));
+            },
+            Instruction::SetSound { x } =>
+            {
+                self.set_timer(EnumTimer::Sound, self.read_register(x));
+            },
+            Instruction::AddI { x } =>
+            {
+                let value = self.read_register(x) as u16;
+                self.reg_i = (self.reg_i + value) & 0x0FFF;
+            },
+            // Point register 'i' at the built-in font glyph for the low nibble of VX.
+            Instruction::Font { x } =>
+            {
+                let digit = (self.read_register(x) & 0x0F) as u16;
+                self.reg_i = FONT_ADDRESS + digit * FONT_GLYPH_SIZE;
+            },
+            // Store the binary-coded decimal value of VX at addresses i, i + 1 and i + 2.
+            Instruction::Bcd { x } =>
+            {
+                let value = self.read_register(x);
+                let effective_address = self.reg_i as usize;
+                self.write_mem_u8(effective_address, value / 100)?;
+                self.write_mem_u8(effective_address + 1, (value / 10) % 10)?;
+                self.write_mem_u8(effective_address + 2, value % 10)?;
+            },
+            Instruction::Store { x } =>
+            {
+                for offset in 0..x.index() + 1
+                {
+                    let effective_address: usize = self.reg_i as usize + offset;
+                    let value = self.read_register(EnumRegister::from_index(offset));
+                    self.write_mem_u8(effective_address, value)?;
+                }
+
+                if self.quirks.load_store_increments_i
+                {
+                    self.reg_i = (self.reg_i + x.index() as u16 + 1) & 0x0FFF;
+                }
+            },
+            Instruction::Load { x } =>
+            {
+                for offset in 0..x.index() + 1
+                {
+                    let effective_address: usize = self.reg_i as usize + offset;
+                    let value = self.read_mem_u8(effective_address)?;
+                    self.write_register(EnumRegister::from_index(offset), value);
+                }
+
+                if self.quirks.load_store_increments_i
+                {
+                    self.reg_i = (self.reg_i + x.index() as u16 + 1) & 0x0FFF;
+                }
+            },
+            Instruction::Halt | Instruction::MachineCall { .. } | Instruction::Unknown =>
+            {
+                self.invalid_count += 1;
+
+                if self.invalid_policy != EnumInvalidPolicy::Nop
+                {
+                    return Err(EnumFault::UnknownOpcode { pc: self.cur_pc, opcode: self.cur_opcode });
+                }
+            },
+        }
+
+        return Ok(EnumTickOutcome::Executed);
+    }
+
+    #[inline]
+    fn read_register(&self, reg: EnumRegister) -> u8
+    {
+        self.registers[reg.index()]
+    }
+
+    #[inline]
+    fn write_register(&mut self, reg: EnumRegister, value: u8)
+    {
+        self.registers[reg.index()] = value;
+    }
+}
+
+/// Escapes quotes, backslashes and control characters for a JSON string.
+fn escape_json(text: &str) -> String
+{
+    let mut result = String::with_capacity(text.len());
+
+    for c in text.chars()
+    {
+        match c
+        {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests
+{
+    #[allow(unused_imports)]
+    use crate::hw::cpu::{EnumDumpFormat, EnumHaltReason, CPU};
+    use crate::hw::cpu::INSTRUCTION_SIZE;
+    use crate::hw::cpu::STACK_BLOCK_SIZE;
+    use crate::hw::cpu::{FONT_ADDRESS, FONT_GLYPH_SIZE};
+    use crate::hw::fault::{EnumFault, EnumInvalidPolicy, EnumTickOutcome};
+    use crate::hw::quirks::Quirks;
+    use crate::hw::rng::Rng;
+
+    use super::EnumRegister;
+    use crate::dbg::coverage::Coverage;
+    use crate::dbg::profiler::Profiler;
+    use crate::dbg::smc::{EnumSmcKind, SmcDetector};
+    use crate::dbg::watchpoint::{EnumAccess, EnumWatchKind};
+
+    const STARTING_PC: u16 = 0x200;
+
+    #[test]
+    fn cpu_and_registers_are_init_correctly()
+    {
+        let capacity: usize = 4096;
+        let cpu = CPU::new(capacity, STARTING_PC);
+        assert_eq!(cpu.mem.size(), capacity);
+
+        let value: u8 = 0;
+
+        for reg in EnumRegister::VALUES
+        {
+            assert_eq!(cpu.read_register(reg), value);
+        }
+
+        assert_eq!(cpu.pc, STARTING_PC);
+        assert_eq!(cpu.sp, 0);
+        assert_eq!(cpu.stack_block.size(), STACK_BLOCK_SIZE as usize);
+        assert!(!cpu.is_halted());
+    }
+
+    #[test]
+    fn fill_stack_then_empty()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        assert_eq!(cpu.stack_block.size(), STACK_BLOCK_SIZE as usize);
+
+        let half_size = (cpu.stack_block.size() / 2) as u16;
+
+        for i in 0..half_size
+        {
+            let ret_address = i + 2;
+            assert!(cpu.has_stack_space());
+            assert!(cpu.push_stack(ret_address));
+        }
+
+        // Try to push one more
+        assert!(!cpu.has_stack_space());
+        assert!(!cpu.push_stack(0xFFFF));
+
+        // Pop until empty
+        for i in (0..half_size).rev()
+        {
+            let expected_ret_address = i + 2;
+            let opt_ret_address = cpu.pop_stack();
+            assert!(opt_ret_address.is_some());
+            assert_eq!(opt_ret_address.unwrap(), expected_ret_address);
+        }
+
+        // Try to pop one more
+        assert!(cpu.has_stack_space());
+        assert!(cpu.pop_stack().is_none());
+    }
+
+    #[test]
+    fn fill_registers_and_readback()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+
+        for (i, reg) in (0u8..).zip(EnumRegister::VALUES)
+        {
+            let value = i * i;
+            cpu.write_register(reg, value);
+            assert_eq!(cpu.read_register(reg), value);
+        }
+    }
+
+    #[test]
+    fn execute_halt_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+
+        let mem_addr = cpu.pc as usize;
+
+        // Load memory with simple halt instruction, which happens to already be all zeros...
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_set_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x61AF);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0xAF);
+
+        // This should set V1 = 0xAF
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0xAF);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_assign_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x61A0);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x620F);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x8120);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA0);
+        assert_ne!(cpu.read_register(EnumRegister::V2), 0x0F);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V2 = 0x0F
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // This should set V1 = V2
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x0F);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_or_equal_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x61A0);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x620F);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x8121);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA0);
+        assert_ne!(cpu.read_register(EnumRegister::V2), 0x0F);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V2 = 0x0F
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // This should set V1 = V2
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0xAF);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_and_equal_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x61A3);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x622F);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x8122);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA3);
+        assert_ne!(cpu.read_register(EnumRegister::V2), 0x2F);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V2 = 0x0F
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // This should set V1 = V2
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0xA3 & 0x2F);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_xor_equal_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x61A3);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x622F);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x8123);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA3);
+        assert_ne!(cpu.read_register(EnumRegister::V2), 0x2F);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V2 = 0x0F
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // This should set V1 = V2
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0xA3 ^ 0x2F);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_plus_equal_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x61A3);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x622F);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x8125);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA3);
+        assert_ne!(cpu.read_register(EnumRegister::V2), 0x2F);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V2 = 0x0F
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // This should set V1 = V2
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0xA3 - 0x2F);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_rshift_equal_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x6103);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x8106);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0x03);
+        assert_ne!(cpu.read_register(EnumRegister::VF), 0x01);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V2 = 0x0F
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x01);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 0x01);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_lshift_equal_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let mut mem_addr = cpu.pc as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x6183);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x810E);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0x03);
+        assert_ne!(cpu.read_register(EnumRegister::VF), 0x01);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V1 = V2
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x06);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 0x01);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
     }
 
     #[test]
-    fn fill_registers_and_readback()
+    fn execute_inv_minus_equal_instruction()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
-        let mut i = 0;
+        let mut mem_addr = cpu.pc as usize;
 
-        for reg in EnumRegister::VALUES
-        {
-            let value = i * i;
-            cpu.write_register(reg, value);
-            assert_eq!(cpu.read_register(reg), value);
-            i += 1;
-        }
+        cpu.mem.write_u16(mem_addr, 0x6101);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x6203);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x8127);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0x01);
+        assert_ne!(cpu.read_register(EnumRegister::V2), 0x03);
+
+        // This should set V1 = 0xA0
+        assert!(cpu.tick().is_ok());
+
+        // This should set V2 = 0x0F
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // This should set V1 = V2
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x02);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_halt_instruction()
+    fn execute_jump_instruction()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        let mem_addr = cpu.pc as usize;
+        let mut mem_addr = cpu.pc as usize;
+        let jump_addr = (capacity as u16) - 4;
 
-        // Load memory with simple halt instruction, which happens to already be all zeros...
+        cpu.mem.write_u16(mem_addr, 0x1000 | jump_addr);
+        mem_addr = jump_addr as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+        assert_ne!(cpu.pc, jump_addr);
+
+        // This should jump to 
+        assert!(cpu.tick().is_ok());
+
+        // Verify we jumped to the correct address.
+        assert_eq!(cpu.pc, jump_addr);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_set_reg_i_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+
+        let mut mem_addr = cpu.pc as usize;
+        let value: u16 = 0x0123;
+
+        cpu.mem.write_u16(mem_addr, 0xA000 | value);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
+        assert!(!cpu.is_halted());
+        assert_ne!(cpu.reg_i, value);
+
+        // This should jump to 
+        assert!(cpu.tick().is_ok());
+
+        // Verify we jumped to the correct address.
+        assert_eq!(cpu.reg_i, value);
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn execute_display_clear_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+
+        let mut mem_addr = cpu.pc as usize;
+
+        // display clear instruction
+        cpu.mem.write_u16(mem_addr, 0x00E0);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
+        cpu.display.draw_sprite(0, 0, &[0xFF], false);
+        assert!(cpu.display.get_pixel(0, 0));
+
         // Try executing our 'fake' program
         assert!(!cpu.is_halted());
-        cpu.tick();
+
+        // This should clear the display
+        assert!(cpu.tick().is_ok());
+        assert!(!cpu.display.get_pixel(0, 0));
+
+        // Execute halt instruction
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
         assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_set_instruction()
+    fn execute_call2_set_return_instructions()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
 
         let mut mem_addr = cpu.pc as usize;
+        let call_addr = (capacity as u16) - 8;
+
+        // Call instruction
+        cpu.mem.write_u16(mem_addr, 0x2000 | call_addr);
+        let ret_addr = mem_addr + 2;
+        mem_addr = call_addr as usize;
 
+        // Then do set
         cpu.mem.write_u16(mem_addr, 0x61AF);
         mem_addr += INSTRUCTION_SIZE as usize;
 
+        // Then do return
+        cpu.mem.write_u16(mem_addr, 0x00EE);
+        mem_addr = ret_addr;
+
         // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
         // Try executing our 'fake' program
         assert!(!cpu.is_halted());
+        assert_ne!(cpu.pc, call_addr);
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xAF);
+        // This should be the call instruction
+        assert!(cpu.tick().is_ok());
 
-        // This should set V1 = 0xAF
-        cpu.tick();
+        // Verify we called/jumped to the correct address.
+        assert_eq!(cpu.pc, call_addr);
 
-        // Verify V1 register is correct
+        // Execute the set instruction
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0xAF);
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
         assert_eq!(cpu.read_register(EnumRegister::V1), 0xAF);
 
+        // Execute the return instruction
+        assert!(!cpu.is_halted());
+        assert_ne!(cpu.pc as usize, ret_addr);
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc as usize, ret_addr);
+
         // Execute halt instruction
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
         assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_assign_instruction()
+    fn execute_cond_instruction_no_skip()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
+
         let mut mem_addr = cpu.pc as usize;
+        let jump_addr = (capacity as u16) - 4;
 
-        cpu.mem.write_u16(mem_addr, 0x61A0);
-        mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x620F);
+        cpu.mem.write_u16(mem_addr, 0x6101);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x8120);
+
+        cpu.mem.write_u16(mem_addr, 0x9120);
         mem_addr += INSTRUCTION_SIZE as usize;
 
+        cpu.mem.write_u16(mem_addr, 0x1000 | jump_addr);
+        mem_addr = jump_addr as usize;
+
         // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
@@ -518,41 +1715,117 @@ mod tests
         assert!(!cpu.is_halted());
 
         // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA0);
-        assert_ne!(cpu.read_register(EnumRegister::V2), 0x0F);
+        assert_ne!(cpu.read_register(EnumRegister::V1), 1);
 
-        // This should set V1 = 0xA0
-        cpu.tick();
+        // This should set V1 = 0x01
+        assert!(cpu.tick().is_ok());
 
-        // This should set V2 = 0x0F
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x01);
+
+        // This should check if V1 != V2
+        assert!(cpu.tick().is_ok());
+
+        // Verify we did NOT jump to the address.
+        assert_ne!(cpu.pc, jump_addr);
+
+        // Execute halt instruction
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.is_halted());
+    }
 
-        // This should set V1 = V2
+    #[test]
+    fn execute_cond_instruction_do_skip()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+
+        let mut mem_addr = cpu.pc as usize;
+        let jump_addr = (capacity as u16) - 4;
+
+        cpu.mem.write_u16(mem_addr, 0x6101);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x8210);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x9120);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        cpu.mem.write_u16(mem_addr, 0x1000 | jump_addr);
+        mem_addr = jump_addr as usize;
+
+        // Then halt
+        cpu.mem.write_u16(mem_addr, 0);
+
+        // Try executing our 'fake' program
         assert!(!cpu.is_halted());
-        cpu.tick();
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V1), 1);
+
+        // This should set V1 = 0x01
+        assert!(cpu.tick().is_ok());
 
         // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0x0F);
+        assert_eq!(cpu.read_register(EnumRegister::V1), 1);
+
+        // Make sure register is not our value first
+        assert_ne!(cpu.read_register(EnumRegister::V2), 1);
+
+        // This should set V2 = 0x01
+        assert!(cpu.tick().is_ok());
+
+        // Verify V1 register is correct
+        assert_eq!(cpu.read_register(EnumRegister::V2), 1);
+
+        // This should check if V1 != V2
+        assert!(cpu.tick().is_ok());
+
+        // Verify we did NOT jump yet to the address.
+        assert_ne!(cpu.pc, jump_addr);
+
+        // This should check if V1 != V2
+        assert!(cpu.tick().is_ok());
+
+        // Verify we did jump to the address.
+        assert_eq!(cpu.pc, jump_addr);
 
         // Execute halt instruction
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
         assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_or_equal_instruction()
+    fn execute_set_three_regs_dump_then_load_instructions()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
+
         let mut mem_addr = cpu.pc as usize;
 
-        cpu.mem.write_u16(mem_addr, 0x61A0);
+        // Set registers V1, V3, and i
+        cpu.mem.write_u16(mem_addr, 0x6110);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x620F);
+        cpu.mem.write_u16(mem_addr, 0x630F);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x8121);
+        cpu.mem.write_u16(mem_addr, 0xAABC);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Dump all registers starting at register 'i'.
+        cpu.mem.write_u16(mem_addr, 0xFF55);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // zero registers
+        cpu.mem.write_u16(mem_addr, 0x6100);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0x6300);
+        mem_addr += INSTRUCTION_SIZE as usize;
+
+        // Load registers back in.
+        cpu.mem.write_u16(mem_addr, 0xFF65);
         mem_addr += INSTRUCTION_SIZE as usize;
 
         // Then halt
@@ -562,673 +1835,852 @@ mod tests
         assert!(!cpu.is_halted());
 
         // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA0);
-        assert_ne!(cpu.read_register(EnumRegister::V2), 0x0F);
-
-        // This should set V1 = 0xA0
-        cpu.tick();
-
-        // This should set V2 = 0x0F
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0x10);
+        assert_ne!(cpu.read_register(EnumRegister::V3), 0x0F);
 
-        // This should set V1 = V2
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        // This should set V1 = 0x10
+        assert!(cpu.tick().is_ok());
 
         // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0xAF);
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x10);
 
-        // Execute halt instruction
+        // This fhould set V3 = 0x0F
         assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
-    }
+        assert!(cpu.tick().is_ok());
 
-    #[test]
-    fn execute_and_equal_instruction()
-    {
-        let capacity: usize = 4096;
-        let mut cpu = CPU::new(capacity, STARTING_PC);
-        let mut mem_addr = cpu.pc as usize;
+        // Verify register 'i' isn't set.
+        assert_ne!(cpu.reg_i, 0x0ABC);
 
-        cpu.mem.write_u16(mem_addr, 0x61A3);
-        mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x622F);
-        mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x8122);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        // This should set register 'i'
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+        // Verify register 'i' is correct
+        assert_eq!(cpu.reg_i, 0x0ABC);
 
-        // Try executing our 'fake' program
+        // This should Dump our registers to memory starting at the address in register 'i'.
         assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA3);
-        assert_ne!(cpu.read_register(EnumRegister::V2), 0x2F);
-
-        // This should set V1 = 0xA0
-        cpu.tick();
+        // Zero registers
+        assert_ne!(cpu.read_register(EnumRegister::V1), 0);
+        assert_ne!(cpu.read_register(EnumRegister::V3), 0);
 
-        // This should set V2 = 0x0F
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
+        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
 
-        // This should set V1 = V2
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0);
+        assert_eq!(cpu.read_register(EnumRegister::V3), 0);
+
+        // Load back into memory.
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0xA3 & 0x2F);
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x10);
+        assert_eq!(cpu.read_register(EnumRegister::V3), 0x0F);
 
         // Execute halt instruction
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
         assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_xor_equal_instruction()
+    fn execute_bcd_instruction()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
         let mut mem_addr = cpu.pc as usize;
 
-        cpu.mem.write_u16(mem_addr, 0x61A3);
+        cpu.mem.write_u16(mem_addr, 0x61FE);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x622F);
+        cpu.mem.write_u16(mem_addr, 0xA300);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x8123);
+        cpu.mem.write_u16(mem_addr, 0xF133);
         mem_addr += INSTRUCTION_SIZE as usize;
 
         // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
-
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA3);
-        assert_ne!(cpu.read_register(EnumRegister::V2), 0x2F);
-
-        // This should set V1 = 0xA0
-        cpu.tick();
-
-        // This should set V2 = 0x0F
-        assert!(!cpu.is_halted());
-        cpu.tick();
-
-        // This should set V1 = V2
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0xA3 ^ 0x2F);
+        // 0xFE == 254
+        assert_eq!(cpu.mem.read_u8(0x300), Some(2));
+        assert_eq!(cpu.mem.read_u8(0x301), Some(5));
+        assert_eq!(cpu.mem.read_u8(0x302), Some(4));
 
         // Execute halt instruction
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
         assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_plus_equal_instruction()
+    fn write_watchpoint_reports_pc_and_opcode()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
         let mut mem_addr = cpu.pc as usize;
 
-        cpu.mem.write_u16(mem_addr, 0x61A3);
+        cpu.mem.write_u16(mem_addr, 0x6110);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x622F);
+        cpu.mem.write_u16(mem_addr, 0xA300);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x8125);
+        cpu.mem.write_u16(mem_addr, 0xF155);
         mem_addr += INSTRUCTION_SIZE as usize;
 
         // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
-
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xA3);
-        assert_ne!(cpu.read_register(EnumRegister::V2), 0x2F);
-
-        // This should set V1 = 0xA0
-        cpu.tick();
-
-        // This should set V2 = 0x0F
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        let id = cpu.get_watch_list_mut().add(EnumWatchKind::Write, 0x301, 0x301, None);
 
-        // This should set V1 = V2
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.take_watch_hit().is_none());
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0xA3 - 0x2F);
+        // Dumps V0 to 0x300 and V1 to 0x301.
+        assert!(cpu.tick().is_ok());
+        let hit = cpu.take_watch_hit().expect("Expected the watchpoint to trigger");
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+        assert_eq!(hit.id, id);
+        assert_eq!(hit.access, EnumAccess::Write);
+        assert_eq!(hit.addr, 0x301);
+        assert_eq!(hit.old_value, 0);
+        assert_eq!(hit.value, 0x10);
+        assert_eq!(hit.pc, STARTING_PC + 2 * INSTRUCTION_SIZE);
+        assert_eq!(hit.opcode, 0xF155);
+        assert!(cpu.take_watch_hit().is_none());
     }
 
     #[test]
-    fn execute_rshift_equal_instruction()
+    fn read_watchpoint_with_value_condition()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
         let mut mem_addr = cpu.pc as usize;
 
-        cpu.mem.write_u16(mem_addr, 0x6103);
+        cpu.mem.write_u8(0x300, 0x01);
+        cpu.mem.write_u8(0x301, 0x02);
+
+        cpu.mem.write_u16(mem_addr, 0xA300);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x8106);
+        cpu.mem.write_u16(mem_addr, 0xF165);
         mem_addr += INSTRUCTION_SIZE as usize;
 
         // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
-
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0x03);
-        assert_ne!(cpu.read_register(EnumRegister::VF), 0x01);
-
-        // This should set V1 = 0xA0
-        cpu.tick();
-
-        // This should set V2 = 0x0F
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        // Only the read of value 0x02 (at 0x301) should trigger.
+        cpu.get_watch_list_mut().add(EnumWatchKind::Read, 0x300, 0x3FF, Some(0x02));
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0x01);
-        assert_eq!(cpu.read_register(EnumRegister::VF), 0x01);
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        let hit = cpu.take_watch_hit().expect("Expected the watchpoint to trigger");
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+        assert_eq!(hit.access, EnumAccess::Read);
+        assert_eq!(hit.addr, 0x301);
+        assert_eq!(hit.value, 0x02);
+        assert_eq!(hit.opcode, 0xF165);
     }
 
     #[test]
-    fn execute_lshift_equal_instruction()
+    fn execute_draw_instruction_sets_collision()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
         let mut mem_addr = cpu.pc as usize;
 
-        cpu.mem.write_u16(mem_addr, 0x6183);
+        // 2 row sprite at 0x300
+        cpu.mem.write_u8(0x300, 0xC0);
+        cpu.mem.write_u8(0x301, 0x40);
+
+        cpu.mem.write_u16(mem_addr, 0x6103);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x810E);
+        cpu.mem.write_u16(mem_addr, 0x6204);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0xA300);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0xD122);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0xD122);
         mem_addr += INSTRUCTION_SIZE as usize;
 
         // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
+        cpu.get_watch_list_mut().add(EnumWatchKind::Read, 0x301, 0x301, None);
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0x03);
-        assert_ne!(cpu.read_register(EnumRegister::VF), 0x01);
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
 
-        // This should set V1 = 0xA0
-        cpu.tick();
+        // First draw lights (3, 4), (4, 4) and (4, 5) without a collision.
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.display.get_pixel(3, 4));
+        assert!(cpu.display.get_pixel(4, 4));
+        assert!(cpu.display.get_pixel(4, 5));
+        assert!(!cpu.display.get_pixel(3, 5));
+        assert_eq!(cpu.read_register(EnumRegister::VF), 0);
 
-        // This should set V1 = V2
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        // Sprite reads are visible to watchpoints.
+        let hit = cpu.take_watch_hit().expect("Expected the watchpoint to trigger");
+        assert_eq!(hit.opcode, 0xD122);
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0x06);
-        assert_eq!(cpu.read_register(EnumRegister::VF), 0x01);
+        // Second draw erases it again and reports the collision.
+        assert!(cpu.tick().is_ok());
+        assert!(!cpu.display.get_pixel(3, 4));
+        assert_eq!(cpu.read_register(EnumRegister::VF), 1);
 
         // Execute halt instruction
         assert!(!cpu.is_halted());
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
         assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_inv_minus_equal_instruction()
+    fn execute_timer_instructions()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
         let mut mem_addr = cpu.pc as usize;
 
-        cpu.mem.write_u16(mem_addr, 0x6101);
+        cpu.mem.write_u16(mem_addr, 0x6105);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x6203);
+        cpu.mem.write_u16(mem_addr, 0xF115);
         mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x8127);
+        cpu.mem.write_u16(mem_addr, 0xF118);
+        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.mem.write_u16(mem_addr, 0xF207);
         mem_addr += INSTRUCTION_SIZE as usize;
 
         // Then halt
         cpu.mem.write_u16(mem_addr, 0);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.get_delay_timer(), 5);
+        assert_eq!(cpu.get_sound_timer(), 5);
+        assert!(cpu.is_sound_active());
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0x01);
-        assert_ne!(cpu.read_register(EnumRegister::V2), 0x03);
+        // Two frames pass before the delay timer is read back.
+        cpu.tick_timers();
+        cpu.tick_timers();
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.read_register(EnumRegister::V2), 3);
 
-        // This should set V1 = 0xA0
-        cpu.tick();
+        for _ in 0..10
+        {
+            cpu.tick_timers();
+        }
 
-        // This should set V2 = 0x0F
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        assert_eq!(cpu.get_delay_timer(), 0);
+        assert!(!cpu.is_sound_active());
+    }
 
-        // This should set V1 = V2
-        assert!(!cpu.is_halted());
-        cpu.tick();
+    /// Loads 'program' at STARTING_PC.
+    fn load_program(cpu: &mut CPU, program: &[u16])
+    {
+        for (i, instruction) in program.iter().enumerate()
+        {
+            cpu.mem.write_u16(STARTING_PC as usize + i * INSTRUCTION_SIZE as usize, *instruction);
+        }
+    }
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0x02);
+    #[test]
+    fn execute_skip_instructions()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        // V1 = 0x10, V2 = 0x10, skip if V1 == 0x10, skip if V1 != 0x10, skip if V1 == V2
+        load_program(&mut cpu, &[0x6110, 0x6210, 0x3110, 0x0000, 0x4110, 0x5120, 0x0000, 0x0000]);
+
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+
+        // 3XNN skips the halt.
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc, STARTING_PC + 4 * INSTRUCTION_SIZE);
+
+        // 4XNN doesn't skip.
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc, STARTING_PC + 5 * INSTRUCTION_SIZE);
+
+        // 5XY0 skips the halt.
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc, STARTING_PC + 7 * INSTRUCTION_SIZE);
+
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::Halted));
         assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_jump_instruction()
+    fn execute_add_immediate_wraps_without_flag()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        let mut mem_addr = cpu.pc as usize;
-        let jump_addr = (capacity as u16) - 4;
+        load_program(&mut cpu, &[0x61F0, 0x7120, 0x0000]);
 
-        cpu.mem.write_u16(mem_addr, 0x1000 | jump_addr);
-        mem_addr = jump_addr as usize;
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x10);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 0);
+    }
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+    #[test]
+    fn execute_add_and_sub_set_flags()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
-        assert_ne!(cpu.pc, jump_addr);
+        // V1 = 0xF0, V2 = 0x20, V1 += V2 (carry), V3 = 0x05, V3 -= V2 (borrow), V4 = 0x01, V4 =- V2 (no borrow)
+        load_program(&mut cpu, &[0x61F0, 0x6220, 0x8124, 0x6305, 0x8325, 0x6401, 0x8427, 0x0000]);
 
-        // This should jump to 
-        cpu.tick();
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x10);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 1);
 
-        // Verify we jumped to the correct address.
-        assert_eq!(cpu.pc, jump_addr);
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.read_register(EnumRegister::V3), 0xE5);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 0);
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.read_register(EnumRegister::V4), 0x1F);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 1);
     }
 
     #[test]
-    fn execute_set_reg_i_instruction()
+    fn execute_jump_plus_v0_instruction()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        let mut mem_addr = cpu.pc as usize;
-        let value: u16 = 0x0123;
-
-        cpu.mem.write_u16(mem_addr, 0xA000 | value);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        load_program(&mut cpu, &[0x6004, 0xB300]);
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc, 0x304);
+    }
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
-        assert_ne!(cpu.reg_i, value);
+    #[test]
+    fn execute_random_instruction_is_masked_and_seeded()
+    {
+        let capacity: usize = 4096;
+        let mut left = CPU::new(capacity, STARTING_PC);
+        let mut right = CPU::new(capacity, STARTING_PC);
+        left.set_rng_seed(1234);
+        right.set_rng_seed(1234);
 
-        // This should jump to 
-        cpu.tick();
+        load_program(&mut left, &[0xC10F, 0xC2FF]);
+        load_program(&mut right, &[0xC10F, 0xC2FF]);
 
-        // Verify we jumped to the correct address.
-        assert_eq!(cpu.reg_i, value);
+        for _ in 0..2
+        {
+            assert!(left.tick().is_ok());
+            assert!(right.tick().is_ok());
+        }
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+        assert_eq!(left.read_register(EnumRegister::V1) & 0xF0, 0);
+        assert_eq!(left.read_register(EnumRegister::V1), right.read_register(EnumRegister::V1));
+        assert_eq!(left.read_register(EnumRegister::V2), right.read_register(EnumRegister::V2));
     }
 
     #[test]
-    fn execute_display_clear_instruction_does_no_op()
+    fn execute_add_to_i_and_font_instructions()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        let mut mem_addr = cpu.pc as usize;
+        // I = 0x300, V1 = 0x10, I += V1, V2 = 0x0A, I = font('A')
+        load_program(&mut cpu, &[0xA300, 0x6110, 0xF11E, 0x620A, 0xF229]);
 
-        // display clear instruction
-        cpu.mem.write_u16(mem_addr, 0x00E0);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.reg_i, 0x310);
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.reg_i, FONT_ADDRESS + 0x0A * FONT_GLYPH_SIZE);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
+        // 'A' glyph
+        assert_eq!(cpu.mem.read_u8(cpu.reg_i as usize), Some(0xF0));
+        assert_eq!(cpu.mem.read_u8(cpu.reg_i as usize + 4), Some(0x90));
+    }
 
-        // This should jump to 
-        cpu.tick();
+    #[test]
+    fn return_with_empty_stack_faults()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        // Execute halt instruction
+        load_program(&mut cpu, &[0x00EE]);
+
+        assert_eq!(cpu.tick(), Err(EnumFault::StackUnderflow { pc: STARTING_PC }));
+        assert_eq!(cpu.get_last_fault(), Some(&EnumFault::StackUnderflow { pc: STARTING_PC }));
+
+        // The CPU leaves halting up to the host.
         assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_call0_set_return_instructions()
+    fn call_with_full_stack_faults()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        let mut mem_addr = cpu.pc as usize;
-        let call_addr = (capacity as u16) - 8;
+        // Calls itself forever.
+        load_program(&mut cpu, &[0x2000 | STARTING_PC]);
 
-        // Call instruction
-        cpu.mem.write_u16(mem_addr, call_addr);
-        let ret_addr = mem_addr + 2;
-        mem_addr = call_addr as usize;
+        for _ in 0..(STACK_BLOCK_SIZE / INSTRUCTION_SIZE)
+        {
+            assert!(cpu.tick().is_ok());
+        }
+
+        assert_eq!(cpu.tick(), Err(EnumFault::StackOverflow { pc: STARTING_PC }));
+    }
+
+    #[test]
+    fn load_past_end_of_memory_faults()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+
+        // I = 0xFFE, load V0..V3
+        load_program(&mut cpu, &[0xAFFE, 0xF365]);
 
-        // Then do set
-        cpu.mem.write_u16(mem_addr, 0x61AF);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.tick(), Err(EnumFault::MemOutOfBounds { pc: STARTING_PC + INSTRUCTION_SIZE, addr: 0x1000 }));
+    }
 
-        // Then do return
-        cpu.mem.write_u16(mem_addr, 0x00EE);
-        mem_addr = ret_addr;
+    #[test]
+    fn unknown_opcode_faults()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+        load_program(&mut cpu, &[0x5121, 0x8FFF, 0xF0FF]);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
-        assert_ne!(cpu.pc, call_addr);
+        assert_eq!(cpu.tick(), Err(EnumFault::UnknownOpcode { pc: STARTING_PC, opcode: 0x5121 }));
+        assert_eq!(cpu.tick(), Err(EnumFault::UnknownOpcode { pc: STARTING_PC + 2, opcode: 0x8FFF }));
+        assert_eq!(cpu.tick(), Err(EnumFault::UnknownOpcode { pc: STARTING_PC + 4, opcode: 0xF0FF }));
 
-        // This should be the call instruction
-        cpu.tick();
+        // Execution can continue past an unknown opcode.
+        assert_eq!(cpu.pc, STARTING_PC + 6);
+        assert_eq!(cpu.get_invalid_count(), 3);
+    }
 
-        // Verify we called/jumped to the correct address.
-        assert_eq!(cpu.pc, call_addr);
+    #[test]
+    fn invalid_policy_and_zero_halts()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        cpu.set_invalid_policy(EnumInvalidPolicy::Nop);
+        cpu.set_zero_halts(false);
 
-        // Execute the set instruction
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xAF);
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0xAF);
+        // (invalid), 0000 (now invalid too), V0 = 1
+        load_program(&mut cpu, &[0x5121, 0x0000, 0x6001]);
 
-        // Execute the return instruction
-        assert!(!cpu.is_halted());
-        assert_ne!(cpu.pc as usize, ret_addr);
-        cpu.tick();
-        assert_eq!(cpu.pc as usize, ret_addr);
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::Executed));
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::Executed));
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::Executed));
+        assert_eq!(cpu.registers[0], 1);
+        assert_eq!(cpu.get_invalid_count(), 2);
+        assert!(cpu.get_last_fault().is_none());
 
-        // Execute halt instruction
+        // Without 'nop', 0000 faults like any other invalid opcode when it doesn't halt.
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        cpu.set_zero_halts(false);
+        load_program(&mut cpu, &[0x0000]);
+
+        assert_eq!(cpu.tick(), Err(EnumFault::UnknownOpcode { pc: STARTING_PC, opcode: 0x0000 }));
         assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
     }
 
     #[test]
-    fn execute_call2_set_return_instructions()
+    fn machine_code_calls_follow_the_invalid_policy()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
+        cpu.set_invalid_policy(EnumInvalidPolicy::Nop);
 
-        let mut mem_addr = cpu.pc as usize;
-        let call_addr = (capacity as u16) - 8;
-
-        // Call instruction
-        cpu.mem.write_u16(mem_addr, 0x2000 | call_addr);
-        let ret_addr = mem_addr + 2;
-        mem_addr = call_addr as usize;
+        // Machine-code call to 0x300, V0 = 1
+        load_program(&mut cpu, &[0x0300, 0x6001]);
 
-        // Then do set
-        cpu.mem.write_u16(mem_addr, 0x61AF);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::Executed));
+        assert_eq!(cpu.pc, STARTING_PC + 2);
+        assert_eq!(cpu.sp, 0);
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::Executed));
+        assert_eq!(cpu.registers[0], 1);
+        assert_eq!(cpu.get_invalid_count(), 1);
 
-        // Then do return
-        cpu.mem.write_u16(mem_addr, 0x00EE);
-        mem_addr = ret_addr;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        load_program(&mut cpu, &[0x0300]);
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+        assert_eq!(cpu.tick(), Err(EnumFault::UnknownOpcode { pc: STARTING_PC, opcode: 0x0300 }));
+    }
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
-        assert_ne!(cpu.pc, call_addr);
+    #[test]
+    fn pc_out_of_bounds_faults()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, (capacity - 1) as u16);
 
-        // This should be the call instruction
-        cpu.tick();
+        assert_eq!(cpu.tick(), Err(EnumFault::PcOutOfBounds { pc: (capacity - 1) as u16 }));
+        assert_eq!(cpu.pc as usize, capacity - 1);
+    }
 
-        // Verify we called/jumped to the correct address.
-        assert_eq!(cpu.pc, call_addr);
+    #[test]
+    fn pc_at_the_top_of_memory_faults()
+    {
+        let capacity: usize = 0x10000;
+        let mut cpu = CPU::new(capacity, 0xFFFC);
 
-        // Execute the set instruction
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0xAF);
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0xAF);
+        // Skip if V0 == 0 from the second to last word, then V0 = 1 in the last word: neither has a next instruction.
+        cpu.mem.write_u16(0xFFFC, 0x3000);
+        cpu.mem.write_u16(0xFFFE, 0x6001);
 
-        // Execute the return instruction
-        assert!(!cpu.is_halted());
-        assert_ne!(cpu.pc as usize, ret_addr);
-        cpu.tick();
-        assert_eq!(cpu.pc as usize, ret_addr);
+        assert_eq!(cpu.tick(), Err(EnumFault::PcOutOfBounds { pc: 0xFFFC }));
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+        cpu.pc = 0xFFFE;
+        assert_eq!(cpu.tick(), Err(EnumFault::PcOutOfBounds { pc: 0xFFFE }));
+        assert_eq!(cpu.pc, 0xFFFE);
+        assert_eq!(cpu.registers[0], 0);
     }
 
     #[test]
-    fn execute_cond_instruction_no_skip()
+    fn execute_key_skip_instructions()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        let mut mem_addr = cpu.pc as usize;
-        let jump_addr = (capacity as u16) - 4;
+        // V1 = 0x0B, skip if key V1 pressed, skip if key V1 not pressed
+        load_program(&mut cpu, &[0x610B, 0xE19E, 0x0000, 0xE1A1, 0x0000]);
+        cpu.keypad.set_key(0x0B, true);
 
-        cpu.mem.write_u16(mem_addr, 0x6101);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc, STARTING_PC + 3 * INSTRUCTION_SIZE);
 
-        cpu.mem.write_u16(mem_addr, 0x9120);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc, STARTING_PC + 4 * INSTRUCTION_SIZE);
+    }
 
-        cpu.mem.write_u16(mem_addr, 0x1000 | jump_addr);
-        mem_addr = jump_addr as usize;
+    #[test]
+    fn execute_wait_for_key_instruction()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+        load_program(&mut cpu, &[0xF30A, 0x0000]);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::WaitingForKey));
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::WaitingForKey));
+        assert_eq!(cpu.pc, STARTING_PC);
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 1);
+        cpu.keypad.set_key(0x07, true);
+        assert_eq!(cpu.tick(), Ok(EnumTickOutcome::Executed));
+        assert_eq!(cpu.read_register(EnumRegister::V3), 0x07);
+        assert_eq!(cpu.pc, STARTING_PC + INSTRUCTION_SIZE);
+    }
 
-        // This should set V1 = 0x01
-        cpu.tick();
+    #[test]
+    fn shift_and_logic_quirks()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        cpu.set_quirks(Quirks { shift_uses_vy: true, logic_resets_vf: true, ..Quirks::default() });
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0x01);
+        // V1 = 0x01, V2 = 0x81, V1 = V2 << 1, VF = 1, V1 |= V2
+        load_program(&mut cpu, &[0x6101, 0x6281, 0x812E, 0x6F01, 0x8121]);
 
-        // This should check if V1 != V2
-        cpu.tick();
+        for _ in 0..3
+        {
+            assert!(cpu.tick().is_ok());
+        }
 
-        // Verify we did NOT jump to the address.
-        assert_ne!(cpu.pc, jump_addr);
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x02);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 0x01);
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+        assert!(cpu.tick().is_ok());
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.read_register(EnumRegister::V1), 0x83);
+        assert_eq!(cpu.read_register(EnumRegister::VF), 0x00);
     }
 
     #[test]
-    fn execute_cond_instruction_do_skip()
+    fn jump_and_load_store_quirks()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
+        cpu.set_quirks(Quirks { jump_uses_vx: true, load_store_increments_i: true, ..Quirks::default() });
 
-        let mut mem_addr = cpu.pc as usize;
-        let jump_addr = (capacity as u16) - 4;
+        // I = 0x300, V2 = 0x04, store V0-V2, jump to 0x200 + V2
+        load_program(&mut cpu, &[0xA300, 0x6204, 0xF255, 0xB200]);
 
-        cpu.mem.write_u16(mem_addr, 0x6101);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        for _ in 0..3
+        {
+            assert!(cpu.tick().is_ok());
+        }
 
-        cpu.mem.write_u16(mem_addr, 0x8210);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert_eq!(cpu.reg_i, 0x303);
 
-        cpu.mem.write_u16(mem_addr, 0x9120);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.pc, 0x204);
+    }
 
-        cpu.mem.write_u16(mem_addr, 0x1000 | jump_addr);
-        mem_addr = jump_addr as usize;
+    #[test]
+    fn register_accessors_use_register_index()
+    {
+        let mut cpu = CPU::new(4096, STARTING_PC);
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+        assert_eq!(EnumRegister::from_index(0x1A), EnumRegister::VA);
+        assert_eq!(EnumRegister::VF.index(), 15);
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
+        cpu.set_register(EnumRegister::VC, 0x42);
+        assert_eq!(cpu.get_register(EnumRegister::VC), 0x42);
+        assert_eq!(cpu.get_registers()[0xC], 0x42);
+    }
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 1);
+    /// A random opcode that is usually valid, with jump targets and 'i' kept inside the first 256 bytes of the
+    /// program so that stores rewrite code that runs later.
+    fn random_opcode(rng: &mut Rng) -> u16
+    {
+        let raw = rng.next_u32() as u16;
+        let program_addr = STARTING_PC + (rng.next_u32() % 128) as u16 * INSTRUCTION_SIZE;
 
-        // This should set V1 = 0x01
-        cpu.tick();
+        match raw >> 12
+        {
+            0x0 => [0x00E0, 0x00EE, 0x0000, program_addr][(raw & 0x03) as usize],
+            0x1 | 0x2 | 0xB => (raw & 0xF000) | program_addr,
+            0x8 => (raw & 0xFFF0) | [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE][(raw & 0x0F) as usize % 9],
+            0xA => 0xA000 | (STARTING_PC + (raw & 0xFF)),
+            0xE => (raw & 0xFF00) | if raw & 0x01 == 0 { 0x9E } else { 0xA1 },
+            0xF => (raw & 0xFF00) | [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65][(raw & 0xFF) as usize % 9],
+            _ => raw,
+        }
+    }
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 1);
+    #[test]
+    fn decode_cache_matches_plain_interpreter()
+    {
+        let capacity: usize = 4096;
+        let mut rng = Rng::new(0x2BAD_5EED);
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V2), 1);
+        for program_index in 0..100
+        {
+            let program: Vec<u16> = (0..128).map(|_| random_opcode(&mut rng)).collect();
 
-        // This should set V2 = 0x01
-        cpu.tick();
+            let mut plain = CPU::new(capacity, STARTING_PC);
+            let mut cached = CPU::new(capacity, STARTING_PC);
+            cached.set_decode_cache(true);
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V2), 1);
+            for cpu in [&mut plain, &mut cached]
+            {
+                load_program(cpu, &program);
+                cpu.set_rng_seed(program_index + 1);
+                cpu.keypad.set_key(0x5, true);
+            }
 
-        // This should check if V1 != V2
-        cpu.tick();
+            for step in 0..1000
+            {
+                let result = plain.tick();
+                assert_eq!(result, cached.tick(), "program {0}, step {1}", program_index, step);
+                assert_eq!((plain.pc, plain.reg_i, plain.sp), (cached.pc, cached.reg_i, cached.sp), "program {0}, step {1}", program_index, step);
+                assert_eq!(plain.registers, cached.registers, "program {0}, step {1}", program_index, step);
 
-        // Verify we did NOT jump yet to the address.
-        assert_ne!(cpu.pc, jump_addr);
+                // Other faults are skipped like with the 'ignore' policy.
+                if plain.is_halted() || matches!(result, Err(EnumFault::PcOutOfBounds { .. }))
+                {
+                    break;
+                }
 
-        // This should check if V1 != V2
-        cpu.tick();
+                if step % 10 == 0
+                {
+                    plain.tick_timers();
+                    cached.tick_timers();
+                }
+            }
 
-        // Verify we did jump to the address.
-        assert_eq!(cpu.pc, jump_addr);
+            for addr in 0..capacity
+            {
+                assert_eq!(plain.mem.read_u8(addr), cached.mem.read_u8(addr), "program {0}, addr 0x{1:03X}", program_index, addr);
+            }
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+            assert_eq!(plain.display.get_pixels(), cached.display.get_pixels(), "program {}", program_index);
+        }
     }
 
     #[test]
-    fn execute_set_three_regs_dump_then_load_instructions()
+    fn decode_cache_sees_self_modifying_code()
     {
         let capacity: usize = 4096;
         let mut cpu = CPU::new(capacity, STARTING_PC);
+        cpu.set_decode_cache(true);
 
-        let mut mem_addr = cpu.pc as usize;
+        // 0x200: V0 = 0x62, V1 = 0x09, I = 0x20A, store V0-V1 (rewrites 0x20A to V2 = 9), jump to 0x20A
+        // 0x20A: V2 = 0x01
+        load_program(&mut cpu, &[0x6062, 0x6109, 0xA20A, 0xF155, 0x120A, 0x6201]);
 
-        // Set registers V1, V3, and i
-        cpu.mem.write_u16(mem_addr, 0x6110);
-        mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x630F);
-        mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0xAABC);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        // Run 0x20A once before it is rewritten so its old decoding is cached.
+        cpu.pc = 0x20A;
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.read_register(EnumRegister::V2), 0x01);
 
-        // Dump all registers starting at register 'i'.
-        cpu.mem.write_u16(mem_addr, 0xFF55);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        cpu.pc = STARTING_PC;
 
-        // zero registers
-        cpu.mem.write_u16(mem_addr, 0x6100);
-        mem_addr += INSTRUCTION_SIZE as usize;
-        cpu.mem.write_u16(mem_addr, 0x6300);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        for _ in 0..6
+        {
+            assert!(cpu.tick().is_ok());
+        }
 
-        // Load registers back in.
-        cpu.mem.write_u16(mem_addr, 0xFF65);
-        mem_addr += INSTRUCTION_SIZE as usize;
+        assert_eq!(cpu.read_register(EnumRegister::V2), 0x09);
+    }
 
-        // Then halt
-        cpu.mem.write_u16(mem_addr, 0);
+    #[test]
+    fn profiler_follows_calls_and_returns()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        let id = cpu.add_observer(Box::new(Profiler::new(capacity)));
 
-        // Try executing our 'fake' program
-        assert!(!cpu.is_halted());
+        // 0x200: call 0x206, 0x202: call 0x206, 0x204: halt / 0x206: V0 += 1, return
+        load_program(&mut cpu, &[0x2206, 0x2206, 0x0000, 0x7001, 0x00EE]);
 
-        // Make sure register is not our value first
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0x10);
-        assert_ne!(cpu.read_register(EnumRegister::V3), 0x0F);
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.get_call_stack(), [0x202]);
 
-        // This should set V1 = 0x10
-        cpu.tick();
+        while !cpu.is_halted()
+        {
+            assert!(cpu.tick().is_ok());
+        }
 
-        // Verify V1 register is correct
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0x10);
+        let profiler = cpu.get_observer::<Profiler>(id).unwrap();
+        assert_eq!(profiler.get_total(), 7);
+        assert_eq!(profiler.get_hits(0x206), 2);
 
-        // This fhould set V3 = 0x0F
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        let subroutines = profiler.subroutines();
+        assert_eq!(subroutines[0].exclusive, 3);
+        assert_eq!(subroutines[1].entry, Some(0x206));
+        assert_eq!(subroutines[1].calls, 2);
+        assert_eq!(subroutines[1].inclusive, 4);
+    }
 
-        // Verify register 'i' isn't set.
-        assert_ne!(cpu.reg_i, 0x0ABC);
+    #[test]
+    fn coverage_records_code_reads_and_writes()
+    {
+        let capacity: usize = 4096;
+        let mut cpu = CPU::new(capacity, STARTING_PC);
+        cpu.add_observer(Box::new(Coverage::new(capacity)));
 
-        // This should set register 'i'
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        // I = 0x300, store V0-V1, load V0, halt
+        load_program(&mut cpu, &[0xA300, 0xF155, 0xF065, 0x0000]);
 
-        // Verify register 'i' is correct
-        assert_eq!(cpu.reg_i, 0x0ABC);
+        while !cpu.is_halted()
+        {
+            assert!(cpu.tick().is_ok());
+        }
 
-        // This should Dump our registers to memory starting at the address in register 'i'.
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        let coverage = cpu.find_observer::<Coverage>().unwrap();
+        assert_eq!(coverage.get_hits(0x202), 1);
+        assert!(coverage.is_executed(0x207));
+        assert!(!coverage.is_executed(0x208));
+        assert!(coverage.is_written(0x300) && coverage.is_written(0x301));
+        assert!(coverage.is_read(0x300) && !coverage.is_read(0x301));
+    }
 
-        // Zero registers
-        assert_ne!(cpu.read_register(EnumRegister::V1), 0);
-        assert_ne!(cpu.read_register(EnumRegister::V3), 0);
+    #[test]
+    fn state_dump_formats()
+    {
+        let mut cpu = CPU::new(4096, STARTING_PC);
+        // CALL 0x206, (unused), (unused), V3 = 0x42, I = 0x123, halt
+        load_program(&mut cpu, &[0x2206, 0x0000, 0x0000, 0x6342, 0xA123, 0x0000]);
+        cpu.set_quirks(Quirks { wrap_sprites: true, ..Quirks::default() });
+        cpu.get_keypad_mut().set_key(0xA, true);
 
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(!cpu.is_halted());
-        cpu.tick();
+        while !cpu.is_halted()
+        {
+            assert!(cpu.tick().is_ok());
+        }
 
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0);
-        assert_eq!(cpu.read_register(EnumRegister::V3), 0);
+        assert_eq!(cpu.get_halt_reason(), Some(&EnumHaltReason::Instruction { pc: 0x20A }));
+
+        let mut text = String::new();
+        cpu.format_state(EnumDumpFormat::Text, &mut text);
+
+        assert!(text.contains("\tpc: 0x20C  i: 0x123  sp: 0x02\n"));
+        assert!(text.contains("\tV0=00 V1=00 V2=00 V3=42 V4=00 V5=00 V6=00 V7=00\n\tV8=00"));
+        assert!(text.contains("\tcall stack: #0 ret 0x202\n"));
+        assert!(text.contains("\tkeys down: A\n"));
+        assert!(text.contains("\thalted: yes (halt instruction at 0x20A)\n"));
+        assert!(text.contains("\tinvalid opcodes: 0 (on-invalid halt, 0000 halts)\n"));
+        assert!(text.contains("\tquirks: wrap\n"));
+        assert!(!text.contains("last fault"));
+
+        let mut json = String::new();
+        cpu.format_state(EnumDumpFormat::Json, &mut json);
+
+        assert!(json.starts_with("{\"pc\":524,\"i\":291,\"sp\":2,\"registers\":[0,0,0,66,0,0,0,0,0,0,0,0,0,0,0,0],\"call_stack\":[514],"));
+        assert!(json.ends_with("\"keys_down\":[10],\"halted\":true,\"halt_reason\":{\"kind\":\"instruction\",\"pc\":522},\"last_fault\":null,\"invalid_opcodes\":0,\"on_invalid\":\"halt\",\"zero_halts\":true,\"quirks\":[\"wrap\"]}\n"));
+
+        // A fault shows up in both formats.
+        let mut cpu = CPU::new(4096, STARTING_PC);
+        load_program(&mut cpu, &[0x00EE]);
+        let fault = cpu.tick().unwrap_err();
+        cpu.halt_on_fault(&fault);
+        assert_eq!(cpu.get_halt_reason(), Some(&EnumHaltReason::Fault(fault)));
+
+        let mut text = String::new();
+        cpu.format_state(EnumDumpFormat::Text, &mut text);
+        assert!(text.contains("\thalted: yes (fault: Stack underflow"));
+        assert!(text.contains("\tlast fault: Stack underflow"));
+
+        let mut json = String::new();
+        cpu.format_state(EnumDumpFormat::Json, &mut json);
+        assert!(json.contains("\"halt_reason\":{\"kind\":\"fault\",\"pc\":512,\"message\":\"Stack underflow: "));
+        assert!(json.contains("\"last_fault\":{\"pc\":512,\"message\":\"Stack underflow: "));
+
+        // The host can also stop it without a fault.
+        cpu.halt();
+
+        let mut text = String::new();
+        cpu.format_state(EnumDumpFormat::Text, &mut text);
+        assert!(text.contains("\thalted: yes (stopped by the host)\n"));
+    }
 
-        // Load back into memory.
-        assert!(!cpu.is_halted());
-        cpu.tick();
+    #[test]
+    fn smc_detection_reports_patches()
+    {
+        let mut cpu = CPU::new(4096, STARTING_PC);
+        cpu.set_decode_cache(true);
+        cpu.add_observer(Box::new(SmcDetector::new(4096)));
 
-        assert_eq!(cpu.read_register(EnumRegister::V1), 0x10);
-        assert_eq!(cpu.read_register(EnumRegister::V3), 0x0F);
+        // V0 = 0x61, I = 0x200, store V0 over the first instruction, I = 0x20A, store V0 over the halt below, (patched), halt
+        load_program(&mut cpu, &[0x6061, 0xA200, 0xF055, 0xA20A, 0xF055, 0x0000, 0x0000]);
 
-        // Execute halt instruction
-        assert!(!cpu.is_halted());
-        cpu.tick();
-        assert!(cpu.is_halted());
+        while !cpu.is_halted()
+        {
+            assert!(cpu.tick().is_ok());
+        }
+
+        // The patched instruction ran (V1 = 0x00) instead of halting at 0x20A.
+        assert_eq!(cpu.get_halt_reason(), Some(&EnumHaltReason::Instruction { pc: 0x20C }));
+
+        let events = cpu.find_observer::<SmcDetector>().unwrap().get_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].kind, events[0].writer_pc, events[0].addr, events[0].old_opcode, events[0].new_opcode),
+                   (EnumSmcKind::CodeOverwritten, 0x204, 0x200, 0x6061, 0x6161));
+        assert_eq!((events[1].kind, events[1].writer_pc, events[1].addr, events[1].old_opcode, events[1].new_opcode),
+                   (EnumSmcKind::PatchExecuted, 0x208, 0x20A, 0x0000, 0x6100));
     }
 }
-
@@ -0,0 +1,297 @@
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+pub struct Display
+{
+    pixels: Vec<bool>,
+    dirty: bool,
+}
+
+impl Display
+{
+    pub fn new() -> Self
+    {
+        Self { pixels: vec![false; DISPLAY_WIDTH * DISPLAY_HEIGHT], dirty: true }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool
+    {
+        self.pixels[y * DISPLAY_WIDTH + x]
+    }
+
+    /// All pixels in row-major order (DISPLAY_WIDTH * DISPLAY_HEIGHT entries, true is lit).
+    pub fn get_pixels(&self) -> &[bool]
+    {
+        &self.pixels
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.pixels.fill(false);
+        self.dirty = true;
+    }
+
+    /// XORs an 8-pixel wide sprite onto the display and returns true if any lit pixel was erased.
+    /// NOTE: The starting position always wraps around the screen; the sprite itself is clipped at the edges
+    /// unless 'wrap' is set.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, rows: &[u8], wrap: bool) -> bool
+    {
+        let start_x = x % DISPLAY_WIDTH;
+        let start_y = y % DISPLAY_HEIGHT;
+        let mut collision = false;
+
+        for (row_index, row) in rows.iter().enumerate()
+        {
+            let mut pixel_y = start_y + row_index;
+
+            if pixel_y >= DISPLAY_HEIGHT
+            {
+                if !wrap
+                {
+                    break;
+                }
+
+                pixel_y %= DISPLAY_HEIGHT;
+            }
+
+            for bit in 0..8
+            {
+                let mut pixel_x = start_x + bit;
+
+                if pixel_x >= DISPLAY_WIDTH
+                {
+                    if !wrap
+                    {
+                        break;
+                    }
+
+                    pixel_x %= DISPLAY_WIDTH;
+                }
+
+                if (row >> (7 - bit)) & 0x01 == 0
+                {
+                    continue;
+                }
+
+                let pixel = &mut self.pixels[pixel_y * DISPLAY_WIDTH + pixel_x];
+                collision |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+
+        self.dirty = true;
+
+        collision
+    }
+
+    /// Returns true if the display changed since the last call.
+    pub fn take_dirty(&mut self) -> bool
+    {
+        let dirty = self.dirty;
+        self.dirty = false;
+
+        dirty
+    }
+
+    /// Renders the display as text, one line per row.
+    pub fn render(&self, stream: &mut String)
+    {
+        for y in 0..DISPLAY_HEIGHT
+        {
+            for x in 0..DISPLAY_WIDTH
+            {
+                stream.push(if self.pixels[y * DISPLAY_WIDTH + x] { '#' } else { ' ' });
+            }
+
+            stream.push('\n');
+        }
+    }
+
+    /// Renders the display with 24-bit ANSI colors, two character cells per pixel.
+    pub fn render_with_palette(&self, palette: &Palette, stream: &mut String)
+    {
+        for y in 0..DISPLAY_HEIGHT
+        {
+            let mut last_lit = None;
+
+            for x in 0..DISPLAY_WIDTH
+            {
+                let lit = self.pixels[y * DISPLAY_WIDTH + x];
+
+                // Only switch colors when they change along the row.
+                if last_lit != Some(lit)
+                {
+                    let [r, g, b] = if lit { palette.foreground } else { palette.background };
+                    stream.push_str(&format!("\x1B[48;2;{0};{1};{2}m", r, g, b));
+                    last_lit = Some(lit);
+                }
+
+                stream.push_str("  ");
+            }
+
+            stream.push_str("\x1B[0m\n");
+        }
+    }
+}
+
+impl Default for Display
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Foreground (lit) and background colors used to render the display, as RGB.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Palette
+{
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+}
+
+impl Palette
+{
+    fn parse_color(text: &str) -> Result<[u8; 3], String>
+    {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(format!("Expected a color like '#RRGGBB' but got '{}'", text));
+        }
+
+        let channel = |index: usize| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).unwrap();
+
+        Ok([channel(0), channel(1), channel(2)])
+    }
+}
+
+impl std::fmt::Display for Palette
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let [fr, fg, fb] = self.foreground;
+        let [br, bg, bb] = self.background;
+        write!(f, "#{0:02X}{1:02X}{2:02X} #{3:02X}{4:02X}{5:02X}", fr, fg, fb, br, bg, bb)
+    }
+}
+
+impl std::str::FromStr for Palette
+{
+    type Err = String;
+
+    /// Parses "<foreground> <background>", e.g. "#FFB000 #1A1A1A" (a comma also separates the colors).
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        let colors: Vec<&str> = text.split([',', ' ']).filter(|token| !token.is_empty()).collect();
+
+        if colors.len() != 2
+        {
+            return Err(format!("Expected a foreground and a background color but got '{}'", text));
+        }
+
+        Ok(Self { foreground: Self::parse_color(colors[0])?, background: Self::parse_color(colors[1])? })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::display::{Display, Palette, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+    #[test]
+    fn display_starts_blank_and_dirty()
+    {
+        let mut display = Display::new();
+
+        for y in 0..DISPLAY_HEIGHT
+        {
+            for x in 0..DISPLAY_WIDTH
+            {
+                assert!(!display.get_pixel(x, y));
+            }
+        }
+
+        assert!(display.take_dirty());
+        assert!(!display.take_dirty());
+    }
+
+    #[test]
+    fn draw_twice_erases_and_collides()
+    {
+        let mut display = Display::new();
+
+        assert!(!display.draw_sprite(1, 2, &[0b1000_0001], false));
+        assert!(display.get_pixel(1, 2));
+        assert!(display.get_pixel(8, 2));
+
+        assert!(display.draw_sprite(1, 2, &[0b1000_0000], false));
+        assert!(!display.get_pixel(1, 2));
+        assert!(display.get_pixel(8, 2));
+    }
+
+    #[test]
+    fn sprite_start_wraps_but_is_clipped()
+    {
+        let mut display = Display::new();
+
+        // Starts at (DISPLAY_WIDTH + 60, 30) == (60, 30) and should be clipped on the right and bottom.
+        display.draw_sprite(DISPLAY_WIDTH + 60, 30, &[0xFF, 0xFF, 0xFF], false);
+
+        assert!(display.get_pixel(60, 30));
+        assert!(display.get_pixel(63, 31));
+        assert!(!display.get_pixel(0, 30));
+        assert!(!display.get_pixel(60, 0));
+    }
+
+    #[test]
+    fn clear_blanks_display()
+    {
+        let mut display = Display::new();
+        display.draw_sprite(0, 0, &[0xFF], false);
+        display.take_dirty();
+
+        display.clear();
+        assert!(!display.get_pixel(0, 0));
+        assert!(display.take_dirty());
+    }
+
+    #[test]
+    fn render_has_one_line_per_row()
+    {
+        let mut display = Display::new();
+        display.draw_sprite(0, 0, &[0x80], false);
+
+        let mut stream = String::new();
+        display.render(&mut stream);
+
+        assert_eq!(stream.lines().count(), DISPLAY_HEIGHT);
+        assert!(stream.starts_with("# "));
+    }
+
+    #[test]
+    fn sprite_wraps_with_quirk()
+    {
+        let mut display = Display::new();
+        display.draw_sprite(60, 31, &[0xFF, 0xFF], true);
+
+        assert!(display.get_pixel(63, 31));
+        assert!(display.get_pixel(0, 31));
+        assert!(display.get_pixel(3, 0));
+        assert!(!display.get_pixel(4, 0));
+    }
+
+    #[test]
+    fn parse_palette()
+    {
+        let palette = "#FFB000 1a1a1a".parse::<Palette>().unwrap();
+        assert_eq!(palette.foreground, [0xFF, 0xB0, 0x00]);
+        assert_eq!(palette.background, [0x1A, 0x1A, 0x1A]);
+        assert_eq!(palette.to_string(), "#FFB000 #1A1A1A");
+
+        assert!("#FFB000".parse::<Palette>().is_err());
+        assert!("#FFB00 #000000".parse::<Palette>().is_err());
+    }
+}
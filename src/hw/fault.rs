@@ -0,0 +1,214 @@
+/// A condition that stopped the CPU from executing an instruction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EnumFault
+{
+    /// A call was made with a full stack.
+    StackOverflow { pc: u16 },
+    /// A return was made with an empty stack.
+    StackUnderflow { pc: u16 },
+    /// The program counter does not point at a whole instruction in main memory.
+    PcOutOfBounds { pc: u16 },
+    /// An instruction accessed main memory past its end (usually through register 'i').
+    MemOutOfBounds { pc: u16, addr: usize },
+    UnknownOpcode { pc: u16, opcode: u16 },
+}
+
+impl EnumFault
+{
+    /// The address of the instruction that faulted.
+    #[allow(dead_code)]
+    pub fn get_pc(&self) -> u16
+    {
+        match self
+        {
+            Self::StackOverflow { pc } => *pc,
+            Self::StackUnderflow { pc } => *pc,
+            Self::PcOutOfBounds { pc } => *pc,
+            Self::MemOutOfBounds { pc, .. } => *pc,
+            Self::UnknownOpcode { pc, .. } => *pc,
+        }
+    }
+
+    /// False if execution cannot continue past this fault even when the host chooses to ignore it.
+    pub fn is_recoverable(&self) -> bool
+    {
+        !matches!(self, Self::PcOutOfBounds { .. })
+    }
+}
+
+impl std::fmt::Display for EnumFault
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::StackOverflow { pc } => write!(f, "Stack overflow: call at pc 0x{:03X} with no stack space left", pc),
+            Self::StackUnderflow { pc } => write!(f, "Stack underflow: return at pc 0x{:03X} with nothing to return to", pc),
+            Self::PcOutOfBounds { pc } => write!(f, "pc 0x{:03X} is outside of main memory", pc),
+            Self::MemOutOfBounds { pc, addr } => write!(f, "Memory access to 0x{0:03X} at pc 0x{1:03X} is outside of main memory", addr, pc),
+            Self::UnknownOpcode { pc, opcode } => write!(f, "Unknown opcode 0x{0:04X} at pc 0x{1:03X}", opcode, pc),
+        }
+    }
+}
+
+impl std::error::Error for EnumFault {}
+
+/// What a successful 'CPU::tick' did.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumTickOutcome
+{
+    Executed,
+    /// FX0A is blocked until a key is pressed (the instruction will run again on the next tick).
+    WaitingForKey,
+    /// The CPU is (or just became) halted and did not execute anything further.
+    Halted,
+}
+
+/// How the host reacts when the CPU reports a fault.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumFaultPolicy
+{
+    Halt,
+    /// Skip the faulting instruction and keep going (unrecoverable faults still halt).
+    Ignore,
+    /// Drop into the debugger at the faulting instruction.
+    Break,
+}
+
+impl std::fmt::Display for EnumFaultPolicy
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Halt => write!(f, "halt"),
+            Self::Ignore => write!(f, "ignore"),
+            Self::Break => write!(f, "break"),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumFaultPolicy
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        match text
+        {
+            "halt" => Ok(Self::Halt),
+            "ignore" => Ok(Self::Ignore),
+            "break" => Ok(Self::Break),
+            _ => Err(format!("Unknown fault policy '{}' (expected halt, ignore or break)", text)),
+        }
+    }
+}
+
+/// What happens when the CPU meets an opcode it doesn't know (including 0NNN machine-code calls, and 0000 when it
+/// isn't a halt).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EnumInvalidPolicy
+{
+    /// Report an 'UnknownOpcode' fault and halt.
+    #[default]
+    Halt,
+    /// Report an 'UnknownOpcode' fault and skip the instruction.
+    Ignore,
+    /// Report an 'UnknownOpcode' fault and drop into the debugger.
+    Break,
+    /// Silently skip it, like a 0NNN machine-code call on interpreters that don't run machine code.
+    Nop,
+}
+
+impl EnumInvalidPolicy
+{
+    /// The host reaction to the fault, or None when the CPU skips the opcode without a fault.
+    pub fn to_fault_policy(self) -> Option<EnumFaultPolicy>
+    {
+        match self
+        {
+            Self::Halt => Some(EnumFaultPolicy::Halt),
+            Self::Ignore => Some(EnumFaultPolicy::Ignore),
+            Self::Break => Some(EnumFaultPolicy::Break),
+            Self::Nop => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EnumInvalidPolicy
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Halt => write!(f, "halt"),
+            Self::Ignore => write!(f, "ignore"),
+            Self::Break => write!(f, "break"),
+            Self::Nop => write!(f, "nop"),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumInvalidPolicy
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        match text
+        {
+            "halt" => Ok(Self::Halt),
+            "ignore" => Ok(Self::Ignore),
+            "break" => Ok(Self::Break),
+            "nop" => Ok(Self::Nop),
+            _ => Err(format!("Unknown invalid opcode policy '{}' (expected halt, ignore, break or nop)", text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::fault::{EnumFault, EnumFaultPolicy, EnumInvalidPolicy};
+
+    #[test]
+    fn fault_reports_pc()
+    {
+        assert_eq!(EnumFault::StackOverflow { pc: 0x200 }.get_pc(), 0x200);
+        assert_eq!(EnumFault::MemOutOfBounds { pc: 0x202, addr: 0x1000 }.get_pc(), 0x202);
+        assert_eq!(EnumFault::UnknownOpcode { pc: 0x204, opcode: 0xFFFF }.get_pc(), 0x204);
+    }
+
+    #[test]
+    fn only_pc_out_of_bounds_is_unrecoverable()
+    {
+        assert!(!EnumFault::PcOutOfBounds { pc: 0x1000 }.is_recoverable());
+        assert!(EnumFault::StackUnderflow { pc: 0x200 }.is_recoverable());
+        assert!(EnumFault::UnknownOpcode { pc: 0x200, opcode: 0x5001 }.is_recoverable());
+    }
+
+    #[test]
+    fn fault_message_names_opcode_and_pc()
+    {
+        let message = EnumFault::UnknownOpcode { pc: 0x2A4, opcode: 0xE1FF }.to_string();
+        assert_eq!(message, "Unknown opcode 0xE1FF at pc 0x2A4");
+    }
+
+    #[test]
+    fn parse_fault_policy()
+    {
+        assert_eq!("halt".parse::<EnumFaultPolicy>(), Ok(EnumFaultPolicy::Halt));
+        assert_eq!("ignore".parse::<EnumFaultPolicy>(), Ok(EnumFaultPolicy::Ignore));
+        assert_eq!("break".parse::<EnumFaultPolicy>(), Ok(EnumFaultPolicy::Break));
+        assert!("panic".parse::<EnumFaultPolicy>().is_err());
+    }
+
+    #[test]
+    fn parse_invalid_policy()
+    {
+        assert_eq!("nop".parse::<EnumInvalidPolicy>(), Ok(EnumInvalidPolicy::Nop));
+        assert_eq!("break".parse::<EnumInvalidPolicy>().unwrap().to_fault_policy(), Some(EnumFaultPolicy::Break));
+        assert_eq!(EnumInvalidPolicy::Nop.to_fault_policy(), None);
+        assert!("skip".parse::<EnumInvalidPolicy>().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+/// Timers and the display are updated at this fixed rate, independently of the CPU clock.
+pub const FRAME_RATE_HZ: u32 = 60;
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+/// Speed multipliers outside this range make frames last hours or nanoseconds; use turbo for unthrottled.
+pub const MIN_SPEED: f64 = 0.01;
+pub const MAX_SPEED: f64 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnumSpeed
+{
+    /// Scales the frame rate (1.0 is real-time).
+    Multiplier(f64),
+    /// Runs frames back to back without sleeping.
+    Turbo,
+}
+
+impl std::fmt::Display for EnumSpeed
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Multiplier(multiplier) => write!(f, "{}x", multiplier),
+            Self::Turbo => write!(f, "turbo"),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumSpeed
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        if text == "turbo"
+        {
+            return Ok(Self::Turbo);
+        }
+
+        let multiplier = text.trim_end_matches('x').parse::<f64>().map_err(|e| format!("Invalid speed '{0}': {1}", text, e))?;
+
+        if !(MIN_SPEED..=MAX_SPEED).contains(&multiplier)
+        {
+            return Err(format!("Invalid speed '{0}': must be between {1} and {2}", text, MIN_SPEED, MAX_SPEED));
+        }
+
+        Ok(Self::Multiplier(multiplier))
+    }
+}
+
+/// Decides how many instructions run per 60 Hz frame and how long each frame lasts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameConfig
+{
+    instructions_per_frame: u32,
+    speed: EnumSpeed,
+}
+
+impl FrameConfig
+{
+    pub fn new(instructions_per_frame: u32) -> Self
+    {
+        Self { instructions_per_frame: instructions_per_frame.max(1), speed: EnumSpeed::Multiplier(1.0) }
+    }
+
+    /// Converts a CPU clock rate into whole instructions per frame (at least 1). Rounds in u64 so u32::MAX Hz
+    /// doesn't overflow.
+    #[allow(dead_code)]
+    pub fn from_cpu_hz(cpu_hz: u32) -> Self
+    {
+        let instructions_per_frame = (cpu_hz as u64 + FRAME_RATE_HZ as u64 / 2) / FRAME_RATE_HZ as u64;
+        Self::new(instructions_per_frame as u32)
+    }
+
+    pub fn get_instructions_per_frame(&self) -> u32
+    {
+        self.instructions_per_frame
+    }
+
+    /// Saturates at u32::MAX, since '--ipf' goes up to u32::MAX as well.
+    #[allow(dead_code)]
+    pub fn get_cpu_hz(&self) -> u32
+    {
+        self.instructions_per_frame.saturating_mul(FRAME_RATE_HZ)
+    }
+
+    pub fn get_speed(&self) -> EnumSpeed
+    {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: EnumSpeed)
+    {
+        self.speed = speed;
+    }
+
+    /// The wall-clock length of a frame at the current speed, or None when running unthrottled. A multiplier built
+    /// without 'from_str' is clamped to the valid range.
+    pub fn frame_duration(&self) -> Option<std::time::Duration>
+    {
+        match self.speed
+        {
+            EnumSpeed::Multiplier(multiplier) =>
+            {
+                let multiplier = if multiplier.is_nan() { 1.0 } else { multiplier.clamp(MIN_SPEED, MAX_SPEED) };
+                Some(std::time::Duration::from_secs_f64(1.0 / (FRAME_RATE_HZ as f64 * multiplier)))
+            },
+            EnumSpeed::Turbo => None,
+        }
+    }
+}
+
+impl Default for FrameConfig
+{
+    fn default() -> Self
+    {
+        Self::new(DEFAULT_INSTRUCTIONS_PER_FRAME)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::frame::{EnumSpeed, FrameConfig, FRAME_RATE_HZ};
+
+    #[test]
+    fn cpu_hz_rounds_to_instructions_per_frame()
+    {
+        assert_eq!(FrameConfig::from_cpu_hz(600).get_instructions_per_frame(), 10);
+        assert_eq!(FrameConfig::from_cpu_hz(700).get_instructions_per_frame(), 12);
+        assert_eq!(FrameConfig::from_cpu_hz(1).get_instructions_per_frame(), 1);
+        assert_eq!(FrameConfig::new(0).get_instructions_per_frame(), 1);
+        assert_eq!(FrameConfig::new(11).get_cpu_hz(), 11 * FRAME_RATE_HZ);
+
+        assert_eq!(FrameConfig::from_cpu_hz(u32::MAX).get_instructions_per_frame(), 71_582_788);
+        assert_eq!(FrameConfig::new(u32::MAX).get_cpu_hz(), u32::MAX);
+    }
+
+    #[test]
+    fn frame_duration_scales_with_speed()
+    {
+        let mut frame_config = FrameConfig::new(10);
+        let real_time = frame_config.frame_duration().unwrap();
+        assert_eq!(real_time.as_micros(), 16_666);
+
+        frame_config.set_speed(EnumSpeed::Multiplier(2.0));
+        assert_eq!(frame_config.frame_duration().unwrap().as_micros(), 8_333);
+
+        frame_config.set_speed(EnumSpeed::Multiplier(1e-300));
+        assert_eq!(frame_config.frame_duration().unwrap().as_secs(), 1);
+
+        frame_config.set_speed(EnumSpeed::Turbo);
+        assert!(frame_config.frame_duration().is_none());
+    }
+
+    #[test]
+    fn parse_speed()
+    {
+        assert_eq!("turbo".parse::<EnumSpeed>(), Ok(EnumSpeed::Turbo));
+        assert_eq!("2".parse::<EnumSpeed>(), Ok(EnumSpeed::Multiplier(2.0)));
+        assert_eq!("0.5x".parse::<EnumSpeed>(), Ok(EnumSpeed::Multiplier(0.5)));
+        assert!("0".parse::<EnumSpeed>().is_err());
+        assert!("-1".parse::<EnumSpeed>().is_err());
+        assert_eq!("1e-300".parse::<EnumSpeed>(), Err(String::from("Invalid speed '1e-300': must be between 0.01 and 1000")));
+        assert!("1001".parse::<EnumSpeed>().is_err());
+        assert!("inf".parse::<EnumSpeed>().is_err());
+        assert!("NaN".parse::<EnumSpeed>().is_err());
+        assert_eq!("1000x".parse::<EnumSpeed>(), Ok(EnumSpeed::Multiplier(1000.0)));
+        assert!("fast".parse::<EnumSpeed>().is_err());
+    }
+}
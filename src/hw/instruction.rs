@@ -0,0 +1,315 @@
+use crate::hw::cpu::EnumRegister;
+use crate::hw::opcode::Opcode;
+
+/// A decoded instruction. 'x'/'y' are the registers from an opcode's 2nd/3rd nibble, 'nn' is its low byte and
+/// 'nnn' its low 12 bits.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Instruction
+{
+    /// 0000, a pseudo instruction that halts the CPU.
+    Halt,
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 2NNN
+    Call { nnn: u16 },
+    /// 0NNN, a call to a machine-code routine of the original computer. It can't run here, so it goes through the
+    /// invalid opcode policy.
+    MachineCall { nnn: u16 },
+    /// 1NNN
+    Jump { nnn: u16 },
+    /// 3XNN
+    SkipIfEqual { x: EnumRegister, nn: u8 },
+    /// 4XNN
+    SkipIfNotEqual { x: EnumRegister, nn: u8 },
+    /// 5XY0
+    SkipIfRegistersEqual { x: EnumRegister, y: EnumRegister },
+    /// 9XY0 (the low nibble is ignored).
+    SkipIfRegistersNotEqual { x: EnumRegister, y: EnumRegister },
+    /// 6XNN
+    Set { x: EnumRegister, nn: u8 },
+    /// 7XNN, no carry flag.
+    AddImmediate { x: EnumRegister, nn: u8 },
+    /// 8XY0
+    Move { x: EnumRegister, y: EnumRegister },
+    /// 8XY1
+    Or { x: EnumRegister, y: EnumRegister },
+    /// 8XY2
+    And { x: EnumRegister, y: EnumRegister },
+    /// 8XY3
+    Xor { x: EnumRegister, y: EnumRegister },
+    /// 8XY4, VF = carry.
+    Add { x: EnumRegister, y: EnumRegister },
+    /// 8XY5, VX -= VY and VF = no borrow.
+    Sub { x: EnumRegister, y: EnumRegister },
+    /// 8XY6, VF = the bit shifted out.
+    ShiftRight { x: EnumRegister, y: EnumRegister },
+    /// 8XY7, VX = VY - VX and VF = no borrow.
+    SubReversed { x: EnumRegister, y: EnumRegister },
+    /// 8XYE, VF = the bit shifted out.
+    ShiftLeft { x: EnumRegister, y: EnumRegister },
+    /// ANNN
+    SetI { nnn: u16 },
+    /// BNNN, jumps to NNN + V0 ('x' is only used with the jump quirk).
+    JumpOffset { x: EnumRegister, nnn: u16 },
+    /// CXNN
+    Random { x: EnumRegister, nn: u8 },
+    /// DXYN
+    Draw { x: EnumRegister, y: EnumRegister, n: u8 },
+    /// EX9E
+    SkipIfKey { x: EnumRegister },
+    /// EXA1
+    SkipIfNotKey { x: EnumRegister },
+    /// FX07
+    GetDelay { x: EnumRegister },
+    /// FX0A
+    WaitForKey { x: EnumRegister },
+    /// FX15
+    SetDelay { x: EnumRegister },
+    /// FX18
+    SetSound { x: EnumRegister },
+    /// FX1E
+    AddI { x: EnumRegister },
+    /// FX29
+    Font { x: EnumRegister },
+    /// FX33
+    Bcd { x: EnumRegister },
+    /// FX55, stores V0-VX.
+    Store { x: EnumRegister },
+    /// FX65, loads V0-VX.
+    Load { x: EnumRegister },
+    Unknown,
+}
+
+impl Instruction
+{
+    #[inline]
+    pub fn decode(raw_opcode: u16) -> Self
+    {
+        let opcode = Opcode::new(raw_opcode);
+        let x = EnumRegister::from_index(opcode.b as usize);
+        let y = EnumRegister::from_index(opcode.c as usize);
+        let nn = (raw_opcode & 0x00FF) as u8;
+        let nnn = raw_opcode & 0x0FFF;
+
+        match opcode.a
+        {
+            0x0 => match nnn
+            {
+                0x000 => Self::Halt,
+                0x0E0 => Self::ClearScreen,
+                0x0EE => Self::Return,
+                _ if opcode.b == 0 => Self::Unknown,
+                _ => Self::MachineCall { nnn },
+            },
+            0x1 => Self::Jump { nnn },
+            0x2 => Self::Call { nnn },
+            0x3 => Self::SkipIfEqual { x, nn },
+            0x4 => Self::SkipIfNotEqual { x, nn },
+            0x5 if opcode.d == 0 => Self::SkipIfRegistersEqual { x, y },
+            0x6 => Self::Set { x, nn },
+            0x7 => Self::AddImmediate { x, nn },
+            0x8 => match opcode.d
+            {
+                0x0 => Self::Move { x, y },
+                0x1 => Self::Or { x, y },
+                0x2 => Self::And { x, y },
+                0x3 => Self::Xor { x, y },
+                0x4 => Self::Add { x, y },
+                0x5 => Self::Sub { x, y },
+                0x6 => Self::ShiftRight { x, y },
+                0x7 => Self::SubReversed { x, y },
+                0xE => Self::ShiftLeft { x, y },
+                _ => Self::Unknown,
+            },
+            0x9 => Self::SkipIfRegistersNotEqual { x, y },
+            0xA => Self::SetI { nnn },
+            0xB => Self::JumpOffset { x, nnn },
+            0xC => Self::Random { x, nn },
+            0xD => Self::Draw { x, y, n: opcode.d as u8 },
+            0xE if nn == 0x9E => Self::SkipIfKey { x },
+            0xE if nn == 0xA1 => Self::SkipIfNotKey { x },
+            0xF => match nn
+            {
+                0x07 => Self::GetDelay { x },
+                0x0A => Self::WaitForKey { x },
+                0x15 => Self::SetDelay { x },
+                0x18 => Self::SetSound { x },
+                0x1E => Self::AddI { x },
+                0x29 => Self::Font { x },
+                0x33 => Self::Bcd { x },
+                0x55 => Self::Store { x },
+                0x65 => Self::Load { x },
+                _ => Self::Unknown,
+            },
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Disassembles an opcode with the classic (Cowgod) mnemonics. Unknown opcodes are shown as data.
+pub fn disassemble(raw_opcode: u16) -> String
+{
+    match Instruction::decode(raw_opcode)
+    {
+        Instruction::Unknown => format!("DW 0x{:04X}", raw_opcode),
+        instruction => instruction.to_string(),
+    }
+}
+
+impl std::fmt::Display for Instruction
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self
+        {
+            Self::Halt => write!(f, "HALT"),
+            Self::ClearScreen => write!(f, "CLS"),
+            Self::Return => write!(f, "RET"),
+            Self::Call { nnn } => write!(f, "CALL 0x{:03X}", nnn),
+            Self::MachineCall { nnn } => write!(f, "SYS 0x{:03X}", nnn),
+            Self::Jump { nnn } => write!(f, "JP 0x{:03X}", nnn),
+            Self::SkipIfEqual { x, nn } => write!(f, "SE {0:?}, 0x{1:02X}", x, nn),
+            Self::SkipIfNotEqual { x, nn } => write!(f, "SNE {0:?}, 0x{1:02X}", x, nn),
+            Self::SkipIfRegistersEqual { x, y } => write!(f, "SE {0:?}, {1:?}", x, y),
+            Self::SkipIfRegistersNotEqual { x, y } => write!(f, "SNE {0:?}, {1:?}", x, y),
+            Self::Set { x, nn } => write!(f, "LD {0:?}, 0x{1:02X}", x, nn),
+            Self::AddImmediate { x, nn } => write!(f, "ADD {0:?}, 0x{1:02X}", x, nn),
+            Self::Move { x, y } => write!(f, "LD {0:?}, {1:?}", x, y),
+            Self::Or { x, y } => write!(f, "OR {0:?}, {1:?}", x, y),
+            Self::And { x, y } => write!(f, "AND {0:?}, {1:?}", x, y),
+            Self::Xor { x, y } => write!(f, "XOR {0:?}, {1:?}", x, y),
+            Self::Add { x, y } => write!(f, "ADD {0:?}, {1:?}", x, y),
+            Self::Sub { x, y } => write!(f, "SUB {0:?}, {1:?}", x, y),
+            Self::ShiftRight { x, y } => write!(f, "SHR {0:?}, {1:?}", x, y),
+            Self::SubReversed { x, y } => write!(f, "SUBN {0:?}, {1:?}", x, y),
+            Self::ShiftLeft { x, y } => write!(f, "SHL {0:?}, {1:?}", x, y),
+            Self::SetI { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            Self::JumpOffset { nnn, .. } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Self::Random { x, nn } => write!(f, "RND {0:?}, 0x{1:02X}", x, nn),
+            Self::Draw { x, y, n } => write!(f, "DRW {0:?}, {1:?}, {2}", x, y, n),
+            Self::SkipIfKey { x } => write!(f, "SKP {:?}", x),
+            Self::SkipIfNotKey { x } => write!(f, "SKNP {:?}", x),
+            Self::GetDelay { x } => write!(f, "LD {:?}, DT", x),
+            Self::WaitForKey { x } => write!(f, "LD {:?}, K", x),
+            Self::SetDelay { x } => write!(f, "LD DT, {:?}", x),
+            Self::SetSound { x } => write!(f, "LD ST, {:?}", x),
+            Self::AddI { x } => write!(f, "ADD I, {:?}", x),
+            Self::Font { x } => write!(f, "LD F, {:?}", x),
+            Self::Bcd { x } => write!(f, "LD B, {:?}", x),
+            Self::Store { x } => write!(f, "LD [I], {:?}", x),
+            Self::Load { x } => write!(f, "LD {:?}, [I]", x),
+            Self::Unknown => write!(f, "???"),
+        }
+    }
+}
+
+/// Decoded instructions by address, so hot code is only decoded once. Each entry keeps the raw opcode too
+/// (for fault messages and watchpoint hits).
+/// NOTE: Any write to main memory must invalidate the entries it overlaps, or stale code will run.
+pub struct DecodeCache
+{
+    entries: Vec<Option<(u16, Instruction)>>,
+}
+
+impl DecodeCache
+{
+    pub fn new(capacity: usize) -> Self
+    {
+        Self { entries: vec![None; capacity] }
+    }
+
+    #[inline]
+    pub fn get(&self, addr: usize) -> Option<(u16, Instruction)>
+    {
+        self.entries.get(addr).copied().flatten()
+    }
+
+    #[inline]
+    pub fn insert(&mut self, addr: usize, raw_opcode: u16, instruction: Instruction)
+    {
+        if let Some(entry) = self.entries.get_mut(addr)
+        {
+            *entry = Some((raw_opcode, instruction));
+        }
+    }
+
+    /// Drops the instructions that include the byte at 'addr' (the ones starting at 'addr' and 'addr - 1').
+    #[inline]
+    pub fn invalidate(&mut self, addr: usize)
+    {
+        if let Some(entry) = self.entries.get_mut(addr)
+        {
+            *entry = None;
+        }
+
+        if let Some(entry) = addr.checked_sub(1).and_then(|prev| self.entries.get_mut(prev))
+        {
+            *entry = None;
+        }
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.entries.fill(None);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::cpu::EnumRegister;
+    use crate::hw::instruction::{disassemble, DecodeCache, Instruction};
+
+    #[test]
+    fn decode_instructions()
+    {
+        assert_eq!(Instruction::decode(0x0000), Instruction::Halt);
+        assert_eq!(Instruction::decode(0x00EE), Instruction::Return);
+        assert_eq!(Instruction::decode(0x0345), Instruction::MachineCall { nnn: 0x345 });
+        assert_eq!(Instruction::decode(0x2345), Instruction::Call { nnn: 0x345 });
+        assert_eq!(Instruction::decode(0x812E), Instruction::ShiftLeft { x: EnumRegister::V1, y: EnumRegister::V2 });
+        assert_eq!(Instruction::decode(0xD125), Instruction::Draw { x: EnumRegister::V1, y: EnumRegister::V2, n: 5 });
+        assert_eq!(Instruction::decode(0xFA65), Instruction::Load { x: EnumRegister::VA });
+    }
+
+    #[test]
+    fn decode_unknown_opcodes()
+    {
+        for raw_opcode in [0x00E1, 0x5121, 0x8128, 0xE1FF, 0xF0FF]
+        {
+            assert_eq!(Instruction::decode(raw_opcode), Instruction::Unknown);
+        }
+    }
+
+    #[test]
+    fn disassemble_opcodes()
+    {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x2ABC), "CALL 0xABC");
+        assert_eq!(disassemble(0x3A07), "SE VA, 0x07");
+        assert_eq!(disassemble(0x8AB5), "SUB VA, VB");
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0xE1FF), "DW 0xE1FF");
+    }
+
+    #[test]
+    fn cache_invalidates_overlapping_entries()
+    {
+        let mut cache = DecodeCache::new(0x10);
+        cache.insert(0x4, 0x6001, Instruction::decode(0x6001));
+        cache.insert(0x5, 0x0160, Instruction::decode(0x0160));
+        cache.insert(0x6, 0x6002, Instruction::decode(0x6002));
+
+        cache.invalidate(0x5);
+        assert!(cache.get(0x4).is_none());
+        assert!(cache.get(0x5).is_none());
+        assert!(cache.get(0x6).is_some());
+
+        cache.invalidate(0x0);
+        cache.insert(0x10, 0x6001, Instruction::Unknown);
+        assert!(cache.get(0x10).is_none());
+    }
+}
@@ -0,0 +1,196 @@
+pub const KEY_COUNT: usize = 16;
+
+/// The 16-key hexadecimal keypad (keys 0x0-0xF).
+pub struct Keypad
+{
+    keys: [bool; KEY_COUNT],
+}
+
+impl Keypad
+{
+    pub fn new() -> Self
+    {
+        Self { keys: [false; KEY_COUNT] }
+    }
+
+    /// Sets the state of 'key'. Keys outside of 0x0-0xF are ignored.
+    pub fn set_key(&mut self, key: u8, pressed: bool)
+    {
+        if let Some(state) = self.keys.get_mut(key as usize)
+        {
+            *state = pressed;
+        }
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool
+    {
+        self.keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    /// The lowest numbered key currently held down, if any.
+    pub fn first_pressed(&self) -> Option<u8>
+    {
+        self.keys.iter().position(|pressed| *pressed).map(|key| key as u8)
+    }
+
+    pub fn release_all(&mut self)
+    {
+        self.keys = [false; KEY_COUNT];
+    }
+}
+
+impl Default for Keypad
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// The host keys for keypad keys 0x0-0xF, in that order. The layout maps the COSMAC VIP keypad
+///     1 2 3 C        1 2 3 4
+///     4 5 6 D   to   q w e r
+///     7 8 9 E        a s d f
+///     A 0 B F        z x c v
+pub const DEFAULT_KEY_MAP: &str = "x123qweasdzc4rfv";
+
+/// Maps host (keyboard) keys to keypad keys.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyMap
+{
+    host_keys: [char; KEY_COUNT],
+}
+
+impl KeyMap
+{
+    pub fn new() -> Self
+    {
+        DEFAULT_KEY_MAP.parse().unwrap()
+    }
+
+    /// The keypad key bound to 'host_key' (letters are matched case insensitively).
+    pub fn get_key(&self, host_key: char) -> Option<u8>
+    {
+        let host_key = host_key.to_ascii_lowercase();
+        self.host_keys.iter().position(|mapped| *mapped == host_key).map(|key| key as u8)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_host_key(&self, key: u8) -> Option<char>
+    {
+        self.host_keys.get(key as usize).copied()
+    }
+}
+
+impl Default for KeyMap
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for KeyMap
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.host_keys.iter().collect::<String>())
+    }
+}
+
+impl std::str::FromStr for KeyMap
+{
+    type Err = String;
+
+    /// Parses one host key per keypad key, in keypad order 0x0-0xF (see DEFAULT_KEY_MAP).
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        let chars: Vec<char> = text.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+        if chars.len() != KEY_COUNT
+        {
+            return Err(format!("Expected {0} host keys (one per keypad key 0-F) but got {1}", KEY_COUNT, chars.len()));
+        }
+
+        let mut host_keys = [' '; KEY_COUNT];
+
+        for (key, host_key) in chars.iter().enumerate()
+        {
+            if chars[..key].contains(host_key)
+            {
+                return Err(format!("Host key '{}' is mapped more than once", host_key));
+            }
+
+            host_keys[key] = *host_key;
+        }
+
+        Ok(Self { host_keys })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::keypad::{KeyMap, Keypad, DEFAULT_KEY_MAP};
+
+    #[test]
+    fn keys_start_released()
+    {
+        let keypad = Keypad::new();
+
+        for key in 0..16
+        {
+            assert!(!keypad.is_pressed(key));
+        }
+
+        assert!(keypad.first_pressed().is_none());
+    }
+
+    #[test]
+    fn press_and_release()
+    {
+        let mut keypad = Keypad::new();
+        keypad.set_key(0xA, true);
+        keypad.set_key(0x3, true);
+
+        assert!(keypad.is_pressed(0xA));
+        assert_eq!(keypad.first_pressed(), Some(0x3));
+
+        keypad.set_key(0x3, false);
+        assert_eq!(keypad.first_pressed(), Some(0xA));
+
+        keypad.release_all();
+        assert!(keypad.first_pressed().is_none());
+    }
+
+    #[test]
+    fn out_of_range_keys_are_ignored()
+    {
+        let mut keypad = Keypad::new();
+        keypad.set_key(0x10, true);
+
+        assert!(!keypad.is_pressed(0x10));
+        assert!(keypad.first_pressed().is_none());
+    }
+
+    #[test]
+    fn default_key_map()
+    {
+        let key_map = KeyMap::new();
+
+        assert_eq!(key_map.get_key('x'), Some(0x0));
+        assert_eq!(key_map.get_key('1'), Some(0x1));
+        assert_eq!(key_map.get_key('V'), Some(0xF));
+        assert_eq!(key_map.get_key('p'), None);
+        assert_eq!(key_map.get_host_key(0xC), Some('4'));
+        assert_eq!(key_map.to_string(), DEFAULT_KEY_MAP);
+    }
+
+    #[test]
+    fn parse_key_map_rejects_bad_layouts()
+    {
+        assert!("0123456789abcdef".parse::<KeyMap>().is_ok());
+        assert!("0123".parse::<KeyMap>().is_err());
+        assert!("0123456789abcdea".parse::<KeyMap>().is_err());
+    }
+}
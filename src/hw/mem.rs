@@ -1,3 +1,8 @@
+use std::ops::Range;
+
+/// Bytes per hexdump row.
+const HEXDUMP_ROW_SIZE: usize = 16;
+
 pub struct Mem
 {
     arr: Vec<u8>,
@@ -9,7 +14,7 @@ impl Mem
     pub fn new(capacity: usize) -> Self
     {
         // Self { arr: Vec::<u8>::with_capacity(capacity), capacity: capacity }
-        Self { arr: vec![0; capacity], capacity: capacity }
+        Self { arr: vec![0; capacity], capacity }
     }
 
     #[allow(dead_code)]
@@ -80,31 +85,166 @@ impl Mem
 
     pub fn print_state(&self, stream: &mut String)
     {
-        for addr in 0..self.get_capacity()
+        let mut dump = String::new();
+        self.hexdump(0..self.get_capacity(), &mut dump);
+
+        let lines: Vec<String> = dump.lines().map(|line| format!("\t\t{}", line)).collect();
+        *stream += &lines.join("\n");
+    }
+
+    /// Address-prefixed hex and ASCII rows for the bytes in 'range' (clipped to memory), 16 per row:
+    ///
+    /// ```text
+    /// 0x0200  60 01 22 06 00 00 70 01  00 EE                   |`."...p...      |
+    /// ```
+    pub fn hexdump(&self, range: Range<usize>, stream: &mut String)
+    {
+        let range = range.start.min(self.capacity)..range.end.min(self.capacity);
+
+        if range.is_empty()
+        {
+            return;
+        }
+
+        let first_row = range.start - range.start % HEXDUMP_ROW_SIZE;
+
+        for row_start in (first_row..range.end).step_by(HEXDUMP_ROW_SIZE)
         {
-            if addr % 8 == 0
+            let mut hex = String::with_capacity(3 * HEXDUMP_ROW_SIZE + 1);
+            let mut ascii = String::with_capacity(HEXDUMP_ROW_SIZE);
+
+            for addr in row_start..row_start + HEXDUMP_ROW_SIZE
             {
-                if addr > 0
+                if addr == row_start + HEXDUMP_ROW_SIZE / 2
                 {
-                    stream.push('\n');
+                    hex.push(' ');
                 }
 
-                stream.push('\t');
-                stream.push('\t');
+                // Bytes outside of the range are left blank so the columns still line up.
+                match self.read_u8(addr).filter(|_| range.contains(&addr))
+                {
+                    Some(value) =>
+                    {
+                        hex += &format!("{:02X} ", value);
+                        ascii.push(if value.is_ascii_graphic() || value == b' ' { value as char } else { '.' });
+                    },
+                    None =>
+                    {
+                        hex += "   ";
+                        ascii.push(' ');
+                    },
+                }
             }
 
-            let value = self.read_u8(addr).expect("Expected to unwrap during 'print_stack_block'");
-            *stream += &value.to_string();
-            stream.push(' ');
+            *stream += &format!("0x{0:04X}  {1}|{2}|\n", row_start, hex, ascii);
+        }
+    }
+
+    /// Start addresses of every (possibly overlapping) match of 'pattern' in 'range'. None matches any byte.
+    pub fn find(&self, pattern: &[Option<u8>], range: Range<usize>) -> Vec<usize>
+    {
+        let end = range.end.min(self.capacity);
+
+        if pattern.is_empty() || range.start >= end
+        {
+            return Vec::new();
+        }
+
+        self.arr[range.start..end].windows(pattern.len())
+            .enumerate()
+            .filter(|(_, window)| window.iter().zip(pattern.iter()).all(|(value, expected)| expected.is_none_or(|expected| expected == *value)))
+            .map(|(offset, _)| range.start + offset)
+            .collect()
+    }
+
+    /// A copy of the current contents, to compare against later (see 'MemSnapshot::diff').
+    pub fn snapshot(&self) -> MemSnapshot
+    {
+        MemSnapshot { arr: self.arr.clone() }
+    }
+}
+
+/// A byte change between a snapshot and the current memory: address, old value, new value.
+pub type MemChange = (usize, u8, u8);
+
+/// The contents of main memory at one point in time.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MemSnapshot
+{
+    arr: Vec<u8>,
+}
+
+impl MemSnapshot
+{
+    pub fn read_u8(&self, addr: usize) -> Option<u8>
+    {
+        self.arr.get(addr).copied()
+    }
+
+    /// The bytes that differ between this snapshot and 'mem', in address order.
+    pub fn diff(&self, mem: &Mem) -> Vec<MemChange>
+    {
+        self.arr.iter().zip(mem.arr.iter()).enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(addr, (old, new))| (addr, *old, *new))
+            .collect()
+    }
+}
+
+/// One line per run of consecutive changed bytes: '0x0300-0x0301  00 00 -> 12 34'.
+pub fn format_changes(changes: &[MemChange], stream: &mut String)
+{
+    let mut index = 0;
+
+    while index < changes.len()
+    {
+        let mut run_end = index + 1;
+
+        while run_end < changes.len() && changes[run_end].0 == changes[run_end - 1].0 + 1
+        {
+            run_end += 1;
+        }
+
+        let run = &changes[index..run_end];
+        let old: Vec<String> = run.iter().map(|(_, old, _)| format!("{:02X}", old)).collect();
+        let new: Vec<String> = run.iter().map(|(_, _, new)| format!("{:02X}", new)).collect();
+
+        if run.len() == 1
+        {
+            *stream += &format!("0x{0:04X}         {1} -> {2}\n", run[0].0, old[0], new[0]);
+        }
+
+        else
+        {
+            *stream += &format!("0x{0:04X}-0x{1:04X}  {2} -> {3}\n", run[0].0, run[run.len() - 1].0, old.join(" "), new.join(" "));
         }
+
+        index = run_end;
     }
 }
 
+/// Parses hex bytes like "A2 F0 ?? 12" or "A2F0??12", where '??' matches any byte.
+pub fn parse_byte_pattern(text: &str) -> Result<Vec<Option<u8>>, String>
+{
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if digits.is_empty() || !digits.len().is_multiple_of(2)
+    {
+        return Err(format!("Expected hex byte pairs (or '??') but got '{}'", text));
+    }
+
+    (0..digits.len()).step_by(2).map(|index| match &digits[index..index + 2]
+    {
+        "??" => Ok(None),
+        pair => u8::from_str_radix(pair, 16).map(Some).map_err(|_| format!("Invalid byte '{}'", pair)),
+    }).collect()
+}
+
 #[cfg(test)]
 mod tests
 {
     #[allow(unused_imports)]
-    use crate::hw::mem::Mem;
+    use crate::hw::mem::{format_changes, parse_byte_pattern, Mem};
 
     #[test]
     fn capacity_matches_arr()
@@ -156,7 +296,7 @@ mod tests
         let nonzero_value: u8 = 1;
 
         assert_eq!(mem.read_u8(addr).unwrap_or(nonzero_value), 0);
-        assert_eq!(mem.write_u8(addr, value), true);
+        assert!(mem.write_u8(addr, value));
         assert_eq!(mem.read_u8(addr).unwrap_or(nonzero_value), value);
     }
 
@@ -171,6 +311,65 @@ mod tests
         assert_ne!(mem.read_u16(addr).unwrap_or(nonzero_value), 0);
     }
 
+    #[test]
+    fn hexdump_rows_have_addresses_and_ascii()
+    {
+        let mut mem = Mem::new(4096);
+
+        for (offset, value) in b"Hi! CHIP-8\x00\xFF".iter().enumerate()
+        {
+            mem.write_u8(0x204 + offset, *value);
+        }
+
+        let mut stream = String::new();
+        mem.hexdump(0x204..0x214, &mut stream);
+
+        assert_eq!(stream, "0x0200              48 69 21 20  43 48 49 50 2D 38 00 FF |    Hi! CHIP-8..|\n\
+                            0x0210  00 00 00 00                                      |....            |\n");
+
+        // Ranges are clipped to memory.
+        let mut stream = String::new();
+        mem.hexdump(0xFF8..0x2000, &mut stream);
+        assert_eq!(stream.lines().count(), 1);
+        assert!(stream.starts_with("0x0FF0                           00 00 00 00 00 00 00 00 |"));
+    }
+
+    #[test]
+    fn find_patterns_with_wildcards()
+    {
+        let mut mem = Mem::new(4096);
+        mem.write_u16(0x200, 0xA2F0);
+        mem.write_u16(0x202, 0x1234);
+        mem.write_u16(0x300, 0xA2F0);
+        mem.write_u16(0x302, 0x5634);
+
+        let pattern = parse_byte_pattern("A2 F0 ?? 34").unwrap();
+        assert_eq!(mem.find(&pattern, 0..4096), [0x200, 0x300]);
+        assert_eq!(mem.find(&pattern, 0x201..4096), [0x300]);
+        assert_eq!(mem.find(&parse_byte_pattern("A2F012").unwrap(), 0..4096), [0x200]);
+
+        assert!(parse_byte_pattern("A2F").is_err());
+        assert!(parse_byte_pattern("ZZ").is_err());
+        assert!(parse_byte_pattern("").is_err());
+    }
+
+    #[test]
+    fn snapshot_diff_shows_changed_runs()
+    {
+        let mut mem = Mem::new(4096);
+        let snapshot = mem.snapshot();
+
+        mem.write_u16(0x300, 0x1234);
+        mem.write_u8(0x310, 0xFF);
+
+        let changes = snapshot.diff(&mem);
+        assert_eq!(changes, [(0x300, 0x00, 0x12), (0x301, 0x00, 0x34), (0x310, 0x00, 0xFF)]);
+
+        let mut stream = String::new();
+        format_changes(&changes, &mut stream);
+        assert_eq!(stream, "0x0300-0x0301  00 00 -> 12 34\n0x0310         00 -> FF\n");
+    }
+
     #[test]
     fn write_u16_in_bounds_passes()
     {
@@ -181,7 +380,7 @@ mod tests
         let nonzero_value: u16 = 1;
 
         assert_eq!(mem.read_u16(addr).unwrap_or(nonzero_value), 0);
-        assert_eq!(mem.write_u16(addr, value), true);
+        assert!(mem.write_u16(addr, value));
         assert_eq!(mem.read_u16(addr).unwrap_or(nonzero_value), value);
     }
 }
@@ -1,5 +1,12 @@
 pub mod cpu;
+pub mod display;
+pub mod fault;
+pub mod frame;
+pub mod instruction;
+pub mod keypad;
 pub mod mem;
+pub mod observer;
 pub mod opcode;
+pub mod quirks;
+pub mod rng;
 pub mod timer;
-
@@ -0,0 +1,265 @@
+use crate::hw::cpu::CPU;
+use crate::hw::fault::{EnumFault, EnumTickOutcome};
+
+use std::any::Any;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumTimer
+{
+    Delay,
+    /// The buzzer sounds while this is non-zero.
+    Sound,
+}
+
+/// Hooks the CPU calls as it runs, for tools that watch execution (tracers, coverage, cheats...).
+/// Every method does nothing by default, so an observer only implements what it needs. 'pc' is always the
+/// address of the instruction being executed.
+///
+/// Register one with 'CPU::add_observer' (or 'Machine::add_observer') and look it up again with 'find_observer'; the
+/// tracer, profiler, coverage and SMC detector in 'dbg' are all used this way. Without any, the CPU skips the calls
+/// entirely.
+pub trait Observer: Any
+{
+    /// An instruction was fetched and is about to run.
+    fn on_fetch(&mut self, _pc: u16, _raw_opcode: u16) {}
+
+    /// The instruction fetched from 'pc' has run (or faulted). 'raw_opcode' is 0 if it couldn't be fetched.
+    fn on_execute(&mut self, _cpu: &CPU, _pc: u16, _raw_opcode: u16, _result: &Result<EnumTickOutcome, EnumFault>) {}
+
+    fn on_mem_read(&mut self, _pc: u16, _addr: usize, _value: u8) {}
+
+    fn on_mem_write(&mut self, _pc: u16, _addr: usize, _old_value: u8, _value: u8) {}
+
+    /// A call pushed 'ret_address'; 'depth' is the number of return addresses on the stack afterwards.
+    fn on_stack_push(&mut self, _pc: u16, _ret_address: u16, _depth: usize) {}
+
+    /// A return popped 'ret_address'; 'depth' is the number of return addresses left.
+    fn on_stack_pop(&mut self, _pc: u16, _ret_address: u16, _depth: usize) {}
+
+    /// A sprite was drawn at ('x', 'y') (before wrapping or clipping).
+    fn on_draw(&mut self, _pc: u16, _x: usize, _y: usize, _rows: &[u8], _collision: bool) {}
+
+    /// A timer changed, either set by an instruction or counted down by a frame.
+    fn on_timer(&mut self, _timer: EnumTimer, _old_value: u8, _value: u8) {}
+}
+
+/// Identifies a registered observer (see 'CPU::remove_observer').
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ObserverId(pub(crate) usize);
+
+/// The observers registered with a CPU.
+#[derive(Default)]
+pub struct ObserverList
+{
+    observers: Vec<(ObserverId, Box<dyn Observer>)>,
+    next_id: usize,
+}
+
+impl ObserverList
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool
+    {
+        self.observers.is_empty()
+    }
+
+    pub fn add(&mut self, observer: Box<dyn Observer>) -> ObserverId
+    {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push((id, observer));
+
+        id
+    }
+
+    pub fn remove(&mut self, id: ObserverId) -> Option<Box<dyn Observer>>
+    {
+        let index = self.observers.iter().position(|(observer_id, _)| *observer_id == id)?;
+        Some(self.observers.remove(index).1)
+    }
+
+    /// The observer registered as 'id', if it is a 'T'.
+    pub fn get<T: Observer>(&self, id: ObserverId) -> Option<&T>
+    {
+        let (_, observer) = self.observers.iter().find(|(observer_id, _)| *observer_id == id)?;
+        (observer.as_ref() as &dyn Any).downcast_ref::<T>()
+    }
+
+    pub fn get_mut<T: Observer>(&mut self, id: ObserverId) -> Option<&mut T>
+    {
+        let (_, observer) = self.observers.iter_mut().find(|(observer_id, _)| *observer_id == id)?;
+        (observer.as_mut() as &mut dyn Any).downcast_mut::<T>()
+    }
+
+    /// The first observer that is a 'T', for tools registered once per run (the profiler, coverage...).
+    pub fn find<T: Observer>(&self) -> Option<&T>
+    {
+        self.observers.iter().find_map(|(_, observer)| (observer.as_ref() as &dyn Any).downcast_ref::<T>())
+    }
+
+    pub fn find_mut<T: Observer>(&mut self) -> Option<&mut T>
+    {
+        self.observers.iter_mut().find_map(|(_, observer)| (observer.as_mut() as &mut dyn Any).downcast_mut::<T>())
+    }
+
+    /// Calls 'notify' for every observer, in the order they were added.
+    #[inline]
+    pub fn for_each(&mut self, mut notify: impl FnMut(&mut dyn Observer))
+    {
+        for (_, observer) in self.observers.iter_mut()
+        {
+            notify(observer.as_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::cpu::CPU;
+    use crate::hw::fault::{EnumFault, EnumTickOutcome};
+    use crate::hw::observer::{EnumTimer, Observer};
+
+    const STARTING_PC: u16 = 0x200;
+
+    /// Records every hook as a line of text.
+    #[derive(Default)]
+    struct Recorder
+    {
+        events: Vec<String>,
+    }
+
+    impl Observer for Recorder
+    {
+        fn on_fetch(&mut self, pc: u16, raw_opcode: u16)
+        {
+            self.events.push(format!("fetch {0:03X} {1:04X}", pc, raw_opcode));
+        }
+
+        fn on_execute(&mut self, cpu: &CPU, pc: u16, raw_opcode: u16, result: &Result<EnumTickOutcome, EnumFault>)
+        {
+            self.events.push(format!("execute {0:03X} {1:04X} ok={2} next={3:03X}", pc, raw_opcode, result.is_ok(), cpu.get_pc()));
+        }
+
+        fn on_mem_read(&mut self, pc: u16, addr: usize, value: u8)
+        {
+            self.events.push(format!("read {0:03X} [{1:03X}]={2:02X}", pc, addr, value));
+        }
+
+        fn on_mem_write(&mut self, pc: u16, addr: usize, old_value: u8, value: u8)
+        {
+            self.events.push(format!("write {0:03X} [{1:03X}] {2:02X}->{3:02X}", pc, addr, old_value, value));
+        }
+
+        fn on_stack_push(&mut self, pc: u16, ret_address: u16, depth: usize)
+        {
+            self.events.push(format!("push {0:03X} {1:03X} depth={2}", pc, ret_address, depth));
+        }
+
+        fn on_stack_pop(&mut self, pc: u16, ret_address: u16, depth: usize)
+        {
+            self.events.push(format!("pop {0:03X} {1:03X} depth={2}", pc, ret_address, depth));
+        }
+
+        fn on_draw(&mut self, pc: u16, x: usize, y: usize, rows: &[u8], collision: bool)
+        {
+            self.events.push(format!("draw {0:03X} ({1},{2}) rows={3} collision={4}", pc, x, y, rows.len(), collision));
+        }
+
+        fn on_timer(&mut self, timer: EnumTimer, old_value: u8, value: u8)
+        {
+            self.events.push(format!("timer {0:?} {1}->{2}", timer, old_value, value));
+        }
+    }
+
+    /// Only counts fetches, relying on the default (empty) hooks for the rest.
+    #[derive(Default)]
+    struct FetchCounter(u32);
+
+    impl Observer for FetchCounter
+    {
+        fn on_fetch(&mut self, _pc: u16, _raw_opcode: u16)
+        {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn hooks_follow_execution()
+    {
+        let mut cpu = CPU::new(4096, STARTING_PC);
+        let id = cpu.add_observer(Box::new(Recorder::default()));
+
+        // 0x200: CALL 0x206, 0x202: halt, 0x204: (unused)
+        // 0x206: V0 = 2, delay = V0, I = 0x300, store V0, draw 1 row at (V0, V0), return
+        for (offset, raw_opcode) in [0x2206u16, 0x0000, 0x0000, 0x6002, 0xF015, 0xA300, 0xF055, 0xD001, 0x00EE].iter().enumerate()
+        {
+            cpu.get_mem_mut().write_u16(STARTING_PC as usize + 2 * offset, *raw_opcode);
+        }
+
+        while !cpu.is_halted()
+        {
+            assert!(cpu.tick().is_ok());
+        }
+
+        cpu.tick_timers();
+
+        let events = &cpu.get_observer::<Recorder>(id).unwrap().events;
+        let expected = [
+            "fetch 200 2206", "push 200 202 depth=1", "execute 200 2206 ok=true next=206",
+            "fetch 206 6002", "execute 206 6002 ok=true next=208",
+            "fetch 208 F015", "timer Delay 0->2", "execute 208 F015 ok=true next=20A",
+            "fetch 20A A300", "execute 20A A300 ok=true next=20C",
+            "fetch 20C F055", "write 20C [300] 00->02", "execute 20C F055 ok=true next=20E",
+            "fetch 20E D001", "read 20E [300]=02", "draw 20E (2,2) rows=1 collision=false", "execute 20E D001 ok=true next=210",
+            "fetch 210 00EE", "pop 210 202 depth=0", "execute 210 00EE ok=true next=202",
+            "fetch 202 0000", "execute 202 0000 ok=true next=204",
+            "timer Delay 2->1",
+        ];
+
+        assert_eq!(events, &expected);
+    }
+
+    #[test]
+    fn execute_reports_the_faulting_pc()
+    {
+        let mut cpu = CPU::new(4096, STARTING_PC);
+        let id = cpu.add_observer(Box::new(Recorder::default()));
+
+        // Jump to the last byte of memory, where there's no room for a whole instruction.
+        cpu.get_mem_mut().write_u16(STARTING_PC as usize, 0x1FFF);
+        assert!(cpu.tick().is_ok());
+        assert_eq!(cpu.tick(), Err(EnumFault::PcOutOfBounds { pc: 0xFFF }));
+
+        let events = &cpu.get_observer::<Recorder>(id).unwrap().events;
+        assert_eq!(events, &["fetch 200 1FFF", "execute 200 1FFF ok=true next=FFF", "execute FFF 0000 ok=false next=FFF"]);
+    }
+
+    #[test]
+    fn observers_can_be_removed()
+    {
+        let mut cpu = CPU::new(4096, STARTING_PC);
+        let first = cpu.add_observer(Box::new(FetchCounter::default()));
+        let second = cpu.add_observer(Box::new(FetchCounter::default()));
+
+        // Jump to self.
+        cpu.get_mem_mut().write_u16(STARTING_PC as usize, 0x1200);
+        assert!(cpu.tick().is_ok());
+
+        let removed = cpu.remove_observer(first).unwrap();
+        assert!(cpu.remove_observer(first).is_none());
+        assert!(cpu.get_observer::<Recorder>(second).is_none());
+
+        assert!(cpu.tick().is_ok());
+
+        assert_eq!((removed.as_ref() as &dyn std::any::Any).downcast_ref::<FetchCounter>().unwrap().0, 1);
+        assert_eq!(cpu.get_observer::<FetchCounter>(second).unwrap().0, 2);
+        assert_eq!(cpu.find_observer::<FetchCounter>().unwrap().0, 2);
+        assert!(cpu.find_observer::<Recorder>().is_none());
+    }
+}
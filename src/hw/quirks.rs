@@ -0,0 +1,150 @@
+/// Behaviors that differ between CHIP-8 interpreters. ROMs written for one interpreter often rely on its quirks.
+/// The default (all off) is the behavior most modern ROMs expect.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Quirks
+{
+    /// 8XY6/8XYE shift VY into VX instead of shifting VX in place (COSMAC VIP).
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register stored/loaded (COSMAC VIP).
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to XNN + VX instead of NNN + V0 (SUPER-CHIP).
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0 (COSMAC VIP).
+    pub logic_resets_vf: bool,
+    /// Sprites wrap around the screen edges instead of being clipped.
+    pub wrap_sprites: bool,
+}
+
+const QUIRK_NAMES: [&str; 5] = ["shift-vy", "load-store-i", "jump-vx", "vf-reset", "wrap"];
+
+impl Quirks
+{
+    /// The original COSMAC VIP interpreter.
+    pub const CHIP8: Self = Self { shift_uses_vy: true, load_store_increments_i: true, jump_uses_vx: false, logic_resets_vf: true, wrap_sprites: false };
+    /// SUPER-CHIP 1.1 on the HP48.
+    pub const SCHIP: Self = Self { shift_uses_vy: false, load_store_increments_i: false, jump_uses_vx: true, logic_resets_vf: false, wrap_sprites: false };
+
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool>
+    {
+        match name
+        {
+            "shift-vy" => Some(&mut self.shift_uses_vy),
+            "load-store-i" => Some(&mut self.load_store_increments_i),
+            "jump-vx" => Some(&mut self.jump_uses_vx),
+            "vf-reset" => Some(&mut self.logic_resets_vf),
+            "wrap" => Some(&mut self.wrap_sprites),
+            _ => None,
+        }
+    }
+
+    fn flag(&self, name: &str) -> bool
+    {
+        let mut copy = *self;
+        copy.flag_mut(name).is_some_and(|flag| *flag)
+    }
+
+    /// The names of the quirks that are on, in a fixed order.
+    pub fn enabled(&self) -> Vec<&'static str>
+    {
+        QUIRK_NAMES.iter().copied().filter(|name| self.flag(name)).collect()
+    }
+}
+
+impl std::fmt::Display for Quirks
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let enabled = self.enabled();
+
+        if enabled.is_empty()
+        {
+            return write!(f, "none");
+        }
+
+        write!(f, "{}", enabled.join(", "))
+    }
+}
+
+impl std::str::FromStr for Quirks
+{
+    type Err = String;
+
+    /// Parses a comma/space separated list of quirk names and presets ('chip8', 'schip', 'none').
+    /// A name prefixed with '-' turns that quirk off again (e.g. "chip8, -vf-reset").
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        let mut quirks = Self::default();
+
+        for token in text.split([',', ' ']).map(|token| token.trim()).filter(|token| !token.is_empty())
+        {
+            match token
+            {
+                "none" => { quirks = Self::default(); },
+                "chip8" => { quirks = Self::CHIP8; },
+                "schip" => { quirks = Self::SCHIP; },
+                _ =>
+                {
+                    let (name, enabled) = match token.strip_prefix('-')
+                    {
+                        Some(name) => (name, false),
+                        None => (token, true),
+                    };
+
+                    match quirks.flag_mut(name)
+                    {
+                        Some(flag) => { *flag = enabled; },
+                        None => { return Err(format!("Unknown quirk '{0}' (expected one of: chip8, schip, none, {1})", name, QUIRK_NAMES.join(", "))); },
+                    }
+                },
+            }
+        }
+
+        Ok(quirks)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::quirks::Quirks;
+
+    #[test]
+    fn default_has_no_quirks()
+    {
+        assert_eq!(Quirks::default().to_string(), "none");
+        assert_eq!("none".parse::<Quirks>(), Ok(Quirks::default()));
+        assert_eq!("".parse::<Quirks>(), Ok(Quirks::default()));
+    }
+
+    #[test]
+    fn parse_names_and_presets()
+    {
+        let quirks = "shift-vy, wrap".parse::<Quirks>().unwrap();
+        assert!(quirks.shift_uses_vy);
+        assert!(quirks.wrap_sprites);
+        assert!(!quirks.jump_uses_vx);
+
+        assert_eq!("chip8".parse::<Quirks>(), Ok(Quirks::CHIP8));
+        assert_eq!("schip".parse::<Quirks>(), Ok(Quirks::SCHIP));
+
+        let quirks = "chip8 -vf-reset".parse::<Quirks>().unwrap();
+        assert!(quirks.shift_uses_vy);
+        assert!(!quirks.logic_resets_vf);
+    }
+
+    #[test]
+    fn display_round_trips()
+    {
+        let quirks = Quirks::CHIP8;
+        assert_eq!(quirks.to_string(), "shift-vy, load-store-i, vf-reset");
+        assert_eq!(quirks.to_string().parse::<Quirks>(), Ok(quirks));
+    }
+
+    #[test]
+    fn unknown_quirk_fails()
+    {
+        let result = "shift-vy, warp".parse::<Quirks>();
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("'warp'"));
+    }
+}
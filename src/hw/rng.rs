@@ -0,0 +1,81 @@
+/// Small xorshift generator for the CXNN instruction. Deterministic for a given seed.
+#[derive(Clone, Debug)]
+pub struct Rng
+{
+    state: u32,
+}
+
+impl Rng
+{
+    pub fn new(seed: u32) -> Self
+    {
+        // NOTE: xorshift gets stuck at 0, so nudge that seed.
+        Self { state: if seed == 0 { 0x2545_F491 } else { seed } }
+    }
+
+    /// Seeds from the system clock.
+    pub fn from_time() -> Self
+    {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos() ^ duration.as_secs() as u32)
+            .unwrap_or(0);
+
+        Self::new(nanos)
+    }
+
+    pub fn next_u32(&mut self) -> u32
+    {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+
+        x
+    }
+
+    pub fn next_u8(&mut self) -> u8
+    {
+        (self.next_u32() >> 24) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::hw::rng::Rng;
+
+    #[test]
+    fn same_seed_same_sequence()
+    {
+        let mut left = Rng::new(42);
+        let mut right = Rng::new(42);
+
+        for _ in 0..100
+        {
+            assert_eq!(left.next_u32(), right.next_u32());
+        }
+    }
+
+    #[test]
+    fn zero_seed_still_produces_values()
+    {
+        let mut rng = Rng::new(0);
+        assert_ne!(rng.next_u32(), 0);
+    }
+
+    #[test]
+    fn bytes_cover_range()
+    {
+        let mut rng = Rng::new(1);
+        let mut seen = [false; 256];
+
+        for _ in 0..10_000
+        {
+            seen[rng.next_u8() as usize] = true;
+        }
+
+        assert!(seen.iter().all(|seen| *seen));
+    }
+}
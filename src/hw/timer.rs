@@ -1,77 +1,380 @@
-pub struct Timer
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// A monotonic time source that can also block for a while.
+pub trait Clock
+{
+    /// Time elapsed since the clock's (arbitrary) epoch.
+    fn now(&self) -> Duration;
+
+    fn sleep(&self, duration: Duration);
+}
+
+/// Wall-clock time backed by 'std::time::Instant'.
+pub struct RealClock
+{
+    epoch: Instant,
+}
+
+impl RealClock
+{
+    pub fn new() -> Self
+    {
+        Self { epoch: Instant::now() }
+    }
+}
+
+impl Default for RealClock
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Clock for RealClock
+{
+    fn now(&self) -> Duration
+    {
+        self.epoch.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration)
+    {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when told to, so timing logic can be tested without sleeping.
+/// NOTE: Sleeping advances the clock by exactly the requested duration.
+#[allow(dead_code)]
+pub struct VirtualClock
+{
+    now: Cell<Duration>,
+}
+
+#[allow(dead_code)]
+impl VirtualClock
+{
+    pub fn new() -> Self
+    {
+        Self { now: Cell::new(Duration::ZERO) }
+    }
+
+    pub fn advance(&self, duration: Duration)
+    {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Default for VirtualClock
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Clock for VirtualClock
+{
+    fn now(&self) -> Duration
+    {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration)
+    {
+        self.advance(duration);
+    }
+}
+
+/// Lateness statistics collected by a 'Timer'.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimerStats
+{
+    /// Periods handed out to the caller (including catch-up periods).
+    pub periods: u64,
+    /// Wake-ups that were a full period or more behind schedule.
+    pub overruns: u64,
+    /// Periods discarded because they exceeded the catch-up cap.
+    pub dropped: u64,
+    pub max_jitter: Duration,
+    total_jitter: Duration,
+    wakeups: u64,
+}
+
+impl TimerStats
+{
+    /// Average lateness of a wake-up relative to its deadline. Divides in nanoseconds, since the count of a long
+    /// run doesn't fit the u32 'Duration' divides by.
+    pub fn mean_jitter(&self) -> Duration
+    {
+        if self.wakeups == 0
+        {
+            return Duration::ZERO;
+        }
+
+        Duration::from_nanos((self.total_jitter.as_nanos() / self.wakeups as u128) as u64)
+    }
+}
+
+impl std::fmt::Display for TimerStats
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "periods: {0}, overruns: {1}, dropped: {2}, max jitter: {3:.3} ms, mean jitter: {4:.3} ms",
+            self.periods, self.overruns, self.dropped,
+            self.max_jitter.as_secs_f64() * 1000.0, self.mean_jitter().as_secs_f64() * 1000.0)
+    }
+}
+
+pub const DEFAULT_MAX_CATCH_UP: u32 = 4;
+
+/// Fixed-rate scheduler. Deadlines are kept on an absolute grid (start + n * period) so that
+/// lateness does not accumulate, and late wake-ups hand out the missed periods so the caller can catch up.
+pub struct Timer<C: Clock = RealClock>
 {
-    duration: std::time::Duration,
-    start_time: std::time::Instant,
+    clock: C,
+    period: Duration,
+    next_deadline: Duration,
+    max_catch_up: u32,
+    stats: TimerStats,
+}
+
+impl Timer<RealClock>
+{
+    pub fn new(period: Duration) -> Self
+    {
+        Self::with_clock(period, RealClock::new())
+    }
 }
 
-impl Timer
+impl<C: Clock> Timer<C>
 {
+    pub fn with_clock(period: Duration, clock: C) -> Self
+    {
+        let next_deadline = clock.now() + period;
+        Self { clock, period, next_deadline, max_catch_up: DEFAULT_MAX_CATCH_UP, stats: TimerStats::default() }
+    }
+
     #[allow(dead_code)]
-    pub fn new(duration: std::time::Duration)-> Self
+    pub fn get_clock(&self) -> &C
     {
-        Self { duration, start_time: std::time::Instant::now() }
+        &self.clock
     }
 
     #[allow(dead_code)]
-    fn elapsed_time(&self) -> std::time::Duration
+    pub fn get_period(&self) -> Duration
     {
-        let now = std::time::Instant::now();
-        now - self.start_time
+        self.period
     }
 
+    /// Changes the period and restarts the schedule from now (a no-op if the period is unchanged).
+    pub fn set_period(&mut self, period: Duration)
+    {
+        if period != self.period
+        {
+            self.period = period;
+            self.reset();
+        }
+    }
+
+    /// The most periods a single 'wait'/'poll' hands out; anything beyond that is dropped.
     #[allow(dead_code)]
+    pub fn set_max_catch_up(&mut self, max_catch_up: u32)
+    {
+        self.max_catch_up = max_catch_up.max(1);
+    }
+
+    pub fn get_stats(&self) -> &TimerStats
+    {
+        &self.stats
+    }
+
+    /// Restarts the schedule so the next deadline is one period from now (e.g. after being paused).
     pub fn reset(&mut self)
     {
-        self.start_time = std::time::Instant::now();
+        self.next_deadline = self.clock.now() + self.period;
     }
 
+    /// Returns the number of periods that are due without blocking (0 if the next deadline hasn't passed).
     #[allow(dead_code)]
-    pub fn sleep_for_remaining(&self) -> Option<std::time::Instant>
+    pub fn poll(&mut self) -> u32
+    {
+        let now = self.clock.now();
+
+        if now < self.next_deadline
+        {
+            return 0;
+        }
+
+        return self.collect_due(now);
+    }
+
+    /// Sleeps until the next deadline (if it hasn't passed yet) and returns the number of due periods (>= 1).
+    pub fn wait(&mut self) -> u32
+    {
+        let now = self.clock.now();
+
+        if now < self.next_deadline
+        {
+            self.clock.sleep(self.next_deadline - now);
+        }
+
+        let now = self.clock.now().max(self.next_deadline);
+
+        return self.collect_due(now);
+    }
+
+    fn collect_due(&mut self, now: Duration) -> u32
     {
-        let time_elapsed = self.elapsed_time();
+        let lateness = now - self.next_deadline;
+        let mut due = 1 + (lateness.as_nanos() / self.period.as_nanos().max(1)) as u64;
+
+        self.stats.wakeups += 1;
+        self.stats.total_jitter += lateness;
+        self.stats.max_jitter = self.stats.max_jitter.max(lateness);
 
-        // Make sure we sleep for >= 0 amount of time
-        // (NOTE: std::time::Duration doesn't support negatives, so we can't simply subtract).
-        if time_elapsed < self.duration
+        if due > 1
         {
-            let sleep_time = self.duration - time_elapsed;
-            std::thread::sleep(sleep_time);
+            self.stats.overruns += 1;
+        }
 
-            return Some(std::time::Instant::now());
+        if due > self.max_catch_up as u64
+        {
+            // Too far behind to catch up: drop the backlog and re-anchor the schedule.
+            self.stats.dropped += due - self.max_catch_up as u64;
+            due = self.max_catch_up as u64;
+            self.next_deadline = now + self.period;
         }
 
-        return None;
+        else
+        {
+            self.next_deadline += self.period * due as u32;
+        }
+
+        self.stats.periods += due;
+
+        return due as u32;
     }
 }
 
 #[cfg(test)]
 mod tests
 {
-    use crate::hw::timer::Timer;
+    use crate::hw::timer::{Clock, RealClock, Timer, TimerStats, VirtualClock};
+
+    use std::time::Duration;
+
+    const PERIOD: Duration = Duration::from_millis(16);
 
     #[test]
-    fn sleep_remaining_time_valid()
+    fn virtual_clock_only_moves_when_advanced()
     {
-        let max_duration_ms = std::time::Duration::from_millis(100);
-        let timer = Timer::new(max_duration_ms);
-        std::thread::sleep(std::time::Duration::from_millis(10));
-        let opt_end_time = timer.sleep_for_remaining();
-        assert!(opt_end_time.is_some());
+        let clock = VirtualClock::new();
+        assert_eq!(clock.now(), Duration::ZERO);
 
-        let sleep_duration = opt_end_time.unwrap() - timer.start_time;
-        assert!(sleep_duration.as_millis().abs_diff(max_duration_ms.as_millis()) <= max_duration_ms.as_millis());
+        clock.advance(Duration::from_millis(5));
+        clock.sleep(Duration::from_millis(3));
+        assert_eq!(clock.now(), Duration::from_millis(8));
     }
 
     #[test]
-    fn sleep_remaining_time_invalid()
+    fn real_clock_moves_forward()
     {
-        let max_duration_ms = std::time::Duration::from_millis(10);
-        let timer = Timer::new(max_duration_ms);
+        let clock = RealClock::new();
+        let start = clock.now();
+        clock.sleep(Duration::from_millis(1));
+        assert!(clock.now() - start >= Duration::from_millis(1));
+    }
 
-        // Simulate elapsed time being larger than max duration.
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        let opt_end_time = timer.sleep_for_remaining();
-        assert!(opt_end_time.is_none());
+    #[test]
+    fn wait_sleeps_until_deadline()
+    {
+        let mut timer = Timer::with_clock(PERIOD, VirtualClock::new());
+        timer.get_clock().advance(Duration::from_millis(10));
+
+        assert_eq!(timer.wait(), 1);
+        assert_eq!(timer.get_clock().now(), PERIOD);
+
+        // The next deadline is on the grid, not relative to when the caller finished its work.
+        timer.get_clock().advance(Duration::from_millis(4));
+        assert_eq!(timer.wait(), 1);
+        assert_eq!(timer.get_clock().now(), PERIOD * 2);
+        assert_eq!(timer.get_stats().max_jitter, Duration::ZERO);
+        assert_eq!(timer.get_stats().overruns, 0);
     }
-}
 
+    #[test]
+    fn poll_does_not_block()
+    {
+        let mut timer = Timer::with_clock(PERIOD, VirtualClock::new());
+        assert_eq!(timer.poll(), 0);
+        assert_eq!(timer.get_clock().now(), Duration::ZERO);
+
+        timer.get_clock().advance(PERIOD);
+        assert_eq!(timer.poll(), 1);
+        assert_eq!(timer.poll(), 0);
+    }
+
+    #[test]
+    fn late_wakeup_catches_up()
+    {
+        let mut timer = Timer::with_clock(PERIOD, VirtualClock::new());
+
+        // 50 ms late: the deadlines at 16, 32 and 48 ms have all passed.
+        timer.get_clock().advance(Duration::from_millis(50));
+        assert_eq!(timer.wait(), 3);
+
+        // Back on schedule: the next deadline is 64 ms.
+        assert_eq!(timer.wait(), 1);
+        assert_eq!(timer.get_clock().now(), Duration::from_millis(64));
+
+        let stats = timer.get_stats();
+        assert_eq!(stats.periods, 4);
+        assert_eq!(stats.overruns, 1);
+        assert_eq!(stats.dropped, 0);
+        assert_eq!(stats.max_jitter, Duration::from_millis(34));
+        assert_eq!(stats.mean_jitter(), Duration::from_millis(17));
+    }
+
+    #[test]
+    fn catch_up_is_capped()
+    {
+        let mut timer = Timer::with_clock(PERIOD, VirtualClock::new());
+        timer.set_max_catch_up(2);
+
+        // 100 ms late: 6 periods are due but only 2 are handed out.
+        timer.get_clock().advance(Duration::from_millis(100));
+        assert_eq!(timer.wait(), 2);
+        assert_eq!(timer.get_stats().dropped, 4);
+
+        // The schedule was re-anchored to the late wake-up.
+        assert_eq!(timer.wait(), 1);
+        assert_eq!(timer.get_clock().now(), Duration::from_millis(116));
+    }
+
+    #[test]
+    fn set_period_restarts_schedule()
+    {
+        let mut timer = Timer::with_clock(PERIOD, VirtualClock::new());
+        timer.get_clock().advance(Duration::from_millis(10));
+
+        timer.set_period(Duration::from_millis(8));
+        assert_eq!(timer.get_period(), Duration::from_millis(8));
+        assert_eq!(timer.wait(), 1);
+        assert_eq!(timer.get_clock().now(), Duration::from_millis(18));
+    }
+
+    #[test]
+    fn mean_jitter_of_a_long_run()
+    {
+        // 2^32 wake-ups would truncate to a count of 0.
+        let stats = TimerStats { total_jitter: Duration::from_secs(1 << 32), wakeups: 1 << 32, ..TimerStats::default() };
+        assert_eq!(stats.mean_jitter(), Duration::from_secs(1));
+
+        let stats = TimerStats { total_jitter: Duration::from_millis(10), wakeups: 3, ..TimerStats::default() };
+        assert_eq!(stats.mean_jitter(), Duration::from_nanos(3_333_333));
+        assert_eq!(TimerStats::default().mean_jitter(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,40 @@
+//! hchip8: a CHIP-8 interpreter that can be embedded in other tools.
+//!
+//! # Stability
+//!
+//! The following are the stable API and only change with a minor version bump:
+//!
+//! - [`Machine`] and [`EnumLoadError`]: loading ROMs (with [`rom::container::Segment`] and
+//!   [`rom::container::EnumRomFormat`] for Intel HEX and hex text files), running cycles/frames, keypad input,
+//!   framebuffer and sound output, and access to the CPU.
+//! - [`hw::display::Display`] (read-only use), `DISPLAY_WIDTH` and `DISPLAY_HEIGHT`.
+//! - [`hw::fault::EnumFault`], [`hw::fault::EnumTickOutcome`] and [`hw::fault::EnumFaultPolicy`].
+//! - [`hw::frame::FrameConfig`] and [`hw::frame::EnumSpeed`].
+//! - [`hw::observer::Observer`], [`hw::observer::EnumTimer`] and [`hw::observer::ObserverId`]: instrumentation hooks,
+//!   registered with `Machine::add_observer`.
+//!
+//! Everything else (`hw::cpu`, `hw::mem`, `hw::observer::ObserverList`, `hw::timer`, `analysis`, `bench`, `dbg`, `env`, `octo` and the rest of `rom`) is public so the bundled binary
+//! and tools can use it, but may change in any release.
+//!
+//! ```
+//! use hchip8::Machine;
+//!
+//! let mut machine = Machine::new(4096, 0x200);
+//! machine.load_rom(&[0x60, 0x2A, 0x00, 0x00]).unwrap(); // V0 = 0x2A, halt
+//!
+//! while !machine.is_halted()
+//! {
+//!     machine.run_frame().unwrap();
+//! }
+//! ```
+
+pub mod analysis;
+pub mod bench;
+pub mod dbg;
+pub mod env;
+pub mod hw;
+pub mod machine;
+pub mod octo;
+pub mod rom;
+
+pub use machine::{EnumLoadError, Machine};
@@ -0,0 +1,643 @@
+use crate::dbg::cheats::{Cheat, CheatList};
+use crate::dbg::watchpoint::WatchHit;
+use crate::hw::cpu::CPU;
+use crate::hw::display::Display;
+use crate::hw::fault::{EnumFault, EnumTickOutcome};
+use crate::hw::frame::FrameConfig;
+use crate::hw::keypad::KeyMap;
+use crate::hw::observer::{Observer, ObserverId};
+use crate::hw::quirks::Quirks;
+use crate::rom::container::{decode_rom, EnumRomFormat, Segment};
+
+use std::collections::BTreeSet;
+
+/// Why a ROM could not be loaded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EnumLoadError
+{
+    /// The image doesn't fit between the starting pc and the end of main memory.
+    TooLarge { size: usize, available: usize },
+    /// A segment of an Intel HEX or hex text file is outside main memory.
+    OutOfRange { addr: usize, size: usize, mem_size: usize },
+    /// An Intel HEX or hex text file is malformed.
+    Format(String),
+    Io(String),
+}
+
+impl std::fmt::Display for EnumLoadError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::TooLarge { size, available } => write!(f, "ROM is {0} bytes but only {1} bytes are available", size, available),
+            Self::OutOfRange { addr, size, mem_size } =>
+            {
+                write!(f, "Segment at 0x{0:X} ({1} bytes) is outside main memory ({2} bytes)", addr, size, mem_size)
+            },
+            Self::Format(msg) => write!(f, "Invalid ROM file: {}", msg),
+            Self::Io(msg) => write!(f, "Failed to read ROM: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for EnumLoadError {}
+
+/// A complete CHIP-8 system: CPU, memory, display, keypad, timers and the frame scheduling between them.
+/// This is the entry point for embedding the emulator; see the crate documentation for what is stable.
+pub struct Machine
+{
+    cpu: CPU,
+    frame_config: FrameConfig,
+    starting_pc: u16,
+    frame_cycles_left: u32,
+    cycles: u64,
+    frames: u64,
+    key_map: KeyMap,
+    /// Size of the last ROM image loaded.
+    rom_size: usize,
+    breakpoints: BTreeSet<u16>,
+    break_hit: Option<u16>,
+    /// The breakpoint execution stopped at, which mustn't stop it again when resuming.
+    resume_pc: Option<u16>,
+    cheats: CheatList,
+}
+
+impl Machine
+{
+    pub fn new(mem_size: usize, starting_pc: u16) -> Self
+    {
+        Self::with_frame_config(mem_size, starting_pc, FrameConfig::default())
+    }
+
+    pub fn with_frame_config(mem_size: usize, starting_pc: u16, frame_config: FrameConfig) -> Self
+    {
+        let frame_cycles_left = frame_config.get_instructions_per_frame();
+        Self { cpu: CPU::new(mem_size, starting_pc), frame_config, starting_pc, frame_cycles_left, cycles: 0, frames: 0, key_map: KeyMap::new(), rom_size: 0,
+               breakpoints: BTreeSet::new(), break_hit: None, resume_pc: None, cheats: CheatList::new() }
+    }
+
+    /// Copies a ROM image into main memory at the starting pc.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), EnumLoadError>
+    {
+        let start = self.starting_pc as usize;
+        let available = self.cpu.get_mem().size().saturating_sub(start);
+
+        if rom.len() > available
+        {
+            return Err(EnumLoadError::TooLarge { size: rom.len(), available });
+        }
+
+        for (offset, value) in rom.iter().enumerate()
+        {
+            self.cpu.get_mem_mut().write_u8(start + offset, *value);
+        }
+
+        self.rom_size = rom.len();
+
+        Ok(())
+    }
+
+    /// Copies segments into main memory at their own addresses, checking that all of them fit before writing any.
+    /// The ROM range then runs from the starting pc to the end of the highest segment.
+    pub fn load_segments(&mut self, segments: &[Segment]) -> Result<(), EnumLoadError>
+    {
+        let mem_size = self.cpu.get_mem().size();
+
+        if let Some(segment) = segments.iter().find(|segment| segment.addr.checked_add(segment.data.len()).is_none_or(|end| end > mem_size))
+        {
+            return Err(EnumLoadError::OutOfRange { addr: segment.addr, size: segment.data.len(), mem_size });
+        }
+
+        for segment in segments
+        {
+            for (offset, value) in segment.data.iter().enumerate()
+            {
+                self.cpu.get_mem_mut().write_u8(segment.addr + offset, *value);
+            }
+        }
+
+        let end = segments.iter().map(|segment| segment.addr + segment.data.len()).max().unwrap_or(0);
+        self.rom_size = end.saturating_sub(self.starting_pc as usize);
+
+        Ok(())
+    }
+
+    /// Loads the contents of a ROM file, detecting the format: raw binary goes to the starting pc like 'load_rom',
+    /// Intel HEX and hex text segments go to their addresses.
+    pub fn load_image(&mut self, bytes: &[u8]) -> Result<EnumRomFormat, EnumLoadError>
+    {
+        let (format, segments) = decode_rom(bytes, self.starting_pc as usize).map_err(EnumLoadError::Format)?;
+
+        if format == EnumRomFormat::Binary
+        {
+            self.load_rom(bytes)?;
+        }
+
+        else
+        {
+            self.load_segments(&segments)?;
+        }
+
+        Ok(format)
+    }
+
+    pub fn load_rom_file(&mut self, path: &str) -> Result<(), EnumLoadError>
+    {
+        let rom = std::fs::read(path).map_err(|e| EnumLoadError::Io(format!("'{0}': {1}", path, e)))?;
+        self.load_image(&rom)?;
+
+        Ok(())
+    }
+
+    /// Executes a single instruction (without advancing the frame).
+    /// NOTE: Breakpoints only stop 'run_cycles' and 'run_frame'; stepping onto (or over) one always executes.
+    pub fn step(&mut self) -> Result<EnumTickOutcome, EnumFault>
+    {
+        self.resume_pc = None;
+
+        let outcome = self.cpu.tick()?;
+
+        if outcome != EnumTickOutcome::Halted
+        {
+            self.cycles += 1;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Executes up to 'count' instructions, stopping early on a halt, breakpoint or watchpoint hit.
+    /// Returns the number of instructions executed. NOTE: Timers are not ticked; see 'run_frame'.
+    pub fn run_cycles(&mut self, count: u64) -> Result<u64, EnumFault>
+    {
+        let cycles_before = self.cycles;
+
+        for _ in 0..count
+        {
+            if self.check_breakpoint() || self.step()? == EnumTickOutcome::Halted || self.has_watch_hit()
+            {
+                break;
+            }
+        }
+
+        Ok(self.cycles - cycles_before)
+    }
+
+    /// Runs the rest of the current frame and then the 60 Hz frame boundary (timers).
+    /// Returns Ok(true) once the frame boundary was reached, or Ok(false) if it stopped early on a halt,
+    /// breakpoint or watchpoint hit. Calling it again after stopping early (or after a fault) resumes the same frame.
+    pub fn run_frame(&mut self) -> Result<bool, EnumFault>
+    {
+        while self.frame_cycles_left > 0
+        {
+            if self.check_breakpoint()
+            {
+                return Ok(false);
+            }
+
+            self.frame_cycles_left -= 1;
+
+            if self.step()? == EnumTickOutcome::Halted
+            {
+                return Ok(false);
+            }
+
+            if self.has_watch_hit()
+            {
+                return Ok(false);
+            }
+        }
+
+        self.cpu.tick_timers();
+        self.cheats.apply_freezes(&mut self.cpu);
+        self.frames += 1;
+        self.frame_cycles_left = self.frame_config.get_instructions_per_frame();
+
+        Ok(true)
+    }
+
+    pub fn is_halted(&self) -> bool
+    {
+        self.cpu.is_halted()
+    }
+
+    /// The addresses the last ROM image was loaded to.
+    pub fn get_rom_range(&self) -> std::ops::Range<usize>
+    {
+        let start = self.starting_pc as usize;
+        start..start + self.rom_size
+    }
+
+    /// Instructions executed so far.
+    pub fn get_cycles(&self) -> u64
+    {
+        self.cycles
+    }
+
+    /// Frames completed so far.
+    pub fn get_frames(&self) -> u64
+    {
+        self.frames
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool)
+    {
+        self.cpu.get_keypad_mut().set_key(key, pressed);
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool
+    {
+        self.cpu.get_keypad().is_pressed(key)
+    }
+
+    /// Presses or releases the keypad key bound to a host key. Returns false if 'host_key' isn't mapped.
+    pub fn set_host_key(&mut self, host_key: char, pressed: bool) -> bool
+    {
+        match self.key_map.get_key(host_key)
+        {
+            Some(key) =>
+            {
+                self.set_key(key, pressed);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn get_key_map(&self) -> &KeyMap
+    {
+        &self.key_map
+    }
+
+    pub fn set_key_map(&mut self, key_map: KeyMap)
+    {
+        self.key_map = key_map;
+    }
+
+    /// Caches decoded instructions for speed (see 'CPU::set_decode_cache'). Off by default.
+    pub fn set_decode_cache(&mut self, enabled: bool)
+    {
+        self.cpu.set_decode_cache(enabled);
+    }
+
+    /// Selects the interpreter behaviors the loaded ROM expects.
+    pub fn set_quirks(&mut self, quirks: Quirks)
+    {
+        self.cpu.set_quirks(quirks);
+    }
+
+    /// The 64x32 monochrome framebuffer.
+    pub fn get_display(&self) -> &Display
+    {
+        self.cpu.get_display()
+    }
+
+    /// Returns true if the framebuffer changed since the last call.
+    pub fn take_display_dirty(&mut self) -> bool
+    {
+        self.cpu.get_display_mut().take_dirty()
+    }
+
+    /// True while the buzzer should be sounding.
+    pub fn is_sound_active(&self) -> bool
+    {
+        self.cpu.is_sound_active()
+    }
+
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit>
+    {
+        self.cpu.take_watch_hit()
+    }
+
+    fn has_watch_hit(&self) -> bool
+    {
+        self.cpu.has_watch_hit()
+    }
+
+    /// Stops 'run_cycles' and 'run_frame' before the instruction at 'addr' executes.
+    /// Returns false if there already was a breakpoint there.
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool
+    {
+        self.breakpoints.insert(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool
+    {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Breakpoint addresses in ascending order.
+    pub fn get_breakpoints(&self) -> impl Iterator<Item = u16> + '_
+    {
+        self.breakpoints.iter().copied()
+    }
+
+    /// The address of the breakpoint execution stopped at, if it stopped at one since the last call.
+    pub fn take_break_hit(&mut self) -> Option<u16>
+    {
+        self.break_hit.take()
+    }
+
+    /// True if the next instruction has a breakpoint (unless execution is just resuming from it).
+    fn check_breakpoint(&mut self) -> bool
+    {
+        let pc = self.cpu.get_pc();
+
+        if self.breakpoints.is_empty() || self.resume_pc == Some(pc) || !self.breakpoints.contains(&pc)
+        {
+            return false;
+        }
+
+        self.break_hit = Some(pc);
+        self.resume_pc = Some(pc);
+
+        true
+    }
+
+    /// Applies a cheat right away; freezes are applied again at the end of every frame.
+    /// Returns false (and drops the cheat) if its bytes don't fit in memory.
+    pub fn add_cheat(&mut self, cheat: Cheat) -> bool
+    {
+        if !cheat.apply(&mut self.cpu)
+        {
+            return false;
+        }
+
+        self.cheats.add(cheat);
+
+        true
+    }
+
+    /// Removes the cheats at 'addr' (patched bytes keep their values). Returns false if there were none.
+    pub fn remove_cheat(&mut self, addr: usize) -> bool
+    {
+        self.cheats.remove(addr)
+    }
+
+    pub fn get_cheats(&self) -> &CheatList
+    {
+        &self.cheats
+    }
+
+    /// Registers hooks that are called as the CPU runs (see 'Observer').
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) -> ObserverId
+    {
+        self.cpu.add_observer(observer)
+    }
+
+    /// Unregisters an observer and hands it back.
+    pub fn remove_observer(&mut self, id: ObserverId) -> Option<Box<dyn Observer>>
+    {
+        self.cpu.remove_observer(id)
+    }
+
+    /// The first registered observer that is a 'T' (see 'ObserverList::find').
+    pub fn find_observer<T: Observer>(&self) -> Option<&T>
+    {
+        self.cpu.find_observer()
+    }
+
+    pub fn find_observer_mut<T: Observer>(&mut self) -> Option<&mut T>
+    {
+        self.cpu.find_observer_mut()
+    }
+
+    pub fn get_frame_config(&self) -> &FrameConfig
+    {
+        &self.frame_config
+    }
+
+    /// NOTE: A new instructions-per-frame value takes effect from the next frame.
+    pub fn get_frame_config_mut(&mut self) -> &mut FrameConfig
+    {
+        &mut self.frame_config
+    }
+
+    pub fn get_cpu(&self) -> &CPU
+    {
+        &self.cpu
+    }
+
+    pub fn get_cpu_mut(&mut self) -> &mut CPU
+    {
+        &mut self.cpu
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::dbg::cheats::Cheat;
+    use crate::hw::fault::{EnumFault, EnumTickOutcome};
+    use crate::hw::frame::FrameConfig;
+    use crate::machine::{EnumLoadError, Machine};
+    use crate::rom::container::{EnumRomFormat, Segment};
+
+    const STARTING_PC: u16 = 0x200;
+
+    fn rom_from_words(words: &[u16]) -> Vec<u8>
+    {
+        words.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn load_rom_places_image_at_pc()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        assert!(machine.load_rom(&[0x12, 0x34, 0x56]).is_ok());
+        assert_eq!(machine.get_rom_range(), 0x200..0x203);
+
+        let mem = machine.get_cpu().get_mem();
+        assert_eq!(mem.read_u8(0x200), Some(0x12));
+        assert_eq!(mem.read_u8(0x202), Some(0x56));
+    }
+
+    #[test]
+    fn load_rom_too_large_fails()
+    {
+        let mut machine = Machine::new(0x204, STARTING_PC);
+        let result = machine.load_rom(&[0; 5]);
+
+        assert_eq!(result, Err(EnumLoadError::TooLarge { size: 5, available: 4 }));
+    }
+
+    #[test]
+    fn load_segments_at_their_addresses()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        let segments = [Segment { addr: 0x200, data: vec![0x12, 0x10] }, Segment { addr: 0x210, data: vec![0xF0, 0x90] }];
+        assert!(machine.load_segments(&segments).is_ok());
+        assert_eq!(machine.get_rom_range(), 0x200..0x212);
+
+        let mem = machine.get_cpu().get_mem();
+        assert_eq!(mem.read_u8(0x201), Some(0x10));
+        assert_eq!(mem.read_u8(0x202), Some(0x00));
+        assert_eq!(mem.read_u8(0x211), Some(0x90));
+
+        // Nothing is written unless everything fits.
+        let segments = [Segment { addr: 0x300, data: vec![0xAA] }, Segment { addr: 0xFFF, data: vec![0x01, 0x02] }];
+        assert_eq!(machine.load_segments(&segments), Err(EnumLoadError::OutOfRange { addr: 0xFFF, size: 2, mem_size: 4096 }));
+        assert_eq!(machine.get_cpu().get_mem().read_u8(0x300), Some(0x00));
+
+        // An address label can be any usize, so the end of the segment can overflow.
+        let result = machine.load_image(b"FFFFFFFFFFFFFFFF: 00E0\n");
+        assert_eq!(result, Err(EnumLoadError::OutOfRange { addr: usize::MAX, size: 2, mem_size: 4096 }));
+    }
+
+    #[test]
+    fn load_image_detects_the_format()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        assert_eq!(machine.load_image(&[0x00, 0xE0]), Ok(EnumRomFormat::Binary));
+        assert_eq!(machine.load_image(b":02030000A30058\n:00000001FF\n"), Ok(EnumRomFormat::IntelHex));
+        assert_eq!(machine.load_image(b"60 2A ; v0 := 0x2A\n"), Ok(EnumRomFormat::HexText));
+
+        let mem = machine.get_cpu().get_mem();
+        assert_eq!(mem.read_u16(0x200), Some(0x602A));
+        assert_eq!(mem.read_u16(0x300), Some(0xA300));
+
+        let result = machine.load_image(b":02030000A30000\n:00000001FF\n");
+        assert_eq!(result, Err(EnumLoadError::Format(String::from("line 1: Checksum is 0x00 but should be 0x58"))));
+    }
+
+    #[test]
+    fn load_missing_rom_file_fails()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        assert!(matches!(machine.load_rom_file("/does/not/exist.ch8"), Err(EnumLoadError::Io(_))));
+    }
+
+    #[test]
+    fn run_frame_runs_instructions_then_timers()
+    {
+        let mut machine = Machine::with_frame_config(4096, STARTING_PC, FrameConfig::new(3));
+
+        // V1 = 5, DT = V1, ST = V1, loop forever
+        machine.load_rom(&rom_from_words(&[0x6105, 0xF115, 0xF118, 0x1206])).unwrap();
+
+        assert_eq!(machine.run_frame(), Ok(true));
+        assert_eq!(machine.get_cycles(), 3);
+        assert_eq!(machine.get_frames(), 1);
+        assert_eq!(machine.get_cpu().get_delay_timer(), 4);
+        assert!(machine.is_sound_active());
+
+        for _ in 0..4
+        {
+            assert_eq!(machine.run_frame(), Ok(true));
+        }
+
+        assert!(!machine.is_sound_active());
+    }
+
+    #[test]
+    fn run_frame_resumes_after_fault()
+    {
+        let mut machine = Machine::with_frame_config(4096, STARTING_PC, FrameConfig::new(3));
+
+        // unknown, V1 = 1, V2 = 2, V3 = 3
+        machine.load_rom(&rom_from_words(&[0xFFFF, 0x6101, 0x6202, 0x6303])).unwrap();
+
+        assert_eq!(machine.run_frame(), Err(EnumFault::UnknownOpcode { pc: STARTING_PC, opcode: 0xFFFF }));
+
+        // The rest of the frame only has 2 instructions left.
+        assert_eq!(machine.run_frame(), Ok(true));
+        assert_eq!(machine.get_frames(), 1);
+        assert_eq!(machine.get_cpu().get_pc(), STARTING_PC + 6);
+    }
+
+    #[test]
+    fn run_cycles_stops_on_halt()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+        machine.load_rom(&rom_from_words(&[0x6101, 0x6202, 0x0000])).unwrap();
+
+        assert_eq!(machine.run_cycles(10), Ok(2));
+        assert!(machine.is_halted());
+        assert_eq!(machine.run_frame(), Ok(false));
+    }
+
+    #[test]
+    fn breakpoints_stop_before_the_instruction()
+    {
+        let mut machine = Machine::with_frame_config(4096, STARTING_PC, FrameConfig::new(10));
+
+        // V0 += 1, loop
+        machine.load_rom(&rom_from_words(&[0x7001, 0x1200])).unwrap();
+        assert!(machine.add_breakpoint(0x202));
+        assert!(!machine.add_breakpoint(0x202));
+
+        assert_eq!(machine.run_frame(), Ok(false));
+        assert_eq!(machine.take_break_hit(), Some(0x202));
+        assert_eq!(machine.take_break_hit(), None);
+        assert_eq!(machine.get_cpu().get_pc(), 0x202);
+        assert_eq!(machine.get_cycles(), 1);
+
+        // Resuming runs the instruction at the breakpoint, then stops there again on the next pass.
+        assert_eq!(machine.run_cycles(10), Ok(2));
+        assert_eq!(machine.take_break_hit(), Some(0x202));
+
+        assert!(machine.remove_breakpoint(0x202));
+        assert_eq!(machine.run_cycles(10), Ok(10));
+        assert_eq!(machine.get_breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn cheats_freeze_memory_every_frame()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+
+        // V0 = 0, I = 0x300, then store V0 forever (lose all lives every instruction)
+        machine.load_rom(&rom_from_words(&[0x6000, 0xA300, 0xF055, 0x1204])).unwrap();
+
+        assert!(machine.add_cheat(Cheat::parse("freeze 0x300 09").unwrap()));
+        assert!(machine.add_cheat(Cheat::parse("patch 0x400 AB CD").unwrap()));
+        assert!(!machine.add_cheat(Cheat::parse("patch 0xFFF AB CD").unwrap()));
+        assert_eq!(machine.get_cheats().iter().count(), 2);
+
+        assert_eq!(machine.run_cycles(3), Ok(3));
+        assert_eq!(machine.get_cpu().get_mem().read_u8(0x300), Some(0x00));
+
+        assert_eq!(machine.run_frame(), Ok(true));
+        assert_eq!(machine.get_cpu().get_mem().read_u8(0x300), Some(0x09));
+        assert_eq!(machine.get_cpu().get_mem().read_u16(0x400), Some(0xABCD));
+
+        assert!(machine.remove_cheat(0x300));
+        assert_eq!(machine.run_frame(), Ok(true));
+        assert_eq!(machine.get_cpu().get_mem().read_u8(0x300), Some(0x00));
+    }
+
+    #[test]
+    fn keypad_input_reaches_program()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+
+        // Wait for a key into V0, then halt
+        machine.load_rom(&rom_from_words(&[0xF00A, 0x0000])).unwrap();
+
+        assert_eq!(machine.step(), Ok(EnumTickOutcome::WaitingForKey));
+
+        // 'w' is bound to key 5 in the default layout.
+        assert!(machine.set_host_key('w', true));
+        assert!(!machine.set_host_key('p', true));
+        assert!(machine.is_key_pressed(0x5));
+        assert_eq!(machine.step(), Ok(EnumTickOutcome::Executed));
+        assert_eq!(machine.step(), Ok(EnumTickOutcome::Halted));
+    }
+
+    #[test]
+    fn framebuffer_reflects_draws()
+    {
+        let mut machine = Machine::new(4096, STARTING_PC);
+
+        // I = font('0'), draw at (0, 0)
+        machine.load_rom(&rom_from_words(&[0x6000, 0xF029, 0xD005, 0x0000])).unwrap();
+        assert!(machine.take_display_dirty());
+
+        machine.run_cycles(10).unwrap();
+
+        assert!(machine.take_display_dirty());
+        assert!(machine.get_display().get_pixels()[0]);
+        assert!(machine.get_display().get_pixel(3, 4));
+        assert!(!machine.get_display().get_pixel(1, 1));
+    }
+}
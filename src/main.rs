@@ -1,11 +1,102 @@
-mod env;
-mod hw;
+use hchip8::dbg::cheats::CheatList;
+use hchip8::dbg::coverage::Coverage;
+use hchip8::analysis::cfg::ControlFlowGraph;
+use hchip8::dbg::debugger::Debugger;
+use hchip8::dbg::profiler::Profiler;
+use hchip8::dbg::smc::SmcDetector;
+use hchip8::dbg::source_map::SourceMap;
+use hchip8::dbg::symbols::SymbolTable;
+use hchip8::dbg::trace::TraceLogger;
+use hchip8::bench::{find_workload, format_results, run_workload, Workload, WORKLOADS};
+use hchip8::env::config_data::{ConfigData, EnumCommand, VERSION};
+use hchip8::hw::cpu::EnumHaltReason;
+use hchip8::hw::fault::{EnumFault, EnumFaultPolicy};
+use hchip8::hw::mem::{format_changes, Mem, MemSnapshot};
+use hchip8::hw::timer::Timer;
+use hchip8::octo::compiler::compile;
+use hchip8::rom::container::read_rom_file;
+use hchip8::rom::crc32::crc32;
+use hchip8::rom::sha1::{format_sha1, sha1};
+use hchip8::Machine;
 
-#[allow(unused_imports)]
-use env::config_data::ConfigData;
-use hw::cpu::CPU;
-use hw::mem::Mem;
-use hw::timer::Timer;
+/// Runs the given number of frames, handing faults to the fault policy and breakpoint and watchpoint hits to the
+/// debugger. Stops early if the machine halts or the debugger takes over.
+fn run_frames(machine: &mut Machine, frame_count: u32, fault_policy: EnumFaultPolicy, opt_debugger: &mut Option<Debugger>, new_debugger: &dyn Fn() -> Debugger)
+{
+    let mut frames_done = 0;
+
+    while frames_done < frame_count && !machine.is_halted()
+    {
+        let result = machine.run_frame();
+
+        if let Some(debugger) = opt_debugger.as_ref()
+        {
+            debugger.report_smc(machine, &mut std::io::stdout());
+        }
+
+        match result
+        {
+            Ok(true) => { frames_done += 1; },
+            Ok(false) =>
+            {
+                if let Some(hit) = machine.take_watch_hit()
+                {
+                    if let Some(debugger) = opt_debugger.as_mut()
+                    {
+                        debugger.on_watch_hit(&hit, &mut std::io::stdout());
+                    }
+                }
+
+                if let Some(addr) = machine.take_break_hit()
+                {
+                    if let Some(debugger) = opt_debugger.as_mut()
+                    {
+                        debugger.on_breakpoint(addr, &mut std::io::stdout());
+                    }
+                }
+            },
+            Err(fault) => { handle_fault(machine, &fault, fault_policy, opt_debugger, new_debugger); },
+        }
+
+        if opt_debugger.as_ref().is_some_and(|debugger| debugger.is_paused())
+        {
+            break;
+        }
+    }
+}
+
+fn handle_fault(machine: &mut Machine, fault: &EnumFault, fault_policy: EnumFaultPolicy, opt_debugger: &mut Option<Debugger>, new_debugger: &dyn Fn() -> Debugger)
+{
+    // Unknown opcodes follow '--on-invalid' instead.
+    let policy = match fault
+    {
+        EnumFault::UnknownOpcode { .. } => machine.get_cpu().get_invalid_policy().to_fault_policy().unwrap_or(fault_policy),
+        _ => fault_policy,
+    };
+
+    match policy
+    {
+        EnumFaultPolicy::Halt =>
+        {
+            println!("[ERROR]: {}... The system will be halted.", fault);
+            machine.get_cpu_mut().halt_on_fault(fault);
+        },
+        EnumFaultPolicy::Ignore if fault.is_recoverable() =>
+        {
+            println!("[WARNING]: {}... The instruction was skipped.", fault);
+        },
+        EnumFaultPolicy::Ignore =>
+        {
+            println!("[ERROR]: {}... This fault can't be ignored and the system will be halted.", fault);
+            machine.get_cpu_mut().halt_on_fault(fault);
+        },
+        EnumFaultPolicy::Break =>
+        {
+            // Start the debugger on demand if it isn't already running.
+            opt_debugger.get_or_insert_with(new_debugger).on_fault(fault, &mut std::io::stdout());
+        },
+    }
+}
 
 fn main()
 {
@@ -13,33 +104,532 @@ fn main()
     let mut config_data = ConfigData::new(args);
     let opt_error_message = config_data.parse();
 
-    match opt_error_message
+    if let Some((code, msg)) = opt_error_message
+    {
+        const EXIT_CODE: i32 = -1;
+        println!("[ERROR]: {0} (exit code: {1}).", msg, code);
+        std::process::exit(EXIT_CODE);
+    }
+
+    if config_data.is_help()
+    {
+        print!("{}", ConfigData::help_text());
+        return;
+    }
+
+    if config_data.is_version()
+    {
+        println!("hchip8 {}", VERSION);
+        return;
+    }
+
+    if config_data.is_print_config()
+    {
+        print!("{}", config_data.format_config());
+        return;
+    }
+
+    match config_data.get_command()
+    {
+        EnumCommand::Run => run_rom(&config_data),
+        EnumCommand::Bench => run_bench(&config_data),
+        EnumCommand::Cfg => print_cfg(&config_data),
+        EnumCommand::Info => print_info(&config_data),
+        EnumCommand::Octo => compile_octo(&config_data),
+    }
+}
+
+/// The labels from '--symbols' (or none).
+fn load_symbols(config_data: &ConfigData) -> SymbolTable
+{
+    match config_data.get_symbols_path().map(SymbolTable::load)
+    {
+        Some(Ok(symbols)) => symbols,
+        Some(Err(e)) =>
+        {
+            println!("[ERROR]: {}", e);
+            std::process::exit(-1);
+        },
+        None => SymbolTable::new(),
+    }
+}
+
+/// Loads the ROM (raw binary, Intel HEX or hex text) with the '--patch' files applied in order, or exits. A patch
+/// that doesn't match the ROM stops here rather than loading a corrupt image.
+fn load_rom(machine: &mut Machine, rom_path: &str, patch_paths: &[String])
+{
+    let rom = match read_rom_file(rom_path, patch_paths)
+    {
+        Ok(rom) => rom,
+        Err(e) =>
+        {
+            println!("[ERROR]: {}", e);
+            std::process::exit(-1);
+        },
+    };
+
+    if let Err(e) = machine.load_image(&rom)
+    {
+        println!("[ERROR]: {}", e);
+        std::process::exit(-1);
+    }
+}
+
+/// Prints the size and hashes of the ROM as loaded (patched and decoded), and what the ROM database knows about it.
+fn print_info(config_data: &ConfigData)
+{
+    let rom_path = config_data.get_rom_path().unwrap_or_default();
+
+    let rom = match config_data.read_rom_image()
+    {
+        Ok(rom) => rom,
+        Err(e) =>
+        {
+            println!("[ERROR]: {}", e);
+            std::process::exit(-1);
+        },
+    };
+
+    let database = match config_data.load_rom_database()
     {
-        Some((code, msg)) =>
+        Ok(database) => database,
+        Err(e) =>
         {
-            const EXIT_CODE: i32 = -1;
-            println!("[ERROR]: '{0}' (exit code: {1}).", msg, code);
-            std::process::exit(EXIT_CODE);
+            println!("[ERROR]: {}", e);
+            std::process::exit(-1);
         },
-        None => {},
+    };
+
+    let mut stream = format!("File: {0}\nSize: {1} bytes\nCRC-32: {2:08x}\nSHA-1: {3}\n", rom_path, rom.len(), crc32(&rom), format_sha1(&sha1(&rom)));
+
+    match database.identify(&rom)
+    {
+        Some(entry) => entry.format_info(&mut stream),
+        None => stream.push_str(&format!("Not in the ROM database ({} entries)\n", database.len())),
     }
 
-    let mut cpu = CPU::new(config_data.get_mem_size() as usize, config_data.get_starting_pc());
+    print!("{}", stream);
+}
 
-    // 100 Hz.
-    let cycle_time_ms = std::time::Duration::from_millis(10);
-    let mut timer = Timer::new(cycle_time_ms);
+/// Compiles Octo source into a ROM, plus the symbols and source map for the debugger if asked for.
+fn compile_octo(config_data: &ConfigData)
+{
+    let source_path = config_data.get_rom_path().unwrap_or_default();
 
-    while !cpu.is_halted()
+    let source = match std::fs::read_to_string(source_path)
     {
-        cpu.tick();
-        timer.sleep_for_remaining();
-        timer.reset();
+        Ok(source) => source,
+        Err(e) =>
+        {
+            println!("[ERROR]: Failed to read source: '{0}': {1}", source_path, e);
+            std::process::exit(-1);
+        },
+    };
+
+    let program = match compile(&source, source_path, config_data.get_octo_target())
+    {
+        Ok(program) => program,
+        Err(e) =>
+        {
+            println!("[ERROR]: {}", e);
+            std::process::exit(-1);
+        },
+    };
+
+    let out_path = config_data.get_octo_out();
+    let mut outputs = vec![(out_path.clone(), program.get_rom().to_vec())];
+
+    if let Some(symbols_path) = config_data.get_symbols_out()
+    {
+        let mut stream = String::new();
+        program.format_symbols(&mut stream);
+        outputs.push((String::from(symbols_path), stream.into_bytes()));
+    }
+
+    if let Some(source_map_path) = config_data.get_source_map_out()
+    {
+        let mut stream = String::new();
+        program.format_source_map(&mut stream);
+        outputs.push((String::from(source_map_path), stream.into_bytes()));
     }
 
-    println!("CPU is halted\nDumping final CPU state:");
-    cpu.print_state(false);
+    for (path, contents) in outputs
+    {
+        if let Err(e) = std::fs::write(&path, contents)
+        {
+            println!("[ERROR]: Failed to write '{0}': {1}", path, e);
+            std::process::exit(-1);
+        }
+    }
 
-    println!("End of emulator");
+    println!("Compiled '{0}' for {1}: {2} bytes, {3} label(s) -> '{4}'", source_path, config_data.get_octo_target(), program.get_rom().len(),
+             program.get_labels().len(), out_path);
+}
+
+/// Loads the ROM without running it and prints its control-flow (or call) graph as DOT.
+fn print_cfg(config_data: &ConfigData)
+{
+    let mut machine = Machine::new(config_data.get_mem_size(), config_data.get_starting_pc());
+    load_rom(&mut machine, config_data.get_rom_path().unwrap_or_default(), config_data.get_patch_paths());
+
+    let symbols = load_symbols(config_data);
+    let cfg = ControlFlowGraph::analyze(machine.get_cpu().get_mem(), config_data.get_starting_pc());
+    let mut stream = String::new();
+
+    if config_data.is_call_graph()
+    {
+        cfg.format_call_graph_dot(&symbols, &mut stream);
+    }
+
+    else
+    {
+        cfg.format_dot(&symbols, &mut stream);
+    }
+
+    print!("{}", stream);
+
+    for addr in cfg.get_unresolved()
+    {
+        eprintln!("[WARNING]: Indirect jump at 0x{:03X} can't be followed statically", addr);
+    }
+}
+
+/// Runs the built-in workloads and prints their throughput.
+fn run_bench(config_data: &ConfigData)
+{
+    let workloads: Vec<&'static Workload> = match config_data.get_bench_workload()
+    {
+        Some(name) => find_workload(name).into_iter().collect(),
+        None => WORKLOADS.iter().collect(),
+    };
+
+    let mut results = Vec::new();
+
+    for workload in workloads
+    {
+        match run_workload(workload, config_data.get_bench_cycles(), config_data.get_frame_config(), config_data.is_decode_cache())
+        {
+            Ok(result) => results.push(result),
+            Err(fault) =>
+            {
+                println!("[ERROR]: Workload '{0}' faulted: {1}", workload.name, fault);
+                std::process::exit(-1);
+            },
+        }
+    }
+
+    let mut stream = String::new();
+    format_results(&results, config_data.get_bench_format(), config_data.is_decode_cache(), &mut stream);
+    print!("{}", stream);
+}
+
+/// Prints the profile report and writes the collapsed stacks if asked to.
+fn print_profile(profiler: &Profiler, mem: &Mem, opt_out_path: Option<&str>)
+{
+    const REPORT_ROWS: usize = 20;
+
+    let mut stream = String::new();
+    profiler.format_report(mem, REPORT_ROWS, &mut stream);
+    print!("{}", stream);
+
+    if let Some(out_path) = opt_out_path
+    {
+        let mut stacks = String::new();
+        profiler.format_collapsed(&mut stacks);
+
+        match std::fs::write(out_path, stacks)
+        {
+            Ok(()) => println!("Collapsed stacks written to '{}'", out_path),
+            Err(e) => println!("[ERROR]: Failed to write '{0}': {1}", out_path, e),
+        }
+    }
 }
 
+/// Prints the coverage report for the ROM and writes the lcov file if asked to.
+fn print_coverage(coverage: &Coverage, machine: &Machine, opt_lcov: Option<(&SourceMap, &str)>)
+{
+    let mut stream = String::new();
+    coverage.format_report(machine.get_cpu().get_mem(), machine.get_rom_range(), &mut stream);
+    print!("{}", stream);
+
+    if let Some((source_map, lcov_path)) = opt_lcov
+    {
+        let mut lcov = String::new();
+        coverage.format_lcov(source_map, &mut lcov);
+
+        match std::fs::write(lcov_path, lcov)
+        {
+            Ok(()) => println!("Line coverage written to '{}'", lcov_path),
+            Err(e) => println!("[ERROR]: Failed to write '{0}': {1}", lcov_path, e),
+        }
+    }
+}
+
+/// Prints what '--hexdump', '--find' and '--mem-diff' asked for.
+fn print_memory(config_data: &ConfigData, mem: &Mem, opt_snapshot: Option<&MemSnapshot>, symbols: &SymbolTable)
+{
+    if let Some(range) = config_data.get_hexdump_range()
+    {
+        let mut stream = String::new();
+        mem.hexdump(range, &mut stream);
+        print!("Memory:\n{}", stream);
+    }
+
+    if let Some(pattern) = config_data.get_find_pattern()
+    {
+        let matches = mem.find(pattern, 0..mem.size());
+        println!("Pattern found at {} address(es)", matches.len());
+
+        for addr in matches
+        {
+            println!("  {}", symbols.format_addr(addr as u16));
+        }
+    }
+
+    if let Some(snapshot) = opt_snapshot
+    {
+        let changes = snapshot.diff(mem);
+        println!("Memory changes since the ROM was loaded: {} byte(s)", changes.len());
+
+        let mut stream = String::new();
+        format_changes(&changes, &mut stream);
+        print!("{}", stream);
+    }
+}
+
+fn run_rom(config_data: &ConfigData)
+{
+    let mut machine = Machine::with_frame_config(config_data.get_mem_size(), config_data.get_starting_pc(),
+                                                 config_data.get_frame_config().clone());
+    machine.set_quirks(config_data.get_quirks());
+    machine.set_key_map(config_data.get_key_map().clone());
+    machine.set_decode_cache(config_data.is_decode_cache());
+    machine.get_cpu_mut().set_invalid_policy(config_data.get_invalid_policy());
+    machine.get_cpu_mut().set_zero_halts(config_data.is_zero_halts());
+
+    // Load the source map up front so a bad path doesn't only show up after the run.
+    let opt_source_map = match config_data.get_source_map_path().map(SourceMap::load)
+    {
+        Some(Ok(source_map)) => Some(source_map),
+        Some(Err(e)) =>
+        {
+            println!("[ERROR]: {}", e);
+            std::process::exit(-1);
+        },
+        None => None,
+    };
+
+    if let Some(rom_path) = config_data.get_rom_path()
+    {
+        load_rom(&mut machine, rom_path, config_data.get_patch_paths());
+    }
+
+    // Patches go over the loaded ROM.
+    if let Some(cheats_path) = config_data.get_cheats_path()
+    {
+        let cheats = match CheatList::load(cheats_path)
+        {
+            Ok(cheats) => cheats,
+            Err(e) =>
+            {
+                println!("[ERROR]: {}", e);
+                std::process::exit(-1);
+            },
+        };
+
+        for cheat in cheats.iter()
+        {
+            if !machine.add_cheat(cheat.clone())
+            {
+                println!("[ERROR]: Cheat '{}' doesn't fit in memory", cheat);
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    let symbols = load_symbols(config_data);
+    let opt_snapshot = if config_data.is_mem_diff() { Some(machine.get_cpu().get_mem().snapshot()) } else { None };
+
+    if let Some(trace_path) = config_data.get_trace_path()
+    {
+        match TraceLogger::create(trace_path, symbols.clone())
+        {
+            Ok(logger) => { machine.add_observer(Box::new(logger)); },
+            Err(e) =>
+            {
+                println!("[ERROR]: {}", e);
+                std::process::exit(-1);
+            },
+        }
+    }
+
+    let mem_size = config_data.get_mem_size();
+
+    if config_data.is_profile()
+    {
+        machine.add_observer(Box::new(Profiler::new(mem_size)));
+    }
+
+    if config_data.is_coverage()
+    {
+        machine.add_observer(Box::new(Coverage::new(mem_size)));
+    }
+
+    if config_data.is_smc()
+    {
+        machine.add_observer(Box::new(SmcDetector::new(mem_size)));
+    }
+
+    let new_debugger = ||
+    {
+        let mut debugger = Debugger::with_symbols(symbols.clone());
+        debugger.set_dump_format(config_data.get_dump_format());
+        debugger
+    };
+
+    let mut opt_debugger = if config_data.is_debug() { Some(new_debugger()) } else { None };
+
+    // A script drives the debugger from the first instruction, so it implies '--debug'.
+    if let Some(script_path) = config_data.get_debug_script_path()
+    {
+        let mut debugger = new_debugger();
+
+        if let Err(e) = debugger.load_script(script_path)
+        {
+            println!("[ERROR]: {}", e);
+            std::process::exit(-1);
+        }
+
+        opt_debugger = Some(debugger);
+    }
+
+    let mut timer = Timer::new(machine.get_frame_config().frame_duration().unwrap_or_default());
+
+    while !machine.is_halted()
+    {
+        if let Some(debugger) = opt_debugger.as_mut()
+        {
+            if debugger.is_paused()
+            {
+                debugger.repl(&mut machine);
+
+                // Don't count the time spent in the debugger as lateness.
+                timer.reset();
+            }
+
+            if debugger.should_quit()
+            {
+                break;
+            }
+        }
+
+        // NOTE: The speed may have been changed from the debugger, and turbo mode never sleeps.
+        let frames_due = match machine.get_frame_config().frame_duration()
+        {
+            Some(frame_duration) =>
+            {
+                timer.set_period(frame_duration);
+                timer.wait()
+            },
+            None =>
+            {
+                // Keep the schedule current so leaving turbo mode doesn't look like a huge overrun.
+                timer.reset();
+                1
+            },
+        };
+
+        run_frames(&mut machine, frames_due, config_data.get_fault_policy(), &mut opt_debugger, &new_debugger);
+
+        // Only the latest frame is worth drawing when catching up.
+        if machine.take_display_dirty() && !config_data.is_headless()
+        {
+            // Move the cursor home and redraw over the previous frame.
+            let mut stream = String::from("\x1B[H");
+
+            match config_data.get_palette()
+            {
+                Some(palette) => machine.get_display().render_with_palette(palette, &mut stream),
+                None => machine.get_display().render(&mut stream),
+            }
+
+            print!("{}", stream);
+        }
+    }
+
+    // Checks on the final state come after the halt.
+    if let Some(debugger) = opt_debugger.as_mut().filter(|debugger| debugger.has_script())
+    {
+        debugger.finish_script(&mut machine, &mut std::io::stdout());
+    }
+
+    // The loop only ends early when the debugger quits.
+    if machine.is_halted()
+    {
+        println!("CPU is halted");
+    }
+
+    else
+    {
+        println!("Debugger quit");
+    }
+
+    println!("Dumping final CPU state:");
+    machine.get_cpu().print_state(config_data.get_dump_format());
+
+    println!("Frame timing: {}", timer.get_stats());
+
+    if let Some(profiler) = machine.find_observer::<Profiler>()
+    {
+        print_profile(profiler, machine.get_cpu().get_mem(), config_data.get_profile_out());
+    }
+
+    if let Some(coverage) = machine.find_observer::<Coverage>()
+    {
+        print_coverage(coverage, &machine, opt_source_map.as_ref().zip(config_data.get_lcov_path()));
+    }
+
+    if let Some(smc) = machine.find_observer::<SmcDetector>()
+    {
+        let mut stream = String::new();
+        smc.format_report(&symbols, &mut stream);
+        print!("{}", stream);
+    }
+
+    print_memory(config_data, machine.get_cpu().get_mem(), opt_snapshot.as_ref(), &symbols);
+
+    let cpu = machine.get_cpu();
+
+    if cpu.get_invalid_count() > 0
+    {
+        println!("{0} invalid opcode(s) met (on-invalid {1})", cpu.get_invalid_count(), cpu.get_invalid_policy());
+    }
+
+    println!("End of emulator");
+
+    let mut exit_code = 0;
+
+    // Stopping on an invalid opcode means the ROM (or the chosen interpreter options) is wrong, so let scripts see it.
+    if matches!(cpu.get_halt_reason(), Some(EnumHaltReason::Fault(EnumFault::UnknownOpcode { .. })))
+    {
+        const EXIT_INVALID_OPCODE: i32 = -5;
+        println!("[ERROR]: Halted on an invalid opcode with on-invalid {0} (exit code: {1}).", cpu.get_invalid_policy(), EXIT_INVALID_OPCODE);
+        exit_code = EXIT_INVALID_OPCODE;
+    }
+
+    let failures = opt_debugger.as_ref().map_or(0, |debugger| debugger.get_failures());
+
+    if failures > 0
+    {
+        const EXIT_SCRIPT_FAILED: i32 = -6;
+        println!("[ERROR]: {0} debug script check(s) failed (exit code: {1}).", failures, EXIT_SCRIPT_FAILED);
+        exit_code = EXIT_SCRIPT_FAILED;
+    }
+
+    if exit_code != 0
+    {
+        // 'exit' skips destructors, so drop the machine first to flush observers like the trace logger.
+        drop(machine);
+        std::process::exit(exit_code);
+    }
+}
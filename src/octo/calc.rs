@@ -0,0 +1,160 @@
+use crate::octo::lexer::Token;
+
+/// Parses a decimal, '0x' hex or '0b' binary number, optionally negative.
+pub fn parse_number(text: &str) -> Option<i64>
+{
+    let (negative, digits) = match text.strip_prefix('-')
+    {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()?
+    }
+
+    else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B"))
+    {
+        i64::from_str_radix(binary, 2).ok()?
+    }
+
+    else
+    {
+        // Not 'digits.parse', which would also take a second sign.
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        digits.parse::<i64>().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+/// Evaluates a ':calc' expression (the tokens between the braces). Names are looked up with 'lookup'.
+///
+/// Like Octo, binary operators have no precedence and group from the right, so '2 * 3 + 1' is 2 * (3 + 1); use
+/// parentheses for anything else. Values are integers (division rounds towards zero).
+pub fn evaluate(tokens: &[Token], lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String>
+{
+    let mut pos = 0;
+    let value = expression(tokens, &mut pos, lookup)?;
+
+    match tokens.get(pos)
+    {
+        Some(token) => Err(format!("Unexpected '{}' in expression", token.text)),
+        None => Ok(value),
+    }
+}
+
+fn expression(tokens: &[Token], pos: &mut usize, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String>
+{
+    let left = unary(tokens, pos, lookup)?;
+
+    let op = match tokens.get(*pos)
+    {
+        Some(token) if token.text != ")" => token.text.as_str(),
+        _ => { return Ok(left); },
+    };
+
+    *pos += 1;
+    let right = expression(tokens, pos, lookup)?;
+
+    let value = match op
+    {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => { return Err(String::from("Division by zero")); },
+        "/" => left.checked_div(right).ok_or(String::from("Division overflows"))?,
+        "%" => left.checked_rem(right).ok_or(String::from("Division overflows"))?,
+        "&" => left & right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "<" => (left < right) as i64,
+        ">" => (left > right) as i64,
+        "<=" => (left <= right) as i64,
+        ">=" => (left >= right) as i64,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "min" => left.min(right),
+        "max" => left.max(right),
+        other => { return Err(format!("Unknown operator '{}' in expression", other)); },
+    };
+
+    Ok(value)
+}
+
+fn unary(tokens: &[Token], pos: &mut usize, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String>
+{
+    let token = tokens.get(*pos).ok_or(String::from("Expression ends too early"))?;
+    *pos += 1;
+
+    match token.text.as_str()
+    {
+        "-" => Ok(unary(tokens, pos, lookup)?.wrapping_neg()),
+        "~" => Ok(!unary(tokens, pos, lookup)?),
+        "!" => Ok((unary(tokens, pos, lookup)? == 0) as i64),
+        "(" =>
+        {
+            let value = expression(tokens, pos, lookup)?;
+
+            match tokens.get(*pos)
+            {
+                Some(token) if token.text == ")" =>
+                {
+                    *pos += 1;
+                    Ok(value)
+                },
+                _ => Err(String::from("Expected ')'")),
+            }
+        },
+        text => parse_number(text).or_else(|| lookup(text)).ok_or(format!("Unknown name '{}' in expression", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::octo::calc::{evaluate, parse_number};
+    use crate::octo::lexer::tokenize;
+
+    fn calc(text: &str) -> Result<i64, String>
+    {
+        let lookup = |name: &str| if name == "WIDTH" { Some(64) } else { None };
+        evaluate(&tokenize(text), &lookup)
+    }
+
+    #[test]
+    fn numbers()
+    {
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("0x2A"), Some(42));
+        assert_eq!(parse_number("0b101010"), Some(42));
+        assert_eq!(parse_number("-1"), Some(-1));
+        assert_eq!(parse_number("--1"), None);
+        assert_eq!(parse_number("v0"), None);
+    }
+
+    #[test]
+    fn right_to_left_without_precedence()
+    {
+        assert_eq!(calc("2 * 3 + 1"), Ok(8));
+        assert_eq!(calc("( 2 * 3 ) + 1"), Ok(7));
+        assert_eq!(calc("WIDTH - 2 * 4"), Ok(56));
+        assert_eq!(calc("- 1 & 0xFF"), Ok(0xFF));
+        assert_eq!(calc("3 max WIDTH >> 1"), Ok(32));
+        assert_eq!(calc("! 0"), Ok(1));
+
+        assert_eq!(calc("1 / 0"), Err(String::from("Division by zero")));
+        assert_eq!(calc("( 1 << 63 ) / -1"), Err(String::from("Division overflows")));
+        assert_eq!(calc("( 1 << 63 ) % -1"), Err(String::from("Division overflows")));
+        assert_eq!(calc("HEIGHT"), Err(String::from("Unknown name 'HEIGHT' in expression")));
+        assert_eq!(calc("( 1 + 2"), Err(String::from("Expected ')'")));
+        assert_eq!(calc("1 +"), Err(String::from("Expression ends too early")));
+    }
+}
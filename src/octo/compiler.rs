@@ -0,0 +1,1285 @@
+use crate::dbg::source_map::SourceMap;
+use crate::dbg::symbols::{Symbol, SymbolTable};
+use crate::octo::calc::{evaluate, parse_number};
+use crate::octo::lexer::{tokenize, Token};
+use crate::rom::database::EnumPlatform;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Where Octo programs are loaded.
+pub const PROGRAM_START: u16 = 0x200;
+
+/// The operators that can follow a register.
+const REGISTER_OPS: [&str; 10] = [":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<=", "=="];
+
+/// Guards against macros that expand themselves forever.
+const MAX_MACRO_EXPANSIONS: usize = 100_000;
+
+/// A compile error and the source line it points to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OctoError
+{
+    pub file: String,
+    pub line: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for OctoError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{0}:{1}: {2}", self.file, self.line, self.message)
+    }
+}
+
+/// A compiled program: the ROM image (loaded at 0x200) and where its labels and instructions came from.
+#[derive(Clone, Debug)]
+pub struct OctoProgram
+{
+    file: String,
+    rom: Vec<u8>,
+    /// In address order.
+    labels: Vec<Symbol>,
+    /// Source line of each instruction.
+    lines: BTreeMap<u16, u32>,
+}
+
+impl OctoProgram
+{
+    pub fn get_rom(&self) -> &[u8]
+    {
+        &self.rom
+    }
+
+    pub fn get_labels(&self) -> &[Symbol]
+    {
+        &self.labels
+    }
+
+    /// The labels for the debugger. Names a symbol table can't hold (like 'a+b') are left out.
+    pub fn symbols(&self) -> SymbolTable
+    {
+        let mut symbols = SymbolTable::new();
+
+        for label in &self.labels
+        {
+            let _ = symbols.insert(label.clone());
+        }
+
+        symbols
+    }
+
+    pub fn source_map(&self) -> SourceMap
+    {
+        let mut source_map = SourceMap::new();
+
+        for (addr, line) in &self.lines
+        {
+            source_map.insert(*addr, &self.file, *line);
+        }
+
+        source_map
+    }
+
+    /// The labels in '--symbols' file syntax.
+    pub fn format_symbols(&self, stream: &mut String)
+    {
+        stream.push_str("# name address source\n");
+
+        let symbols = self.symbols();
+
+        for label in self.labels.iter().filter(|label| symbols.get(&label.name).is_some())
+        {
+            stream.push_str(&format!("{0} 0x{1:03X} {2}:{3}\n", label.name, label.addr, self.file, label.location.as_ref().map_or(0, |(_, line)| *line)));
+        }
+    }
+
+    /// The instruction lines in '--source-map' file syntax.
+    pub fn format_source_map(&self, stream: &mut String)
+    {
+        stream.push_str(&format!("# {}\n", self.file));
+
+        for (addr, line) in &self.lines
+        {
+            stream.push_str(&format!("0x{0:03X} {1}:{2}\n", addr, self.file, line));
+        }
+    }
+}
+
+/// What a forward reference patches once its label is known.
+#[derive(Clone, Copy, Debug)]
+enum EnumFixup
+{
+    /// The low 12 bits of the opcode at the address.
+    Nnn,
+    /// The whole 16-bit word at the address ('i := long').
+    Long,
+    /// The byte after the address gets the nibble in the high half and address bits 8-11 in the low one (':unpack').
+    UnpackHigh(u8),
+    /// The byte after the address gets address bits 0-7 (':unpack').
+    UnpackLow,
+}
+
+#[derive(Clone, Debug)]
+struct Fixup
+{
+    addr: usize,
+    kind: EnumFixup,
+    name: String,
+    line: u32,
+}
+
+/// An open 'begin', 'else' or 'loop', with the jumps to patch when it's closed.
+#[derive(Clone, Debug)]
+enum EnumFlow
+{
+    If { jump: usize, line: u32 },
+    Else { jump: usize, line: u32 },
+    Loop { start: usize, breaks: Vec<usize>, line: u32 },
+}
+
+#[derive(Clone, Debug)]
+struct Macro
+{
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum EnumOperand
+{
+    Register(u8),
+    Byte(u8),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EnumCompare
+{
+    Equal,
+    NotEqual,
+    Key,
+    NotKey,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+impl EnumCompare
+{
+    fn negate(self) -> Self
+    {
+        match self
+        {
+            Self::Equal => Self::NotEqual,
+            Self::NotEqual => Self::Equal,
+            Self::Key => Self::NotKey,
+            Self::NotKey => Self::Key,
+            Self::Less => Self::GreaterEqual,
+            Self::GreaterEqual => Self::Less,
+            Self::Greater => Self::LessEqual,
+            Self::LessEqual => Self::Greater,
+        }
+    }
+}
+
+/// 'vx <compare> operand' ('key' and '-key' have no operand).
+#[derive(Clone, Copy, Debug)]
+struct Condition
+{
+    register: u8,
+    compare: EnumCompare,
+    operand: EnumOperand,
+}
+
+/// Compiles Octo source for 'target'. 'file' is the name used in diagnostics, symbols and the source map.
+///
+/// Programs start at 0x200 with a 'jump main', unless ': main' is the first thing in the source. Instructions
+/// that need a later target (like 'hires' on chip8) are errors, as is a program that doesn't fit in its memory
+/// (4K, or 64K for xochip).
+pub fn compile(source: &str, file: &str, target: EnumPlatform) -> Result<OctoProgram, OctoError>
+{
+    let mut compiler = Compiler::new(source, file, target);
+    compiler.compile_all()?;
+    compiler.finish()
+}
+
+struct Compiler
+{
+    file: String,
+    target: EnumPlatform,
+    tokens: VecDeque<Token>,
+    /// The line errors at the end of the file point to.
+    last_line: u32,
+    rom: Vec<u8>,
+    here: usize,
+    mem_size: usize,
+    labels: Vec<Symbol>,
+    label_index: HashMap<String, usize>,
+    consts: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    macro_expansions: usize,
+    fixups: Vec<Fixup>,
+    flow: Vec<EnumFlow>,
+    /// The label ':next' defines at the second byte of the next instruction.
+    pending_next: Option<(String, u32)>,
+    lines: BTreeMap<u16, u32>,
+    jump_main: bool,
+}
+
+impl Compiler
+{
+    fn new(source: &str, file: &str, target: EnumPlatform) -> Self
+    {
+        let mem_size = if target == EnumPlatform::XoChip { 0x10000 } else { 0x1000 };
+
+        Self { file: String::from(file), target, tokens: tokenize(source).into(), last_line: source.lines().count().max(1) as u32, rom: Vec::new(),
+               here: PROGRAM_START as usize, mem_size, labels: Vec::new(), label_index: HashMap::new(), consts: HashMap::new(), aliases: HashMap::new(),
+               macros: HashMap::new(), macro_expansions: 0, fixups: Vec::new(), flow: Vec::new(), pending_next: None, lines: BTreeMap::new(), jump_main: false }
+    }
+
+    fn error(&self, line: u32, message: String) -> OctoError
+    {
+        OctoError { file: self.file.clone(), line, message }
+    }
+
+    fn compile_all(&mut self) -> Result<(), OctoError>
+    {
+        let main_first = matches!((self.tokens.front(), self.tokens.get(1)), (Some(colon), Some(name)) if colon.text == ":" && name.text == "main");
+
+        if !main_first
+        {
+            self.jump_main = true;
+            self.emit_word(0x1000, 1)?;
+            self.fixups.push(Fixup { addr: PROGRAM_START as usize, kind: EnumFixup::Nnn, name: String::from("main"), line: 1 });
+        }
+
+        while let Some(token) = self.tokens.pop_front()
+        {
+            self.statement(token)?;
+        }
+
+        match self.flow.last()
+        {
+            Some(EnumFlow::If { line, .. }) | Some(EnumFlow::Else { line, .. }) => Err(self.error(*line, String::from("'begin' without an 'end'"))),
+            Some(EnumFlow::Loop { line, .. }) => Err(self.error(*line, String::from("'loop' without an 'again'"))),
+            None => Ok(()),
+        }?;
+
+        if let Some((name, line)) = &self.pending_next
+        {
+            return Err(self.error(*line, format!("':next {}' isn't followed by an instruction", name)));
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the forward references.
+    fn finish(mut self) -> Result<OctoProgram, OctoError>
+    {
+        if self.jump_main
+        {
+            let main = self.label_index.get("main").map(|index| &self.labels[*index]).ok_or(self.error(1, String::from("No ': main' label (programs start at main)")))?;
+            let main_line = main.location.as_ref().map_or(1, |(_, line)| *line);
+            self.lines.insert(PROGRAM_START, main_line);
+        }
+
+        for fixup in std::mem::take(&mut self.fixups)
+        {
+            let addr = match self.label_index.get(&fixup.name)
+            {
+                Some(index) => self.labels[*index].addr as i64,
+                None => { return Err(self.error(fixup.line, format!("Undefined label '{}'", fixup.name))); },
+            };
+
+            self.patch(fixup.addr, fixup.kind, addr, &fixup.name, fixup.line)?;
+        }
+
+        self.labels.sort_by_key(|label| label.addr);
+
+        Ok(OctoProgram { file: self.file, rom: self.rom, labels: self.labels, lines: self.lines })
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), OctoError>
+    {
+        let line = token.line;
+
+        match token.text.as_str()
+        {
+            ":" =>
+            {
+                let name = self.next_name(line)?;
+                self.define_label(&name, self.here, line)?;
+            },
+            ":const" =>
+            {
+                let name = self.next_name(line)?;
+                let value_token = self.next(line)?;
+                let value = self.value(&value_token)?;
+                self.define_const(&name, value, line)?;
+            },
+            ":alias" =>
+            {
+                let name = self.next_name(line)?;
+                let register_token = self.next(line)?;
+                let register = self.register(&register_token)?;
+                self.aliases.insert(name, register);
+            },
+            ":macro" => self.define_macro(line)?,
+            ":calc" =>
+            {
+                let name = self.next_name(line)?;
+                self.expect("{", line)?;
+                let value = self.calc_block(line)?;
+
+                // Unlike ':const', calculated names can be redefined (e.g. a counter in a macro).
+                if self.label_index.contains_key(&name)
+                {
+                    return Err(self.error(line, format!("'{}' is already a label", name)));
+                }
+
+                self.consts.insert(name, value);
+            },
+            ":byte" =>
+            {
+                let value_token = self.next(line)?;
+
+                let value = if value_token.text == "{" { self.calc_block(line)? } else { self.value(&value_token)? };
+                let byte = self.check_byte(value, &value_token)?;
+                self.emit_byte(byte, line)?;
+            },
+            ":org" =>
+            {
+                let addr_token = self.next(line)?;
+                let addr = self.value(&addr_token)?;
+
+                if addr < PROGRAM_START as i64 || addr >= self.mem_size as i64
+                {
+                    return Err(self.error(line, format!("':org' 0x{0:X} is outside the program (0x200-0x{1:X})", addr, self.mem_size - 1)));
+                }
+
+                self.here = addr as usize;
+            },
+            ":next" =>
+            {
+                let name = self.next_name(line)?;
+                self.pending_next = Some((name, line));
+            },
+            ":unpack" =>
+            {
+                let nibble_token = self.next(line)?;
+                let nibble = self.check_range(self.value(&nibble_token)?, 0, 0xF, &nibble_token)? as u8;
+                let label_token = self.next(line)?;
+
+                // v0 := nibble and the high address bits, v1 := the low ones.
+                self.emit_reference(0x6000, &label_token, EnumFixup::UnpackHigh(nibble))?;
+                self.emit_reference(0x6100, &label_token, EnumFixup::UnpackLow)?;
+            },
+            ":call" =>
+            {
+                let target_token = self.next(line)?;
+                self.emit_reference(0x2000, &target_token, EnumFixup::Nnn)?;
+            },
+            "clear" => self.emit_word(0x00E0, line)?,
+            "return" | ";" => self.emit_word(0x00EE, line)?,
+            "hires" => self.emit_for(EnumPlatform::Schip, "hires", 0x00FF, line)?,
+            "lores" => self.emit_for(EnumPlatform::Schip, "lores", 0x00FE, line)?,
+            "exit" => self.emit_for(EnumPlatform::Schip, "exit", 0x00FD, line)?,
+            "scroll-right" => self.emit_for(EnumPlatform::Schip, "scroll-right", 0x00FB, line)?,
+            "scroll-left" => self.emit_for(EnumPlatform::Schip, "scroll-left", 0x00FC, line)?,
+            "scroll-down" =>
+            {
+                let rows = self.next_nibble(line)?;
+                self.emit_for(EnumPlatform::Schip, "scroll-down", 0x00C0 | rows, line)?;
+            },
+            "scroll-up" =>
+            {
+                let rows = self.next_nibble(line)?;
+                self.emit_for(EnumPlatform::XoChip, "scroll-up", 0x00D0 | rows, line)?;
+            },
+            "audio" => self.emit_for(EnumPlatform::XoChip, "audio", 0xF002, line)?,
+            "plane" =>
+            {
+                let plane_token = self.next(line)?;
+                let plane = self.check_range(self.value(&plane_token)?, 0, 3, &plane_token)? as u16;
+                self.emit_for(EnumPlatform::XoChip, "plane", 0xF001 | (plane << 8), line)?;
+            },
+            "jump" =>
+            {
+                let target_token = self.next(line)?;
+                self.emit_reference(0x1000, &target_token, EnumFixup::Nnn)?;
+            },
+            "jump0" =>
+            {
+                let target_token = self.next(line)?;
+                self.emit_reference(0xB000, &target_token, EnumFixup::Nnn)?;
+            },
+            "native" =>
+            {
+                let target_token = self.next(line)?;
+                self.emit_reference(0x0000, &target_token, EnumFixup::Nnn)?;
+            },
+            "sprite" =>
+            {
+                let x = self.next_register(line)?;
+                let y = self.next_register(line)?;
+                let height = self.next_nibble(line)?;
+                self.emit_word(0xD000 | (x << 8) | (y << 4) | height, line)?;
+            },
+            "bcd" =>
+            {
+                let x = self.next_register(line)?;
+                self.emit_word(0xF033 | (x << 8), line)?;
+            },
+            "save" | "load" =>
+            {
+                let x = self.next_register(line)?;
+
+                if self.tokens.front().is_some_and(|token| token.text == "-")
+                {
+                    self.tokens.pop_front();
+                    let y = self.next_register(line)?;
+                    let opcode = if token.text == "save" { 0x5002 } else { 0x5003 };
+                    self.emit_for(EnumPlatform::XoChip, &format!("{} vx - vy", token.text), opcode | (x << 8) | (y << 4), line)?;
+                }
+
+                else
+                {
+                    let opcode = if token.text == "save" { 0xF055 } else { 0xF065 };
+                    self.emit_word(opcode | (x << 8), line)?;
+                }
+            },
+            "saveflags" | "loadflags" =>
+            {
+                let x = self.next_register(line)?;
+                let opcode = if token.text == "saveflags" { 0xF075 } else { 0xF085 };
+                self.emit_for(EnumPlatform::Schip, &token.text, opcode | (x << 8), line)?;
+            },
+            "delay" | "buzzer" | "pitch" =>
+            {
+                self.expect(":=", line)?;
+                let x = self.next_register(line)?;
+
+                match token.text.as_str()
+                {
+                    "delay" => self.emit_word(0xF015 | (x << 8), line)?,
+                    "buzzer" => self.emit_word(0xF018 | (x << 8), line)?,
+                    _ => self.emit_for(EnumPlatform::XoChip, "pitch", 0xF03A | (x << 8), line)?,
+                }
+            },
+            "i" => self.assign_i(line)?,
+            "if" => self.compile_if(line)?,
+            "else" =>
+            {
+                let jump = match self.flow.pop()
+                {
+                    Some(EnumFlow::If { jump, .. }) => jump,
+                    _ => { return Err(self.error(line, String::from("'else' without an 'if ... begin'"))); },
+                };
+
+                let else_jump = self.here;
+                self.emit_word(0x1000, line)?;
+                self.patch(jump, EnumFixup::Nnn, self.here as i64, "else", line)?;
+                self.flow.push(EnumFlow::Else { jump: else_jump, line });
+            },
+            "end" =>
+            {
+                match self.flow.pop()
+                {
+                    Some(EnumFlow::If { jump, .. }) | Some(EnumFlow::Else { jump, .. }) => self.patch(jump, EnumFixup::Nnn, self.here as i64, "end", line)?,
+                    _ => { return Err(self.error(line, String::from("'end' without an 'if ... begin'"))); },
+                }
+            },
+            "loop" => self.flow.push(EnumFlow::Loop { start: self.here, breaks: Vec::new(), line }),
+            "while" =>
+            {
+                // Leave the loop when the condition is false: skip the jump out when it's true.
+                let condition = self.condition(line)?;
+                self.emit_skip_unless(Condition { compare: condition.compare.negate(), ..condition }, line)?;
+
+                let jump = self.here;
+                self.emit_word(0x1000, line)?;
+
+                match self.flow.iter_mut().rev().find(|flow| matches!(flow, EnumFlow::Loop { .. }))
+                {
+                    Some(EnumFlow::Loop { breaks, .. }) => breaks.push(jump),
+                    _ => { return Err(self.error(line, String::from("'while' outside of a 'loop'"))); },
+                }
+            },
+            "again" =>
+            {
+                let (start, breaks) = match self.flow.pop()
+                {
+                    Some(EnumFlow::Loop { start, breaks, .. }) => (start, breaks),
+                    _ => { return Err(self.error(line, String::from("'again' without a 'loop'"))); },
+                };
+
+                self.emit_word(0x1000, line)?;
+                self.patch(self.here - 2, EnumFixup::Nnn, start as i64, "loop", line)?;
+
+                for jump in breaks
+                {
+                    self.patch(jump, EnumFixup::Nnn, self.here as i64, "again", line)?;
+                }
+            },
+            "then" | "begin" => { return Err(self.error(line, format!("'{}' without an 'if'", token.text))); },
+            text =>
+            {
+                if let Some(x) = self.register_of(text)
+                {
+                    self.assign_register(x as u16, line)?;
+                }
+
+                else if let Some(value) = parse_number(text)
+                {
+                    let byte = self.check_byte(value, &token)?;
+                    self.emit_byte(byte, line)?;
+                }
+
+                else if self.macros.contains_key(text)
+                {
+                    self.expand_macro(text, line)?;
+                }
+
+                else if text.starts_with(':')
+                {
+                    return Err(self.error(line, format!("Unknown directive '{}'", text)));
+                }
+
+                else if self.tokens.front().is_some_and(|next| REGISTER_OPS.contains(&next.text.as_str()))
+                {
+                    return Err(self.error(line, format!("Expected a register (v0-vF) but got '{}'", text)));
+                }
+
+                else
+                {
+                    // Any other name calls the subroutine with that label.
+                    self.emit_reference(0x2000, &token, EnumFixup::Nnn)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// 'vx := ...', 'vx += ...' and the other register operations.
+    fn assign_register(&mut self, x: u16, line: u32) -> Result<(), OctoError>
+    {
+        let op = self.next(line)?;
+        let rhs = self.next(line)?;
+        let opt_y = self.register_of(&rhs.text).map(|y| y as u16);
+
+        let opcode = match (op.text.as_str(), opt_y)
+        {
+            (":=", Some(y)) => 0x8000 | (x << 8) | (y << 4),
+            (":=", None) if rhs.text == "random" =>
+            {
+                let mask_token = self.next(line)?;
+                0xC000 | (x << 8) | self.check_byte(self.value(&mask_token)?, &mask_token)? as u16
+            },
+            (":=", None) if rhs.text == "delay" => 0xF007 | (x << 8),
+            (":=", None) if rhs.text == "key" => 0xF00A | (x << 8),
+            (":=", None) => 0x6000 | (x << 8) | self.check_byte(self.value(&rhs)?, &rhs)? as u16,
+            ("+=", Some(y)) => 0x8004 | (x << 8) | (y << 4),
+            ("+=", None) => 0x7000 | (x << 8) | self.check_byte(self.value(&rhs)?, &rhs)? as u16,
+            ("-=", Some(y)) => 0x8005 | (x << 8) | (y << 4),
+            ("-=", None) => 0x7000 | (x << 8) | self.check_byte(self.value(&rhs)?, &rhs)?.wrapping_neg() as u16,
+            ("=-", Some(y)) => 0x8007 | (x << 8) | (y << 4),
+            ("|=", Some(y)) => 0x8001 | (x << 8) | (y << 4),
+            ("&=", Some(y)) => 0x8002 | (x << 8) | (y << 4),
+            ("^=", Some(y)) => 0x8003 | (x << 8) | (y << 4),
+            (">>=", Some(y)) => 0x8006 | (x << 8) | (y << 4),
+            ("<<=", Some(y)) => 0x800E | (x << 8) | (y << 4),
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) =>
+            {
+                return Err(self.error(rhs.line, format!("'{0}' needs a register on the right but got '{1}'", op.text, rhs.text)));
+            },
+            _ => { return Err(self.error(op.line, format!("Unknown operator '{0}' for v{1:X}", op.text, x))); },
+        };
+
+        self.emit_word(opcode, line)
+    }
+
+    /// 'i := addr', 'i := long addr', 'i := hex vx', 'i := bighex vx' and 'i += vx'.
+    fn assign_i(&mut self, line: u32) -> Result<(), OctoError>
+    {
+        let op = self.next(line)?;
+
+        if op.text == "+="
+        {
+            let x = self.next_register(line)?;
+            return self.emit_word(0xF01E | (x << 8), line);
+        }
+
+        if op.text != ":="
+        {
+            return Err(self.error(op.line, format!("Unknown operator '{}' for i", op.text)));
+        }
+
+        let rhs = self.next(line)?;
+
+        match rhs.text.as_str()
+        {
+            "hex" =>
+            {
+                let x = self.next_register(line)?;
+                self.emit_word(0xF029 | (x << 8), line)
+            },
+            "bighex" =>
+            {
+                let x = self.next_register(line)?;
+                self.emit_for(EnumPlatform::Schip, "i := bighex", 0xF030 | (x << 8), line)
+            },
+            "long" =>
+            {
+                self.emit_for(EnumPlatform::XoChip, "i := long", 0xF000, line)?;
+
+                let addr_token = self.next(line)?;
+                let addr = self.here;
+                self.emit_byte(0, line)?;
+                self.emit_byte(0, line)?;
+                self.reference(addr, &addr_token, EnumFixup::Long)
+            },
+            _ => self.emit_reference(0xA000, &rhs, EnumFixup::Nnn),
+        }
+    }
+
+    /// 'if <condition> then <statement>' or 'if <condition> begin ... [else ...] end'.
+    fn compile_if(&mut self, line: u32) -> Result<(), OctoError>
+    {
+        let condition = self.condition(line)?;
+        let keyword = self.next(line)?;
+
+        match keyword.text.as_str()
+        {
+            "then" => self.emit_skip_unless(condition, line),
+            "begin" =>
+            {
+                // Jump over the block when the condition is false.
+                self.emit_skip_unless(Condition { compare: condition.compare.negate(), ..condition }, line)?;
+
+                let jump = self.here;
+                self.emit_word(0x1000, line)?;
+                self.flow.push(EnumFlow::If { jump, line });
+                Ok(())
+            },
+            text => Err(self.error(keyword.line, format!("Expected 'then' or 'begin' but got '{}'", text))),
+        }
+    }
+
+    fn condition(&mut self, line: u32) -> Result<Condition, OctoError>
+    {
+        let register = self.next_register(line)? as u8;
+        let op = self.next(line)?;
+
+        let compare = match op.text.as_str()
+        {
+            "==" => EnumCompare::Equal,
+            "!=" => EnumCompare::NotEqual,
+            "key" => EnumCompare::Key,
+            "-key" => EnumCompare::NotKey,
+            "<" => EnumCompare::Less,
+            ">" => EnumCompare::Greater,
+            "<=" => EnumCompare::LessEqual,
+            ">=" => EnumCompare::GreaterEqual,
+            text => { return Err(self.error(op.line, format!("Unknown comparison '{}'", text))); },
+        };
+
+        if compare == EnumCompare::Key || compare == EnumCompare::NotKey
+        {
+            return Ok(Condition { register, compare, operand: EnumOperand::Byte(0) });
+        }
+
+        let rhs = self.next(line)?;
+
+        let operand = match self.register_of(&rhs.text)
+        {
+            Some(y) => EnumOperand::Register(y),
+            None => EnumOperand::Byte(self.check_byte(self.value(&rhs)?, &rhs)?),
+        };
+
+        Ok(Condition { register, compare, operand })
+    }
+
+    /// Emits code that skips the next instruction when the condition is false. '<', '>', '<=' and '>=' subtract
+    /// into vF first, like Octo, so they clobber it.
+    fn emit_skip_unless(&mut self, condition: Condition, line: u32) -> Result<(), OctoError>
+    {
+        let x = condition.register as u16;
+
+        let opcode = match (condition.compare, condition.operand)
+        {
+            (EnumCompare::Key, _) => 0xE0A1 | (x << 8),
+            (EnumCompare::NotKey, _) => 0xE09E | (x << 8),
+            (EnumCompare::Equal, EnumOperand::Register(y)) => 0x9000 | (x << 8) | ((y as u16) << 4),
+            (EnumCompare::Equal, EnumOperand::Byte(n)) => 0x4000 | (x << 8) | n as u16,
+            (EnumCompare::NotEqual, EnumOperand::Register(y)) => 0x5000 | (x << 8) | ((y as u16) << 4),
+            (EnumCompare::NotEqual, EnumOperand::Byte(n)) => 0x3000 | (x << 8) | n as u16,
+            (compare, operand) =>
+            {
+                // vF := vx - operand for '<' and '>=', operand - vx for '>' and '<='. Either way vF ends up as the
+                // no-borrow flag.
+                let x_minus_operand = compare == EnumCompare::Less || compare == EnumCompare::GreaterEqual;
+
+                let (first, second) = match (x_minus_operand, operand)
+                {
+                    (true, EnumOperand::Register(y)) => (0x8F00 | (x << 4), 0x8F05 | ((y as u16) << 4)),
+                    (true, EnumOperand::Byte(n)) => (0x6F00 | n as u16, 0x8F07 | (x << 4)),
+                    (false, EnumOperand::Register(y)) => (0x8F00 | ((y as u16) << 4), 0x8F05 | (x << 4)),
+                    (false, EnumOperand::Byte(n)) => (0x6F00 | n as u16, 0x8F05 | (x << 4)),
+                };
+
+                self.emit_word(first, line)?;
+                self.emit_word(second, line)?;
+
+                // '<' and '>' hold when there was a borrow (vF == 0), '<=' and '>=' when there wasn't.
+                let flag = if compare == EnumCompare::Less || compare == EnumCompare::Greater { 0 } else { 1 };
+                0x4F00 | flag
+            },
+        };
+
+        self.emit_word(opcode, line)
+    }
+
+    fn define_macro(&mut self, line: u32) -> Result<(), OctoError>
+    {
+        let name = self.next_name(line)?;
+        let mut params = Vec::new();
+
+        loop
+        {
+            let token = self.next(line)?;
+
+            if token.text == "{"
+            {
+                break;
+            }
+
+            params.push(token.text);
+        }
+
+        let body = self.block(line)?;
+        self.macros.insert(name, Macro { params, body });
+
+        Ok(())
+    }
+
+    /// Replaces a macro invocation with its body. The expanded tokens point to the invocation's line.
+    fn expand_macro(&mut self, name: &str, line: u32) -> Result<(), OctoError>
+    {
+        self.macro_expansions += 1;
+
+        if self.macro_expansions > MAX_MACRO_EXPANSIONS
+        {
+            return Err(self.error(line, format!("Too many macro expansions (does '{}' expand itself?)", name)));
+        }
+
+        let mac = self.macros[name].clone();
+        let mut args = HashMap::new();
+
+        for param in &mac.params
+        {
+            let arg = self.next(line)?;
+            args.insert(param.as_str(), arg.text);
+        }
+
+        for token in mac.body.iter().rev()
+        {
+            let text = args.get(token.text.as_str()).unwrap_or(&token.text);
+            self.tokens.push_front(Token::new(text, line));
+        }
+
+        Ok(())
+    }
+
+    /// The tokens up to the '}' matching an already read '{'.
+    fn block(&mut self, line: u32) -> Result<Vec<Token>, OctoError>
+    {
+        let mut tokens = Vec::new();
+        let mut depth = 1;
+
+        loop
+        {
+            let token = self.tokens.pop_front().ok_or(self.error(line, String::from("'{' without a matching '}'")))?;
+
+            match token.text.as_str()
+            {
+                "{" => { depth += 1; },
+                "}" =>
+                {
+                    depth -= 1;
+
+                    if depth == 0
+                    {
+                        return Ok(tokens);
+                    }
+                },
+                _ => {},
+            }
+
+            tokens.push(token);
+        }
+    }
+
+    /// Evaluates the expression up to the '}' matching an already read '{'. 'HERE' is the current address.
+    fn calc_block(&mut self, line: u32) -> Result<i64, OctoError>
+    {
+        let body = self.block(line)?;
+
+        let lookup = |name: &str|
+        {
+            if name == "HERE"
+            {
+                return Some(self.here as i64);
+            }
+
+            self.known_value(name).or(self.label_index.get(name).map(|index| self.labels[*index].addr as i64))
+        };
+
+        evaluate(&body, &lookup).map_err(|e| self.error(line, e))
+    }
+
+    fn define_label(&mut self, name: &str, addr: usize, line: u32) -> Result<(), OctoError>
+    {
+        if let Some(index) = self.label_index.get(name)
+        {
+            let first_line = self.labels[*index].location.as_ref().map_or(0, |(_, line)| *line);
+            return Err(self.error(line, format!("Label '{0}' is already defined on line {1}", name, first_line)));
+        }
+
+        if self.consts.contains_key(name)
+        {
+            return Err(self.error(line, format!("'{}' is already a constant", name)));
+        }
+
+        if addr >= self.mem_size
+        {
+            return Err(self.error(line, format!("Label '{0}' at 0x{1:X} is outside memory", name, addr)));
+        }
+
+        self.label_index.insert(String::from(name), self.labels.len());
+        self.labels.push(Symbol { name: String::from(name), addr: addr as u16, location: Some((self.file.clone(), line)) });
+
+        Ok(())
+    }
+
+    fn define_const(&mut self, name: &str, value: i64, line: u32) -> Result<(), OctoError>
+    {
+        if self.consts.contains_key(name) || self.label_index.contains_key(name)
+        {
+            return Err(self.error(line, format!("'{}' is already defined", name)));
+        }
+
+        self.consts.insert(String::from(name), value);
+
+        Ok(())
+    }
+
+    /// A number or a constant.
+    fn known_value(&self, text: &str) -> Option<i64>
+    {
+        parse_number(text).or(self.consts.get(text).copied())
+    }
+
+    fn value(&self, token: &Token) -> Result<i64, OctoError>
+    {
+        self.known_value(&token.text).ok_or(self.error(token.line, format!("Expected a number or constant but got '{}'", token.text)))
+    }
+
+    fn check_range(&self, value: i64, min: i64, max: i64, token: &Token) -> Result<i64, OctoError>
+    {
+        if value < min || value > max
+        {
+            return Err(self.error(token.line, format!("'{0}' ({1}) is out of range ({2} to {3})", token.text, value, min, max)));
+        }
+
+        Ok(value)
+    }
+
+    /// Bytes can be given signed (-128 to 255).
+    fn check_byte(&self, value: i64, token: &Token) -> Result<u8, OctoError>
+    {
+        Ok(self.check_range(value, -128, 255, token)? as u8)
+    }
+
+    fn register_of(&self, text: &str) -> Option<u8>
+    {
+        if let Some(register) = self.aliases.get(text)
+        {
+            return Some(*register);
+        }
+
+        let digit = text.strip_prefix('v').or(text.strip_prefix('V'))?;
+
+        if digit.len() != 1
+        {
+            return None;
+        }
+
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register(&self, token: &Token) -> Result<u8, OctoError>
+    {
+        self.register_of(&token.text).ok_or(self.error(token.line, format!("Expected a register (v0-vF) but got '{}'", token.text)))
+    }
+
+    fn next(&mut self, line: u32) -> Result<Token, OctoError>
+    {
+        let last_line = self.last_line.max(line);
+        self.tokens.pop_front().ok_or(self.error(last_line, String::from("Unexpected end of file")))
+    }
+
+    fn expect(&mut self, text: &str, line: u32) -> Result<(), OctoError>
+    {
+        let token = self.next(line)?;
+
+        if token.text != text
+        {
+            return Err(self.error(token.line, format!("Expected '{0}' but got '{1}'", text, token.text)));
+        }
+
+        Ok(())
+    }
+
+    fn next_register(&mut self, line: u32) -> Result<u16, OctoError>
+    {
+        let token = self.next(line)?;
+        Ok(self.register(&token)? as u16)
+    }
+
+    fn next_nibble(&mut self, line: u32) -> Result<u16, OctoError>
+    {
+        let token = self.next(line)?;
+        Ok(self.check_range(self.value(&token)?, 0, 0xF, &token)? as u16)
+    }
+
+    /// A label, constant or macro name.
+    fn next_name(&mut self, line: u32) -> Result<String, OctoError>
+    {
+        let token = self.next(line)?;
+
+        if parse_number(&token.text).is_some() || self.register_of(&token.text).is_some() || token.text.starts_with(':') || token.text == "{" || token.text == "}"
+        {
+            return Err(self.error(token.line, format!("'{}' can't be used as a name", token.text)));
+        }
+
+        Ok(token.text)
+    }
+
+    fn emit_byte(&mut self, byte: u8, line: u32) -> Result<(), OctoError>
+    {
+        if self.here >= self.mem_size
+        {
+            return Err(self.error(line, format!("The program doesn't fit in memory ({0} bytes for {1})", self.mem_size, self.target)));
+        }
+
+        let index = self.here - PROGRAM_START as usize;
+
+        if index >= self.rom.len()
+        {
+            self.rom.resize(index + 1, 0);
+        }
+
+        self.rom[index] = byte;
+        self.here += 1;
+
+        Ok(())
+    }
+
+    fn emit_word(&mut self, word: u16, line: u32) -> Result<(), OctoError>
+    {
+        let addr = self.here;
+        self.emit_byte((word >> 8) as u8, line)?;
+        self.emit_byte(word as u8, line)?;
+        self.lines.insert(addr as u16, line);
+
+        if let Some((name, next_line)) = self.pending_next.take()
+        {
+            self.define_label(&name, addr + 1, next_line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Emits an instruction that only exists on 'platform' and later.
+    fn emit_for(&mut self, platform: EnumPlatform, what: &str, word: u16, line: u32) -> Result<(), OctoError>
+    {
+        if self.target < platform
+        {
+            return Err(self.error(line, format!("'{0}' needs the {1} target (compiling for {2})", what, platform, self.target)));
+        }
+
+        self.emit_word(word, line)
+    }
+
+    /// Emits an instruction with an address operand, patched now for a number or constant or later for a label.
+    fn emit_reference(&mut self, word: u16, token: &Token, kind: EnumFixup) -> Result<(), OctoError>
+    {
+        let addr = self.here;
+        self.emit_word(word, token.line)?;
+        self.reference(addr, token, kind)
+    }
+
+    fn reference(&mut self, addr: usize, token: &Token, kind: EnumFixup) -> Result<(), OctoError>
+    {
+        if let Some(value) = self.known_value(&token.text)
+        {
+            return self.patch(addr, kind, value, &token.text, token.line);
+        }
+
+        if self.register_of(&token.text).is_some() || token.text.starts_with(':')
+        {
+            return Err(self.error(token.line, format!("Expected an address but got '{}'", token.text)));
+        }
+
+        self.fixups.push(Fixup { addr, kind, name: token.text.clone(), line: token.line });
+
+        Ok(())
+    }
+
+    fn patch(&mut self, addr: usize, kind: EnumFixup, value: i64, name: &str, line: u32) -> Result<(), OctoError>
+    {
+        let max = if let EnumFixup::Long = kind { 0xFFFF } else { 0xFFF };
+
+        if value < 0 || value > max
+        {
+            let hint = if max == 0xFFF { " (use 'i := long' for addresses above 0xFFF)" } else { "" };
+            return Err(self.error(line, format!("'{0}' (0x{1:X}) doesn't fit in {2} bits{3}", name, value, if max == 0xFFF { 12 } else { 16 }, hint)));
+        }
+
+        let index = addr - PROGRAM_START as usize;
+
+        match kind
+        {
+            EnumFixup::Nnn =>
+            {
+                self.rom[index] = (self.rom[index] & 0xF0) | (value >> 8) as u8;
+                self.rom[index + 1] = value as u8;
+            },
+            EnumFixup::Long =>
+            {
+                self.rom[index] = (value >> 8) as u8;
+                self.rom[index + 1] = value as u8;
+            },
+            EnumFixup::UnpackHigh(nibble) => { self.rom[index + 1] = (nibble << 4) | (value >> 8) as u8; },
+            EnumFixup::UnpackLow => { self.rom[index + 1] = value as u8; },
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::octo::compiler::{compile, OctoError};
+    use crate::rom::database::EnumPlatform;
+    use crate::Machine;
+
+    fn rom(source: &str) -> Vec<u8>
+    {
+        compile(source, "test.8o", EnumPlatform::Chip8).unwrap().get_rom().to_vec()
+    }
+
+    fn error(source: &str, target: EnumPlatform) -> String
+    {
+        compile(source, "test.8o", target).unwrap_err().to_string()
+    }
+
+    fn words(rom: &[u8]) -> Vec<u16>
+    {
+        rom.chunks(2).map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])).collect()
+    }
+
+    #[test]
+    fn statements()
+    {
+        let source = "
+: main
+    clear
+    v0 := 5  v1 := v0  v2 := random 0x0F  v3 := delay  v4 := key
+    v0 += 1  v0 += v1  v0 -= 1  v0 -= v1  v0 =- v1
+    v0 |= v1  v0 &= v1  v0 ^= v1  v0 >>= v1  v0 <<= v1
+    i := sprite  i += v0  i := hex v1  bcd v2  save v3  load v4
+    delay := v5  buzzer := v6
+    sprite v0 v1 5
+    jump main
+    draw ;
+: draw
+    return
+: sprite
+    0xF0 0x90 -1
+";
+
+        let expected: Vec<u16> = vec![0x00E0, 0x6005, 0x8100, 0xC20F, 0xF307, 0xF40A,
+                                      0x7001, 0x8014, 0x70FF, 0x8015, 0x8017,
+                                      0x8011, 0x8012, 0x8013, 0x8016, 0x801E,
+                                      0xA23A, 0xF01E, 0xF129, 0xF233, 0xF355, 0xF465,
+                                      0xF515, 0xF618,
+                                      0xD015,
+                                      0x1200,
+                                      0x2238, 0x00EE,
+                                      0x00EE,
+                                      0xF090, 0xFF00];
+        assert_eq!(words(&rom(source)), expected);
+    }
+
+    #[test]
+    fn jump_to_main_unless_it_comes_first()
+    {
+        let program = compile("# Data first\n: data 1 2\n: main\n  i := data\n", "game.8o", EnumPlatform::Chip8);
+        let program = match program { Ok(program) => program, Err(e) => panic!("{}", e) };
+
+        assert_eq!(program.get_rom(), [0x12, 0x04, 0x01, 0x02, 0xA2, 0x02].as_slice());
+
+        let mut symbols = String::new();
+        program.format_symbols(&mut symbols);
+        assert_eq!(symbols, "# name address source\ndata 0x202 game.8o:2\nmain 0x204 game.8o:3\n");
+
+        let mut source_map = String::new();
+        program.format_source_map(&mut source_map);
+        assert_eq!(source_map, "# game.8o\n0x200 game.8o:3\n0x204 game.8o:4\n");
+
+        assert_eq!(program.symbols().resolve("main"), Some(0x204));
+        assert_eq!(program.source_map().get_location(0x204).map(|location| location.line), Some(4));
+        assert_eq!(error(": data 1\n", EnumPlatform::Chip8), "test.8o:1: No ': main' label (programs start at main)");
+    }
+
+    #[test]
+    fn control_flow()
+    {
+        let source = "
+: main
+    if v0 == 1 then v1 := 2
+    if v0 != v1 then v1 := 2
+    if v0 key then v1 := 2
+    if v0 -key begin
+        v1 := 2
+    else
+        v1 := 3
+    end
+    loop
+        v0 += 1
+        while v0 != 8
+    again
+";
+
+        let expected: Vec<u16> = vec![0x4001, 0x6102,
+                                      0x5010, 0x6102,
+                                      0xE0A1, 0x6102,
+                                      0xE0A1, 0x1214, 0x6102, 0x1216, 0x6103,
+                                      0x7001, 0x4008, 0x121E, 0x1216];
+        assert_eq!(words(&rom(source)), expected);
+    }
+
+    #[test]
+    fn comparisons_run_correctly()
+    {
+        // v2 counts the true comparisons, then the 0000 halts.
+        let source = "
+: main
+    v0 := 3  v1 := 7  v2 := 0
+    if v0 < v1 then v2 += 1
+    if v0 < 3 then v2 += 0x10
+    if v0 <= 3 then v2 += 1
+    if v1 > v0 then v2 += 1
+    if v1 > 7 then v2 += 0x10
+    if v1 >= 7 then v2 += 1
+    if v0 >= v1 then v2 += 0x10
+    v3 := 0
+    loop
+        v3 += 1
+        while v3 < 10
+    again
+    0 0
+";
+
+        let mut machine = Machine::new(4096, 0x200);
+        machine.load_rom(&rom(source)).unwrap();
+
+        let mut frames = 0;
+
+        while !machine.is_halted() && frames < 100
+        {
+            machine.run_frame().unwrap();
+            frames += 1;
+        }
+
+        assert!(machine.is_halted());
+        assert_eq!(machine.get_cpu().get_registers()[2], 4);
+        assert_eq!(machine.get_cpu().get_registers()[3], 10);
+    }
+
+    #[test]
+    fn directives()
+    {
+        let source = "
+:const SPEED 3
+:alias x v4
+:macro move reg amount { reg += amount }
+:calc HALF { SPEED * 4 / 2 }
+: main
+    x := SPEED
+    move x 2
+    move v5 HALF
+    :next target v6 := 0
+    i := target
+    :unpack 0xA sprite
+    :byte { HALF + 1 }
+    :org 0x220
+: sprite
+    :byte 0x3C
+";
+
+        let program = compile(source, "test.8o", EnumPlatform::Chip8).unwrap();
+        let rom = program.get_rom();
+
+        // The directives come first, so main is jumped to.
+        assert_eq!(words(&rom[..18]), [0x1202, 0x6403, 0x7402, 0x7506, 0x6600, 0xA209, 0x60A2, 0x6120, 0x0700]);
+        assert_eq!(rom.len(), 0x21);
+        assert_eq!(rom[0x20], 0x3C);
+        assert_eq!(program.get_labels().iter().map(|label| (label.name.as_str(), label.addr)).collect::<Vec<_>>(), [("main", 0x202), ("target", 0x209), ("sprite", 0x220)]);
+    }
+
+    #[test]
+    fn targets()
+    {
+        assert_eq!(error(": main\n  hires\n", EnumPlatform::Chip8), "test.8o:2: 'hires' needs the schip target (compiling for chip8)");
+        assert_eq!(error(": main\n  i := long main\n", EnumPlatform::Schip), "test.8o:2: 'i := long' needs the xochip target (compiling for schip)");
+
+        let schip = compile(": main hires scroll-down 4 scroll-left exit saveflags v7 i := bighex v1", "s.8o", EnumPlatform::Schip).unwrap();
+        assert_eq!(words(schip.get_rom()), [0x00FF, 0x00C4, 0x00FC, 0x00FD, 0xF775, 0xF130]);
+
+        let xochip = compile(": main i := long data save v1 - v3 plane 2 audio pitch := v0 :org 0x1000 : data", "x.8o", EnumPlatform::XoChip).unwrap();
+        assert_eq!(words(&xochip.get_rom()[..12]), [0xF000, 0x1000, 0x5132, 0xF201, 0xF002, 0xF03A]);
+        assert_eq!(xochip.get_labels()[1].addr, 0x1000);
+
+        assert_eq!(error(": main :org 0xFFF 1 2", EnumPlatform::Chip8), "test.8o:1: The program doesn't fit in memory (4096 bytes for chip8)");
+    }
+
+    #[test]
+    fn errors_point_to_the_line()
+    {
+        let chip8 = EnumPlatform::Chip8;
+
+        assert_eq!(error(": main\n  v0 := 1\n  draw\n", chip8), "test.8o:3: Undefined label 'draw'");
+        assert_eq!(error(": main\n  vG := 1\n", chip8), "test.8o:2: Expected a register (v0-vF) but got 'vG'");
+        assert_eq!(error(": main\n  v0 += 300\n", chip8), "test.8o:2: '300' (300) is out of range (-128 to 255)");
+        assert_eq!(error(": main\n  v0 ** v1\n", chip8), "test.8o:2: Unknown operator '**' for v0");
+        assert_eq!(error(": main\n  if v0 == 1 begin\n  v0 := 2\n", chip8), "test.8o:2: 'begin' without an 'end'");
+        assert_eq!(error(": main\n  loop\n", chip8), "test.8o:2: 'loop' without an 'again'");
+        assert_eq!(error(": main\n  again\n", chip8), "test.8o:2: 'again' without a 'loop'");
+        assert_eq!(error(": main\n  if v0 == 1 v0 := 2\n", chip8), "test.8o:2: Expected 'then' or 'begin' but got 'v0'");
+        assert_eq!(error(": main\n: main\n", chip8), "test.8o:2: Label 'main' is already defined on line 1");
+        assert_eq!(error(": main\n  :bogus\n", chip8), "test.8o:2: Unknown directive ':bogus'");
+        assert_eq!(error(": main\n  :calc X { 1 / 0 }\n", chip8), "test.8o:2: Division by zero");
+        assert_eq!(error(": main\n  :calc X { ( 1 << 63 ) / -1 }\n", chip8), "test.8o:2: Division overflows");
+        assert_eq!(error(": main\n  v0 :=\n", chip8), "test.8o:2: Unexpected end of file");
+        assert_eq!(error(": main\n  :macro loopy { loopy }\n  loopy\n", chip8), "test.8o:3: Too many macro expansions (does 'loopy' expand itself?)");
+
+        let e = compile(": main jump 0x1000", "far.8o", chip8).unwrap_err();
+        assert_eq!(e, OctoError { file: String::from("far.8o"), line: 1, message: String::from("'0x1000' (0x1000) doesn't fit in 12 bits (use 'i := long' for addresses above 0xFFF)") });
+    }
+}
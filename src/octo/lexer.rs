@@ -0,0 +1,53 @@
+/// A word of Octo source and the line it's on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Token
+{
+    pub text: String,
+    pub line: u32,
+}
+
+impl Token
+{
+    pub fn new(text: &str, line: u32) -> Self
+    {
+        Self { text: String::from(text), line }
+    }
+}
+
+/// Splits Octo source into tokens. Octo is whitespace separated, so every word is a token (': main', 'v0 += 1',
+/// '{' and '}' included); a '#' at the start of a word comments out the rest of the line.
+pub fn tokenize(source: &str) -> Vec<Token>
+{
+    let mut tokens = Vec::new();
+
+    for (index, line) in source.lines().enumerate()
+    {
+        for word in line.split_whitespace()
+        {
+            if word.starts_with('#')
+            {
+                break;
+            }
+
+            tokens.push(Token::new(word, index as u32 + 1));
+        }
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::octo::lexer::{tokenize, Token};
+
+    #[test]
+    fn words_comments_and_lines()
+    {
+        let tokens = tokenize("# Title\n: main\n\tv0 := 0x1F # comment\n\ti := sprite#1\n");
+
+        let expected = [Token::new(":", 2), Token::new("main", 2), Token::new("v0", 3), Token::new(":=", 3), Token::new("0x1F", 3),
+                        Token::new("i", 4), Token::new(":=", 4), Token::new("sprite#1", 4)];
+        assert_eq!(tokens, expected);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod calc;
+pub mod compiler;
+pub mod lexer;
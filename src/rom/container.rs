@@ -0,0 +1,352 @@
+use crate::rom::patch::apply_patch;
+
+/// A run of bytes and where it goes in memory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment
+{
+    pub addr: usize,
+    pub data: Vec<u8>,
+}
+
+/// How a ROM file is stored.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnumRomFormat
+{
+    /// The raw image, loaded at the starting pc.
+    Binary,
+    /// Intel HEX records (':10020000...').
+    IntelHex,
+    /// Whitespace separated hex bytes or words, as in magazine listings.
+    HexText,
+}
+
+impl std::fmt::Display for EnumRomFormat
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Binary => write!(f, "binary"),
+            Self::IntelHex => write!(f, "Intel HEX"),
+            Self::HexText => write!(f, "hex text"),
+        }
+    }
+}
+
+/// Guesses the format from the contents. Only text files can be Intel HEX or hex text: Intel HEX if the first
+/// line starts with ':', hex text if every word is hex digits. Anything else (real ROMs nearly always contain
+/// 0x00 or bytes above 0x7F) is binary.
+pub fn detect_format(bytes: &[u8]) -> EnumRomFormat
+{
+    let is_text = bytes.iter().all(|byte| byte.is_ascii_graphic() || matches!(byte, b' ' | b'\t' | b'\r' | b'\n'));
+
+    if !is_text || bytes.is_empty()
+    {
+        return EnumRomFormat::Binary;
+    }
+
+    let text = String::from_utf8_lossy(bytes);
+
+    if text.trim_start().starts_with(':')
+    {
+        return EnumRomFormat::IntelHex;
+    }
+
+    let mut words = text.lines().flat_map(|line| strip_comment(line).split_whitespace()).peekable();
+
+    if words.peek().is_some() && words.all(|word| is_hex_word(word.strip_suffix(':').unwrap_or(word)))
+    {
+        return EnumRomFormat::HexText;
+    }
+
+    return EnumRomFormat::Binary;
+}
+
+/// Detects the format and splits the file into segments. Binary images and hex text without addresses go to
+/// 'load_addr'.
+pub fn decode_rom(bytes: &[u8], load_addr: usize) -> Result<(EnumRomFormat, Vec<Segment>), String>
+{
+    let format = detect_format(bytes);
+
+    let segments = match format
+    {
+        EnumRomFormat::Binary => vec![Segment { addr: load_addr, data: bytes.to_vec() }],
+        EnumRomFormat::IntelHex => parse_intel_hex(&String::from_utf8_lossy(bytes))?,
+        EnumRomFormat::HexText => parse_hex_text(&String::from_utf8_lossy(bytes), load_addr)?,
+    };
+
+    Ok((format, segments))
+}
+
+/// Reads a ROM file and applies the patches in order. Patches are made against binary images, so other formats
+/// can't be patched.
+pub fn read_rom_file(rom_path: &str, patch_paths: &[String]) -> Result<Vec<u8>, String>
+{
+    let mut rom = std::fs::read(rom_path).map_err(|e| format!("Failed to read ROM: '{0}': {1}", rom_path, e))?;
+    let format = detect_format(&rom);
+
+    if format != EnumRomFormat::Binary && !patch_paths.is_empty()
+    {
+        return Err(format!("'--patch' only applies to binary ROMs but '{0}' is {1}", rom_path, format));
+    }
+
+    for patch_path in patch_paths
+    {
+        rom = std::fs::read(patch_path).map_err(|e| e.to_string())
+            .and_then(|patch| apply_patch(&rom, &patch).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to apply patch '{0}': {1}", patch_path, e))?;
+    }
+
+    Ok(rom)
+}
+
+/// Lays segments out as one image, from the lowest address to the end of the highest segment with the gaps
+/// zero-filled: the program as it sits in memory, whichever format stored it. None if that's over 'max_size' bytes.
+pub fn flatten(segments: &[Segment], max_size: usize) -> Option<Vec<u8>>
+{
+    let start = segments.iter().map(|segment| segment.addr).min().unwrap_or(0);
+    let mut end = start;
+
+    for segment in segments
+    {
+        end = end.max(segment.addr.checked_add(segment.data.len())?);
+    }
+
+    if end - start > max_size
+    {
+        return None;
+    }
+
+    let mut image = vec![0u8; end - start];
+
+    for segment in segments
+    {
+        let offset = segment.addr - start;
+        image[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+    }
+
+    Some(image)
+}
+
+/// Parses Intel HEX: data (00), end of file (01), extended segment (02) and extended linear (04) address records.
+/// Start address records (03, 05) are ignored since CHIP-8 programs start at the pc. Every record's checksum is
+/// checked, and consecutive data records are merged into one segment.
+pub fn parse_intel_hex(text: &str) -> Result<Vec<Segment>, String>
+{
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut base = 0usize;
+    let mut line_number = 0;
+
+    for (index, raw_line) in text.lines().enumerate()
+    {
+        line_number = index + 1;
+        let line = raw_line.trim();
+
+        if line.is_empty()
+        {
+            continue;
+        }
+
+        let digits = line.strip_prefix(':').ok_or(format!("line {}: Expected ':' to start a record", line_number))?;
+        let record = decode_hex(digits).map_err(|e| format!("line {0}: {1}", line_number, e))?;
+
+        if record.len() < 5
+        {
+            return Err(format!("line {}: The record is too short", line_number));
+        }
+
+        let count = record[0] as usize;
+
+        if record.len() != count + 5
+        {
+            return Err(format!("line {0}: The byte count is {1} but the record has {2} data bytes", line_number, count, record.len() - 5));
+        }
+
+        let (body, checksum) = record.split_at(record.len() - 1);
+        let expected = body.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg();
+
+        if checksum[0] != expected
+        {
+            return Err(format!("line {0}: Checksum is 0x{1:02X} but should be 0x{2:02X}", line_number, checksum[0], expected));
+        }
+
+        let offset = u16::from_be_bytes([record[1], record[2]]) as usize;
+        let data = &record[4..4 + count];
+
+        match record[3]
+        {
+            0x00 =>
+            {
+                let addr = base + offset;
+
+                match segments.last_mut()
+                {
+                    Some(segment) if segment.addr + segment.data.len() == addr => segment.data.extend_from_slice(data),
+                    _ => segments.push(Segment { addr, data: data.to_vec() }),
+                }
+            },
+            0x01 => { return Ok(segments); },
+            0x02 | 0x04 =>
+            {
+                if count != 2
+                {
+                    return Err(format!("line {}: An extended address record has 2 data bytes", line_number));
+                }
+
+                let value = u16::from_be_bytes([data[0], data[1]]) as usize;
+                base = if record[3] == 0x02 { value << 4 } else { value << 16 };
+            },
+            0x03 | 0x05 => {},
+            kind => { return Err(format!("line {0}: Unknown record type 0x{1:02X}", line_number, kind)); },
+        }
+    }
+
+    Err(format!("line {}: No end of file record (is the file truncated?)", line_number))
+}
+
+/// Parses hex text: bytes or big-endian words like '6A02' or '0x6A', separated by whitespace. '#', ';' and '//'
+/// start comments, and a word like '0300:' places what follows at that address (otherwise it goes to 'load_addr').
+pub fn parse_hex_text(text: &str, load_addr: usize) -> Result<Vec<Segment>, String>
+{
+    let mut segments = vec![Segment { addr: load_addr, data: Vec::new() }];
+
+    for (index, line) in text.lines().enumerate()
+    {
+        let line_number = index + 1;
+
+        for word in strip_comment(line).split_whitespace()
+        {
+            if let Some(addr) = word.strip_suffix(':')
+            {
+                let addr = usize::from_str_radix(strip_hex_prefix(addr), 16).map_err(|_| format!("line {0}: Invalid address '{1}'", line_number, word))?;
+                segments.push(Segment { addr, data: Vec::new() });
+                continue;
+            }
+
+            let bytes = decode_hex(strip_hex_prefix(word)).map_err(|e| format!("line {0}: {1}", line_number, e))?;
+            segments.last_mut().unwrap().data.extend(bytes);
+        }
+    }
+
+    segments.retain(|segment| !segment.data.is_empty());
+
+    Ok(segments)
+}
+
+fn strip_comment(line: &str) -> &str
+{
+    let end = [line.find('#'), line.find(';'), line.find("//")].into_iter().flatten().min().unwrap_or(line.len());
+    &line[..end]
+}
+
+fn strip_hex_prefix(word: &str) -> &str
+{
+    word.strip_prefix("0x").or(word.strip_prefix("0X")).unwrap_or(word)
+}
+
+fn is_hex_word(word: &str) -> bool
+{
+    let digits = strip_hex_prefix(word);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Pairs of hex digits to bytes.
+fn decode_hex(digits: &str) -> Result<Vec<u8>, String>
+{
+    if !digits.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(format!("'{}' isn't hex", digits));
+    }
+
+    if !digits.len().is_multiple_of(2)
+    {
+        return Err(format!("'{}' has an odd number of hex digits", digits));
+    }
+
+    Ok((0..digits.len()).step_by(2).map(|index| u8::from_str_radix(&digits[index..index + 2], 16).unwrap()).collect())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::rom::container::{decode_rom, detect_format, flatten, parse_hex_text, parse_intel_hex, EnumRomFormat, Segment};
+
+    #[test]
+    fn detect_formats()
+    {
+        assert_eq!(detect_format(&[0x00, 0xE0, 0x12, 0x00]), EnumRomFormat::Binary);
+        assert_eq!(detect_format(b""), EnumRomFormat::Binary);
+        assert_eq!(detect_format(b":0400000000E012000A\n:00000001FF\n"), EnumRomFormat::IntelHex);
+        assert_eq!(detect_format(b"# Pong\n00E0 1200 ; loop\n"), EnumRomFormat::HexText);
+        assert_eq!(detect_format(b"0300: 0xF0 90\n"), EnumRomFormat::HexText);
+        assert_eq!(detect_format(b"Not a ROM\n"), EnumRomFormat::Binary);
+
+        let (format, segments) = decode_rom(&[0x00, 0xE0], 0x200).unwrap();
+        assert_eq!(format, EnumRomFormat::Binary);
+        assert_eq!(segments, [Segment { addr: 0x200, data: vec![0x00, 0xE0] }]);
+    }
+
+    #[test]
+    fn intel_hex_segments()
+    {
+        let text = "\
+:0402000000E0120404
+:02020400A30055
+:02000004000AF0
+:02000000F0907E
+:00000001FF
+";
+
+        let segments = parse_intel_hex(text).unwrap();
+        assert_eq!(segments, [Segment { addr: 0x200, data: vec![0x00, 0xE0, 0x12, 0x04, 0xA3, 0x00] },
+                              Segment { addr: 0xA0000, data: vec![0xF0, 0x90] }]);
+
+        // Extended segment addresses are in 16 byte paragraphs; records after the end are ignored.
+        let segments = parse_intel_hex(":020000020030CC\n:01000000AA55\n:00000001FF\n:01000000BB44\n").unwrap();
+        assert_eq!(segments, [Segment { addr: 0x300, data: vec![0xAA] }]);
+    }
+
+    #[test]
+    fn intel_hex_errors_name_the_line()
+    {
+        assert_eq!(parse_intel_hex(":0400000000E012000A\n:0400000000E012000B\n"), Err(String::from("line 2: Checksum is 0x0B but should be 0x0A")));
+        assert_eq!(parse_intel_hex(":0400000000E012000A\n"), Err(String::from("line 1: No end of file record (is the file truncated?)")));
+        assert_eq!(parse_intel_hex(":0500000000E012000A\n"), Err(String::from("line 1: The byte count is 5 but the record has 4 data bytes")));
+        assert_eq!(parse_intel_hex("\n:0000000AF6\n"), Err(String::from("line 2: Unknown record type 0x0A")));
+        assert_eq!(parse_intel_hex(":00000001F\n"), Err(String::from("line 1: '00000001F' has an odd number of hex digits")));
+        assert_eq!(parse_intel_hex(":00000001FF\n0000\n").unwrap(), []);
+    }
+
+    #[test]
+    fn hex_text_with_comments_and_addresses()
+    {
+        let text = "\
+# Listing from page 42
+00E0 A20A  ; clear, i := sprite
+D015 1206  // draw, loop
+0x020A: 0xF0 90 90 F0
+";
+
+        let segments = parse_hex_text(text, 0x200).unwrap();
+        assert_eq!(segments, [Segment { addr: 0x200, data: vec![0x00, 0xE0, 0xA2, 0x0A, 0xD0, 0x15, 0x12, 0x06] },
+                              Segment { addr: 0x20A, data: vec![0xF0, 0x90, 0x90, 0xF0] }]);
+
+        assert_eq!(parse_hex_text("00E0\n120\n", 0x200), Err(String::from("line 2: '120' has an odd number of hex digits")));
+        assert_eq!(parse_hex_text("G0:\n", 0x200), Err(String::from("line 1: Invalid address 'G0:'")));
+    }
+
+    #[test]
+    fn flatten_fills_the_gaps()
+    {
+        let segments = [Segment { addr: 0x204, data: vec![0xF0, 0x90] }, Segment { addr: 0x200, data: vec![0x00, 0xE0] }];
+        assert_eq!(flatten(&segments, 4096), Some(vec![0x00, 0xE0, 0x00, 0x00, 0xF0, 0x90]));
+        assert_eq!(flatten(&segments, 5), None);
+        assert_eq!(flatten(&[], 4096), Some(Vec::new()));
+
+        // Hex text without addresses is the binary it was written from.
+        let (_, segments) = decode_rom(b"00E0 1200\n", 0x200).unwrap();
+        assert_eq!(flatten(&segments, 4096), Some(vec![0x00, 0xE0, 0x12, 0x00]));
+
+        assert_eq!(flatten(&[Segment { addr: usize::MAX, data: vec![0x00] }], 4096), None);
+    }
+}
@@ -0,0 +1,33 @@
+/// CRC-32 (IEEE 802.3, the one used by zip and PNG) of 'data'.
+pub fn crc32(data: &[u8]) -> u32
+{
+    let mut crc = !0u32;
+
+    for byte in data
+    {
+        crc ^= *byte as u32;
+
+        for _ in 0..8
+        {
+            // Reflected polynomial 0x04C11DB7.
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::rom::crc32::crc32;
+
+    #[test]
+    fn crc32_known_values()
+    {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414F_A339);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::env::config_file::ConfigEntry;
+use crate::rom::sha1::{format_sha1, sha1};
+
+const BUILTIN_DATABASE: &str = include_str!("rom_db.txt");
+
+/// The system a ROM was written for. Each one extends the one before it.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum EnumPlatform
+{
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl std::fmt::Display for EnumPlatform
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Chip8 => write!(f, "chip8"),
+            Self::Schip => write!(f, "schip"),
+            Self::XoChip => write!(f, "xochip"),
+        }
+    }
+}
+
+impl std::str::FromStr for EnumPlatform
+{
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err>
+    {
+        match text
+        {
+            "chip8" => Ok(Self::Chip8),
+            "schip" => Ok(Self::Schip),
+            "xochip" => Ok(Self::XoChip),
+            _ => Err(format!("Unknown platform '{}' (expected chip8, schip or xochip)", text)),
+        }
+    }
+}
+
+/// What the database knows about one ROM image.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RomEntry
+{
+    /// 40 lowercase hex digits.
+    pub sha1: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub platform: Option<EnumPlatform>,
+    /// Config file options the ROM needs (quirks, speed...), applied when it's loaded.
+    pub options: Vec<ConfigEntry>,
+    /// The database file it came from, for messages ('<built-in>' for the compiled-in one).
+    pub source: String,
+}
+
+impl RomEntry
+{
+    /// 'Title: ...' lines for 'hchip8 info'.
+    pub fn format_info(&self, stream: &mut String)
+    {
+        stream.push_str(&format!("Title: {}\n", self.title));
+
+        if let Some(author) = &self.author
+        {
+            stream.push_str(&format!("Author: {}\n", author));
+        }
+
+        if let Some(platform) = self.platform
+        {
+            stream.push_str(&format!("Platform: {}\n", platform));
+        }
+
+        for option in &self.options
+        {
+            stream.push_str(&format!("Setting: {0} = {1}\n", option.key, option.value));
+        }
+
+        stream.push_str(&format!("Database: {}\n", self.source));
+    }
+}
+
+/// ROMs identified by the SHA-1 of their image, in config-file-like syntax (see 'rom_db.txt'):
+///
+/// ```text
+/// [<sha1>]
+/// title = Pong
+/// platform = chip8
+/// quirks = chip8
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RomDatabase
+{
+    entries: Vec<RomEntry>,
+}
+
+impl RomDatabase
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// The database compiled into hchip8.
+    pub fn builtin() -> Self
+    {
+        Self::parse(BUILTIN_DATABASE, "<built-in>").expect("The built-in ROM database is valid")
+    }
+
+    /// Parses a database; 'source' names it in entries and messages.
+    pub fn parse(text: &str, source: &str) -> Result<Self, String>
+    {
+        let mut entries: Vec<RomEntry> = Vec::new();
+
+        for (index, raw_line) in text.lines().enumerate()
+        {
+            let line_number = index + 1;
+            let line = raw_line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';')
+            {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[')
+            {
+                let hash = header.strip_suffix(']').ok_or(format!("line {}: Expected ']' to close the section header", line_number))?.trim();
+
+                if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit())
+                {
+                    return Err(format!("line {0}: Expected a SHA-1 (40 hex digits) but got '[{1}]'", line_number, hash));
+                }
+
+                Self::check_title(entries.last(), line_number)?;
+                entries.push(RomEntry { sha1: hash.to_ascii_lowercase(), title: String::new(), author: None, platform: None, options: Vec::new(),
+                                        source: String::from(source) });
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or(format!("line {0}: Expected 'key = value' but got '{1}'", line_number, line))?;
+            let (key, value) = (key.trim(), value.trim());
+            let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+
+            let entry = entries.last_mut().ok_or(format!("line {}: Expected a '[<sha1>]' header before the first key", line_number))?;
+
+            match key
+            {
+                "title" => { entry.title = String::from(value); },
+                "author" => { entry.author = Some(String::from(value)); },
+                "platform" => { entry.platform = Some(value.parse::<EnumPlatform>().map_err(|e| format!("line {0}: {1}", line_number, e))?); },
+                _ => { entry.options.push(ConfigEntry { key: String::from(key), value: String::from(value), line: line_number }); },
+            }
+        }
+
+        Self::check_title(entries.last(), text.lines().count())?;
+
+        Ok(Self { entries })
+    }
+
+    fn check_title(entry: Option<&RomEntry>, line_number: usize) -> Result<(), String>
+    {
+        match entry
+        {
+            Some(entry) if entry.title.is_empty() => Err(format!("line {0}: [{1}] has no title", line_number, entry.sha1)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String>
+    {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read ROM database '{0}': {1}", path, e))?;
+        Self::parse(&text, path).map_err(|e| format!("{0}: {1}", path, e))
+    }
+
+    /// Adds the entries of 'other', which take precedence for the same ROM.
+    pub fn extend(&mut self, other: RomDatabase)
+    {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    /// The entry for a SHA-1 in hex (the last one added wins).
+    pub fn find(&self, sha1: &str) -> Option<&RomEntry>
+    {
+        self.entries.iter().rev().find(|entry| entry.sha1.eq_ignore_ascii_case(sha1))
+    }
+
+    pub fn identify(&self, rom: &[u8]) -> Option<&RomEntry>
+    {
+        self.find(&format_sha1(&sha1(rom)))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::bench::WORKLOADS;
+    use crate::rom::database::{EnumPlatform, RomDatabase};
+
+    const TEXT: &str = "
+# Local additions
+[1BFABD70888AEF30B2EB1050310C114A3E5D2EFF]
+title = \"My alu\"
+platform = schip
+speed = turbo
+";
+
+    #[test]
+    fn builtin_entries_match_their_roms()
+    {
+        let database = RomDatabase::builtin();
+        assert_eq!(database.len(), WORKLOADS.len());
+
+        for workload in WORKLOADS.iter()
+        {
+            let rom: Vec<u8> = workload.program.iter().flat_map(|word| word.to_be_bytes()).collect();
+            let entry = database.identify(&rom).unwrap();
+
+            assert_eq!(entry.title, format!("Benchmark: {}", workload.name));
+            assert_eq!(entry.platform, Some(EnumPlatform::Chip8));
+            assert_eq!(entry.source, "<built-in>");
+        }
+
+        assert!(database.identify(&[0x00, 0xE0]).is_none());
+    }
+
+    #[test]
+    fn local_entries_take_precedence()
+    {
+        let mut database = RomDatabase::builtin();
+        database.extend(RomDatabase::parse(TEXT, "local.db").unwrap());
+
+        let entry = database.find("1bfabd70888aef30b2eb1050310c114a3e5d2eff").unwrap();
+        assert_eq!(entry.title, "My alu");
+        assert_eq!(entry.author, None);
+        assert_eq!(entry.platform, Some(EnumPlatform::Schip));
+        assert_eq!(entry.options.len(), 1);
+        assert_eq!((entry.options[0].key.as_str(), entry.options[0].value.as_str(), entry.options[0].line), ("speed", "turbo", 6));
+
+        let mut stream = String::new();
+        entry.format_info(&mut stream);
+        assert_eq!(stream, "Title: My alu\nPlatform: schip\nSetting: speed = turbo\nDatabase: local.db\n");
+    }
+
+    #[test]
+    fn parse_errors_name_the_line()
+    {
+        assert!(RomDatabase::parse("title = Pong\n", "db").unwrap_err().starts_with("line 1: Expected a '[<sha1>]' header"));
+        assert!(RomDatabase::parse("[1234]\n", "db").unwrap_err().starts_with("line 1: Expected a SHA-1"));
+        assert!(RomDatabase::parse("[da39a3ee5e6b4b0d3255bfef95601890afd80709]\nquirks = chip8\n", "db").unwrap_err().contains("has no title"));
+        assert!(RomDatabase::parse("[da39a3ee5e6b4b0d3255bfef95601890afd80709]\ntitle = Empty\nplatform = c64\n", "db").unwrap_err().starts_with("line 3: Unknown platform"));
+    }
+}
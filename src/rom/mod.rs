@@ -0,0 +1,5 @@
+pub mod container;
+pub mod crc32;
+pub mod database;
+pub mod patch;
+pub mod sha1;
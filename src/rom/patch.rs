@@ -0,0 +1,387 @@
+use crate::rom::crc32::crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: usize = 0x454F46;
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Source, target and patch CRC-32s at the end of a BPS patch.
+const BPS_FOOTER_SIZE: usize = 12;
+
+/// Why a patch could not be applied. Nothing is loaded when it fails.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EnumPatchError
+{
+    /// Neither an IPS ('PATCH') nor a BPS ('BPS1') header.
+    UnknownFormat,
+    /// The patch ends in the middle of a record.
+    Truncated,
+    /// A BPS command reads or writes outside the source or target image.
+    OutOfRange,
+    /// The BPS patch was made for a ROM of a different size.
+    SourceSize { expected: usize, actual: usize },
+    /// The BPS patch was made for a different ROM (or another patch was applied first).
+    SourceChecksum { expected: u32, actual: u32 },
+    TargetChecksum { expected: u32, actual: u32 },
+    /// The BPS patch file itself is damaged.
+    PatchChecksum { expected: u32, actual: u32 },
+}
+
+impl std::fmt::Display for EnumPatchError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::UnknownFormat => write!(f, "not an IPS or BPS patch"),
+            Self::Truncated => write!(f, "the patch is truncated"),
+            Self::OutOfRange => write!(f, "the patch reads or writes outside the ROM"),
+            Self::SourceSize { expected, actual } => write!(f, "the patch expects a {0} byte ROM but it is {1} bytes", expected, actual),
+            Self::SourceChecksum { expected, actual } => write!(f, "the patch expects a ROM with CRC-32 {0:08x} but it is {1:08x}", expected, actual),
+            Self::TargetChecksum { expected, actual } => write!(f, "the patched ROM has CRC-32 {1:08x} instead of {0:08x}", expected, actual),
+            Self::PatchChecksum { expected, actual } => write!(f, "the patch has CRC-32 {1:08x} instead of {0:08x}", expected, actual),
+        }
+    }
+}
+
+impl std::error::Error for EnumPatchError {}
+
+/// Applies an IPS or BPS patch (told apart by their headers) to a ROM image.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, EnumPatchError>
+{
+    if patch.starts_with(IPS_MAGIC)
+    {
+        return apply_ips(rom, patch);
+    }
+
+    else if patch.starts_with(BPS_MAGIC)
+    {
+        return apply_bps(rom, patch);
+    }
+
+    Err(EnumPatchError::UnknownFormat)
+}
+
+/// Reads through a patch, failing with 'Truncated' at its end.
+struct Reader<'a>
+{
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a>
+{
+    fn new(data: &'a [u8], pos: usize) -> Self
+    {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], EnumPatchError>
+    {
+        let bytes = self.data.get(self.pos..self.pos + count).ok_or(EnumPatchError::Truncated)?;
+        self.pos += count;
+
+        Ok(bytes)
+    }
+
+    /// A big-endian number of 'count' bytes (IPS).
+    fn number(&mut self, count: usize) -> Result<usize, EnumPatchError>
+    {
+        Ok(self.bytes(count)?.iter().fold(0, |value, byte| (value << 8) | *byte as usize))
+    }
+
+    /// A BPS variable-length number: 7 bits per byte, the last byte has the high bit set.
+    fn varint(&mut self) -> Result<usize, EnumPatchError>
+    {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+
+        loop
+        {
+            let byte = self.bytes(1)?[0] as usize;
+            value = value.checked_add((byte & 0x7F).checked_mul(shift).ok_or(EnumPatchError::OutOfRange)?).ok_or(EnumPatchError::OutOfRange)?;
+
+            if byte & 0x80 != 0
+            {
+                return Ok(value);
+            }
+
+            shift = shift.checked_shl(7).ok_or(EnumPatchError::OutOfRange)?;
+            value = value.checked_add(shift).ok_or(EnumPatchError::OutOfRange)?;
+        }
+    }
+}
+
+/// IPS: records of (3-byte offset, 2-byte size, data), where a zero size means a run of one byte instead,
+/// up to 'EOF' and an optional 3-byte size to truncate the image to.
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, EnumPatchError>
+{
+    if !patch.starts_with(IPS_MAGIC)
+    {
+        return Err(EnumPatchError::UnknownFormat);
+    }
+
+    let mut image = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+
+    loop
+    {
+        let offset = reader.number(3)?;
+
+        if offset == IPS_EOF
+        {
+            break;
+        }
+
+        let size = reader.number(2)?;
+
+        let data = match size
+        {
+            0 =>
+            {
+                let run_size = reader.number(2)?;
+                vec![reader.bytes(1)?[0]; run_size]
+            },
+            _ => reader.bytes(size)?.to_vec(),
+        };
+
+        if image.len() < offset + data.len()
+        {
+            image.resize(offset + data.len(), 0);
+        }
+
+        image[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    if let Ok(truncated_size) = reader.number(3)
+    {
+        image.truncate(truncated_size);
+    }
+
+    Ok(image)
+}
+
+/// BPS: the source and target sizes, metadata, then commands that build the target from the source, the
+/// patch itself and what was built so far, checked with CRC-32s of all three.
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, EnumPatchError>
+{
+    if !patch.starts_with(BPS_MAGIC)
+    {
+        return Err(EnumPatchError::UnknownFormat);
+    }
+
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE
+    {
+        return Err(EnumPatchError::Truncated);
+    }
+
+    let footer_start = patch.len() - BPS_FOOTER_SIZE;
+    let footer: Vec<u32> = patch[footer_start..].chunks(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect();
+    let (source_crc, target_crc, patch_crc) = (footer[0], footer[1], footer[2]);
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+
+    if actual != patch_crc
+    {
+        return Err(EnumPatchError::PatchChecksum { expected: patch_crc, actual });
+    }
+
+    // Only look at the commands, not into the footer.
+    let mut reader = Reader::new(&patch[..footer_start], BPS_MAGIC.len());
+
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != rom.len()
+    {
+        return Err(EnumPatchError::SourceSize { expected: source_size, actual: rom.len() });
+    }
+
+    let actual = crc32(rom);
+
+    if actual != source_crc
+    {
+        return Err(EnumPatchError::SourceChecksum { expected: source_crc, actual });
+    }
+
+    let mut target: Vec<u8> = Vec::new();
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+
+    while reader.pos < footer_start
+    {
+        let command = reader.varint()?;
+        let length = (command >> 2) + 1;
+
+        if target.len() + length > target_size
+        {
+            return Err(EnumPatchError::OutOfRange);
+        }
+
+        match command & 3
+        {
+            // SourceRead: the bytes at the same offset in the source.
+            0 =>
+            {
+                let start = target.len();
+                target.extend_from_slice(rom.get(start..start + length).ok_or(EnumPatchError::OutOfRange)?);
+            },
+            // TargetRead: bytes from the patch.
+            1 => { target.extend_from_slice(reader.bytes(length)?); },
+            // SourceCopy and TargetCopy: bytes from a relative offset in the source or target.
+            kind =>
+            {
+                let delta = reader.varint()?;
+                let offset = if kind == 2 { &mut source_offset } else { &mut target_offset };
+
+                *offset = match delta & 1
+                {
+                    0 => offset.checked_add(delta >> 1),
+                    _ => offset.checked_sub(delta >> 1),
+                }.ok_or(EnumPatchError::OutOfRange)?;
+
+                if kind == 2
+                {
+                    target.extend_from_slice(rom.get(*offset..*offset + length).ok_or(EnumPatchError::OutOfRange)?);
+                }
+
+                else
+                {
+                    // The copy may overlap what it writes (repeating a pattern), so go byte by byte.
+                    for index in *offset..*offset + length
+                    {
+                        let value = *target.get(index).ok_or(EnumPatchError::OutOfRange)?;
+                        target.push(value);
+                    }
+                }
+
+                *offset += length;
+            },
+        }
+    }
+
+    if target.len() != target_size
+    {
+        return Err(EnumPatchError::Truncated);
+    }
+
+    let actual = crc32(&target);
+
+    if actual != target_crc
+    {
+        return Err(EnumPatchError::TargetChecksum { expected: target_crc, actual });
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::rom::crc32::crc32;
+    use crate::rom::patch::{apply_bps, apply_ips, apply_patch, EnumPatchError};
+
+    const ROM: &[u8] = &[0x60, 0x01, 0x61, 0x02, 0x12, 0x04];
+
+    fn varint(mut value: usize, patch: &mut Vec<u8>)
+    {
+        loop
+        {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+
+            if value == 0
+            {
+                patch.push(byte | 0x80);
+                return;
+            }
+
+            patch.push(byte);
+            value -= 1;
+        }
+    }
+
+    /// A BPS command: 0 SourceRead, 1 TargetRead, 2 SourceCopy, 3 TargetCopy.
+    fn command(kind: usize, length: usize, patch: &mut Vec<u8>)
+    {
+        varint(((length - 1) << 2) | kind, patch);
+    }
+
+    /// A BPS patch from 'ROM' to 'target' made of the given commands (already encoded), with correct checksums.
+    fn bps(target: &[u8], commands: &[u8]) -> Vec<u8>
+    {
+        let mut patch = b"BPS1".to_vec();
+        varint(ROM.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        patch.extend_from_slice(commands);
+        patch.extend_from_slice(&crc32(ROM).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+
+        patch
+    }
+
+    #[test]
+    fn ips_records_runs_and_truncation()
+    {
+        // Change V1's value, append 3 bytes of 0xFF after a gap, then end.
+        let patch = b"PATCH\x00\x00\x03\x00\x01\x09\x00\x00\x08\x00\x00\x00\x03\xFFEOF";
+        assert_eq!(apply_ips(ROM, patch), Ok(vec![0x60, 0x01, 0x61, 0x09, 0x12, 0x04, 0x00, 0x00, 0xFF, 0xFF, 0xFF]));
+
+        // Truncate to 4 bytes.
+        assert_eq!(apply_patch(ROM, b"PATCHEOF\x00\x00\x04"), Ok(ROM[..4].to_vec()));
+
+        assert_eq!(apply_ips(ROM, b"PATCH\x00\x00\x03\x00\x04\x09"), Err(EnumPatchError::Truncated));
+        assert_eq!(apply_patch(ROM, b"UPS1"), Err(EnumPatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn bps_commands()
+    {
+        let target = [0x60, 0x01, 0x61, 0x07, 0x61, 0x07, 0x61, 0x07, 0x12, 0x04];
+
+        // SourceRead 3, TargetRead 1 (0x07), TargetCopy 4 from offset 2, SourceCopy 2 from offset 4
+        let mut commands = Vec::new();
+        command(0, 3, &mut commands);
+        command(1, 1, &mut commands);
+        commands.push(0x07);
+        command(3, 4, &mut commands);
+        varint(2 << 1, &mut commands);
+        command(2, 2, &mut commands);
+        varint(4 << 1, &mut commands);
+
+        assert_eq!(apply_bps(ROM, &bps(&target, &commands)), Ok(target.to_vec()));
+    }
+
+    #[test]
+    fn bps_checksums()
+    {
+        let mut commands = Vec::new();
+        command(0, ROM.len(), &mut commands);
+        let patch = bps(ROM, &commands);
+
+        assert_eq!(apply_patch(ROM, &patch), Ok(ROM.to_vec()));
+
+        // A different ROM of the same size.
+        let other = [0x60, 0x01, 0x61, 0x02, 0x12, 0x06];
+        assert_eq!(apply_bps(&other, &patch), Err(EnumPatchError::SourceChecksum { expected: crc32(ROM), actual: crc32(&other) }));
+        assert_eq!(apply_bps(&ROM[..4], &patch), Err(EnumPatchError::SourceSize { expected: 6, actual: 4 }));
+
+        // A damaged patch.
+        let mut damaged = patch.clone();
+        damaged[6] ^= 1;
+        assert!(matches!(apply_bps(ROM, &damaged), Err(EnumPatchError::PatchChecksum { .. })));
+
+        // A patch whose target checksum doesn't match what it builds.
+        let mut patch = b"BPS1".to_vec();
+        varint(ROM.len(), &mut patch);
+        varint(ROM.len(), &mut patch);
+        varint(0, &mut patch);
+        patch.extend_from_slice(&commands);
+        patch.extend_from_slice(&crc32(ROM).to_le_bytes());
+        patch.extend_from_slice(&0u32.to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        assert_eq!(apply_bps(ROM, &patch), Err(EnumPatchError::TargetChecksum { expected: 0, actual: crc32(ROM) }));
+    }
+}
@@ -0,0 +1,89 @@
+/// SHA-1 (FIPS 180-4) of 'data'. Only used to identify ROM images, not for anything security related.
+pub fn sha1(data: &[u8]) -> [u8; 20]
+{
+    let mut state: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+    // The message, a 1 bit, zeros up to 8 bytes short of a block, then the length in bits.
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56
+    {
+        message.push(0);
+    }
+
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+
+    for block in message.chunks(64)
+    {
+        let mut words = [0u32; 80];
+
+        for (index, bytes) in block.chunks(4).enumerate()
+        {
+            words[index] = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        for index in 16..80
+        {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+
+        for (index, word) in words.iter().enumerate()
+        {
+            let (f, k) = match index
+            {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e])
+        {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+
+    for (bytes, value) in digest.chunks_mut(4).zip(state)
+    {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+
+    digest
+}
+
+/// The digest as 40 lowercase hex digits, the way 'sha1sum' prints it.
+pub fn format_sha1(digest: &[u8; 20]) -> String
+{
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::rom::sha1::{format_sha1, sha1};
+
+    #[test]
+    fn sha1_known_values()
+    {
+        assert_eq!(format_sha1(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(format_sha1(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(format_sha1(&sha1(b"The quick brown fox jumps over the lazy dog")), "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12");
+
+        // Two blocks once the padding is added.
+        assert_eq!(format_sha1(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(format_sha1(&sha1(&[b'a'; 1000])), "291e9a6c66994949b57ba5e650361e98fc36b1ba");
+    }
+}